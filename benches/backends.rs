@@ -0,0 +1,121 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Compares insertions, queries and unions across the provided
+//! backends and filter sizes.
+
+use bitvec::prelude::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use generic_bloom::traits::set::{BinaryBloomSet, BloomSetRead};
+use generic_bloom::*;
+use std::hint::black_box;
+use std::sync::atomic::AtomicUsize;
+
+const N_HASHERS: u64 = 7;
+/// Numbers of counters to benchmark: one filter which fits in cache
+/// and one which does not.
+const SIZES: [usize; 2] = [1 << 16, 1 << 26];
+/// Number of keys inserted or queried per iteration.
+const N_KEYS: u64 = 1024;
+
+fn hashers() -> Box<[SeededState]> {
+    (0..N_HASHERS).map(|i| SeededState::new(0, i)).collect()
+}
+
+type Simple<B> = SimpleBloomFilter<B, SeededState, Box<[SeededState]>>;
+
+/// Benchmarks insertions and queries of a filter created by `new`
+/// for each size, under the name `name`.
+fn bench_filter<F: BloomFilter>(c: &mut Criterion, name: &str, new: impl Fn(usize) -> F) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(N_KEYS));
+    for size in SIZES {
+        let mut filter = new(size);
+        group.bench_with_input(BenchmarkId::new("insert", size), &size, |b, _| {
+            b.iter(|| {
+                for x in 0..N_KEYS {
+                    filter.insert(&black_box(x));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("insert_batch", size), &size, |b, _| {
+            let keys: Vec<u64> = (0..N_KEYS).collect();
+            b.iter(|| filter.insert_batch(black_box(&keys)))
+        });
+        group.bench_with_input(BenchmarkId::new("contains", size), &size, |b, _| {
+            b.iter(|| {
+                for x in N_KEYS / 2..N_KEYS * 3 / 2 {
+                    black_box(filter.contains(&black_box(x)));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("contains_batch", size), &size, |b, _| {
+            let keys: Vec<u64> = (N_KEYS / 2..N_KEYS * 3 / 2).collect();
+            b.iter(|| black_box(filter.contains_batch(black_box(&keys))))
+        });
+    }
+    group.finish();
+}
+
+/// Benchmarks unions of sets created by `new` for each size, under
+/// the name `name`.
+fn bench_union<F: BinaryBloomFilter>(c: &mut Criterion, name: &str, new: impl Fn(usize) -> F)
+where
+    F::Set: BinaryBloomSet,
+{
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        let mut a = new(size);
+        let mut b = new(size);
+        a.insert_batch(&(0..N_KEYS).collect::<Vec<_>>());
+        b.insert_batch(&(N_KEYS..2 * N_KEYS).collect::<Vec<_>>());
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("union", size), &size, |bench, _| {
+            bench.iter(|| a.union(black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("count_ones", size), &size, |bench, _| {
+            bench.iter(|| black_box(a.counters().count_ones()))
+        });
+    }
+    group.finish();
+}
+
+fn backends(c: &mut Criterion) {
+    bench_filter(c, "bitbox", |n| Simple::<BitBox<usize, Lsb0>>::with_hashers(hashers(), n));
+    bench_filter(c, "inline", |n| {
+        InlineBloomFilter::<BitBox<usize, Lsb0>, { N_HASHERS as usize }, SeededState>::with_hashers(
+            std::array::from_fn(|i| SeededState::new(0, i as u64)),
+            n,
+        )
+    });
+    bench_filter(c, "epoch", |n| Simple::<EpochSet>::with_hashers(hashers(), n));
+    bench_filter(c, "counting_u8", |n| Simple::<Box<[u8]>>::with_hashers(hashers(), n));
+    bench_filter(c, "counting_u32", |n| Simple::<Box<[u32]>>::with_hashers(hashers(), n));
+    bench_filter(c, "concurrent", |n| {
+        ConcurrentBloomFilter::<BitBox<AtomicUsize, Lsb0>, SeededState>::with_hashers(hashers(), n)
+    });
+    bench_filter(c, "block", |n| {
+        BlockBloomFilter::<SeededState>::with_hasher(SeededState::new(0, 0), n)
+    });
+
+    bench_union(c, "bitbox", |n| Simple::<BitBox<usize, Lsb0>>::with_hashers(hashers(), n));
+    bench_union(c, "epoch", |n| Simple::<EpochSet>::with_hashers(hashers(), n));
+    bench_union(c, "counting_u8", |n| Simple::<Box<[u8]>>::with_hashers(hashers(), n));
+    bench_union(c, "block", |n| {
+        BlockBloomFilter::<SeededState>::with_hasher(SeededState::new(0, 0), n)
+    });
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
@@ -0,0 +1,136 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Measures insertions and queries on each filter type, and checks
+//! that none of them allocate once the filter has been created.
+
+use bitvec::prelude::*;
+use generic_bloom::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Forwards to the system allocator, counting allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const N_HASHERS: u64 = 7;
+const N_COUNTERS: usize = 1 << 24;
+const N_OPS: u64 = 1 << 18;
+
+fn hashers() -> Box<[SeededState]> {
+    (0..N_HASHERS).map(|i| SeededState::new(0, i)).collect()
+}
+
+/// Runs `op` on `N_OPS` keys, printing its time per operation and
+/// panicking if it allocates.
+fn bench(name: &str, mut op: impl FnMut(u64)) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for x in 0..N_OPS {
+        op(black_box(x));
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<36} {:>8.1} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / N_OPS as f64
+    );
+    assert_eq!(allocations, 0, "{} allocated {} times", name, allocations);
+}
+
+fn main() {
+    let mut simple: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, _> =
+        SimpleBloomFilter::with_hashers(hashers(), N_COUNTERS);
+    bench("SimpleBloomFilter<BitBox> insert", |x| simple.insert(&x));
+    bench("SimpleBloomFilter<BitBox> contains", |x| {
+        black_box(simple.contains(&x));
+    });
+
+    let mut inline: InlineBloomFilter<BitBox<usize, Lsb0>, { N_HASHERS as usize }, SeededState> =
+        SimpleBloomFilter::with_hashers(std::array::from_fn(|i| SeededState::new(0, i as u64)), N_COUNTERS);
+    bench("InlineBloomFilter insert", |x| inline.insert(&x));
+    bench("InlineBloomFilter contains", |x| {
+        black_box(inline.contains(&x));
+    });
+
+    let mut epoch: SimpleBloomFilter<EpochSet, SeededState, _> =
+        SimpleBloomFilter::with_hashers(hashers(), N_COUNTERS);
+    bench("SimpleBloomFilter<EpochSet> insert", |x| epoch.insert(&x));
+    bench("SimpleBloomFilter<EpochSet> clear", |_| epoch.clear());
+
+    let mut counting: SimpleBloomFilter<Box<[u8]>, SeededState, _> =
+        SimpleBloomFilter::with_hashers(hashers(), N_COUNTERS);
+    bench("SimpleBloomFilter<[u8]> insert", |x| counting.insert(&x));
+    bench("SimpleBloomFilter<[u8]> remove", |x| counting.remove(&x));
+    bench("SimpleBloomFilter<[u8]> find_count", |x| {
+        black_box(counting.find_count(&x));
+    });
+
+    let concurrent: ConcurrentBloomFilter<BitBox<AtomicUsize, Lsb0>, SeededState> =
+        ConcurrentBloomFilter::with_hashers(hashers(), N_COUNTERS);
+    bench("ConcurrentBloomFilter insert", |x| concurrent.insert(&x));
+    bench("ConcurrentBloomFilter contains", |x| {
+        black_box(concurrent.contains(&x));
+    });
+
+    let sharded: ShardedBloomFilter<BitBox<usize, Lsb0>, SeededState, _, SeededState> =
+        ShardedBloomFilter::with_router(16, hashers(), N_COUNTERS / 16, SeededState::new(1, 0));
+    bench("ShardedBloomFilter insert", |x| sharded.insert(&x));
+    bench("ShardedBloomFilter contains", |x| {
+        black_box(sharded.contains(&x));
+    });
+
+    let mut block: BlockBloomFilter<SeededState> =
+        BlockBloomFilter::with_hasher(SeededState::new(0, 0), N_COUNTERS);
+    bench("BlockBloomFilter insert", |x| block.insert(&x));
+    bench("BlockBloomFilter contains", |x| {
+        black_box(block.contains(&x));
+    });
+
+    let dir = std::env::temp_dir().join(format!("generic-bloom-bench-{}", std::process::id()));
+    let mut durable = DurableBloomFilter::create(
+        &dir,
+        SimpleBloomFilter::<BitBox<usize, Lsb0>, _, _>::with_hashers(hashers(), N_COUNTERS),
+    )
+    .unwrap();
+    durable.set_checkpoint_interval(usize::MAX);
+    // The first insertion sizes the reused buffers.
+    durable.insert(&u64::MAX).unwrap();
+    bench("DurableBloomFilter insert", |x| durable.insert(&x).unwrap());
+    drop(durable);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,345 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::next_random;
+use crate::SeededState;
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of slots in each bucket.
+const BUCKET_SLOTS: usize = 4;
+
+/// Number of fingerprint functions a slot can select between.
+const SELECTORS: u8 = 4;
+
+/// Number of keys moved while inserting a key before the filter is
+/// considered full.
+const MAX_KICKS: usize = 500;
+
+/// Load factor at which filters are sized to hold the requested
+/// number of keys.
+const MAX_LOAD: f64 = 0.95;
+
+/// A cuckoo filter which stops repeating a false positive once it is
+/// told about it.
+///
+/// Like a cuckoo filter, it stores an 8-bit fingerprint of each key
+/// in one of two buckets of four slots, and supports deletion. Each
+/// slot also records which of four fingerprint functions its
+/// fingerprint was computed with. When a query for a key which was
+/// never inserted is found to be a false positive, e.g. because the
+/// database behind the filter did not have it,
+/// [`report_false_positive`](AdaptiveCuckooFilter::report_false_positive)
+/// switches the colliding slots to another fingerprint function, so
+/// the key most likely stops matching. A hot negative key therefore
+/// costs a few wasted lookups rather than one per query.
+///
+/// Recomputing a fingerprint needs the key it belongs to, so the
+/// filter keeps every key in a table beside the fingerprints, as the
+/// backing store of an adaptive filter would; queries only read the
+/// fingerprints. Keeping the keys also makes
+/// [`remove`](AdaptiveCuckooFilter::remove) exact: removing a key
+/// which was never inserted has no effect.
+///
+/// # Example
+/// ```
+/// use generic_bloom::AdaptiveCuckooFilter;
+///
+/// let mut filter: AdaptiveCuckooFilter<u64> = AdaptiveCuckooFilter::with_seed(1, 1000);
+/// for i in 0..1000 {
+///     assert!(filter.insert(i));
+/// }
+///
+/// // Find a false positive, and report it.
+/// let hot = (1000..).find(|i| filter.contains(i)).unwrap();
+/// assert!(filter.report_false_positive(&hot));
+/// assert!(!filter.contains(&hot));
+/// // The inserted keys are unaffected.
+/// assert!((0..1000).all(|i| filter.contains(&i)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct AdaptiveCuckooFilter<K, S = SeededState> {
+    hasher: S,
+    // Slot `i` is in bucket `i / BUCKET_SLOTS`, and is empty if its
+    // fingerprint is zero.
+    fingerprints: Box<[u8]>,
+    selectors: Box<[u8]>,
+    keys: Box<[Option<K>]>,
+    len: usize,
+    // A key which could not be placed when the filter filled up, and
+    // its fingerprint function.
+    victim: Option<(K, u8)>,
+    // State of the generator choosing keys to move.
+    rng: u64,
+}
+
+impl<K: Hash + Eq> AdaptiveCuckooFilter<K, SeededState> {
+    /// Creates an empty `AdaptiveCuckooFilter` with room for at least
+    /// `capacity` keys, hashing them with a [`SeededState`] created
+    /// from `seed`.
+    pub fn with_seed(seed: u128, capacity: usize) -> Self {
+        AdaptiveCuckooFilter::with_hasher(SeededState::from_seed(seed), capacity)
+    }
+}
+
+impl<K: Hash + Eq, S: BuildHasher> AdaptiveCuckooFilter<K, S> {
+    /// Creates an empty `AdaptiveCuckooFilter` with room for at least
+    /// `capacity` keys. The [`BuildHasher`] will be initialized by
+    /// [`default`](Default::default).
+    pub fn new(capacity: usize) -> Self
+    where
+        S: Default,
+    {
+        AdaptiveCuckooFilter::with_hasher(S::default(), capacity)
+    }
+
+    /// Creates an empty `AdaptiveCuckooFilter` with a specified
+    /// `BuildHasher` and room for at least `capacity` keys. The
+    /// number of buckets is rounded up to a power of two, so the
+    /// actual [`capacity`](AdaptiveCuckooFilter::capacity) may be up
+    /// to twice as large.
+    pub fn with_hasher(hasher: S, capacity: usize) -> Self {
+        let n_buckets = (capacity as f64 / (BUCKET_SLOTS as f64 * MAX_LOAD))
+            .ceil()
+            .max(1.0) as usize;
+        let n_slots = n_buckets.next_power_of_two() * BUCKET_SLOTS;
+        AdaptiveCuckooFilter {
+            hasher,
+            fingerprints: vec![0; n_slots].into_boxed_slice(),
+            selectors: vec![0; n_slots].into_boxed_slice(),
+            keys: std::iter::repeat_with(|| None).take(n_slots).collect(),
+            len: 0,
+            victim: None,
+            rng: 0x853c_49e6_748f_ea9b,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the number of keys in the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the filter holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of slots.
+    pub fn capacity(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Returns the fraction of slots in use.
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Inserts `key`, returning `false` if the filter is full, in
+    /// which case `key` was not inserted.
+    ///
+    /// When the filter fills up, the last key which could not be
+    /// placed is kept aside, so the insertion which fills it still
+    /// succeeds; later insertions fail until a key is removed.
+    pub fn insert(&mut self, key: K) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+        self.place((key, 0));
+        self.len += 1;
+        true
+    }
+
+    /// Checks whether the filter contains `key`. Only the
+    /// fingerprints are read.
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        self.candidate_slots(hash)
+            .any(|slot| self.fingerprints[slot] == fingerprint(hash, self.selectors[slot]))
+            || self.is_victim(key)
+    }
+
+    /// Removes `key`, returning whether it was in the filter.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let found = self
+            .candidate_slots(hash)
+            .find(|&slot| self.keys[slot].as_ref().is_some_and(|k| k.borrow() == key));
+        if let Some(slot) = found {
+            self.keys[slot] = None;
+            self.fingerprints[slot] = 0;
+            self.selectors[slot] = 0;
+            self.len -= 1;
+            // Make room for the key kept aside, if any.
+            if let Some(victim) = self.victim.take() {
+                self.place(victim);
+            }
+            true
+        } else if self.is_victim(key) {
+            self.victim = None;
+            self.len -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Tells the filter that `key`, which it reports as present, was
+    /// not inserted. Every slot whose fingerprint `key` matches is
+    /// switched to another fingerprint function, recomputing its
+    /// fingerprint from the key it holds. Returns whether any slot
+    /// was changed; this is `false` if `key` does not match or was
+    /// inserted.
+    pub fn report_false_positive<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let colliding: Vec<usize> = self
+            .candidate_slots(hash)
+            .filter(|&slot| self.fingerprints[slot] == fingerprint(hash, self.selectors[slot]))
+            .collect();
+        if self.is_victim(key)
+            || colliding
+                .iter()
+                .any(|&slot| self.keys[slot].as_ref().is_some_and(|k| k.borrow() == key))
+        {
+            return false;
+        }
+        for &slot in &colliding {
+            let resident = self.keys[slot].as_ref().expect("occupied slots hold a key");
+            let resident_hash = self.hasher.hash_one(resident);
+            let selector = (self.selectors[slot] + 1) % SELECTORS;
+            self.selectors[slot] = selector;
+            self.fingerprints[slot] = fingerprint(resident_hash, selector);
+        }
+        !colliding.is_empty()
+    }
+
+    /// Removes every key.
+    pub fn clear(&mut self) {
+        self.fingerprints.fill(0);
+        self.selectors.fill(0);
+        self.keys.iter_mut().for_each(|k| *k = None);
+        self.len = 0;
+        self.victim = None;
+    }
+
+    /// Returns the approximate probability that a key which was not
+    /// inserted, and not reported, is reported as present, given the
+    /// current load: the chance that its fingerprint matches one of
+    /// the occupied slots of its two buckets.
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        let compared = 2.0 * BUCKET_SLOTS as f64 * self.load_factor();
+        1.0 - (1.0 - 1.0 / 255.0f64).powf(compared)
+    }
+
+    /// Returns the number of bytes of heap memory used by the
+    /// fingerprints and the table of keys, not counting memory the
+    /// keys themselves own.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.fingerprints)
+            + std::mem::size_of_val(&*self.selectors)
+            + std::mem::size_of_val(&*self.keys)
+    }
+
+    /// Returns the two buckets of the key with hash `hash`.
+    fn buckets(&self, hash: u64) -> [usize; 2] {
+        let mask = self.fingerprints.len() / BUCKET_SLOTS - 1;
+        let mixed = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(29);
+        [hash as usize & mask, mixed as usize & mask]
+    }
+
+    /// Returns the slots of the buckets of the key with hash `hash`.
+    fn candidate_slots(&self, hash: u64) -> impl Iterator<Item = usize> {
+        self.buckets(hash)
+            .into_iter()
+            .flat_map(|b| b * BUCKET_SLOTS..(b + 1) * BUCKET_SLOTS)
+    }
+
+    fn is_victim<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.victim.as_ref().is_some_and(|(v, _)| v.borrow() == key)
+    }
+
+    /// Stores a key with the given fingerprint function, moving other
+    /// keys as needed. If no room is found, the last key moved is
+    /// kept aside as the victim.
+    fn place(&mut self, mut entry: (K, u8)) {
+        let mut bucket = None;
+        for _ in 0..MAX_KICKS {
+            let hash = self.hasher.hash_one(&entry.0);
+            let buckets = self.buckets(hash);
+            // Try both buckets of a new key, and the other bucket of
+            // a moved one.
+            let targets = match bucket {
+                None => &buckets[..],
+                Some(from) if buckets[0] == from => &buckets[1..],
+                Some(_) => &buckets[..1],
+            };
+            let empty = targets
+                .iter()
+                .flat_map(|&b| b * BUCKET_SLOTS..(b + 1) * BUCKET_SLOTS)
+                .find(|&slot| self.fingerprints[slot] == 0);
+            if let Some(slot) = empty {
+                self.store(slot, entry, hash);
+                return;
+            }
+            let r = next_random(&mut self.rng);
+            let target = targets[r as usize % targets.len()];
+            let slot = target * BUCKET_SLOTS + (r >> 32) as usize % BUCKET_SLOTS;
+            let evicted = (
+                self.keys[slot].take().expect("occupied slots hold a key"),
+                self.selectors[slot],
+            );
+            self.store(slot, entry, hash);
+            entry = evicted;
+            bucket = Some(target);
+        }
+        self.victim = Some(entry);
+    }
+
+    /// Stores `key`, with hash `hash`, in `slot` using fingerprint
+    /// function `selector`.
+    fn store(&mut self, slot: usize, (key, selector): (K, u8), hash: u64) {
+        self.fingerprints[slot] = fingerprint(hash, selector);
+        self.selectors[slot] = selector;
+        self.keys[slot] = Some(key);
+    }
+}
+
+/// Returns the nonzero fingerprint of the key with hash `hash` under
+/// fingerprint function `selector`, which is a byte of the hash not
+/// used to choose buckets.
+fn fingerprint(hash: u64, selector: u8) -> u8 {
+    match (hash >> (32 + 8 * selector as u32)) as u8 {
+        0 => 1,
+        fp => fp,
+    }
+}
@@ -0,0 +1,193 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::reduce;
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{SeededState, SimpleBloomFilter};
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+/// A [`SimpleBloomFilter`] whose queries use only as many hashers as
+/// are needed to keep the false positive rate near a target.
+///
+/// Insertions always use every hasher, but while the filter is
+/// lightly loaded, a query probing fewer counters is already as
+/// selective as the target requires, and faster. The filter counts
+/// the counters which indicate presence as they are set, and after
+/// each insertion picks the smallest number of probes `j` for which
+/// `fill^j` is at most the target, as with
+/// [`contains_approx`](SimpleBloomFilter::contains_approx). Once the
+/// filter is too full for the target, every hasher is used.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{AdaptiveProbeFilter, BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let filter: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20_000);
+/// let mut f = AdaptiveProbeFilter::new(filter, 0.01);
+/// f.extend(0..100);
+/// assert_eq!(f.probes(), 2);
+/// f.extend(100..1000);
+/// assert_eq!(f.probes(), 5);
+/// assert!((0..1000).all(|x| f.contains(&x)));
+///
+/// let hits = (1000..101_000).filter(|x| f.contains(x)).count();
+/// assert!(hits < 1500);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AdaptiveProbeFilter<B, S = SeededState, V = Arc<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    filter: SimpleBloomFilter<B, S, V>,
+    target_fpr: f64,
+    // Number of counters which indicate presence.
+    ones: usize,
+    probes: usize,
+}
+
+impl<B, S, V> AdaptiveProbeFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Wraps `filter`, adjusting the number of probes of queries to
+    /// keep their false positive rate near `target_fpr`.
+    ///
+    /// # Panics
+    /// Panics if `target_fpr` is not between 0 and 1.
+    pub fn new(filter: SimpleBloomFilter<B, S, V>, target_fpr: f64) -> Self {
+        assert!(
+            target_fpr > 0.0 && target_fpr < 1.0,
+            "the target false positive rate must be between 0 and 1"
+        );
+        let mut adaptive = AdaptiveProbeFilter {
+            ones: filter.counters().count_ones(),
+            filter,
+            target_fpr,
+            probes: 0,
+        };
+        adaptive.update_probes();
+        adaptive
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &SimpleBloomFilter<B, S, V> {
+        &self.filter
+    }
+
+    /// Returns the underlying filter, whose queries use every hasher.
+    pub fn into_inner(self) -> SimpleBloomFilter<B, S, V> {
+        self.filter
+    }
+
+    pub fn target_false_positive_rate(&self) -> f64 {
+        self.target_fpr
+    }
+
+    /// Returns the number of hashers currently used by queries.
+    pub fn probes(&self) -> usize {
+        self.probes
+    }
+
+    fn update_probes(&mut self) {
+        let n_hashers = BloomFilter::hashers(&self.filter).len();
+        let fill = self.ones as f64 / self.filter.counters().size() as f64;
+        self.probes = if fill == 0.0 {
+            1
+        } else if fill == 1.0 {
+            n_hashers
+        } else {
+            let probes = (self.target_fpr.ln() / fill.ln()).ceil();
+            (probes as usize).clamp(1, n_hashers)
+        };
+    }
+}
+
+impl<B, S, V> BloomFilter for AdaptiveProbeFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    type Set = B;
+    type Hasher = S;
+
+    fn counters(&self) -> &B {
+        self.filter.counters()
+    }
+
+    fn hashers(&self) -> &[S] {
+        BloomFilter::hashers(&self.filter)
+    }
+
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        let size = self.filter.counters().size();
+        let ones = self.ones;
+        for h in 0..BloomFilter::hashers(&self.filter).len() {
+            let i = reduce(BloomFilter::hashers(&self.filter)[h].hash_one(val), size);
+            if !self.filter.counters().query(i) {
+                self.ones += 1;
+            }
+            self.filter.increment_counter(i);
+        }
+        self.filter.record_inserts(1);
+        if self.ones != ones {
+            self.update_probes();
+        }
+    }
+
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.filter.contains_approx(val, self.probes)
+    }
+
+    fn clear(&mut self) {
+        self.filter.clear();
+        self.ones = 0;
+        self.update_probes();
+    }
+
+    /// Returns the false positive rate of queries with the current
+    /// number of probes.
+    fn expected_false_positive_rate(&self) -> f64 {
+        self.filter.approx_false_positive_rate(self.probes)
+    }
+
+    fn lost_increments(&self) -> usize {
+        self.filter.lost_increments()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.filter.heap_size_bytes()
+    }
+}
+
+impl<A: Hash, B, S, V> Extend<A> for AdaptiveProbeFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::hash_indices;
+use crate::traits::filter::ConfigError;
+use crate::SeededState;
+use bitvec::{order::Lsb0, vec::BitVec};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::ops::Range;
+
+/// Many small binary Bloom filters of the same size, stored end to
+/// end in a single bit vector and sharing one set of hashers.
+///
+/// Filters are [`alloc`](FilterArena::alloc)ated and addressed by
+/// id, and cost only their bits: there is no allocation, hasher
+/// vector or insertion count per filter. Ids of
+/// [`free`](FilterArena::free)d filters are reused by later
+/// allocations, so an arena holding e.g. one filter per session does
+/// not grow as sessions come and go.
+///
+/// # Example
+/// ```
+/// use generic_bloom::FilterArena;
+///
+/// let mut arena: FilterArena = FilterArena::with_seed(1, 4, 512);
+/// let alice = arena.alloc();
+/// let bob = arena.alloc();
+///
+/// arena.insert(alice, "/home");
+/// arena.insert(bob, "/settings");
+/// assert!(arena.contains(alice, "/home"));
+/// assert!(!arena.contains(bob, "/home"));
+///
+/// arena.free(alice);
+/// // The freed filter's bits are reused, cleared.
+/// let carol = arena.alloc();
+/// assert_eq!(carol, alice);
+/// assert!(!arena.contains(carol, "/home"));
+/// assert_eq!(arena.len(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterArena<S = SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    hashers: V,
+    filter_bits: usize,
+    // The filter with id `i` is `bits[i * filter_bits..(i + 1) * filter_bits]`.
+    bits: BitVec<u64, Lsb0>,
+    // Whether each id is allocated.
+    live: BitVec<u64, Lsb0>,
+    free: Vec<usize>,
+    _phantom: PhantomData<S>,
+}
+
+impl<V> FilterArena<SeededState, V>
+where
+    V: AsRef<[SeededState]> + FromIterator<SeededState>,
+{
+    /// Creates an empty arena of filters with `filter_bits` bits
+    /// each and `n_hashers` hashers [derived](SeededState::derive)
+    /// from `seed`.
+    ///
+    /// # Panics
+    /// Panics if `n_hashers` or `filter_bits` is zero.
+    pub fn with_seed(seed: u128, n_hashers: usize, filter_bits: usize) -> Self {
+        FilterArena::with_hashers(SeededState::from_seed(seed).derive(n_hashers).collect(), filter_bits)
+    }
+}
+
+impl<S, V> FilterArena<S, V>
+where
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Creates an empty arena of filters with `filter_bits` bits
+    /// each, all using `hashers`.
+    ///
+    /// # Panics
+    /// Panics if `hashers` is empty or `filter_bits` is zero.
+    pub fn with_hashers(hashers: V, filter_bits: usize) -> Self {
+        if let Err(e) = ConfigError::check(hashers.as_ref().len(), filter_bits) {
+            panic!("{}", e);
+        }
+        FilterArena {
+            hashers,
+            filter_bits,
+            bits: BitVec::new(),
+            live: BitVec::new(),
+            free: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn hashers(&self) -> &V {
+        &self.hashers
+    }
+
+    /// Returns the number of bits in each filter.
+    pub fn filter_bits(&self) -> usize {
+        self.filter_bits
+    }
+
+    /// Returns the number of allocated filters.
+    pub fn len(&self) -> usize {
+        self.live.len() - self.free.len()
+    }
+
+    /// Checks whether no filters are allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks whether `id` is the id of an allocated filter.
+    pub fn is_allocated(&self, id: usize) -> bool {
+        self.live.get(id).is_some_and(|live| *live)
+    }
+
+    /// Allocates an empty filter, returning its id. Ids of freed
+    /// filters are reused before the arena grows.
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.free.pop() {
+            self.live.set(id, true);
+            id
+        } else {
+            self.bits.resize(self.bits.len() + self.filter_bits, false);
+            self.live.push(true);
+            self.live.len() - 1
+        }
+    }
+
+    /// Frees the filter with id `id`, clearing it so that the id can
+    /// be reused.
+    ///
+    /// # Panics
+    /// Panics if `id` is not allocated.
+    pub fn free(&mut self, id: usize) {
+        let bits = self.filter_range(id);
+        self.bits[bits].fill(false);
+        self.live.set(id, false);
+        self.free.push(id);
+    }
+
+    /// Inserts `val` into the filter with id `id`.
+    ///
+    /// # Panics
+    /// Panics if `id` is not allocated.
+    pub fn insert<T: Hash + ?Sized>(&mut self, id: usize, val: &T) {
+        let start = self.filter_range(id).start;
+        for i in hash_indices(self.hashers.as_ref(), self.filter_bits, val) {
+            self.bits.set(start + i, true);
+        }
+    }
+
+    /// Checks whether the filter with id `id` contains `val`.
+    ///
+    /// # Panics
+    /// Panics if `id` is not allocated.
+    pub fn contains<T: Hash + ?Sized>(&self, id: usize, val: &T) -> bool {
+        let start = self.filter_range(id).start;
+        hash_indices(self.hashers.as_ref(), self.filter_bits, val).all(|i| self.bits[start + i])
+    }
+
+    /// Clears the filter with id `id`, leaving it allocated.
+    ///
+    /// # Panics
+    /// Panics if `id` is not allocated.
+    pub fn clear(&mut self, id: usize) {
+        let bits = self.filter_range(id);
+        self.bits[bits].fill(false);
+    }
+
+    /// Returns the number of bits set in the filter with id `id`.
+    ///
+    /// # Panics
+    /// Panics if `id` is not allocated.
+    pub fn count_ones(&self, id: usize) -> usize {
+        self.bits[self.filter_range(id)].count_ones()
+    }
+
+    /// Returns the approximate number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        8 * (self.bits.capacity().div_ceil(64) + self.live.capacity().div_ceil(64))
+            + self.free.capacity() * std::mem::size_of::<usize>()
+            + crate::hash::hashers_heap_size(&self.hashers)
+    }
+
+    /// Returns the range of bits used by the filter with id `id`.
+    fn filter_range(&self, id: usize) -> Range<usize> {
+        assert!(self.is_allocated(id), "filter {} is not allocated", id);
+        id * self.filter_bits..(id + 1) * self.filter_bits
+    }
+}
@@ -0,0 +1,269 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Conversions between [`BitBox`]es and Apache Arrow buffers, and
+//! membership queries over Arrow arrays, enabled by the `arrow`
+//! feature.
+//!
+//! Arrow bitmaps store bits least significant first within each
+//! byte, which on little-endian targets is exactly the memory layout
+//! of a `BitBox<T, Lsb0>`. The conversions here therefore hand over
+//! the underlying allocation instead of copying it whenever
+//! possible. A copy is made if the Arrow buffer is shared, sliced or
+//! differently aligned, or on big-endian targets.
+//!
+//! # Example
+//! ```
+//! use generic_bloom::{BloomFilter, SimpleBloomFilter};
+//! use generic_bloom::arrow::{from_boolean_buffer, to_boolean_buffer};
+//! use bitvec::prelude::*;
+//!
+//! let mut filter: SimpleBloomFilter<BitBox<u64, Lsb0>> = SimpleBloomFilter::new(10, 200);
+//! filter.insert(&48);
+//!
+//! let (hashers, bits) = filter.into_inner();
+//! let column = to_boolean_buffer(bits);
+//! assert_eq!(column.len(), 200);
+//!
+//! let filter: SimpleBloomFilter<BitBox<u64, Lsb0>> =
+//!     SimpleBloomFilter::from_parts(hashers, from_boolean_buffer(column));
+//! assert!(filter.contains(&48));
+//! ```
+//!
+//! For Bloom joins, [`insert_arrow`] builds a filter from the key
+//! column of one side, and [`probe_arrow`] checks a whole batch of
+//! keys from the other side against it, giving a [`BooleanArray`]
+//! which can be passed to Arrow's `filter` kernel. Keys are hashed
+//! like the corresponding Rust values, e.g. `u64`, `[u8]` or `str`,
+//! so filters can also be built or probed outside of Arrow.
+//!
+//! ```
+//! use generic_bloom::{BloomFilter, SimpleBloomFilter};
+//! use generic_bloom::arrow::{insert_arrow, probe_arrow};
+//! use arrow_array::{Array, UInt64Array};
+//! use bitvec::prelude::*;
+//!
+//! let build = UInt64Array::from(vec![1, 2, 3, 5, 8]);
+//! let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::for_items(build.len(), 0.01);
+//! insert_arrow(&mut filter, &build);
+//!
+//! let probe = UInt64Array::from(vec![Some(3), None, Some(8), Some(13)]);
+//! let matches = probe_arrow(&filter, &probe);
+//! assert!(matches.value(0) && matches.value(2));
+//! assert!(matches.is_null(1));
+//! // May fail if 13 is a false positive
+//! assert!(!matches.value(3));
+//! ```
+use crate::traits::filter::BloomFilter;
+use arrow_array::types::ArrowPrimitiveType;
+use arrow_array::{Array, BooleanArray, GenericBinaryArray, GenericStringArray, OffsetSizeTrait, PrimitiveArray};
+use arrow_buffer::{ArrowNativeType, BooleanBuffer, Buffer};
+use bitvec::{boxed::BitBox, order::Lsb0, slice::BitSlice, store::BitStore, vec::BitVec, view::BitView};
+use std::hash::Hash;
+
+/// Number of variable-length keys which are gathered before being
+/// passed to [`insert_batch`](BloomFilter::insert_batch) or
+/// [`contains_batch`](BloomFilter::contains_batch).
+const KEY_BATCH_LEN: usize = 1024;
+
+/// Whether `T` words are laid out in memory like Arrow bitmaps.
+fn same_layout<T>() -> bool {
+    cfg!(target_endian = "little") || std::mem::size_of::<T>() == 1
+}
+
+fn copy_bits<T, T2>(src: &BitSlice<T2, Lsb0>) -> BitBox<T, Lsb0>
+where
+    T: BitStore,
+    T2: BitStore,
+{
+    let mut bits = BitVec::with_capacity(src.len());
+    bits.extend_from_bitslice(src);
+    bits.into_boxed_bitslice()
+}
+
+/// Converts `bits` into an Arrow [`BooleanBuffer`] of the same
+/// length.
+pub fn to_boolean_buffer<T>(bits: BitBox<T, Lsb0>) -> BooleanBuffer
+where
+    T: BitStore + ArrowNativeType,
+{
+    let len = bits.len();
+    BooleanBuffer::new(to_buffer(bits), 0, len)
+}
+
+/// Converts `bits` into an Arrow [`Buffer`] bitmap. The length of
+/// the bitmap is not preserved and must be tracked separately.
+pub fn to_buffer<T>(bits: BitBox<T, Lsb0>) -> Buffer
+where
+    T: BitStore + ArrowNativeType,
+{
+    if same_layout::<T>() {
+        Buffer::from_vec(bits.into_bitvec().into_vec())
+    } else {
+        Buffer::from_vec(copy_bits::<u8, T>(&bits).into_bitvec().into_vec())
+    }
+}
+
+/// Converts an Arrow [`BooleanBuffer`] into a [`BitBox`] of the same
+/// length.
+pub fn from_boolean_buffer<T>(buf: BooleanBuffer) -> BitBox<T, Lsb0>
+where
+    T: BitStore + ArrowNativeType,
+{
+    let (offset, len) = (buf.offset(), buf.len());
+    if offset != 0 || !same_layout::<T>() {
+        return copy_bits(&buf.values().view_bits::<Lsb0>()[offset..][..len]);
+    }
+
+    let buffer = buf.into_inner();
+    if !buffer.len().is_multiple_of(std::mem::size_of::<T>()) {
+        return copy_bits(&buffer.as_slice().view_bits::<Lsb0>()[..len]);
+    }
+    match buffer.into_vec::<T>() {
+        Ok(words) => {
+            let mut bits = BitVec::from_vec(words);
+            bits.truncate(len);
+            bits.into_boxed_bitslice()
+        }
+        Err(buffer) => copy_bits(&buffer.as_slice().view_bits::<Lsb0>()[..len]),
+    }
+}
+
+/// Converts the first `len` bits of an Arrow [`Buffer`] bitmap into
+/// a [`BitBox`].
+///
+/// # Panics
+/// Panics if `buffer` holds fewer than `len` bits.
+pub fn from_buffer<T>(buffer: Buffer, len: usize) -> BitBox<T, Lsb0>
+where
+    T: BitStore + ArrowNativeType,
+{
+    from_boolean_buffer(BooleanBuffer::new(buffer, 0, len))
+}
+
+/// Arrow arrays whose values can be used as filter keys. Each value
+/// is hashed like the Rust value it corresponds to: the native value
+/// of a primitive array, `[u8]` for binary arrays and `str` for
+/// string arrays.
+pub trait ArrowKeys: Array {
+    /// Inserts every non-null value into `filter`.
+    fn insert_into<F: BloomFilter>(&self, filter: &mut F);
+
+    /// Checks whether `filter` contains each value, ignoring nulls.
+    /// The result has one bit per value, including null ones.
+    fn contained_in<F: BloomFilter>(&self, filter: &F) -> BitVec;
+}
+
+impl<T> ArrowKeys for PrimitiveArray<T>
+where
+    T: ArrowPrimitiveType,
+    T::Native: Hash,
+{
+    fn insert_into<F: BloomFilter>(&self, filter: &mut F) {
+        match self.nulls() {
+            None => filter.insert_batch(self.values()),
+            Some(nulls) => {
+                for i in nulls.valid_indices() {
+                    filter.insert(&self.values()[i]);
+                }
+            }
+        }
+    }
+
+    fn contained_in<F: BloomFilter>(&self, filter: &F) -> BitVec {
+        // Probing the values behind nulls is cheaper than skipping
+        // them, and the results are masked by the nulls anyway.
+        filter.contains_batch(self.values())
+    }
+}
+
+impl<O: OffsetSizeTrait> ArrowKeys for GenericBinaryArray<O> {
+    fn insert_into<F: BloomFilter>(&self, filter: &mut F) {
+        insert_chunked(filter, self.iter().flatten());
+    }
+
+    fn contained_in<F: BloomFilter>(&self, filter: &F) -> BitVec {
+        contains_chunked(filter, (0..self.len()).map(|i| self.value(i)))
+    }
+}
+
+impl<O: OffsetSizeTrait> ArrowKeys for GenericStringArray<O> {
+    fn insert_into<F: BloomFilter>(&self, filter: &mut F) {
+        insert_chunked(filter, self.iter().flatten());
+    }
+
+    fn contained_in<F: BloomFilter>(&self, filter: &F) -> BitVec {
+        contains_chunked(filter, (0..self.len()).map(|i| self.value(i)))
+    }
+}
+
+fn insert_chunked<F, K>(filter: &mut F, keys: impl Iterator<Item = K>)
+where
+    F: BloomFilter,
+    K: Hash,
+{
+    let mut batch = Vec::with_capacity(KEY_BATCH_LEN);
+    for key in keys {
+        batch.push(key);
+        if batch.len() == KEY_BATCH_LEN {
+            filter.insert_batch(&batch);
+            batch.clear();
+        }
+    }
+    filter.insert_batch(&batch);
+}
+
+fn contains_chunked<F, K>(filter: &F, keys: impl ExactSizeIterator<Item = K>) -> BitVec
+where
+    F: BloomFilter,
+    K: Hash,
+{
+    let mut found = BitVec::with_capacity(keys.len());
+    let mut batch = Vec::with_capacity(KEY_BATCH_LEN);
+    for key in keys {
+        batch.push(key);
+        if batch.len() == KEY_BATCH_LEN {
+            found.extend_from_bitslice(&filter.contains_batch(&batch));
+            batch.clear();
+        }
+    }
+    found.extend_from_bitslice(&filter.contains_batch(&batch));
+    found
+}
+
+/// Inserts every non-null value of `keys` into `filter`, e.g. to
+/// build the filter of a Bloom join from the key column of its build
+/// side.
+pub fn insert_arrow<F, A>(filter: &mut F, keys: &A)
+where
+    F: BloomFilter,
+    A: ArrowKeys,
+{
+    keys.insert_into(filter)
+}
+
+/// Checks whether `filter` contains each value of `keys`, returning
+/// a [`BooleanArray`] of the same length with the same nulls. Values
+/// are hashed and probed in batches with
+/// [`contains_batch`](BloomFilter::contains_batch).
+pub fn probe_arrow<F, A>(filter: &F, keys: &A) -> BooleanArray
+where
+    F: BloomFilter,
+    A: ArrowKeys,
+{
+    // `usize` is not an Arrow native type, so the words are copied,
+    // which is cheap next to hashing the values.
+    let found = to_boolean_buffer(copy_bits::<u64, usize>(&keys.contained_in(filter)));
+    BooleanArray::new(found, keys.nulls().cloned())
+}
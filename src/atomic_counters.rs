@@ -0,0 +1,273 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::serialize::SerializableSet;
+use crate::traits::set::*;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8};
+
+/// Saturating counters which can be incremented and decremented
+/// through shared references, so that a
+/// [`ConcurrentBloomFilter`](crate::ConcurrentBloomFilter) can count
+/// values and remove them, rather than only setting bits.
+///
+/// `A` is one of the atomic integer types `AtomicU8` to `AtomicU64`.
+/// Each shared update is a compare-and-swap loop which keeps the
+/// semantics of a `Box<[u8]>` and the like: a counter at its maximum
+/// stays there, since its true count is unknown, and neither
+/// saturated nor zero counters are decremented. Updates through
+/// `&mut self` need no atomic instructions.
+///
+/// `AtomicCounters` are serialized in the same format as the boxed
+/// slice of the corresponding plain integer type, so either can be
+/// read from data written by the other.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{AtomicCounters, ConcurrentBloomFilter};
+/// use std::sync::atomic::AtomicU8;
+/// use std::thread;
+///
+/// let filter: ConcurrentBloomFilter<AtomicCounters<AtomicU8>> =
+///     ConcurrentBloomFilter::new(7, 10_000);
+/// thread::scope(|s| {
+///     for _ in 0..4 {
+///         s.spawn(|| {
+///             for x in 0..100 {
+///                 filter.insert(&x);
+///             }
+///         });
+///     }
+/// });
+/// assert!((0..100).all(|x| filter.find_count(&x) >= 4));
+///
+/// filter.remove(&0);
+/// assert!(filter.find_count(&0) >= 3);
+/// ```
+pub struct AtomicCounters<A>(Box<[A]>);
+
+macro_rules! impl_atomic_counters {
+    ($($atomic:ty => $int:ty),*) => {
+        $(
+            impl AtomicCounters<$atomic> {
+                /// Returns the current counts.
+                pub fn to_counts(&self) -> Box<[$int]> {
+                    self.0.iter().map(|c| c.load(Relaxed)).collect()
+                }
+            }
+
+            impl From<Box<[$int]>> for AtomicCounters<$atomic> {
+                fn from(counts: Box<[$int]>) -> Self {
+                    AtomicCounters(<[$int]>::iter(&counts).map(|&c| <$atomic>::new(c)).collect())
+                }
+            }
+
+            impl Clone for AtomicCounters<$atomic> {
+                fn clone(&self) -> Self {
+                    AtomicCounters::from(self.to_counts())
+                }
+            }
+
+            impl fmt::Debug for AtomicCounters<$atomic> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.debug_tuple("AtomicCounters").field(&self.0).finish()
+                }
+            }
+
+            impl PartialEq for AtomicCounters<$atomic> {
+                fn eq(&self, other: &Self) -> bool {
+                    self.size() == other.size() && self.iter_counts().eq(other.iter_counts())
+                }
+            }
+
+            impl Eq for AtomicCounters<$atomic> {}
+
+            impl BloomSetRead for AtomicCounters<$atomic> {
+                fn size(&self) -> usize {
+                    self.0.len()
+                }
+
+                fn query(&self, index: usize) -> bool {
+                    self.0[index].load(Relaxed) != 0
+                }
+
+                fn count_ones(&self) -> usize {
+                    self.0.iter().filter(|c| c.load(Relaxed) != 0).count()
+                }
+
+                fn is_clear(&self) -> bool {
+                    self.0.iter().all(|c| c.load(Relaxed) == 0)
+                }
+
+                fn heap_size_bytes(&self) -> usize {
+                    std::mem::size_of_val(&*self.0)
+                }
+
+                fn prefetch(&self, index: usize) {
+                    if let Some(counter) = self.0.get(index) {
+                        prefetch_read(counter);
+                    }
+                }
+            }
+
+            impl BloomSet for AtomicCounters<$atomic> {
+                fn new(count: usize) -> Self {
+                    AtomicCounters((0..count).map(|_| <$atomic>::new(0)).collect())
+                }
+
+                fn increment(&mut self, index: usize) {
+                    self.try_increment(index);
+                }
+
+                fn try_increment(&mut self, index: usize) -> bool {
+                    let c = self.0[index].get_mut();
+                    match c.checked_add(1) {
+                        Some(count) => {
+                            *c = count;
+                            true
+                        }
+                        None => false,
+                    }
+                }
+
+                fn clear(&mut self) {
+                    for c in self.0.iter_mut() {
+                        *c.get_mut() = 0;
+                    }
+                }
+            }
+
+            /// Like those of a boxed slice of counters, saturated
+            /// counters are never decremented, and zero counters stay
+            /// at zero.
+            impl BloomSetDelete for AtomicCounters<$atomic> {
+                fn decrement(&mut self, index: usize) {
+                    self.try_decrement(index);
+                }
+
+                fn try_decrement(&mut self, index: usize) -> bool {
+                    let c = self.0[index].get_mut();
+                    let counted = *c != 0 && *c != <$int>::MAX;
+                    if counted {
+                        *c -= 1;
+                    }
+                    counted
+                }
+
+                fn subtract(&mut self, other: &Self) {
+                    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+                        let a = a.get_mut();
+                        if *a != <$int>::MAX {
+                            *a = a.saturating_sub(b.load(Relaxed));
+                        }
+                    }
+                }
+            }
+
+            /// Unions and intersections take the element-wise maximum
+            /// and minimum, as for a boxed slice of counters.
+            impl BinaryBloomSet for AtomicCounters<$atomic> {
+                fn union(&mut self, other: &Self) {
+                    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+                        let a = a.get_mut();
+                        *a = (*a).max(b.load(Relaxed));
+                    }
+                }
+
+                fn intersect(&mut self, other: &Self) {
+                    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+                        let a = a.get_mut();
+                        *a = (*a).min(b.load(Relaxed));
+                    }
+                }
+
+                fn symmetric_difference(&mut self, other: &Self) {
+                    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+                        let a = a.get_mut();
+                        *a = a.abs_diff(b.load(Relaxed));
+                    }
+                }
+
+                /// Checks whether each counter of `self` is at most the
+                /// corresponding counter of `other`.
+                fn is_subset(&self, other: &Self) -> bool {
+                    self.size() == other.size() && self.iter_counts().zip(other.iter_counts()).all(|(a, b)| a <= b)
+                }
+            }
+
+            impl SpectralBloomSet for AtomicCounters<$atomic> {
+                type Count = $int;
+
+                fn query_count(&self, index: usize) -> $int {
+                    self.0[index].load(Relaxed)
+                }
+
+                fn iter_counts(&self) -> impl Iterator<Item = $int> + '_ {
+                    self.0.iter().map(|c| c.load(Relaxed))
+                }
+            }
+
+            impl SpectralBloomSetMut for AtomicCounters<$atomic> {
+                fn set_count(&mut self, index: usize, count: $int) {
+                    *self.0[index].get_mut() = count;
+                }
+
+                /// Adds element-wise, saturating at the maximum count.
+                fn merge_counts(&mut self, other: &Self) {
+                    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+                        let a = a.get_mut();
+                        *a = a.saturating_add(b.load(Relaxed));
+                    }
+                }
+            }
+
+            impl AtomicBloomSet for AtomicCounters<$atomic> {
+                fn increment_shared(&self, index: usize) {
+                    let _ = self.0[index].fetch_update(Relaxed, Relaxed, |c| c.checked_add(1));
+                }
+            }
+
+            impl AtomicBloomSetDelete for AtomicCounters<$atomic> {
+                fn decrement_shared(&self, index: usize) {
+                    let _ = self.0[index].fetch_update(Relaxed, Relaxed, |c| {
+                        (c != 0 && c != <$int>::MAX).then(|| c - 1)
+                    });
+                }
+            }
+
+            impl SerializableSet for AtomicCounters<$atomic> {
+                const KIND: u8 = std::mem::size_of::<$int>() as u8;
+
+                fn serialized_len(&self) -> usize {
+                    self.0.len() * std::mem::size_of::<$int>()
+                }
+
+                fn write_counters<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                    for c in self.0.iter() {
+                        w.write_all(&c.load(Relaxed).to_le_bytes())?;
+                    }
+                    Ok(())
+                }
+
+                fn read_counters<R: Read>(size: usize, r: &mut R) -> io::Result<Self> {
+                    Box::<[$int]>::read_counters(size, r).map(AtomicCounters::from)
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_counters!(AtomicU8 => u8, AtomicU16 => u16, AtomicU32 => u32, AtomicU64 => u64);
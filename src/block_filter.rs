@@ -0,0 +1,429 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::sizing;
+use crate::{Explain, Probe, SeededState};
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use std::hash::{BuildHasher, Hash};
+
+/// Multipliers selecting the bit set in each word of a [`Block`],
+/// as used by the split block Bloom filters of Impala and Parquet.
+const SALTS: [u32; 8] = [
+    0x47b6137b, 0x44974d91, 0x8824ad5b, 0xa2b7289d, 0x705495c7, 0x2df1424b, 0x9efc4947, 0x5c6bfb31,
+];
+
+/// A 256-bit block of a [`BlockBloomFilter`], made up of eight
+/// 32-bit words and aligned so that it fits in a single cache line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(C, align(32))]
+pub struct Block(pub [u32; 8]);
+
+impl Block {
+    /// Number of bits in a block.
+    pub const BITS: usize = 256;
+
+    /// Returns the bit to set in each word of the block for a value
+    /// with the 32-bit hash `hash`.
+    pub(crate) fn mask(hash: u32) -> [u32; 8] {
+        let mut mask = [0; 8];
+        for (m, salt) in mask.iter_mut().zip(SALTS) {
+            *m = 1 << (hash.wrapping_mul(salt) >> 27);
+        }
+        mask
+    }
+
+    /// Sets the bits of the block selected by `hash`.
+    fn insert(&mut self, hash: u32) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 is available, as checked above.
+            return unsafe { avx2::insert(self, hash) };
+        }
+        for (w, m) in self.0.iter_mut().zip(Block::mask(hash)) {
+            *w |= m;
+        }
+    }
+
+    /// Returns the probability that a value which was never inserted
+    /// into this block is found in it, since such a value probes one
+    /// bit in each word.
+    fn false_positive_rate(&self) -> f64 {
+        self.0.iter().map(|w| w.count_ones() as f64 / 32.0).product()
+    }
+
+    /// Checks whether all bits of the block selected by `hash` are
+    /// set.
+    fn contains(&self, hash: u32) -> bool {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: AVX2 is available, as checked above.
+            return unsafe { avx2::contains(self, hash) };
+        }
+        self.0
+            .iter()
+            .zip(Block::mask(hash))
+            .all(|(w, m)| w & m != 0)
+    }
+}
+
+/// Vectorized block operations, which test or set all eight words
+/// of a block with a few AVX2 instructions.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use super::{Block, SALTS};
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    fn mask(hash: u32) -> __m256i {
+        let salts = _mm256_setr_epi32(
+            SALTS[0] as i32,
+            SALTS[1] as i32,
+            SALTS[2] as i32,
+            SALTS[3] as i32,
+            SALTS[4] as i32,
+            SALTS[5] as i32,
+            SALTS[6] as i32,
+            SALTS[7] as i32,
+        );
+        let shifts = _mm256_srli_epi32::<27>(_mm256_mullo_epi32(_mm256_set1_epi32(hash as i32), salts));
+        _mm256_sllv_epi32(_mm256_set1_epi32(1), shifts)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn insert(block: &mut Block, hash: u32) {
+        let p = block.0.as_mut_ptr().cast::<__m256i>();
+        // SAFETY: `Block` is 32 bytes long and 32-byte aligned.
+        unsafe { _mm256_store_si256(p, _mm256_or_si256(_mm256_load_si256(p), mask(hash))) };
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn contains(block: &Block, hash: u32) -> bool {
+        // SAFETY: `Block` is 32 bytes long and 32-byte aligned.
+        let words = unsafe { _mm256_load_si256(block.0.as_ptr().cast()) };
+        _mm256_testc_si256(words, mask(hash)) != 0
+    }
+}
+
+impl BloomSetRead for Box<[Block]> {
+    fn size(&self) -> usize {
+        self.len() * Block::BITS
+    }
+
+    fn query(&self, index: usize) -> bool {
+        self[index / Block::BITS].0[index % Block::BITS / 32] & (1 << (index % 32)) != 0
+    }
+
+    fn count_ones(&self) -> usize {
+        <[Block]>::iter(self)
+            .flat_map(|b| b.0)
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
+
+    fn is_clear(&self) -> bool {
+        <[Block]>::iter(self).all(|b| b.0 == [0; 8])
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&**self)
+    }
+
+    fn prefetch(&self, index: usize) {
+        if let Some(block) = self.get(index / Block::BITS) {
+            crate::traits::set::prefetch_read(block);
+        }
+    }
+}
+
+impl BloomSet for Box<[Block]> {
+    /// Creates a new set with at least `count` counters, rounded up
+    /// to a whole number of blocks.
+    fn new(count: usize) -> Self {
+        vec![Block::default(); count.div_ceil(Block::BITS).max(1)].into_boxed_slice()
+    }
+
+    fn increment(&mut self, index: usize) {
+        self[index / Block::BITS].0[index % Block::BITS / 32] |= 1 << (index % 32);
+    }
+
+    fn clear(&mut self) {
+        self.fill(Block::default());
+    }
+}
+
+impl BinaryBloomSet for Box<[Block]> {
+    fn union(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[Block]>::iter(other)) {
+            for (x, y) in a.0.iter_mut().zip(b.0) {
+                *x |= y;
+            }
+        }
+    }
+
+    fn intersect(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[Block]>::iter(other)) {
+            for (x, y) in a.0.iter_mut().zip(b.0) {
+                *x &= y;
+            }
+        }
+    }
+
+    fn symmetric_difference(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[Block]>::iter(other)) {
+            for (x, y) in a.0.iter_mut().zip(b.0) {
+                *x ^= y;
+            }
+        }
+    }
+
+    fn union_count_ones(&self, other: &Self) -> usize {
+        <[Block]>::iter(self)
+            .zip(<[Block]>::iter(other))
+            .flat_map(|(a, b)| a.0.into_iter().zip(b.0))
+            .map(|(x, y)| (x | y).count_ones() as usize)
+            .sum()
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && <[Block]>::iter(self)
+                .zip(<[Block]>::iter(other))
+                .all(|(a, b)| a.0.iter().zip(b.0).all(|(x, y)| x | y == y))
+    }
+}
+
+/// A split block Bloom filter, as used by Impala and Parquet. Each
+/// value is hashed once by the [`BuildHasher`] `S`: the hash selects
+/// a 256-bit [`Block`], and sets one bit in each of its eight words.
+/// Every query therefore touches a single cache line, at the cost of
+/// a slightly higher false positive rate than a [`SimpleBloomFilter`]
+/// of the same size.
+///
+/// With the `simd` feature, blocks are tested and updated with AVX2
+/// instructions on x86_64 processors which support them.
+///
+/// [`SimpleBloomFilter`]: crate::SimpleBloomFilter
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, BlockBloomFilter};
+///
+/// let mut filter: BlockBloomFilter = BlockBloomFilter::new(4096);
+/// filter.insert(&48);
+/// assert!(filter.contains(&48));
+/// // May fail if 39 is a false positive
+/// assert!(!filter.contains(&39));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockBloomFilter<S = SeededState> {
+    hasher: S,
+    blocks: Box<[Block]>,
+}
+
+impl<S: BuildHasher> BlockBloomFilter<S> {
+    /// Creates a new `BlockBloomFilter` with at least `n_counters`
+    /// bits. The [`BuildHasher`] will be initialized by
+    /// [`default`](Default::default).
+    pub fn new(n_counters: usize) -> Self
+    where
+        S: Default,
+    {
+        BlockBloomFilter::with_hasher(S::default(), n_counters)
+    }
+
+    /// Creates a new `BlockBloomFilter` with a specified
+    /// `BuildHasher` and at least `n_counters` bits.
+    pub fn with_hasher(hasher: S, n_counters: usize) -> Self {
+        BlockBloomFilter {
+            hasher,
+            blocks: BloomSet::new(n_counters),
+        }
+    }
+
+    /// Creates a `BlockBloomFilter` from a hasher and blocks, such as
+    /// those returned by [`into_inner`](BlockBloomFilter::into_inner).
+    ///
+    /// # Panics
+    /// Panics if `blocks` is empty.
+    pub fn from_parts(hasher: S, blocks: Box<[Block]>) -> Self {
+        assert!(!blocks.is_empty(), "{}", ConfigError::NoCounters);
+        BlockBloomFilter { hasher, blocks }
+    }
+
+    /// Returns the hasher and blocks of the filter.
+    pub fn into_inner(self) -> (S, Box<[Block]>) {
+        (self.hasher, self.blocks)
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the bits probed when looking up `val`, one in each
+    /// word of the block it maps to, and whether each of them is set.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BlockBloomFilter, BloomFilter};
+    ///
+    /// let mut f: BlockBloomFilter = BlockBloomFilter::new(4096);
+    /// f.insert(&48);
+    /// let explain = f.explain(&48);
+    /// assert_eq!(explain.probes.len(), 8);
+    /// assert!(explain.contains());
+    /// ```
+    pub fn explain<T: Hash + ?Sized>(&self, val: &T) -> Explain {
+        let (block, hash) = self.locate(val);
+        Explain {
+            probes: Block::mask(hash)
+                .iter()
+                .enumerate()
+                .map(|(word, m)| {
+                    let index = block * Block::BITS + word * 32 + m.trailing_zeros() as usize;
+                    Probe { index, value: self.blocks.query(index) }
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the index of the block `val` maps to and the hash
+    /// selecting bits within it.
+    fn locate<T: Hash + ?Sized>(&self, val: &T) -> (usize, u32) {
+        let hash = self.hasher.hash_one(val);
+        let block = ((hash >> 32) * self.blocks.len() as u64) >> 32;
+        (block as usize, hash as u32)
+    }
+}
+
+impl<S: BuildHasher> BloomFilter for BlockBloomFilter<S> {
+    type Set = Box<[Block]>;
+    type Hasher = S;
+
+    fn counters(&self) -> &Box<[Block]> {
+        &self.blocks
+    }
+
+    fn hashers(&self) -> &[S] {
+        std::slice::from_ref(&self.hasher)
+    }
+
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        let (block, hash) = self.locate(val);
+        self.blocks[block].insert(hash);
+    }
+
+    fn insert_checked<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        // Inserting a value which is already present changes nothing.
+        self.insert_if_absent(val)
+    }
+
+    fn insert_if_absent<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let (block, hash) = self.locate(val);
+        let block = &mut self.blocks[block];
+        if block.contains(hash) {
+            return false;
+        }
+        block.insert(hash);
+        true
+    }
+
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        let (block, hash) = self.locate(val);
+        self.blocks[block].contains(hash)
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear()
+    }
+
+    fn expected_false_positive_rate(&self) -> f64 {
+        let total: f64 = <[Block]>::iter(&self.blocks).map(Block::false_positive_rate).sum();
+        total / self.blocks.len() as f64
+    }
+
+    /// The probability that the answer is right only depends on the
+    /// block which `val` maps to, so this is as fast as
+    /// [`contains`](BloomFilter::contains).
+    fn contains_with_confidence<T: Hash + ?Sized>(&self, val: &T) -> (bool, f64) {
+        let (block, hash) = self.locate(val);
+        let block = &self.blocks[block];
+        if block.contains(hash) {
+            (true, 1.0 - block.false_positive_rate())
+        } else {
+            (false, 1.0)
+        }
+    }
+}
+
+impl<A: Hash, S: BuildHasher> Extend<A> for BlockBloomFilter<S> {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
+
+/// Collects values into a filter sized for them, like the
+/// [`FromIterator`] implementation of
+/// [`SimpleBloomFilter`](crate::SimpleBloomFilter).
+impl<A: Hash, S: BuildHasher + Default> FromIterator<A> for BlockBloomFilter<S> {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = A>,
+    {
+        let vals: Vec<A> = iter.into_iter().collect();
+        let mut filter = BlockBloomFilter::new(sizing::optimal_counters(vals.len(), sizing::DEFAULT_FPR));
+        filter.extend(vals);
+        filter
+    }
+}
+
+impl<S: BuildHasher> MergeableFilter for BlockBloomFilter<S> {
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        MismatchError::check(self, other)?;
+        self.blocks.union(&other.blocks);
+        Ok(())
+    }
+}
+
+impl<S: BuildHasher> BinaryBloomFilter for BlockBloomFilter<S> {
+    fn union<Other>(&mut self, other: &Other)
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.blocks.union(other.counters());
+    }
+
+    fn intersect<Other>(&mut self, other: &Other)
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.blocks.intersect(other.counters());
+    }
+
+    fn symmetric_difference<Other>(&mut self, other: &Other)
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.blocks.symmetric_difference(other.counters());
+    }
+
+    fn hasher_fingerprint(&self) -> Option<u64> {
+        Some(crate::hash::fingerprint(BloomFilter::hashers(self)))
+    }
+}
@@ -0,0 +1,377 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::{reduce, SeededState};
+use crate::serialize::{self, SerializableHasher};
+use bitvec::{boxed::BitBox, order::Lsb0, vec::BitVec};
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+
+const MAGIC: [u8; 4] = *b"GBFS";
+
+/// Maximum number of hashers for which [`BlockFilterSet::probe`]
+/// keeps the hashes of the key on the stack.
+const PROBE_BUF_LEN: usize = 32;
+
+/// The hashes of a key being probed for, on the stack unless there
+/// are more than [`PROBE_BUF_LEN`] of them.
+#[allow(clippy::large_enum_variant)]
+enum KeyHashes {
+    Inline([u64; PROBE_BUF_LEN], usize),
+    Heap(Box<[u64]>),
+}
+
+impl KeyHashes {
+    fn new<S: BuildHasher, T: Hash + ?Sized>(hashers: &[S], key: &T) -> Self {
+        if hashers.len() > PROBE_BUF_LEN {
+            return KeyHashes::Heap(hashers.iter().map(|h| h.hash_one(key)).collect());
+        }
+        let mut buf = [0; PROBE_BUF_LEN];
+        for (slot, h) in buf.iter_mut().zip(hashers) {
+            *slot = h.hash_one(key);
+        }
+        KeyHashes::Inline(buf, hashers.len())
+    }
+
+    fn as_slice(&self) -> &[u64] {
+        match self {
+            KeyHashes::Inline(buf, len) => &buf[..*len],
+            KeyHashes::Heap(hashes) => hashes,
+        }
+    }
+}
+
+/// A collection of small Bloom filters, one for each data block (or
+/// partition) of a file, as attached to the blocks of an SSTable by
+/// LSM storage engines. The blocks here are unrelated to the
+/// [`Block`](crate::Block)s of a
+/// [`BlockBloomFilter`](crate::BlockBloomFilter).
+///
+/// Each block is numbered in the order it was added, and its filter
+/// is sized for the keys it holds. All filters share the same
+/// hashers and are stored end to end in a single bit set, so a key
+/// is hashed only once when [`probe`](BlockFilterSet::probe)ing a
+/// range of blocks, and the whole collection serializes compactly.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{sizing, BlockFilterSet, SeededState};
+///
+/// // Ten bits per key, with the number of hashers that suits them.
+/// let n_hashers = sizing::optimal_hashers(10, 1) as u64;
+/// let hashers: Box<[SeededState]> = (0..n_hashers).map(|i| SeededState::new(0, i)).collect();
+///
+/// let blocks = vec![vec!["apple", "banana"], vec!["cherry"], vec![], vec!["date", "fig"]];
+/// let filters = BlockFilterSet::from_blocks(hashers, 10, blocks);
+/// assert_eq!(filters.len(), 4);
+///
+/// let candidates: Vec<usize> = filters.probe(0..4, "date").collect();
+/// assert!(candidates.contains(&3));
+/// // Empty blocks never match.
+/// assert!(!candidates.contains(&2));
+///
+/// let copy = BlockFilterSet::from_bytes(&filters.to_bytes()).unwrap();
+/// assert_eq!(copy, filters);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockFilterSet<S = SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    hashers: V,
+    bits: BitBox<u64, Lsb0>,
+    // The filter of block `i` is `bits[offsets[i]..offsets[i + 1]]`.
+    offsets: Box<[usize]>,
+    _phantom: PhantomData<S>,
+}
+
+impl<S, V> BlockFilterSet<S, V>
+where
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Builds a filter for each block in `blocks` with `hashers`,
+    /// using `bits_per_key` bits for each key. See
+    /// [`BlockFilterSetBuilder`] for building the filters one block
+    /// at a time.
+    pub fn from_blocks<I>(hashers: V, bits_per_key: usize, blocks: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: IntoIterator,
+        <I::Item as IntoIterator>::Item: Hash,
+    {
+        let mut builder = BlockFilterSetBuilder::new(hashers, bits_per_key);
+        for block in blocks {
+            for key in block {
+                builder.add_key(&key);
+            }
+            builder.finish_block();
+        }
+        builder.build()
+    }
+
+    /// Returns the number of blocks.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Checks whether there are no blocks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hashers(&self) -> &V {
+        &self.hashers
+    }
+
+    /// Returns the range of bits used by the filter of `block`.
+    fn block_bits(&self, block: usize) -> Range<usize> {
+        self.offsets[block]..self.offsets[block + 1]
+    }
+
+    /// Checks whether the filter of `block` contains the key with the
+    /// given hashes.
+    fn block_contains(&self, block: usize, hashes: impl IntoIterator<Item = u64>) -> bool {
+        let Range { start, end } = self.block_bits(block);
+        let len = end - start;
+        len > 0 && hashes.into_iter().all(|h| self.bits[start + reduce(h, len)])
+    }
+
+    /// Checks whether block `block` may contain `key`.
+    ///
+    /// # Panics
+    /// Panics if `block` is out of range.
+    pub fn may_contain<T: Hash + ?Sized>(&self, block: usize, key: &T) -> bool {
+        self.block_contains(block, self.hashers.as_ref().iter().map(|h| h.hash_one(key)))
+    }
+
+    /// Returns the blocks in `blocks` which may contain `key`, in
+    /// order. The key is hashed once for all of them.
+    ///
+    /// # Panics
+    /// Panics if `blocks` extends past the last block.
+    pub fn probe<'a, T: Hash + ?Sized>(
+        &'a self,
+        blocks: Range<usize>,
+        key: &T,
+    ) -> impl Iterator<Item = usize> + 'a {
+        assert!(blocks.end <= self.len(), "block range out of bounds");
+        let hashes = KeyHashes::new(self.hashers.as_ref(), key);
+        blocks.filter(move |&block| self.block_contains(block, hashes.as_slice().iter().copied()))
+    }
+
+    /// Returns the approximate number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bits.as_raw_slice())
+            + std::mem::size_of_val(&*self.offsets)
+            + crate::hash::hashers_heap_size(&self.hashers)
+    }
+}
+
+impl<S, V> BlockFilterSet<S, V>
+where
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    /// Writes the filters to `w`. The format is the magic bytes
+    /// `GBFS`, the number of blocks as a `u64`, the number of bits in
+    /// each block's filter as a `u32`, and the CRC-32 of those
+    /// fields, all little-endian, followed by all the filters'
+    /// bits as a single filter in the format described in
+    /// [`serialize`](crate::serialize).
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<(), serialize::Error> {
+        let mut table = Vec::with_capacity(MAGIC.len() + 8 + 4 * self.len() + 4);
+        table.extend_from_slice(&MAGIC);
+        table.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        for block in 0..self.len() {
+            let len = u32::try_from(self.block_bits(block).len())
+                .expect("block filters have fewer than 2^32 bits");
+            table.extend_from_slice(&len.to_le_bytes());
+        }
+        table.extend_from_slice(&crc32fast::hash(&table).to_le_bytes());
+        w.write_all(&table)?;
+        if self.bits.is_empty() {
+            // Serialized filters have at least one counter.
+            let padding: BitBox<u64, Lsb0> = BitVec::repeat(false, 1).into_boxed_bitslice();
+            serialize::write_filter(w, self.hashers.as_ref(), &padding)
+        } else {
+            serialize::write_filter(w, self.hashers.as_ref(), &self.bits)
+        }
+    }
+
+    /// Reads filters written by
+    /// [`write_to`](BlockFilterSet::write_to).
+    pub fn read_from<R: Read>(mut r: R) -> Result<Self, serialize::Error>
+    where
+        V: FromIterator<S>,
+    {
+        let mut head = [0u8; 12];
+        r.read_exact(&mut head)?;
+        if head[..4] != MAGIC {
+            return Err(serialize::Error::BadMagic);
+        }
+        let n_blocks = u64::from_le_bytes(head[4..].try_into().unwrap());
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&head);
+
+        let mut offsets = vec![0usize];
+        let mut len = [0u8; 4];
+        for _ in 0..n_blocks {
+            r.read_exact(&mut len)?;
+            crc.update(&len);
+            let end = offsets[offsets.len() - 1] + u32::from_le_bytes(len) as usize;
+            offsets.push(end);
+        }
+        let mut expected = [0u8; 4];
+        r.read_exact(&mut expected)?;
+        if u32::from_le_bytes(expected) != crc.finalize() {
+            return Err(serialize::Error::ChecksumMismatch);
+        }
+
+        let (hashers, bits): (V, BitBox<u64, Lsb0>) = serialize::read_filter(r)?;
+        let total = offsets[offsets.len() - 1];
+        if bits.len() != total.max(1) {
+            return Err(serialize::Error::BadCounterCount(bits.len() as u64));
+        }
+        let mut bits = bits.into_bitvec();
+        bits.truncate(total);
+        Ok(BlockFilterSet {
+            hashers,
+            bits: bits.into_boxed_bitslice(),
+            offsets: offsets.into_boxed_slice(),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Serializes the filters into a new buffer, as
+    /// [`write_to`](BlockFilterSet::write_to) does.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserializes filters from `bytes`, which must contain nothing
+    /// else.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, serialize::Error>
+    where
+        V: FromIterator<S>,
+    {
+        let filters = Self::read_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(serialize::Error::TrailingBytes);
+        }
+        Ok(filters)
+    }
+}
+
+/// Builds a [`BlockFilterSet`] one block at a time, as the blocks of
+/// a file are written.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BlockFilterSetBuilder, SeededState};
+///
+/// let hashers: Box<[SeededState]> = (0..7).map(|i| SeededState::new(0, i)).collect();
+/// let mut builder = BlockFilterSetBuilder::new(hashers, 10);
+/// for block in 0..100u32 {
+///     for key in block * 50..(block + 1) * 50 {
+///         builder.add_key(&key);
+///     }
+///     assert_eq!(builder.finish_block(), block as usize);
+/// }
+/// let filters = builder.build();
+///
+/// assert!(filters.may_contain(42, &2100u32));
+/// // Other blocks match with a probability of about 1% each.
+/// let candidates: Vec<usize> = filters.probe(0..100, &2100u32).collect();
+/// assert!(candidates.contains(&42) && candidates.len() < 10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockFilterSetBuilder<S = SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    hashers: V,
+    bits_per_key: usize,
+    bits: BitVec<u64, Lsb0>,
+    offsets: Vec<usize>,
+    // The hashes of each key added to the current block.
+    hashes: Vec<u64>,
+    _phantom: PhantomData<S>,
+}
+
+impl<S, V> BlockFilterSetBuilder<S, V>
+where
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Creates a builder whose filters use `hashers` and
+    /// `bits_per_key` bits for each key of their block.
+    ///
+    /// # Panics
+    /// Panics if `hashers` is empty or `bits_per_key` is zero.
+    pub fn new(hashers: V, bits_per_key: usize) -> Self {
+        if let Err(e) = crate::ConfigError::check(hashers.as_ref().len(), bits_per_key) {
+            panic!("{}", e);
+        }
+        BlockFilterSetBuilder {
+            hashers,
+            bits_per_key,
+            bits: BitVec::new(),
+            offsets: vec![0],
+            hashes: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Adds `key` to the current block.
+    pub fn add_key<T: Hash + ?Sized>(&mut self, key: &T) {
+        self.hashes
+            .extend(self.hashers.as_ref().iter().map(|h| h.hash_one(key)));
+    }
+
+    /// Ends the current block, building its filter, and returns its
+    /// number. A block without keys takes no space.
+    pub fn finish_block(&mut self) -> usize {
+        let n_keys = self.hashes.len() / self.hashers.as_ref().len();
+        let start = self.bits.len();
+        let len = n_keys * self.bits_per_key;
+        self.bits.resize(start + len, false);
+        if len > 0 {
+            for &h in &self.hashes {
+                self.bits.set(start + reduce(h, len), true);
+            }
+        }
+        self.hashes.clear();
+        self.offsets.push(start + len);
+        self.offsets.len() - 2
+    }
+
+    /// Returns the filters of every finished block. Keys added since
+    /// the last call to
+    /// [`finish_block`](BlockFilterSetBuilder::finish_block) are
+    /// discarded.
+    pub fn build(self) -> BlockFilterSet<S, V> {
+        BlockFilterSet {
+            hashers: self.hashers,
+            bits: self.bits.into_boxed_bitslice(),
+            offsets: self.offsets.into_boxed_slice(),
+            _phantom: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::set::*;
+use crate::SimpleBloomFilter;
+use std::hash::{BuildHasher, Hash};
+
+/// An ingestion mode for a [`SimpleBloomFilter`] which buffers the
+/// counter updates of inserted values, and applies each full buffer
+/// in order of counter index.
+///
+/// Inserting directly touches `k` unrelated counters per value, so a
+/// filter much larger than the CPU caches, or backed by memory-mapped
+/// storage, spends most of its build time on cache and page misses.
+/// Sorting a batch of updates first turns these into a mostly
+/// sequential sweep over the set, visiting each cache line or page
+/// at most once per batch; the larger the buffer relative to the
+/// set, the denser the sweep. The filter's contents are the same as
+/// if every value had been inserted directly.
+///
+/// Created by [`SimpleBloomFilter::buffered`]. Updates still pending
+/// when the inserter is dropped are applied then; call
+/// [`flush`](BufferedInserter::flush) to apply them earlier.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut direct: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+///     SimpleBloomFilter::with_seed(1, 7, 100_000);
+/// direct.extend(0..10_000);
+///
+/// let mut buffered: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+///     SimpleBloomFilter::with_seed(1, 7, 100_000);
+/// buffered.buffered(4096).extend(0..10_000);
+///
+/// assert_eq!(buffered, direct);
+/// assert_eq!(buffered.insert_count(), 10_000);
+/// ```
+#[derive(Debug)]
+pub struct BufferedInserter<'a, B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    filter: &'a mut SimpleBloomFilter<B, S, V>,
+    pending: Vec<usize>,
+    pending_inserts: usize,
+    capacity: usize,
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Returns a [`BufferedInserter`] which inserts into `self`,
+    /// applying its updates in sorted batches of about `capacity`
+    /// counter indices.
+    pub fn buffered(&mut self, capacity: usize) -> BufferedInserter<'_, B, S, V> {
+        BufferedInserter {
+            filter: self,
+            pending: Vec::with_capacity(capacity),
+            pending_inserts: 0,
+            capacity,
+        }
+    }
+}
+
+impl<B, S, V> BufferedInserter<'_, B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Inserts `val`, applying the buffered updates if the buffer is
+    /// full.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        self.pending.extend(self.filter.indices(val));
+        self.pending_inserts += 1;
+        if self.pending.len() >= self.capacity {
+            self.flush();
+        }
+    }
+
+    /// Applies every buffered update to the filter.
+    pub fn flush(&mut self) {
+        self.pending.sort_unstable();
+        self.filter.record_inserts(self.pending_inserts);
+        for &i in self.pending.iter() {
+            self.filter.increment_counter(i);
+        }
+        self.pending.clear();
+        self.pending_inserts = 0;
+    }
+
+    /// Returns the number of counter updates waiting to be applied.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl<B, S, V> Drop for BufferedInserter<'_, B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl<A, B, S, V> Extend<A> for BufferedInserter<'_, B, S, V>
+where
+    A: Hash,
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
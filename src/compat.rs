@@ -0,0 +1,835 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Compatibility with filters produced by other libraries.
+//!
+//! # The `bloomfilter` crate
+//! Filters built by version 1 of the
+//! [`bloomfilter`](https://crates.io/crates/bloomfilter) crate can be
+//! migrated using the values returned by its `bitmap()`,
+//! `number_of_bits()`, `number_of_hash_functions()` and `sip_keys()`
+//! methods, and exported for its `Bloom::from_existing`. Each of its
+//! probes is reproduced by a [`BloomfilterState`], so the migrated
+//! filter answers queries exactly like the original one.
+//!
+//! ```
+//! use generic_bloom::BloomFilter;
+//! use generic_bloom::compat::{from_bloomfilter, to_bloomfilter};
+//! use bitvec::prelude::*;
+//!
+//! // Values obtained from an existing `bloomfilter::Bloom`
+//! let bitmap = vec![0u8; 128];
+//! let sip_keys = [(1, 2), (3, 4)];
+//!
+//! let mut filter = from_bloomfilter::<usize, Lsb0>(&bitmap, 1024, 7, sip_keys).unwrap();
+//! filter.insert(&"example.com");
+//! assert!(filter.contains(&"example.com"));
+//!
+//! let (bitmap, bits, k_num, keys) = to_bloomfilter(&filter);
+//! assert_eq!((bitmap.len(), bits, k_num, keys), (128, 1024, 7, sip_keys));
+//! ```
+//!
+//! # RocksDB full filters
+//! [`RocksDbFilterBuilder`] writes, and [`RocksDbFilterBlock`] reads,
+//! the contents of the full-filter blocks RocksDB writes to SST files
+//! with `format_version` 5 or later: a cache-local Bloom filter
+//! (RocksDB's `FastLocalBloom`), where each key sets bits in one
+//! 64-byte cache line, followed by a 5-byte metadata footer. Legacy
+//! Bloom and Ribbon filter blocks are not supported.
+//!
+//! Both work on the 64-bit hashes RocksDB computes for each key (or
+//! prefix), rather than on keys, since RocksDB hashes keys with its
+//! own frozen copy of a preview release of XXH3 (its `Hash64`, with
+//! seed 0), which callers must supply to match it exactly.
+//!
+//! ```
+//! use generic_bloom::compat::{RocksDbFilterBlock, RocksDbFilterBuilder};
+//! # fn rocksdb_hash64(key: &[u8]) -> u64 {
+//! #     use std::hash::BuildHasher;
+//! #     generic_bloom::SeededState::from_seed(1).hash_one(key)
+//! # }
+//!
+//! let mut builder = RocksDbFilterBuilder::new(10.0);
+//! for key in ["apple", "banana", "cherry"] {
+//!     builder.add_hash(rocksdb_hash64(key.as_bytes()));
+//! }
+//! let block = builder.finish();
+//! // One cache line, and a footer recording 6 probes.
+//! assert_eq!(block.len(), 64 + 5);
+//! assert_eq!(block[64..], [0xff, 0, 6, 0, 0]);
+//!
+//! let filter = RocksDbFilterBlock::parse(&block).unwrap();
+//! assert!(filter.may_match_hash(rocksdb_hash64(b"banana")));
+//!
+//! // A Ribbon filter's footer starts with -2.
+//! let mut ribbon = block.clone();
+//! ribbon[64] = 0xfe;
+//! assert_eq!(RocksDbFilterBlock::parse(&ribbon), None);
+//! ```
+//!
+//! # Bitcoin BIP-37 filters
+//! A [`Bip37Filter`] is the filter an SPV client sends a Bitcoin node
+//! in a `filterload` message, as specified by
+//! [BIP-37](https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki):
+//! sized from an expected number of elements and false positive rate,
+//! probed with MurmurHash3 seeded from the filter's tweak, and
+//! updated with the outpoints of matched outputs according to its
+//! flags. It gives the same results as Bitcoin Core's `CBloomFilter`.
+//!
+//! ```
+//! use generic_bloom::compat::{Bip37Filter, Bip37Update};
+//! # fn hex(s: &str) -> Vec<u8> {
+//! #     (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+//! # }
+//!
+//! let mut filter = Bip37Filter::new(3, 0.01, 0, Bip37Update::All);
+//! filter.insert(&hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8"));
+//! assert!(filter.contains(&hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+//! assert!(!filter.contains(&hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+//! filter.insert(&hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee"));
+//! filter.insert(&hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5"));
+//!
+//! // The payload of the `filterload` message.
+//! let payload = filter.to_bytes();
+//! assert_eq!(payload, hex("03614e9b050000000000000001"));
+//! assert_eq!(Bip37Filter::from_bytes(&payload), Some(filter));
+//! ```
+use crate::serialize::SerializableHasher;
+use crate::{BloomFilter, SimpleBloomFilter};
+use bitvec::{boxed::BitBox, order::BitOrder, order::Msb0, store::BitStore, vec::BitVec, view::BitView};
+use siphasher::sip::SipHasher13;
+use std::hash::{BuildHasher, Hasher};
+use std::f64::consts::LN_2;
+use std::io::{self, Read, Write};
+
+/// Modulus applied to derived probes by the `bloomfilter` crate.
+const BLOOMFILTER_MODULUS: u64 = 0xffff_ffff_ffff_ffc5;
+
+/// Length of the metadata footer of a RocksDB filter block.
+const ROCKSDB_METADATA_LEN: usize = 5;
+
+/// Largest length of a RocksDB `FastLocalBloom` filter, without its
+/// metadata footer.
+const ROCKSDB_MAX_LEN: u32 = 0xffff_ffc0;
+
+/// Size of the cache lines of a RocksDB `FastLocalBloom` filter.
+const ROCKSDB_CACHE_LINE: usize = 64;
+
+/// Multiplier RocksDB uses to derive each probe from the last.
+const ROCKSDB_PROBE_MULTIPLIER: u32 = 0x9e37_79b9;
+
+/// Largest size in bytes of a BIP-37 filter.
+const BIP37_MAX_SIZE: usize = 36_000;
+
+/// Largest number of hash functions of a BIP-37 filter.
+const BIP37_MAX_HASH_FUNCS: u32 = 50;
+
+/// Multiplier of the hash function number in BIP-37 seeds.
+const BIP37_SEED_MULTIPLIER: u32 = 0xfba4_c795;
+
+/// A [`BuildHasher`] reproducing one probe of a filter from the
+/// `bloomfilter` crate. The first two probes are SipHash-1-3 hashes
+/// keyed by the filter's two SIP keys; probe `k` for `k >= 2` is
+/// derived from those two hashes by double hashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BloomfilterState {
+    sip_keys: [(u64, u64); 2],
+    probe: u32,
+}
+
+impl BloomfilterState {
+    /// Creates the state for probe number `probe` of a filter with
+    /// the given SIP keys.
+    pub fn new(sip_keys: [(u64, u64); 2], probe: u32) -> Self {
+        BloomfilterState { sip_keys, probe }
+    }
+
+    /// Returns the SIP keys of this state.
+    pub fn sip_keys(&self) -> [(u64, u64); 2] {
+        self.sip_keys
+    }
+
+    /// Returns the probe number of this state.
+    pub fn probe(&self) -> u32 {
+        self.probe
+    }
+}
+
+impl BuildHasher for BloomfilterState {
+    type Hasher = BloomfilterHasher;
+
+    fn build_hasher(&self) -> BloomfilterHasher {
+        let [(a0, a1), (b0, b1)] = self.sip_keys;
+        BloomfilterHasher {
+            sips: [
+                SipHasher13::new_with_keys(a0, a1),
+                SipHasher13::new_with_keys(b0, b1),
+            ],
+            probe: self.probe,
+        }
+    }
+}
+
+/// The [`Hasher`] created by a [`BloomfilterState`].
+#[derive(Debug, Clone)]
+pub struct BloomfilterHasher {
+    sips: [SipHasher13; 2],
+    probe: u32,
+}
+
+macro_rules! forward_writes {
+    ($($method:ident: $t:ty),*) => {
+        $(
+            fn $method(&mut self, i: $t) {
+                self.sips[0].$method(i);
+                self.sips[1].$method(i);
+            }
+        )*
+    };
+}
+
+impl Hasher for BloomfilterHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.sips[0].write(bytes);
+        self.sips[1].write(bytes);
+    }
+
+    forward_writes!(
+        write_u8: u8,
+        write_u16: u16,
+        write_u32: u32,
+        write_u64: u64,
+        write_u128: u128,
+        write_usize: usize
+    );
+
+    fn finish(&self) -> u64 {
+        match self.probe {
+            0 => self.sips[0].finish(),
+            1 => self.sips[1].finish(),
+            k => {
+                self.sips[0]
+                    .finish()
+                    .wrapping_add((k as u64).wrapping_mul(self.sips[1].finish()))
+                    % BLOOMFILTER_MODULUS
+            }
+        }
+    }
+}
+
+impl SerializableHasher for BloomfilterState {
+    const KIND: u8 = 2;
+
+    fn write_seed<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (k0, k1) in self.sip_keys {
+            w.write_all(&k0.to_le_bytes())?;
+            w.write_all(&k1.to_le_bytes())?;
+        }
+        w.write_all(&self.probe.to_le_bytes())
+    }
+
+    fn read_seed<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut bytes = [0u8; 36];
+        r.read_exact(&mut bytes)?;
+        let word = |i: usize| u64::from_le_bytes(bytes[8 * i..][..8].try_into().unwrap());
+        Ok(BloomfilterState {
+            sip_keys: [(word(0), word(1)), (word(2), word(3))],
+            probe: u32::from_le_bytes(bytes[32..].try_into().unwrap()),
+        })
+    }
+}
+
+/// A [`SimpleBloomFilter`] which hashes like a filter from the
+/// `bloomfilter` crate.
+pub type BloomfilterCompat<T, O> = SimpleBloomFilter<BitBox<T, O>, BloomfilterState, Box<[BloomfilterState]>>;
+
+/// Creates the hashers of a `bloomfilter` filter with `k_num` hash
+/// functions and the given SIP keys.
+pub fn bloomfilter_hashers(sip_keys: [(u64, u64); 2], k_num: u32) -> Box<[BloomfilterState]> {
+    (0..k_num)
+        .map(|probe| BloomfilterState::new(sip_keys, probe))
+        .collect()
+}
+
+/// Creates a filter equivalent to a `bloomfilter` filter with the
+/// given bitmap, number of bits, number of hash functions and SIP
+/// keys. Returns `None` if the parameters are inconsistent.
+pub fn from_bloomfilter<T, O>(
+    bitmap: &[u8],
+    bitmap_bits: u64,
+    k_num: u32,
+    sip_keys: [(u64, u64); 2],
+) -> Option<BloomfilterCompat<T, O>>
+where
+    T: BitStore,
+    O: BitOrder,
+{
+    let bits = usize::try_from(bitmap_bits).ok()?;
+    if bits == 0 || k_num == 0 || bitmap.len() < bits.div_ceil(8) {
+        return None;
+    }
+
+    // `bloomfilter` stores the first bit in the most significant bit
+    // of the first byte.
+    let mut set = BitVec::<T, O>::with_capacity(bits);
+    set.extend_from_bitslice(&bitmap.view_bits::<Msb0>()[..bits]);
+    Some(SimpleBloomFilter::from_parts(
+        bloomfilter_hashers(sip_keys, k_num),
+        set.into_boxed_bitslice(),
+    ))
+}
+
+/// Returns the bitmap, number of bits, number of hash functions and
+/// SIP keys to pass to `bloomfilter`'s `Bloom::from_existing` to
+/// recreate `filter`.
+///
+/// # Panics
+/// Panics if the hashers of `filter` are not those created by
+/// [`bloomfilter_hashers`].
+pub fn to_bloomfilter<T, O>(filter: &BloomfilterCompat<T, O>) -> (Vec<u8>, u64, u32, [(u64, u64); 2])
+where
+    T: BitStore,
+    O: BitOrder,
+{
+    let hashers = filter.hashers();
+    let sip_keys = hashers[0].sip_keys();
+    assert_eq!(
+        **hashers,
+        *bloomfilter_hashers(sip_keys, hashers.len() as u32),
+        "filter does not use bloomfilter hashers"
+    );
+
+    let set = filter.counters();
+    let mut bitmap = BitVec::<u8, Msb0>::with_capacity(set.len());
+    bitmap.extend_from_bitslice(set);
+    (bitmap.into_vec(), set.len() as u64, hashers.len() as u32, sip_keys)
+}
+
+/// Builds a RocksDB full-filter block from key hashes, as RocksDB's
+/// `FastLocalBloomBitsBuilder` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RocksDbFilterBuilder {
+    millibits_per_key: u32,
+    hashes: Vec<u64>,
+}
+
+impl RocksDbFilterBuilder {
+    /// Creates a builder for a filter with `bits_per_key` bits per
+    /// key, as passed to RocksDB's `NewBloomFilterPolicy`. Like
+    /// RocksDB, values are clamped to between 1 and 100 and rounded
+    /// to thousandths.
+    pub fn new(bits_per_key: f64) -> Self {
+        // Written so NaN becomes 100, as in RocksDB.
+        let bits_per_key = if bits_per_key < 100.0 {
+            bits_per_key.max(1.0)
+        } else {
+            100.0
+        };
+        RocksDbFilterBuilder {
+            millibits_per_key: (bits_per_key * 1000.0 + 0.500000001) as u32,
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Adds the key or prefix whose hash, as computed by RocksDB, is
+    /// `hash`. A hash equal to the last one added is skipped, as
+    /// RocksDB skips repeated keys.
+    pub fn add_hash(&mut self, hash: u64) {
+        if self.hashes.last() != Some(&hash) {
+            self.hashes.push(hash);
+        }
+    }
+
+    /// Returns the number of hashes added.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Checks whether no hashes have been added.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Returns the number of probes each key will set, which RocksDB
+    /// chooses from the number of bits per key.
+    pub fn num_probes(&self) -> u8 {
+        match self.millibits_per_key {
+            0..=2080 => 1,
+            2081..=3580 => 2,
+            3581..=5100 => 3,
+            5101..=6640 => 4,
+            6641..=8300 => 5,
+            8301..=10070 => 6,
+            10071..=11720 => 7,
+            11721..=14001 => 8,
+            14002..=16050 => 9,
+            16051..=18300 => 10,
+            18301..=22001 => 11,
+            22002..=25501 => 12,
+            25502..=50000 => ((self.millibits_per_key - 1) / 2000 - 1) as u8,
+            _ => 24,
+        }
+    }
+
+    /// Returns the filter block, with its metadata footer. A builder
+    /// with no hashes gives a block of just the footer, which matches
+    /// nothing. As in RocksDB, the filter is limited to just under
+    /// 4 GiB, so very large filters have more than their target false
+    /// positive rate.
+    pub fn finish(&self) -> Vec<u8> {
+        let millibits = self.hashes.len() as u64 * self.millibits_per_key as u64;
+        let len_bytes = (millibits.div_ceil(8000).min(ROCKSDB_MAX_LEN as u64) as u32)
+            .next_multiple_of(ROCKSDB_CACHE_LINE as u32);
+        let len = len_bytes as usize;
+        let num_probes = self.num_probes();
+
+        let mut block = vec![0u8; len + ROCKSDB_METADATA_LEN];
+        for &hash in &self.hashes {
+            let line = rocksdb_cache_line(hash, len_bytes);
+            let mut h = (hash >> 32) as u32;
+            for _ in 0..num_probes {
+                let bit = (h >> (32 - 9)) as usize;
+                block[line + (bit >> 3)] |= 1 << (bit & 7);
+                h = h.wrapping_mul(ROCKSDB_PROBE_MULTIPLIER);
+            }
+        }
+        // The marker for newer Bloom filters, `FastLocalBloom`, and
+        // the number of probes with 64-byte cache lines. The last two
+        // bytes are reserved.
+        block[len..len + 3].copy_from_slice(&[0xff, 0, num_probes]);
+        block
+    }
+}
+
+/// A RocksDB full-filter block, borrowed from the block's contents,
+/// which can be queried as RocksDB's `FastLocalBloomBitsReader`
+/// would.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RocksDbFilterBlock<'a> {
+    data: &'a [u8],
+    num_probes: u8,
+}
+
+impl<'a> RocksDbFilterBlock<'a> {
+    /// Reads the contents of a filter block, including its metadata
+    /// footer. Returns `None` if the block is not a `FastLocalBloom`
+    /// filter with 64-byte cache lines, e.g. if it is a legacy Bloom
+    /// or Ribbon filter, its reserved metadata is not zero, or its
+    /// bits are not a whole number of cache lines. A block no longer
+    /// than the footer is an empty filter.
+    pub fn parse(block: &'a [u8]) -> Option<Self> {
+        let Some(len) = block.len().checked_sub(ROCKSDB_METADATA_LEN).filter(|&len| len > 0) else {
+            return Some(RocksDbFilterBlock { data: &[], num_probes: 0 });
+        };
+        let (data, metadata) = block.split_at(len);
+        let [marker, sub_impl, block_and_probes, 0, 0] = *metadata else {
+            return None;
+        };
+        let num_probes = block_and_probes & 0x1f;
+        let log2_line = (block_and_probes >> 5) + 6;
+        let supported = marker == 0xff
+            && sub_impl == 0
+            && (1..=30).contains(&num_probes)
+            && 1 << log2_line == ROCKSDB_CACHE_LINE
+            && len % ROCKSDB_CACHE_LINE == 0
+            && u32::try_from(len).is_ok();
+        supported.then_some(RocksDbFilterBlock { data, num_probes })
+    }
+
+    /// Returns the filter's bits, without the metadata footer.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the number of probes each key sets.
+    pub fn num_probes(&self) -> u8 {
+        self.num_probes
+    }
+
+    /// Checks whether the filter may contain the key or prefix whose
+    /// hash, as computed by RocksDB, is `hash`.
+    pub fn may_match_hash(&self, hash: u64) -> bool {
+        if self.data.is_empty() {
+            return false;
+        }
+        let line = &self.data[rocksdb_cache_line(hash, self.data.len() as u32)..][..ROCKSDB_CACHE_LINE];
+        let mut h = (hash >> 32) as u32;
+        (0..self.num_probes).all(|_| {
+            let bit = (h >> (32 - 9)) as usize;
+            h = h.wrapping_mul(ROCKSDB_PROBE_MULTIPLIER);
+            line[bit >> 3] & (1 << (bit & 7)) != 0
+        })
+    }
+}
+
+/// Returns the offset of the cache line of a RocksDB filter of
+/// `len_bytes` bytes which the key with hash `hash` maps to, using
+/// the lower half of the hash.
+fn rocksdb_cache_line(hash: u64, len_bytes: u32) -> usize {
+    let n_lines = (len_bytes / ROCKSDB_CACHE_LINE as u32) as u64;
+    ((hash as u32 as u64 * n_lines) >> 32) as usize * ROCKSDB_CACHE_LINE
+}
+
+/// What a [`Bip37Filter`] adds to itself when an output of a
+/// transaction matches it, so that later transactions spending the
+/// output match too. These are the `nFlags` of BIP-37.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bip37Update {
+    /// Add nothing.
+    None = 0,
+    /// Add the outpoint of every matching output.
+    All = 1,
+    /// Add the outpoint of matching pay-to-pubkey and bare multisig
+    /// outputs only.
+    P2PubkeyOnly = 2,
+}
+
+/// A transaction output, identified by the hash of its transaction,
+/// in the byte order transactions are serialized in (the reverse of
+/// the order txids are usually displayed in), and its index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub txid: [u8; 32],
+    pub vout: u32,
+}
+
+impl OutPoint {
+    /// Returns the serialization of the outpoint, which is what
+    /// BIP-37 filters hash.
+    pub fn to_bytes(&self) -> [u8; 36] {
+        let mut bytes = [0; 36];
+        bytes[..32].copy_from_slice(&self.txid);
+        bytes[32..].copy_from_slice(&self.vout.to_le_bytes());
+        bytes
+    }
+}
+
+/// A Bitcoin BIP-37 Bloom filter. See the [module
+/// documentation](self#bitcoin-bip-37-filters).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bip37Filter {
+    data: Vec<u8>,
+    n_hash_funcs: u32,
+    tweak: u32,
+    flags: u8,
+}
+
+impl Bip37Filter {
+    /// Creates an empty filter sized for `n_elements` elements with
+    /// false positive rate `fp_rate`, limited to the largest filter
+    /// BIP-37 allows, whose hash functions are seeded by `tweak`.
+    /// The sizes are rounded as Bitcoin Core rounds them, so the same
+    /// parameters give the same filter.
+    pub fn new(n_elements: u32, fp_rate: f64, tweak: u32, update: Bip37Update) -> Self {
+        let n_elements = n_elements.max(1);
+        let bits = (-1.0 / (LN_2 * LN_2) * n_elements as f64 * fp_rate.ln()) as u32;
+        let size = bits.min(BIP37_MAX_SIZE as u32 * 8) as usize / 8;
+        let n_hash_funcs = (((size * 8) as u32 / n_elements) as f64 * LN_2) as u32;
+        Bip37Filter {
+            data: vec![0; size],
+            n_hash_funcs: n_hash_funcs.min(BIP37_MAX_HASH_FUNCS),
+            tweak,
+            flags: update as u8,
+        }
+    }
+
+    /// Creates a filter from its bit array, number of hash
+    /// functions, tweak and flags, as sent in a `filterload`
+    /// message. Returns `None` if the filter is larger than BIP-37
+    /// allows.
+    pub fn from_parts(data: Vec<u8>, n_hash_funcs: u32, tweak: u32, flags: u8) -> Option<Self> {
+        (data.len() <= BIP37_MAX_SIZE && n_hash_funcs <= BIP37_MAX_HASH_FUNCS).then_some(Bip37Filter {
+            data,
+            n_hash_funcs,
+            tweak,
+            flags,
+        })
+    }
+
+    /// Reads the payload of a `filterload` message. Returns `None` if
+    /// it is malformed, or the filter is larger than BIP-37 allows.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (len, rest) = read_compact_size(bytes)?;
+        let len = usize::try_from(len).ok().filter(|&len| len <= BIP37_MAX_SIZE)?;
+        if rest.len() != len + 9 {
+            return None;
+        }
+        let (data, rest) = rest.split_at(len);
+        let word = |i: usize| u32::from_le_bytes(rest[i..i + 4].try_into().unwrap());
+        Bip37Filter::from_parts(data.to_vec(), word(0), word(4), rest[8])
+    }
+
+    /// Returns the payload of a `filterload` message loading this
+    /// filter.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len() + 12);
+        write_compact_size(&mut bytes, self.data.len() as u64);
+        bytes.extend_from_slice(&self.data);
+        bytes.extend_from_slice(&self.n_hash_funcs.to_le_bytes());
+        bytes.extend_from_slice(&self.tweak.to_le_bytes());
+        bytes.push(self.flags);
+        bytes
+    }
+
+    /// Returns the filter's bit array.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn n_hash_funcs(&self) -> u32 {
+        self.n_hash_funcs
+    }
+
+    pub fn tweak(&self) -> u32 {
+        self.tweak
+    }
+
+    /// Returns the `nFlags` byte, whose low two bits are a
+    /// [`Bip37Update`].
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Inserts the data element `elem`, e.g. a public key or the
+    /// hash in a script.
+    pub fn insert(&mut self, elem: &[u8]) {
+        // An empty filter cannot hold anything.
+        if self.data.is_empty() {
+            return;
+        }
+        for i in 0..self.n_hash_funcs {
+            let bit = self.bit_index(i, elem);
+            self.data[bit >> 3] |= 1 << (bit & 7);
+        }
+    }
+
+    /// Checks whether the filter contains the data element `elem`.
+    /// Like Bitcoin Core, an empty filter matches everything.
+    pub fn contains(&self, elem: &[u8]) -> bool {
+        self.data.is_empty()
+            || (0..self.n_hash_funcs).all(|i| {
+                let bit = self.bit_index(i, elem);
+                self.data[bit >> 3] & (1 << (bit & 7)) != 0
+            })
+    }
+
+    /// Inserts an outpoint, so transactions spending it match.
+    pub fn insert_outpoint(&mut self, outpoint: &OutPoint) {
+        self.insert(&outpoint.to_bytes())
+    }
+
+    /// Checks whether the filter contains an outpoint.
+    pub fn contains_outpoint(&self, outpoint: &OutPoint) -> bool {
+        self.contains(&outpoint.to_bytes())
+    }
+
+    /// Checks whether a transaction matches the filter, as a node
+    /// checks before relaying it to an SPV client, and adds the
+    /// outpoints of matching outputs according to the filter's
+    /// flags.
+    ///
+    /// The transaction is given by its `txid`, in serialized byte
+    /// order, the outpoints spent by its inputs with their
+    /// `scriptSig`s, and the `scriptPubKey`s of its outputs. It
+    /// matches if the filter contains its txid, a data element pushed
+    /// by any of the scripts, or an outpoint it spends.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::compat::{Bip37Filter, Bip37Update, OutPoint};
+    ///
+    /// let pubkey = [2; 33];
+    /// let mut filter = Bip37Filter::new(10, 0.0001, 7, Bip37Update::P2PubkeyOnly);
+    /// filter.insert(&pubkey);
+    ///
+    /// // A transaction paying to the key, with a pay-to-pubkey output.
+    /// let funding = [1; 32];
+    /// let pay_to_pubkey = [&[33][..], &pubkey, &[0xac]].concat();
+    /// assert!(filter.is_relevant_and_update(&funding, [], [&pay_to_pubkey[..]]));
+    ///
+    /// // A transaction spending it matches, since the output's
+    /// // outpoint was added.
+    /// let spent = OutPoint { txid: funding, vout: 0 };
+    /// assert!(filter.contains_outpoint(&spent));
+    /// assert!(filter.is_relevant_and_update(&[3; 32], [(spent, &[][..])], []));
+    /// ```
+    pub fn is_relevant_and_update<'s, I, O>(&mut self, txid: &[u8; 32], inputs: I, outputs: O) -> bool
+    where
+        I: IntoIterator<Item = (OutPoint, &'s [u8])>,
+        O: IntoIterator<Item = &'s [u8]>,
+    {
+        let mut found = self.contains(txid);
+        for (vout, script_pubkey) in outputs.into_iter().enumerate() {
+            if !script_pushes(script_pubkey).any(|data| self.contains(data)) {
+                continue;
+            }
+            found = true;
+            let update = match self.flags & 3 {
+                1 => true,
+                2 => is_pay_to_pubkey(script_pubkey) || is_bare_multisig(script_pubkey),
+                _ => false,
+            };
+            if update {
+                self.insert_outpoint(&OutPoint {
+                    txid: *txid,
+                    vout: vout as u32,
+                });
+            }
+        }
+        found
+            || inputs.into_iter().any(|(prevout, script_sig)| {
+                self.contains_outpoint(&prevout) || script_pushes(script_sig).any(|data| self.contains(data))
+            })
+    }
+
+    /// Returns the bit set by hash function `i` for `elem`.
+    fn bit_index(&self, i: u32, elem: &[u8]) -> usize {
+        let seed = i.wrapping_mul(BIP37_SEED_MULTIPLIER).wrapping_add(self.tweak);
+        (murmur3_32(seed, elem) as u64 % (self.data.len() as u64 * 8)) as usize
+    }
+}
+
+/// Returns the 32-bit x86 variant of MurmurHash3 of `data`.
+fn murmur3_32(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h = seed;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let k = u32::from_le_bytes(block.try_into().unwrap());
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0, |k, &b| k << 8 | b as u32);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// The script opcodes of a script, with the data each pushes. Like
+/// Bitcoin Core's `GetOp`, iteration stops at a truncated push.
+struct ScriptOps<'s>(&'s [u8]);
+
+impl<'s> Iterator for ScriptOps<'s> {
+    type Item = (u8, &'s [u8]);
+
+    fn next(&mut self) -> Option<(u8, &'s [u8])> {
+        let (&opcode, rest) = self.0.split_first()?;
+        let (len, rest) = match opcode {
+            0x01..=0x4b => (opcode as usize, rest),
+            0x4c => (*rest.first()? as usize, &rest[1..]),
+            0x4d => (u16::from_le_bytes(rest.get(..2)?.try_into().unwrap()) as usize, &rest[2..]),
+            0x4e => (u32::from_le_bytes(rest.get(..4)?.try_into().unwrap()) as usize, &rest[4..]),
+            _ => (0, rest),
+        };
+        if rest.len() < len {
+            self.0 = &[];
+            return None;
+        }
+        let (data, rest) = rest.split_at(len);
+        self.0 = rest;
+        Some((opcode, data))
+    }
+}
+
+/// Returns the non-empty data elements pushed by a script.
+fn script_pushes(script: &[u8]) -> impl Iterator<Item = &[u8]> {
+    ScriptOps(script).map(|(_, data)| data).filter(|data| !data.is_empty())
+}
+
+const OP_1: u8 = 0x51;
+const OP_16: u8 = 0x60;
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKMULTISIG: u8 = 0xae;
+
+/// Checks whether `data` has the length its first byte implies for
+/// a public key.
+fn is_pubkey_sized(data: &[u8]) -> bool {
+    match data.first() {
+        Some(2 | 3) => data.len() == 33,
+        Some(4 | 6 | 7) => data.len() == 65,
+        _ => false,
+    }
+}
+
+/// Checks whether `script` is `<pubkey> OP_CHECKSIG`.
+fn is_pay_to_pubkey(script: &[u8]) -> bool {
+    match script {
+        [len @ (33 | 65), pubkey @ .., OP_CHECKSIG] => pubkey.len() == *len as usize && is_pubkey_sized(pubkey),
+        _ => false,
+    }
+}
+
+/// Checks whether `script` is `m <pubkey>... n OP_CHECKMULTISIG`.
+fn is_bare_multisig(script: &[u8]) -> bool {
+    let Some((&OP_CHECKMULTISIG, body)) = script.split_last() else {
+        return false;
+    };
+    let mut ops = ScriptOps(body);
+    let Some((required @ OP_1..=OP_16, _)) = ops.next() else {
+        return false;
+    };
+    let mut keys = 0;
+    let total = loop {
+        match ops.next() {
+            Some((_, data)) if is_pubkey_sized(data) => keys += 1,
+            Some((total @ OP_1..=OP_16, _)) => break total,
+            _ => return false,
+        }
+    };
+    // The key count must be followed only by OP_CHECKMULTISIG.
+    ops.0.is_empty() && keys == (total - OP_1 + 1) as usize && required <= total
+}
+
+/// Appends a Bitcoin compact-size integer to `bytes`.
+pub(crate) fn write_compact_size(bytes: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => bytes.push(n as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(0xfe);
+            bytes.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// Reads a Bitcoin compact-size integer from the start of `bytes`,
+/// returning it and the bytes after it. Returns `None` if it is
+/// truncated, or not in its shortest encoding.
+pub(crate) fn read_compact_size(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (&first, rest) = bytes.split_first()?;
+    let (n, len, min) = match first {
+        0xfd => (u16::from_le_bytes(rest.get(..2)?.try_into().unwrap()) as u64, 2, 0xfd),
+        0xfe => (u32::from_le_bytes(rest.get(..4)?.try_into().unwrap()) as u64, 4, 0x1_0000),
+        0xff => (u64::from_le_bytes(rest.get(..8)?.try_into().unwrap()), 8, 0x1_0000_0000),
+        n => return Some((n as u64, rest)),
+    };
+    (n >= min).then(|| (n, &rest[len..]))
+}
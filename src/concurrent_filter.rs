@@ -0,0 +1,276 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::hash_indices;
+use crate::SeededState;
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+/// A Bloom filter which can be inserted into and queried through
+/// shared references, so that it can be shared between threads
+/// without a lock. The underlying set `B` must be an
+/// [`AtomicBloomSet`], such as a [`BitBox`](bitvec::boxed::BitBox)
+/// of atomic integers.
+///
+/// An insertion which has not returned yet may or may not be visible
+/// to concurrent queries, but never causes false negatives for values
+/// inserted earlier.
+///
+/// # Example
+/// ```
+/// use generic_bloom::ConcurrentBloomFilter;
+/// use bitvec::prelude::*;
+/// use std::sync::Arc;
+/// use std::sync::atomic::AtomicUsize;
+/// use std::thread;
+///
+/// let filter: Arc<ConcurrentBloomFilter<BitBox<AtomicUsize, Lsb0>>> =
+///     Arc::new(ConcurrentBloomFilter::new(10, 2000));
+///
+/// let threads: Vec<_> = (0..4)
+///     .map(|t| {
+///         let filter = Arc::clone(&filter);
+///         thread::spawn(move || {
+///             for x in 0..25 {
+///                 filter.insert(&(t * 25 + x));
+///             }
+///         })
+///     })
+///     .collect();
+/// for t in threads {
+///     t.join().unwrap();
+/// }
+///
+/// assert!((0..100).all(|x| filter.contains(&x)));
+/// ```
+#[derive(Debug)]
+pub struct ConcurrentBloomFilter<B, S = SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    hashers: V,
+    set: B,
+    _phantom: PhantomData<S>,
+}
+
+impl<B, S, V> ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Creates a new `ConcurrentBloomFilter` with a specified number
+    /// of counters and [`BuildHasher`]s. The `BuildHasher`s will be
+    /// initialized by [`default`](Default::default).
+    pub fn new(n_hashers: usize, n_counters: usize) -> Self
+    where
+        S: Default,
+        V: FromIterator<S>,
+    {
+        ConcurrentBloomFilter::with_hashers(
+            std::iter::repeat_with(|| S::default())
+                .take(n_hashers)
+                .collect(),
+            n_counters,
+        )
+    }
+
+    /// Creates a new `ConcurrentBloomFilter` with specified
+    /// `BuildHasher`s and a specified number of counters.
+    ///
+    /// # Panics
+    /// Panics if `hashers` is empty or `n_counters` is zero. See
+    /// [`try_with_hashers`](ConcurrentBloomFilter::try_with_hashers)
+    /// for a fallible version.
+    pub fn with_hashers(hashers: V, n_counters: usize) -> Self {
+        match ConcurrentBloomFilter::try_with_hashers(hashers, n_counters) {
+            Ok(filter) => filter,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a new `ConcurrentBloomFilter` with specified
+    /// `BuildHasher`s and a specified number of counters, or returns
+    /// an error if `hashers` is empty or `n_counters` is zero.
+    pub fn try_with_hashers(hashers: V, n_counters: usize) -> Result<Self, ConfigError> {
+        ConfigError::check(hashers.as_ref().len(), n_counters)?;
+        Ok(ConcurrentBloomFilter {
+            hashers,
+            set: B::new(n_counters),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the hashers and set of the filter.
+    pub fn into_inner(self) -> (V, B) {
+        (self.hashers, self.set)
+    }
+
+    pub fn hashers(&self) -> &V {
+        &self.hashers
+    }
+
+    /// Inserts `val` into the set through a shared reference.
+    pub fn insert<T: Hash + ?Sized>(&self, val: &T) {
+        for i in hash_indices(self.hashers.as_ref(), self.set.size(), val) {
+            self.set.increment_shared(i);
+        }
+    }
+
+    /// Checks whether the set contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        query_all(&self.set, hash_indices(self.hashers.as_ref(), self.set.size(), val))
+    }
+}
+
+impl<B, S, V> ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSetDelete,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Removes `val` from the set through a shared reference, such as
+    /// an [`AtomicCounters`](crate::AtomicCounters). **If `val` was
+    /// not previously added to the set, this may cause false
+    /// negatives in future queries.**
+    pub fn remove<T: Hash + ?Sized>(&self, val: &T) {
+        for i in hash_indices(self.hashers.as_ref(), self.set.size(), val) {
+            self.set.decrement_shared(i);
+        }
+    }
+}
+
+impl<B, S, V> ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet + SpectralBloomSet,
+    B::Count: Ord,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Returns an estimate of the number of times the set contains
+    /// `val`, the smallest of its counters.
+    pub fn find_count<T: Hash + ?Sized>(&self, val: &T) -> B::Count {
+        hash_indices(self.hashers.as_ref(), self.set.size(), val)
+            .map(|i| self.set.query_count(i))
+            .min()
+            .expect("filters are constructed with at least one hasher")
+    }
+}
+
+impl<B, S, V> BloomFilter for ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    type Set = B;
+    type Hasher = S;
+
+    fn counters(&self) -> &B {
+        &self.set
+    }
+
+    fn hashers(&self) -> &[S] {
+        self.hashers.as_ref()
+    }
+
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        ConcurrentBloomFilter::insert(self, val)
+    }
+
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        ConcurrentBloomFilter::contains(self, val)
+    }
+
+    fn clear(&mut self) {
+        self.set.clear()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.set.heap_size_bytes() + crate::hash::hashers_heap_size(&self.hashers)
+    }
+}
+
+impl<B, S, V> MergeableFilter for ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet + BinaryBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        MismatchError::check(self, other)?;
+        self.set.union(&other.set);
+        Ok(())
+    }
+}
+
+impl<B, S, V> BloomFilterDelete for ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSetDelete,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn remove<T: Hash + ?Sized>(&mut self, val: &T) {
+        ConcurrentBloomFilter::remove(self, val)
+    }
+
+    fn subtract<Other>(&mut self, other: &Other)
+    where
+        Other: BloomFilterDelete<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.set.subtract(other.counters());
+    }
+}
+
+impl<B, S, V> SpectralBloomFilter for ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet + SpectralBloomSetMut,
+    B::Count: Ord,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn contains_more_than<T: Hash + ?Sized>(&self, val: &T, count: &B::Count) -> bool {
+        self.find_count(val) > *count
+    }
+
+    fn find_count<T: Hash + ?Sized>(&self, val: &T) -> B::Count {
+        ConcurrentBloomFilter::find_count(self, val)
+    }
+
+    fn merge_counts<Other>(&mut self, other: &Other)
+    where
+        Other: SpectralBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.set.merge_counts(other.counters());
+    }
+}
+
+impl<A, B, S, V> Extend<A> for ConcurrentBloomFilter<B, S, V>
+where
+    A: Hash,
+    B: AtomicBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            ConcurrentBloomFilter::insert(self, &val);
+        }
+    }
+}
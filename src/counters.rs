@@ -0,0 +1,557 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Counting storage with configurable overflow and underflow
+//! behavior.
+//!
+//! A `Box<[T]>` of counters saturates at the maximum of `T`, and never
+//! decrements a saturated counter again, since its true count is
+//! unknown: a heavily shared counter is then stuck at the maximum
+//! forever, but removals never cause false negatives. This behavior
+//! is deprecated in favor of [`Counters`], which makes both choices
+//! explicit, as an [`OverflowPolicy`] applied when a counter would
+//! exceed its maximum and an [`UnderflowPolicy`] applied when it
+//! would drop below zero:
+//!
+//! | Policy       | Overflow                            | Underflow                   |
+//! |--------------|-------------------------------------|-----------------------------|
+//! | [`Saturate`] | stops at the maximum, which sticks  | stops at zero               |
+//! | [`Wrap`]     | wraps around to zero                | wraps around to the maximum |
+//! | [`Fail`]     | is refused and reported             | is refused and reported     |
+//! | [`Panic`]    | panics                              | panics                      |
+//! | [`Spill`]    | keeps the excess in a side table    |                             |
+//!
+//! `Counters<T, Saturate, Saturate>` behaves like `Box<[T]>`, whose
+//! counters also stick at the maximum and stop at zero.
+//! With [`Spill`], counts stay exact however large they grow, so
+//! saturated counters are released again by removals; the side table
+//! only holds the counters which overflowed.
+//!
+//! Lost updates are reported by
+//! [`try_increment`](BloomSet::try_increment) and
+//! [`try_decrement`](BloomSetDelete::try_decrement), and a filter
+//! counts lost increments in
+//! [`lost_increments`](crate::BloomFilter::lost_increments).
+//!
+//! # Example
+//! ```
+//! use generic_bloom::{BloomFilter, BloomFilterDelete, SimpleBloomFilter, SpectralBloomFilter};
+//! use generic_bloom::counters::{Counters, Saturate, Spill};
+//!
+//! let mut sticky: SimpleBloomFilter<Counters<u8, Saturate>> = SimpleBloomFilter::with_seed(1, 3, 100);
+//! let mut spill: SimpleBloomFilter<Counters<u8, Spill>> = SimpleBloomFilter::with_seed(1, 3, 100);
+//! for _ in 0..300 {
+//!     sticky.insert(&48);
+//!     spill.insert(&48);
+//! }
+//! for _ in 0..300 {
+//!     sticky.remove(&48);
+//!     spill.remove(&48);
+//! }
+//! assert_eq!(sticky.find_count(&48), u8::MAX);
+//! assert_eq!(spill.find_count(&48), 0);
+//! ```
+
+use crate::traits::set::*;
+use num_traits::{PrimInt, Unsigned, WrappingAdd, WrappingSub};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Integer types which can be used as [`Counters`].
+pub trait Counter: PrimInt + Unsigned + WrappingAdd + WrappingSub {}
+
+impl<T: PrimInt + Unsigned + WrappingAdd + WrappingSub> Counter for T {}
+
+/// What a counter does when it would exceed its maximum.
+pub trait OverflowPolicy {
+    /// State kept by the policy for a whole set of counters, such as
+    /// the excess counts of [`Spill`].
+    type State: Default + Clone + PartialEq + fmt::Debug;
+
+    /// Adds `n` to `count`, the counter with index `index`, returning
+    /// `false` if some of it was lost.
+    fn add<T: Counter>(state: &mut Self::State, index: usize, count: &mut T, n: T) -> bool;
+
+    /// Takes up to `n` from the part of the counter with index
+    /// `index` which is held by the policy, before `count` is
+    /// decremented, and returns how much is left to subtract from
+    /// `count`, or `None` if `count` must not be decremented at all.
+    fn release<T: Counter>(state: &mut Self::State, index: usize, count: &T, n: T) -> Option<T>;
+
+    /// Forgets any part of the counter with index `index` held by the
+    /// policy, before it is overwritten.
+    fn reset(state: &mut Self::State, index: usize);
+
+    /// Returns the indices of the counters which have a part held by
+    /// the policy, and the size of that part.
+    fn excess(state: &Self::State) -> impl Iterator<Item = (usize, u64)> + '_;
+}
+
+/// What a counter does when it would drop below zero, e.g. when a
+/// value which was never inserted is removed.
+pub trait UnderflowPolicy {
+    /// Subtracts `n` from `count`, returning `false` if `count` was
+    /// less than `n`, so that the subtraction was not exact.
+    fn sub<T: Counter>(count: &mut T, n: T) -> bool;
+}
+
+/// Counters stop at their maximum or at zero. A saturated counter
+/// may have been incremented any number of times more, so it is
+/// never decremented again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Saturate;
+
+impl OverflowPolicy for Saturate {
+    type State = ();
+
+    fn add<T: Counter>(_: &mut (), _: usize, count: &mut T, n: T) -> bool {
+        match count.checked_add(&n) {
+            Some(sum) => {
+                *count = sum;
+                true
+            }
+            None => {
+                *count = T::max_value();
+                false
+            }
+        }
+    }
+
+    fn release<T: Counter>(_: &mut (), _: usize, count: &T, n: T) -> Option<T> {
+        (*count != T::max_value()).then_some(n)
+    }
+
+    fn reset(_: &mut (), _: usize) {}
+
+    fn excess(_: &()) -> impl Iterator<Item = (usize, u64)> + '_ {
+        std::iter::empty()
+    }
+}
+
+impl UnderflowPolicy for Saturate {
+    fn sub<T: Counter>(count: &mut T, n: T) -> bool {
+        let exact = n <= *count;
+        *count = count.saturating_sub(n);
+        exact
+    }
+}
+
+/// Counters wrap around, as unsigned integers do in release
+/// builds. A counter which overflows reads as zero, so the values
+/// sharing it become false negatives, and a counter which underflows
+/// reads as present.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Wrap;
+
+impl OverflowPolicy for Wrap {
+    type State = ();
+
+    fn add<T: Counter>(_: &mut (), _: usize, count: &mut T, n: T) -> bool {
+        *count = count.wrapping_add(&n);
+        true
+    }
+
+    fn release<T: Counter>(_: &mut (), _: usize, _: &T, n: T) -> Option<T> {
+        Some(n)
+    }
+
+    fn reset(_: &mut (), _: usize) {}
+
+    fn excess(_: &()) -> impl Iterator<Item = (usize, u64)> + '_ {
+        std::iter::empty()
+    }
+}
+
+impl UnderflowPolicy for Wrap {
+    fn sub<T: Counter>(count: &mut T, n: T) -> bool {
+        let exact = n <= *count;
+        *count = count.wrapping_sub(&n);
+        exact
+    }
+}
+
+/// Counters refuse updates which would overflow or underflow them,
+/// leaving the counter unchanged and reporting the update as lost,
+/// for applications which check for either, such as removing a
+/// value which was never inserted.
+///
+/// # Example
+/// ```
+/// use generic_bloom::traits::set::{BloomSet, BloomSetDelete};
+/// use generic_bloom::counters::{Counters, Fail};
+///
+/// let mut counters: Counters<u8, Fail, Fail> = BloomSet::new(10);
+/// assert!(!counters.try_decrement(3));
+/// assert_eq!(counters.counts()[3], 0);
+///
+/// for _ in 0..255 {
+///     assert!(counters.try_increment(3));
+/// }
+/// assert!(!counters.try_increment(3));
+/// assert_eq!(counters.counts()[3], 255);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fail;
+
+impl OverflowPolicy for Fail {
+    type State = ();
+
+    fn add<T: Counter>(_: &mut (), _: usize, count: &mut T, n: T) -> bool {
+        match count.checked_add(&n) {
+            Some(sum) => {
+                *count = sum;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn release<T: Counter>(_: &mut (), _: usize, _: &T, n: T) -> Option<T> {
+        Some(n)
+    }
+
+    fn reset(_: &mut (), _: usize) {}
+
+    fn excess(_: &()) -> impl Iterator<Item = (usize, u64)> + '_ {
+        std::iter::empty()
+    }
+}
+
+impl UnderflowPolicy for Fail {
+    fn sub<T: Counter>(count: &mut T, n: T) -> bool {
+        match count.checked_sub(&n) {
+            Some(diff) => {
+                *count = diff;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Counters panic when they would overflow or underflow, for
+/// applications in which either indicates a bug. Use [`Fail`] to
+/// handle such updates instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Panic;
+
+impl OverflowPolicy for Panic {
+    type State = ();
+
+    fn add<T: Counter>(_: &mut (), index: usize, count: &mut T, n: T) -> bool {
+        *count = count
+            .checked_add(&n)
+            .unwrap_or_else(|| panic!("counter {} overflowed", index));
+        true
+    }
+
+    fn release<T: Counter>(_: &mut (), _: usize, _: &T, n: T) -> Option<T> {
+        Some(n)
+    }
+
+    fn reset(_: &mut (), _: usize) {}
+
+    fn excess(_: &()) -> impl Iterator<Item = (usize, u64)> + '_ {
+        std::iter::empty()
+    }
+}
+
+impl UnderflowPolicy for Panic {
+    fn sub<T: Counter>(count: &mut T, n: T) -> bool {
+        *count = count.checked_sub(&n).expect("counter underflowed");
+        true
+    }
+}
+
+/// Counters stop at their maximum, and the excess is kept in a side
+/// table, so that counts are never lost and saturated counters are
+/// decremented correctly. Queries still read the counters, so
+/// [`query_count`](SpectralBloomSet::query_count) returns at most the
+/// maximum; use [`Counters::spilled`] to read the excess.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spill;
+
+impl OverflowPolicy for Spill {
+    type State = HashMap<usize, u64>;
+
+    fn add<T: Counter>(state: &mut Self::State, index: usize, count: &mut T, n: T) -> bool {
+        let room = T::max_value() - *count;
+        if n <= room {
+            *count = *count + n;
+        } else {
+            *count = T::max_value();
+            let excess = (n - room).to_u64().unwrap_or(u64::MAX);
+            let spilled = state.entry(index).or_insert(0);
+            *spilled = spilled.saturating_add(excess);
+        }
+        true
+    }
+
+    fn release<T: Counter>(state: &mut Self::State, index: usize, _: &T, n: T) -> Option<T> {
+        let Some(spilled) = state.get_mut(&index) else {
+            return Some(n);
+        };
+        let wanted = n.to_u64().unwrap_or(u64::MAX);
+        if wanted < *spilled {
+            *spilled -= wanted;
+            return Some(T::zero());
+        }
+        let taken = std::mem::take(spilled);
+        state.remove(&index);
+        // `taken` is at most `n`, which fits in `T`.
+        Some(n - T::from(taken).expect("spilled count fits in the counter type"))
+    }
+
+    fn reset(state: &mut Self::State, index: usize) {
+        state.remove(&index);
+    }
+
+    fn excess(state: &Self::State) -> impl Iterator<Item = (usize, u64)> + '_ {
+        state.iter().map(|(&index, &excess)| (index, excess))
+    }
+}
+
+/// Splits `n` into amounts which fit in `T`.
+fn chunks<T: Counter>(mut n: u64) -> impl Iterator<Item = T> {
+    let max = T::max_value().to_u64().unwrap_or(u64::MAX);
+    std::iter::from_fn(move || {
+        (n > 0).then(|| {
+            let chunk = n.min(max);
+            n -= chunk;
+            T::from(chunk).expect("chunk fits in the counter type")
+        })
+    })
+}
+
+/// A set of counters of type `T`, which overflow according to `O`
+/// and underflow according to `U`. See the [module
+/// documentation](self) for the available policies.
+pub struct Counters<T, O: OverflowPolicy = Saturate, U = Saturate> {
+    counts: Box<[T]>,
+    overflow: O::State,
+    _underflow: PhantomData<U>,
+}
+
+impl<T, O: OverflowPolicy, U> Counters<T, O, U> {
+    /// Returns the counters, without any part held by the overflow
+    /// policy.
+    pub fn counts(&self) -> &[T] {
+        &self.counts
+    }
+}
+
+impl<T, O, U> Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+    U: UnderflowPolicy,
+{
+    /// Subtracts `n` from the counter with index `index`, first from
+    /// any part held by the overflow policy, returning `false` if
+    /// some of it could not be subtracted.
+    fn sub(&mut self, index: usize, n: T) -> bool {
+        match O::release(&mut self.overflow, index, &self.counts[index], n) {
+            Some(rest) if rest.is_zero() => true,
+            Some(rest) => U::sub(&mut self.counts[index], rest),
+            None => false,
+        }
+    }
+}
+
+impl<T, U> Counters<T, Spill, U> {
+    /// Returns the part of the counter with index `index` which did
+    /// not fit in `T`, so that its true count is
+    /// `query_count(index) + spilled(index)`.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::BloomSet;
+    /// use generic_bloom::counters::{Counters, Spill};
+    ///
+    /// let mut counters: Counters<u8, Spill> = BloomSet::new(10);
+    /// for _ in 0..300 {
+    ///     counters.increment(3);
+    /// }
+    /// assert_eq!(counters.counts()[3], 255);
+    /// assert_eq!(counters.spilled(3), 45);
+    /// ```
+    pub fn spilled(&self, index: usize) -> u64 {
+        self.overflow.get(&index).copied().unwrap_or(0)
+    }
+}
+
+impl<T: Clone, O: OverflowPolicy, U> Clone for Counters<T, O, U> {
+    fn clone(&self) -> Self {
+        Counters {
+            counts: self.counts.clone(),
+            overflow: self.overflow.clone(),
+            _underflow: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, O: OverflowPolicy, U> fmt::Debug for Counters<T, O, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Counters")
+            .field("counts", &self.counts)
+            .field("overflow", &self.overflow)
+            .finish()
+    }
+}
+
+impl<T: PartialEq, O: OverflowPolicy, U> PartialEq for Counters<T, O, U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.counts == other.counts && self.overflow == other.overflow
+    }
+}
+
+impl<T, O, U> BloomSetRead for Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+{
+    fn size(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn query(&self, index: usize) -> bool {
+        !self.counts[index].is_zero()
+    }
+
+    fn count_ones(&self) -> usize {
+        self.counts.iter().filter(|c| !c.is_zero()).count()
+    }
+
+    fn is_clear(&self) -> bool {
+        self.counts.iter().all(|c| c.is_zero())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.counts.iter().map(|c| !c.is_zero())
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.counts)
+    }
+
+    fn prefetch(&self, index: usize) {
+        if let Some(counter) = self.counts.get(index) {
+            prefetch_read(counter);
+        }
+    }
+}
+
+impl<T, O, U> BloomSet for Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+{
+    fn new(count: usize) -> Self {
+        Counters {
+            counts: vec![T::zero(); count].into_boxed_slice(),
+            overflow: O::State::default(),
+            _underflow: PhantomData,
+        }
+    }
+
+    fn increment(&mut self, index: usize) {
+        self.try_increment(index);
+    }
+
+    fn try_increment(&mut self, index: usize) -> bool {
+        O::add(&mut self.overflow, index, &mut self.counts[index], T::one())
+    }
+
+    fn clear(&mut self) {
+        self.counts.fill(T::zero());
+        self.overflow = O::State::default();
+    }
+}
+
+impl<T, O, U> BloomSetDelete for Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+    U: UnderflowPolicy,
+{
+    fn decrement(&mut self, index: usize) {
+        self.sub(index, T::one());
+    }
+
+    fn try_decrement(&mut self, index: usize) -> bool {
+        self.sub(index, T::one())
+    }
+
+    /// Subtracts element-wise, underflowing according to `U`.
+    ///
+    /// # Panics
+    /// Panics if `self` and `other` have different numbers of
+    /// counters.
+    fn subtract(&mut self, other: &Self) {
+        assert_eq!(self.size(), other.size(), "counter sets differ in size");
+        for (index, b) in other.counts.iter().enumerate() {
+            self.sub(index, *b);
+        }
+        for (index, excess) in O::excess(&other.overflow) {
+            for n in chunks(excess) {
+                self.sub(index, n);
+            }
+        }
+    }
+}
+
+impl<T, O, U> SpectralBloomSet for Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+{
+    type Count = T;
+
+    fn query_count(&self, index: usize) -> T {
+        self.counts[index]
+    }
+
+    fn iter_counts(&self) -> impl Iterator<Item = T> + '_ {
+        self.counts.iter().copied()
+    }
+}
+
+impl<T, O, U> SpectralBloomSetMut for Counters<T, O, U>
+where
+    T: Counter,
+    O: OverflowPolicy,
+{
+    fn set_count(&mut self, index: usize, count: T) {
+        O::reset(&mut self.overflow, index);
+        self.counts[index] = count;
+    }
+
+    /// Adds element-wise, overflowing according to `O`.
+    ///
+    /// # Panics
+    /// Panics if `self` and `other` have different numbers of
+    /// counters.
+    fn merge_counts(&mut self, other: &Self) {
+        assert_eq!(self.size(), other.size(), "counter sets differ in size");
+        for (index, b) in other.counts.iter().enumerate() {
+            O::add(&mut self.overflow, index, &mut self.counts[index], *b);
+        }
+        for (index, excess) in O::excess(&other.overflow) {
+            for n in chunks(excess) {
+                O::add(&mut self.overflow, index, &mut self.counts[index], n);
+            }
+        }
+    }
+}
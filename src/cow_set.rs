@@ -0,0 +1,209 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::set::*;
+use crate::{FrozenBloomFilter, SimpleBloomFilter};
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+/// A set whose counters are shared between clones until one of them
+/// is modified, at which point that clone copies them.
+///
+/// Cloning a [`SimpleBloomFilter`] backed by a `CowSet` takes
+/// constant time, so [`snapshot`](SimpleBloomFilter::snapshot) can
+/// hand out consistent point-in-time copies of a large filter while
+/// insertions continue. The first modification after a snapshot
+/// copies the whole set once; later ones modify the copy in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CowSet<B>(Arc<B>);
+
+impl<B> CowSet<B> {
+    /// Wraps `set`.
+    pub fn new(set: B) -> Self {
+        CowSet(Arc::new(set))
+    }
+
+    /// Returns the underlying set.
+    pub fn get(&self) -> &B {
+        &self.0
+    }
+
+    /// Checks whether the counters are shared with a clone, so that
+    /// the next modification will copy them.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+
+    /// Returns the underlying set, copying it if it is shared.
+    pub fn into_inner(self) -> B
+    where
+        B: Clone,
+    {
+        Arc::unwrap_or_clone(self.0)
+    }
+}
+
+impl<B: Clone> CowSet<B> {
+    fn get_mut(&mut self) -> &mut B {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<B: BloomSetRead> BloomSetRead for CowSet<B> {
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    fn query(&self, index: usize) -> bool {
+        self.0.query(index)
+    }
+
+    fn count_ones(&self) -> usize {
+        self.0.count_ones()
+    }
+
+    fn is_clear(&self) -> bool {
+        self.0.is_clear()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.0.iter()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.0.heap_size_bytes()
+    }
+
+    fn prefetch(&self, index: usize) {
+        self.0.prefetch(index)
+    }
+}
+
+impl<B: BloomSet + Clone> BloomSet for CowSet<B> {
+    fn new(count: usize) -> Self {
+        CowSet::new(B::new(count))
+    }
+
+    fn increment(&mut self, index: usize) {
+        self.get_mut().increment(index)
+    }
+
+    fn try_increment(&mut self, index: usize) -> bool {
+        self.get_mut().try_increment(index)
+    }
+
+    /// Clears the counters, without copying them first if they are
+    /// shared.
+    fn clear(&mut self) {
+        if self.is_shared() {
+            *self = CowSet::new(B::new(self.size()));
+        } else {
+            self.get_mut().clear();
+        }
+    }
+}
+
+impl<B: BloomSetDelete + Clone> BloomSetDelete for CowSet<B> {
+    fn decrement(&mut self, index: usize) {
+        self.get_mut().decrement(index)
+    }
+
+    fn try_decrement(&mut self, index: usize) -> bool {
+        self.get_mut().try_decrement(index)
+    }
+
+    fn subtract(&mut self, other: &Self) {
+        self.get_mut().subtract(&other.0)
+    }
+}
+
+impl<B: BinaryBloomSet + Clone> BinaryBloomSet for CowSet<B> {
+    fn union(&mut self, other: &Self) {
+        self.get_mut().union(&other.0)
+    }
+
+    fn union_all(&mut self, others: &[&Self]) {
+        let others: Vec<&B> = others.iter().map(|o| &*o.0).collect();
+        self.get_mut().union_all(&others)
+    }
+
+    fn intersect(&mut self, other: &Self) {
+        self.get_mut().intersect(&other.0)
+    }
+
+    fn symmetric_difference(&mut self, other: &Self) {
+        self.get_mut().symmetric_difference(&other.0)
+    }
+
+    fn union_count_ones(&self, other: &Self) -> usize {
+        self.0.union_count_ones(&other.0)
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.0.is_subset(&other.0)
+    }
+}
+
+impl<B: SpectralBloomSet> SpectralBloomSet for CowSet<B> {
+    type Count = B::Count;
+
+    fn query_count(&self, index: usize) -> B::Count {
+        self.0.query_count(index)
+    }
+
+    fn iter_counts(&self) -> impl Iterator<Item = B::Count> + '_ {
+        self.0.iter_counts()
+    }
+}
+
+impl<B: SpectralBloomSetMut + Clone> SpectralBloomSetMut for CowSet<B> {
+    fn set_count(&mut self, index: usize, count: B::Count) {
+        self.get_mut().set_count(index, count)
+    }
+
+    fn merge_counts(&mut self, other: &Self) {
+        self.get_mut().merge_counts(&other.0)
+    }
+}
+
+impl<B, S, V> SimpleBloomFilter<CowSet<B>, S, V>
+where
+    B: BloomSet + Clone,
+    S: BuildHasher + Clone,
+    V: AsRef<[S]> + Clone,
+{
+    /// Returns an immutable copy of the filter in its current state,
+    /// in constant time. The copy shares its counters with `self`
+    /// until `self` is next modified; see [`CowSet`].
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, CowSet, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut live: SimpleBloomFilter<CowSet<BitBox<usize, Lsb0>>> = SimpleBloomFilter::with_seed(1, 7, 100_000);
+    /// live.extend(0..1000);
+    ///
+    /// let snapshot = live.snapshot();
+    /// assert!(live.counters().is_shared());
+    /// live.extend(1000..2000);
+    /// assert!(!live.counters().is_shared());
+    ///
+    /// assert!(snapshot.contains(&48) && live.contains(&1048));
+    /// assert!(!snapshot.contains(&1048));
+    /// ```
+    pub fn snapshot(&self) -> FrozenBloomFilter<CowSet<B>, S, V> {
+        self.clone().freeze()
+    }
+}
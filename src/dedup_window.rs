@@ -0,0 +1,237 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{Block, SeededState};
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// A [`Block`] whose words can be updated through shared references.
+#[derive(Debug, Default)]
+#[repr(C, align(32))]
+struct AtomicBlock([AtomicU32; 8]);
+
+impl AtomicBlock {
+    fn contains(&self, mask: &[u32; 8]) -> bool {
+        self.0
+            .iter()
+            .zip(mask)
+            .all(|(w, m)| w.load(Ordering::Relaxed) & m != 0)
+    }
+
+    fn insert(&self, mask: &[u32; 8]) {
+        for (w, m) in self.0.iter().zip(mask) {
+            w.fetch_or(*m, Ordering::Relaxed);
+        }
+    }
+
+    fn clear(&self) {
+        for w in &self.0 {
+            w.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A filter of recently seen keys, for dropping duplicate packets.
+///
+/// Time is divided into slots, and the keys seen in each slot are
+/// recorded in a separate sub-filter. Once a slot is older than the
+/// window, its sub-filter is cleared and reused, so memory use is
+/// fixed and keys expire without any work per key. A key is
+/// remembered for at least the window, and at most one slot longer.
+///
+/// Every operation takes `&self` and neither locks nor allocates: a
+/// key is hashed once and, as in a
+/// [`BlockBloomFilter`](crate::BlockBloomFilter), touches a single
+/// cache line in each sub-filter. Two threads inserting the same key
+/// at once may both see it as new, and a key inserted while its
+/// sub-filter is being cleared may be forgotten early, so rare
+/// duplicates can slip through, but a key which was never inserted
+/// is only reported as seen with the false positive rate of the
+/// sub-filters.
+///
+/// # Example
+/// ```
+/// use generic_bloom::DedupWindow;
+/// use std::time::{Duration, Instant};
+///
+/// let window: DedupWindow = DedupWindow::new(Duration::from_millis(100), 4, 1 << 16);
+/// let start = Instant::now();
+///
+/// let flow = ("10.0.0.1", 5353u16, "10.0.0.2", 53u16, 0x1234u32);
+/// assert!(window.insert_if_absent_at(&flow, start));
+/// assert!(!window.insert_if_absent_at(&flow, start + Duration::from_millis(90)));
+/// // The key was last seen more than a window and a slot ago.
+/// assert!(window.insert_if_absent_at(&flow, start + Duration::from_millis(300)));
+/// ```
+#[derive(Debug)]
+pub struct DedupWindow<S = SeededState> {
+    hasher: S,
+    // Sub-filter `f` is `blocks[f * n_blocks..(f + 1) * n_blocks]`,
+    // and holds the keys of the slots congruent to `f`.
+    blocks: Box<[AtomicBlock]>,
+    n_blocks: usize,
+    n_filters: u64,
+    slot_nanos: u64,
+    start: Instant,
+    // The slot which is currently being written.
+    slot: AtomicU64,
+}
+
+impl<S: BuildHasher> DedupWindow<S> {
+    /// Creates a `DedupWindow` remembering keys for `window`, split
+    /// into `n_slots` slots, with sub-filters of at least
+    /// `n_counters` bits each. The [`BuildHasher`] will be
+    /// initialized by [`default`](Default::default).
+    ///
+    /// # Panics
+    /// Panics if `n_slots` is zero or `window` is shorter than
+    /// `n_slots` nanoseconds.
+    pub fn new(window: Duration, n_slots: usize, n_counters: usize) -> Self
+    where
+        S: Default,
+    {
+        DedupWindow::with_hasher(S::default(), window, n_slots, n_counters)
+    }
+
+    /// Creates a `DedupWindow` with a specified `BuildHasher`,
+    /// remembering keys for `window`, split into `n_slots` slots,
+    /// with sub-filters of at least `n_counters` bits each. Memory
+    /// use is about `(n_slots + 1) * n_counters / 8` bytes.
+    ///
+    /// # Panics
+    /// Panics if `n_slots` is zero or `window` is shorter than
+    /// `n_slots` nanoseconds.
+    pub fn with_hasher(hasher: S, window: Duration, n_slots: usize, n_counters: usize) -> Self {
+        assert!(n_slots > 0, "a window needs at least one slot");
+        let slot_nanos = u64::try_from(window.as_nanos() / n_slots as u128).unwrap_or(u64::MAX);
+        assert!(slot_nanos > 0, "slots must be at least a nanosecond long");
+        let n_blocks = n_counters.div_ceil(Block::BITS).max(1);
+        // One more sub-filter than slots, so that the keys of the
+        // oldest slot in the window are still present while the
+        // current slot is written.
+        let n_filters = n_slots + 1;
+        DedupWindow {
+            hasher,
+            blocks: std::iter::repeat_with(AtomicBlock::default)
+                .take(n_filters * n_blocks)
+                .collect(),
+            n_blocks,
+            n_filters: n_filters as u64,
+            slot_nanos,
+            start: Instant::now(),
+            slot: AtomicU64::new(0),
+        }
+    }
+
+    /// Records `key` as seen now, returning whether it was not seen
+    /// within the window, i.e. whether a packet with this key should
+    /// be kept.
+    pub fn insert_if_absent<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.insert_if_absent_at(key, Instant::now())
+    }
+
+    /// Like [`insert_if_absent`](DedupWindow::insert_if_absent), but
+    /// at the time `now`, e.g. a packet's capture timestamp.
+    pub fn insert_if_absent_at<T: Hash + ?Sized>(&self, key: &T, now: Instant) -> bool {
+        let slot = self.advance(now);
+        let (block, mask) = self.locate(key);
+        if self.seen(block, &mask) {
+            return false;
+        }
+        self.blocks[self.filter_start(slot) + block].insert(&mask);
+        true
+    }
+
+    /// Checks whether `key` was seen within the window, without
+    /// recording it.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.contains_at(key, Instant::now())
+    }
+
+    /// Like [`contains`](DedupWindow::contains), but at the time
+    /// `now`.
+    pub fn contains_at<T: Hash + ?Sized>(&self, key: &T, now: Instant) -> bool {
+        self.advance(now);
+        let (block, mask) = self.locate(key);
+        self.seen(block, &mask)
+    }
+
+    /// Moves to the current slot, clearing the sub-filters of slots
+    /// which have left the window. Every operation does this anyway,
+    /// but calling it periodically, e.g. from a
+    /// [`Maintainer`](crate::maintenance::Maintainer) at the start of
+    /// each slot, moves most of the cost of clearing off the path of
+    /// insertions.
+    pub fn rotate(&self) {
+        self.advance(Instant::now());
+    }
+
+    /// Returns the number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.blocks)
+    }
+
+    /// Returns the index of the block `key` maps to in every
+    /// sub-filter, and the bits it sets there.
+    fn locate<T: Hash + ?Sized>(&self, key: &T) -> (usize, [u32; 8]) {
+        let hash = self.hasher.hash_one(key);
+        let block = ((hash >> 32) * self.n_blocks as u64) >> 32;
+        (block as usize, Block::mask(hash as u32))
+    }
+
+    /// Checks whether any sub-filter contains the block `mask`.
+    fn seen(&self, block: usize, mask: &[u32; 8]) -> bool {
+        self.blocks[block..]
+            .iter()
+            .step_by(self.n_blocks)
+            .any(|b| b.contains(mask))
+    }
+
+    fn filter_start(&self, slot: u64) -> usize {
+        (slot % self.n_filters) as usize * self.n_blocks
+    }
+
+    /// Moves to the slot containing `now`, clearing the sub-filters
+    /// of the slots which have left the window, and returns the
+    /// current slot. If `now` is earlier than the current slot, the
+    /// current slot is returned unchanged.
+    fn advance(&self, now: Instant) -> u64 {
+        let target = (now.saturating_duration_since(self.start).as_nanos() / self.slot_nanos as u128)
+            .try_into()
+            .unwrap_or(u64::MAX);
+        let mut slot = self.slot.load(Ordering::Acquire);
+        while slot < target {
+            match self
+                .slot
+                .compare_exchange_weak(slot, target, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    // After a long pause, every sub-filter is cleared
+                    // once rather than once per skipped slot.
+                    let first = (slot + 1).max(target.saturating_sub(self.n_filters - 1));
+                    for s in first..=target {
+                        let start = self.filter_start(s);
+                        for b in &self.blocks[start..start + self.n_blocks] {
+                            b.clear();
+                        }
+                    }
+                    return target;
+                }
+                Err(current) => slot = current,
+            }
+        }
+        slot
+    }
+}
@@ -0,0 +1,414 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::serialize::{Error, SerializableHasher, SerializableSet};
+use crate::traits::filter::*;
+use crate::traits::set::BloomSet;
+use crate::SimpleBloomFilter;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CHECKPOINT: &str = "checkpoint";
+const CHECKPOINT_TMP: &str = "checkpoint.tmp";
+const WAL: &str = "wal";
+const WAL_TMP: &str = "wal.tmp";
+
+/// A [`SimpleBloomFilter`] persisted in a directory as a checkpoint
+/// of the full filter plus a write-ahead log of the counters touched
+/// since the checkpoint.
+///
+/// Every insertion appends the affected counter indices to the log
+/// before returning. Once [`sync`](DurableBloomFilter::sync)
+/// returns, or after every operation if
+/// [`set_sync_on_write`](DurableBloomFilter::set_sync_on_write) is
+/// enabled, the logged operations survive a crash: reopening the
+/// directory with [`open`](DurableBloomFilter::open) restores the
+/// checkpoint and replays the log. With
+/// [`set_sync_interval`](DurableBloomFilter::set_sync_interval), the
+/// log is instead synced periodically, bounding how much recent work
+/// a crash can lose. In every case, the operations which survive a
+/// crash are exactly those up to some point at or after the last
+/// sync: an operation is never half applied, and never survives
+/// while an earlier one is lost. Once enough operations have been
+/// logged (see
+/// [`set_checkpoint_interval`](DurableBloomFilter::set_checkpoint_interval)),
+/// the whole filter is checkpointed and the log is started afresh.
+///
+/// Checkpoints and logs are tagged with a generation number, so a log
+/// is only ever replayed onto the checkpoint it extends; operations
+/// are never applied twice, even for counting filters.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, DurableBloomFilter, SimpleBloomFilter, SeededState};
+/// use bitvec::prelude::*;
+///
+/// let dir = std::env::temp_dir().join("generic-bloom-durable-example");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+/// let filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+///     SimpleBloomFilter::with_hashers(hashers, 200);
+///
+/// let mut durable = DurableBloomFilter::create(&dir, filter).unwrap();
+/// durable.insert(&48).unwrap();
+/// durable.sync().unwrap();
+/// drop(durable);
+///
+/// let durable: DurableBloomFilter<BitBox<usize, Lsb0>> = DurableBloomFilter::open(&dir).unwrap();
+/// assert!(durable.filter().contains(&48));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// As the "seen set" of a crawler, which only logs URLs it has not
+/// seen before:
+/// ```
+/// use generic_bloom::{DurableBloomFilter, SimpleBloomFilter, SeededState};
+/// use bitvec::prelude::*;
+/// use std::time::Duration;
+///
+/// let dir = std::env::temp_dir().join("generic-bloom-durable-seen-example");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let hashers: Box<[SeededState]> = (0..7).map(|i| SeededState::new(0, i)).collect();
+/// let filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+///     SimpleBloomFilter::with_hashers(hashers, 10_000);
+/// let mut seen = DurableBloomFilter::create(&dir, filter).unwrap();
+/// seen.set_sync_interval(Some(Duration::from_secs(1)));
+///
+/// let mut fetched = 0;
+/// for url in ["https://example.com/", "https://example.org/", "https://example.com/"] {
+///     if seen.insert_if_absent(url).unwrap() {
+///         fetched += 1;
+///     }
+/// }
+/// // May fail if example.org is a false positive
+/// assert_eq!(fetched, 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct DurableBloomFilter<B, S = crate::SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    filter: SimpleBloomFilter<B, S, V>,
+    dir: PathBuf,
+    wal: BufWriter<File>,
+    generation: u64,
+    logged: usize,
+    checkpoint_interval: usize,
+    sync_on_write: bool,
+    sync_interval: Option<Duration>,
+    last_sync: Instant,
+    // Reused between insertions, so that they do not allocate.
+    indices: Vec<usize>,
+    record: Vec<u8>,
+}
+
+impl<B, S, V> DurableBloomFilter<B, S, V>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    /// Creates a new durable filter in `dir`, which is created if
+    /// necessary, with `filter` as its initial contents. Any filter
+    /// previously stored in `dir` is replaced.
+    pub fn create<P: AsRef<Path>>(dir: P, filter: SimpleBloomFilter<B, S, V>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        write_checkpoint(&dir, &filter, 0)?;
+        let wal = create_wal(&dir, 0)?;
+        Ok(DurableBloomFilter {
+            filter,
+            dir,
+            wal,
+            generation: 0,
+            logged: 0,
+            checkpoint_interval: 1 << 20,
+            sync_on_write: false,
+            sync_interval: None,
+            last_sync: Instant::now(),
+            indices: Vec::new(),
+            record: Vec::new(),
+        })
+    }
+
+    /// Opens the durable filter stored in `dir`, recovering all
+    /// operations which were logged before the last crash. A
+    /// partially written operation at the end of the log is
+    /// discarded.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error>
+    where
+        V: FromIterator<S>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        let mut checkpoint = BufReader::new(File::open(dir.join(CHECKPOINT))?);
+        let generation = read_u64(&mut checkpoint)?;
+        let mut filter: SimpleBloomFilter<B, S, V> = SimpleBloomFilter::read_from(&mut checkpoint)?;
+
+        let mut logged = 0;
+        let wal = match OpenOptions::new().read(true).write(true).open(dir.join(WAL)) {
+            Ok(mut file) => {
+                let mut reader = BufReader::new(&mut file);
+                if read_u64(&mut reader).ok() == Some(generation) {
+                    let mut valid_len = 8;
+                    let (n_hashers, size) = (filter.hashers().as_ref().len(), filter.counters().size());
+                    while let Some((len, indices)) = read_record(&mut reader, n_hashers, size)? {
+                        apply(&mut filter, &indices);
+                        valid_len += len;
+                        logged += 1;
+                    }
+                    // Drop any torn record so new records follow the
+                    // last complete one.
+                    file.set_len(valid_len)?;
+                    file.seek(SeekFrom::Start(valid_len))?;
+                    BufWriter::new(file)
+                } else {
+                    // The log predates the checkpoint, whose contents
+                    // already include it.
+                    create_wal(&dir, generation)?
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => create_wal(&dir, generation)?,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(DurableBloomFilter {
+            filter,
+            dir,
+            wal,
+            generation,
+            logged,
+            checkpoint_interval: 1 << 20,
+            sync_on_write: false,
+            sync_interval: None,
+            last_sync: Instant::now(),
+            indices: Vec::new(),
+            record: Vec::new(),
+        })
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &SimpleBloomFilter<B, S, V> {
+        &self.filter
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the filter, including the buffered log.
+    pub fn heap_size_bytes(&self) -> usize {
+        self.filter.heap_size_bytes()
+            + self.wal.capacity()
+            + self.indices.capacity() * std::mem::size_of::<usize>()
+            + self.record.capacity()
+    }
+
+    /// Sets the number of logged operations after which the filter is
+    /// checkpointed automatically.
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        self.checkpoint_interval = interval;
+    }
+
+    /// Sets whether every operation is synced to disk before
+    /// returning. This is disabled by default.
+    pub fn set_sync_on_write(&mut self, sync: bool) {
+        self.sync_on_write = sync;
+    }
+
+    /// Sets the longest time for which logged operations may go
+    /// unsynced, or disables periodic syncing if `None`, which is the
+    /// default. The interval is checked whenever an operation is
+    /// logged, so operations logged just before the filter goes idle
+    /// are only persisted by the next operation or an explicit
+    /// [`sync`](DurableBloomFilter::sync).
+    pub fn set_sync_interval(&mut self, interval: Option<Duration>) {
+        self.sync_interval = interval;
+    }
+
+    /// Checks whether the filter contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.filter.contains(val)
+    }
+
+    /// Inserts `val` into the filter, logging the operation.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) -> Result<(), Error> {
+        self.indices.clear();
+        self.indices.extend(self.filter.indices(val));
+        self.log()?;
+        apply(&mut self.filter, &self.indices);
+        if self.logged >= self.checkpoint_interval {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Inserts `val` unless the filter already contains it, returning
+    /// whether it was inserted. Values which are already present are
+    /// not logged, so repeatedly inserting the same values does not
+    /// grow the log.
+    pub fn insert_if_absent<T: Hash + ?Sized>(&mut self, val: &T) -> Result<bool, Error> {
+        if self.filter.contains(val) {
+            return Ok(false);
+        }
+        self.insert(val)?;
+        Ok(true)
+    }
+
+    /// Clears the filter and checkpoints it immediately.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.filter.clear();
+        self.checkpoint()
+    }
+
+    /// Writes all logged operations to disk and waits for them to be
+    /// persisted.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.flush()?;
+        self.wal.get_ref().sync_data()?;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Writes the whole filter to a new checkpoint and starts a new
+    /// log. The previous checkpoint stays valid until the new one is
+    /// completely written.
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        write_checkpoint(&self.dir, &self.filter, self.generation + 1)?;
+        self.generation += 1;
+        self.wal = create_wal(&self.dir, self.generation)?;
+        self.logged = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Appends a record of the operation on `self.indices` to the
+    /// log.
+    fn log(&mut self) -> io::Result<()> {
+        let record = &mut self.record;
+        record.clear();
+        record.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        for &i in self.indices.iter() {
+            record.extend_from_slice(&(i as u64).to_le_bytes());
+        }
+        record.extend_from_slice(&crc32fast::hash(record).to_le_bytes());
+        self.wal.write_all(record)?;
+        self.logged += 1;
+        let sync_due = self
+            .sync_interval
+            .is_some_and(|interval| self.last_sync.elapsed() >= interval);
+        if self.sync_on_write || sync_due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+}
+
+fn apply<B, S, V>(filter: &mut SimpleBloomFilter<B, S, V>, indices: &[usize])
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    filter.record_inserts(1);
+    for &i in indices {
+        filter.increment_counter(i);
+    }
+}
+
+/// Reads the next complete record from the log, returning its length
+/// in bytes and its indices, or `None` at the end of the valid part
+/// of the log. Each record holds one index per hasher.
+fn read_record<R: Read>(r: &mut R, n_hashers: usize, size: usize) -> io::Result<Option<(u64, Vec<usize>)>> {
+    let mut head = [0u8; 4];
+    if !read_full(r, &mut head)? {
+        return Ok(None);
+    }
+    let count = u32::from_le_bytes(head) as usize;
+    if count != n_hashers {
+        return Ok(None);
+    }
+    let mut body = vec![0u8; 8 * count + 4];
+    if !read_full(r, &mut body)? {
+        return Ok(None);
+    }
+
+    let (data, crc) = body.split_at(8 * count);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&head);
+    hasher.update(data);
+    if hasher.finalize() != u32::from_le_bytes(crc.try_into().unwrap()) {
+        return Ok(None);
+    }
+
+    let indices = data
+        .chunks_exact(8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+        .collect::<Vec<_>>();
+    if indices.iter().any(|&i| i >= size) {
+        return Ok(None);
+    }
+    Ok(Some((4 + body.len() as u64, indices)))
+}
+
+/// Fills `buf`, returning `false` if the input ends first.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    match r.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_checkpoint<B, S, V>(dir: &Path, filter: &SimpleBloomFilter<B, S, V>, generation: u64) -> Result<(), Error>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    let tmp = dir.join(CHECKPOINT_TMP);
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    w.write_all(&generation.to_le_bytes())?;
+    filter.write_to(&mut w)?;
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, dir.join(CHECKPOINT))?;
+    sync_dir(dir)?;
+    Ok(())
+}
+
+fn create_wal(dir: &Path, generation: u64) -> io::Result<BufWriter<File>> {
+    let tmp = dir.join(WAL_TMP);
+    let mut file = File::create(&tmp)?;
+    file.write_all(&generation.to_le_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(WAL))?;
+    sync_dir(dir)?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(unix)]
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,226 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::serialize::SerializableSet;
+use crate::traits::set::*;
+use std::io::{self, Read, Write};
+
+/// A binary [`BloomSet`] which can be cleared in constant time.
+///
+/// Each 64-bit word of the bitmap is tagged with the epoch in which
+/// it was last written, and words from earlier epochs read as zero.
+/// [`clear`](BloomSet::clear) therefore only starts a new epoch; the
+/// words are actually zeroed when they are next written, or all at
+/// once every 65535 clears when the epoch counter wraps around. The
+/// tags take up a quarter as much memory as the bitmap itself.
+///
+/// `EpochSet` is serialized in the same format as a
+/// [`BitBox`](bitvec::boxed::BitBox), so either can be read from
+/// data written by the other.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, EpochSet, SimpleBloomFilter};
+///
+/// let mut filter: SimpleBloomFilter<EpochSet> = SimpleBloomFilter::new(10, 2000);
+/// filter.insert(&48);
+/// assert!(filter.contains(&48));
+/// filter.clear();
+/// assert!(!filter.contains(&48));
+/// ```
+#[derive(Debug, Clone)]
+pub struct EpochSet {
+    words: Box<[u64]>,
+    epochs: Box<[u16]>,
+    epoch: u16,
+    len: usize,
+}
+
+impl EpochSet {
+    /// Returns the word with index `w`, as of the current epoch.
+    fn word(&self, w: usize) -> u64 {
+        if self.epochs[w] == self.epoch {
+            self.words[w]
+        } else {
+            0
+        }
+    }
+
+    /// Returns the word with index `w`, zeroing it first if it was
+    /// last written in an earlier epoch.
+    fn word_mut(&mut self, w: usize) -> &mut u64 {
+        if self.epochs[w] != self.epoch {
+            self.epochs[w] = self.epoch;
+            self.words[w] = 0;
+        }
+        &mut self.words[w]
+    }
+}
+
+impl PartialEq for EpochSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && (0..self.words.len()).all(|w| self.word(w) == other.word(w))
+    }
+}
+
+impl Eq for EpochSet {}
+
+impl BloomSetRead for EpochSet {
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    fn query(&self, index: usize) -> bool {
+        assert!(index < self.len, "index out of bounds");
+        self.word(index / 64) & (1 << (index % 64)) != 0
+    }
+
+    fn count_ones(&self) -> usize {
+        (0..self.words.len())
+            .map(|w| self.word(w).count_ones() as usize)
+            .sum()
+    }
+
+    fn is_clear(&self) -> bool {
+        (0..self.words.len()).all(|w| self.word(w) == 0)
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.words) + std::mem::size_of_val(&*self.epochs)
+    }
+
+    fn prefetch(&self, index: usize) {
+        if let (Some(word), Some(epoch)) = (self.words.get(index / 64), self.epochs.get(index / 64)) {
+            prefetch_read(epoch);
+            prefetch_read(word);
+        }
+    }
+}
+
+impl BloomSet for EpochSet {
+    fn new(count: usize) -> Self {
+        let n_words = count.div_ceil(64);
+        EpochSet {
+            words: vec![0; n_words].into_boxed_slice(),
+            epochs: vec![0; n_words].into_boxed_slice(),
+            epoch: 0,
+            len: count,
+        }
+    }
+
+    fn increment(&mut self, index: usize) {
+        assert!(index < self.len, "index out of bounds");
+        *self.word_mut(index / 64) |= 1 << (index % 64);
+    }
+
+    fn clear(&mut self) {
+        if self.epoch == u16::MAX {
+            self.words.fill(0);
+            self.epochs.fill(0);
+            self.epoch = 0;
+        } else {
+            self.epoch += 1;
+        }
+    }
+}
+
+impl BinaryBloomSet for EpochSet {
+    fn union(&mut self, other: &Self) {
+        for w in 0..self.words.len().min(other.words.len()) {
+            let bits = other.word(w);
+            if bits != 0 {
+                *self.word_mut(w) |= bits;
+            }
+        }
+    }
+
+    fn intersect(&mut self, other: &Self) {
+        for w in 0..self.words.len().min(other.words.len()) {
+            let bits = self.word(w) & other.word(w);
+            *self.word_mut(w) = bits;
+        }
+    }
+
+    fn symmetric_difference(&mut self, other: &Self) {
+        for w in 0..self.words.len().min(other.words.len()) {
+            let bits = other.word(w);
+            if bits != 0 {
+                *self.word_mut(w) ^= bits;
+            }
+        }
+    }
+
+    fn union_count_ones(&self, other: &Self) -> usize {
+        (0..self.words.len().min(other.words.len()))
+            .map(|w| (self.word(w) | other.word(w)).count_ones() as usize)
+            .sum()
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.len == other.len
+            && (0..self.words.len()).all(|w| {
+                let b = other.word(w);
+                self.word(w) | b == b
+            })
+    }
+}
+
+/// Bits are written like those of a
+/// [`BitBox`](bitvec::boxed::BitBox): packed into bytes, least
+/// significant bit first.
+impl SerializableSet for EpochSet {
+    const KIND: u8 = 0;
+
+    fn serialized_len(&self) -> usize {
+        self.len.div_ceil(8)
+    }
+
+    fn write_counters<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut remaining = self.len.div_ceil(8);
+        for i in 0..self.words.len() {
+            let bytes = self.word(i).to_le_bytes();
+            let n = remaining.min(bytes.len());
+            w.write_all(&bytes[..n])?;
+            remaining -= n;
+        }
+        Ok(())
+    }
+
+    fn read_counters<R: Read>(size: usize, r: &mut R) -> io::Result<Self> {
+        // The words grow as they are read, so that a corrupt size
+        // fails with an early end of input rather than a huge
+        // allocation.
+        let mut words = Vec::new();
+        let mut remaining = size.div_ceil(8);
+        while remaining > 0 {
+            let mut bytes = [0; 8];
+            let n = remaining.min(bytes.len());
+            r.read_exact(&mut bytes[..n])?;
+            words.push(u64::from_le_bytes(bytes));
+            remaining -= n;
+        }
+        // Drop any padding bits, which never read as set.
+        if !size.is_multiple_of(64) {
+            if let Some(last) = words.last_mut() {
+                *last &= (1 << (size % 64)) - 1;
+            }
+        }
+        Ok(EpochSet {
+            epochs: vec![0; words.len()].into_boxed_slice(),
+            words: words.into_boxed_slice(),
+            epoch: 0,
+            len: size,
+        })
+    }
+}
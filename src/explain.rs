@@ -0,0 +1,86 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+/// A counter probed when looking up a value, as reported by an
+/// [`Explain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Probe<C> {
+    /// The index of the counter in the filter's set.
+    pub index: usize,
+    /// The value of the counter: whether the bit is set, or its count
+    /// for spectral sets.
+    pub value: C,
+}
+
+/// The counters which a filter probes when looking up a value, in
+/// the order its hashers produce them. Returned by methods such as
+/// [`SimpleBloomFilter::explain`](crate::SimpleBloomFilter::explain),
+/// it shows which counters made a false positive look present, and
+/// two deployments hash values identically exactly when they probe
+/// the same indices.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+/// f.insert(&48);
+///
+/// let explain = f.explain(&48);
+/// assert_eq!(explain.probes.len(), 10);
+/// assert!(explain.contains());
+/// assert!(f.explain(&49).unset().count() > 0 || f.contains(&49));
+/// println!("{}", explain);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Explain<C = bool> {
+    /// The probed counters.
+    pub probes: Vec<Probe<C>>,
+}
+
+impl<C> Explain<C> {
+    /// Returns the indices of the probed counters.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.probes.iter().map(|p| p.index)
+    }
+}
+
+impl Explain<bool> {
+    /// Returns whether every probed bit is set, i.e. whether the
+    /// filter reports that it contains the value.
+    pub fn contains(&self) -> bool {
+        self.probes.iter().all(|p| p.value)
+    }
+
+    /// Returns the probes whose bits are not set, any one of which
+    /// rules the value out.
+    pub fn unset(&self) -> impl Iterator<Item = &Probe<bool>> + '_ {
+        self.probes.iter().filter(|p| !p.value)
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for Explain<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, p) in self.probes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "probe {}: counter {} = {}", i, p.index, p.value)?;
+        }
+        Ok(())
+    }
+}
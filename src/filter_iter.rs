@@ -0,0 +1,95 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use std::hash::Hash;
+use std::iter::FusedIterator;
+
+/// Extension methods for filtering iterators with a Bloom filter.
+pub trait FilterIterExt: Iterator + Sized {
+    /// Returns an iterator over the items of `self` which `filter`
+    /// does not contain, in order. Items are checked as they are
+    /// reached, so `self` may be infinite.
+    ///
+    /// Since the filter may report items it does not contain as
+    /// present, some items which were never inserted may be dropped,
+    /// but items which were inserted never pass.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, FilterIterExt, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut processed: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 7, 1000);
+    /// processed.extend(["a", "c"]);
+    ///
+    /// let todo: Vec<&str> = ["a", "b", "c", "d"].into_iter().filter_not_in(&processed).collect();
+    /// assert_eq!(todo, ["b", "d"]);
+    /// ```
+    fn filter_not_in<F: BloomFilter>(self, filter: &F) -> FilterNotIn<'_, Self, F>
+    where
+        Self::Item: Hash,
+    {
+        FilterNotIn { iter: self, filter }
+    }
+}
+
+impl<I: Iterator> FilterIterExt for I {}
+
+/// An iterator over the items of another iterator which a filter
+/// does not contain, created by
+/// [`filter_not_in`](FilterIterExt::filter_not_in).
+#[derive(Debug, Clone)]
+pub struct FilterNotIn<'a, I, F> {
+    iter: I,
+    filter: &'a F,
+}
+
+impl<I, F> Iterator for FilterNotIn<'_, I, F>
+where
+    I: Iterator,
+    I::Item: Hash,
+    F: BloomFilter,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let filter = self.filter;
+        self.iter.find(|item| !filter.contains(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<I, F> DoubleEndedIterator for FilterNotIn<'_, I, F>
+where
+    I: DoubleEndedIterator,
+    I::Item: Hash,
+    F: BloomFilter,
+{
+    fn next_back(&mut self) -> Option<I::Item> {
+        let filter = self.filter;
+        self.iter.rfind(|item| !filter.contains(item))
+    }
+}
+
+impl<I, F> FusedIterator for FilterNotIn<'_, I, F>
+where
+    I: FusedIterator,
+    I::Item: Hash,
+    F: BloomFilter,
+{
+}
@@ -0,0 +1,203 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::serialize::{self, SerializableHasher, SerializableSet};
+use crate::traits::filter::*;
+use crate::{SeededState, SimpleBloomFilter};
+use std::hash::{BuildHasher, Hash};
+use std::io::{Read, Write};
+
+/// A collection of independent filters, each holding the keys which a
+/// routing hash assigns to it.
+///
+/// Unlike the shards of a
+/// [`ShardedBloomFilter`](crate::ShardedBloomFilter), which share
+/// their hashers and size so that they can be merged, the filters of
+/// a `ShardedFilterMap` are separate: each can be sized, serialized,
+/// shipped and rebuilt on its own, and only the shards whose keys
+/// changed need to be. Keys are routed by the [`BuildHasher`] `R`,
+/// which must be the same wherever the shards are used, e.g. a
+/// [`SeededState`] with a fixed seed.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SeededState, ShardedFilterMap, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>>;
+///
+/// let router = SeededState::from_seed(48);
+/// let mut map = ShardedFilterMap::new(4, router, |_| Filter::with_seed(1, 7, 2000));
+/// map.extend(0..400);
+///
+/// // Rebuild the shard of key 48 without it, leaving the others.
+/// let shard = map.shard_index(&48);
+/// map.rebuild_shard(shard, (0..400).filter(|&x| x != 48));
+/// assert!(!map.contains(&48));
+/// assert!((0..400).filter(|&x| x != 48).all(|x| map.contains(&x)));
+///
+/// // Ship only that shard to a replica.
+/// let mut replica = ShardedFilterMap::new(4, router, |_| Filter::with_seed(1, 7, 2000));
+/// replica.extend(0..400);
+/// let mut bytes = Vec::new();
+/// map.write_shard(shard, &mut bytes).unwrap();
+/// replica.read_shard(shard, &bytes[..]).unwrap();
+/// assert!(!replica.contains(&48));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardedFilterMap<F, R = SeededState> {
+    shards: Box<[F]>,
+    router: R,
+}
+
+impl<F, R> ShardedFilterMap<F, R>
+where
+    F: BloomFilter,
+    R: BuildHasher,
+{
+    /// Creates a `ShardedFilterMap` with `n_shards` shards, the
+    /// `i`-th of which is created by `make_shard(i)`, routing keys
+    /// with `router`.
+    ///
+    /// # Panics
+    /// Panics if `n_shards` is zero.
+    pub fn new(n_shards: usize, router: R, make_shard: impl FnMut(usize) -> F) -> Self {
+        ShardedFilterMap::from_shards((0..n_shards).map(make_shard).collect(), router)
+    }
+
+    /// Creates a `ShardedFilterMap` from existing shards, which must
+    /// have been filled using the same `router`.
+    ///
+    /// # Panics
+    /// Panics if `shards` is empty.
+    pub fn from_shards(shards: Vec<F>, router: R) -> Self {
+        assert!(!shards.is_empty(), "a ShardedFilterMap needs at least one shard");
+        ShardedFilterMap {
+            shards: shards.into_boxed_slice(),
+            router,
+        }
+    }
+
+    /// Returns the shards and the router.
+    pub fn into_inner(self) -> (Vec<F>, R) {
+        (self.shards.into_vec(), self.router)
+    }
+
+    pub fn router(&self) -> &R {
+        &self.router
+    }
+
+    pub fn shards(&self) -> &[F] {
+        &self.shards
+    }
+
+    /// Returns the shard with index `index`, e.g. to insert keys
+    /// which are known to be routed to it.
+    pub fn shard_mut(&mut self, index: usize) -> &mut F {
+        &mut self.shards[index]
+    }
+
+    /// Returns the index of the shard which `key` is routed to.
+    pub fn shard_index<T: Hash + ?Sized>(&self, key: &T) -> usize {
+        crate::hash::reduce(self.router.hash_one(key), self.shards.len())
+    }
+
+    /// Inserts `key` into the shard it is routed to.
+    pub fn insert<T: Hash + ?Sized>(&mut self, key: &T) {
+        let i = self.shard_index(key);
+        self.shards[i].insert(key);
+    }
+
+    /// Checks whether the shard `key` is routed to contains `key`.
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        self.shards[self.shard_index(key)].contains(key)
+    }
+
+    /// Replaces the shard with index `index` by `shard`, returning the
+    /// old one.
+    pub fn replace_shard(&mut self, index: usize, shard: F) -> F {
+        std::mem::replace(&mut self.shards[index], shard)
+    }
+
+    /// Clears the shard with index `index` and inserts those of
+    /// `keys` which are routed to it, e.g. all current keys after
+    /// some were deleted. Keys routed to other shards are skipped, so
+    /// the whole key set can be passed.
+    pub fn rebuild_shard<T, I>(&mut self, index: usize, keys: I)
+    where
+        T: Hash,
+        I: IntoIterator<Item = T>,
+    {
+        self.shards[index].clear();
+        for key in keys {
+            if self.shard_index(&key) == index {
+                self.shards[index].insert(&key);
+            }
+        }
+    }
+
+    /// Clears every shard.
+    pub fn clear(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard.clear();
+        }
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the shards.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.shards) + self.shards.iter().map(F::heap_size_bytes).sum::<usize>()
+    }
+}
+
+impl<B, S, V, R> ShardedFilterMap<SimpleBloomFilter<B, S, V>, R>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+    R: BuildHasher,
+{
+    /// Serializes the shard with index `index` to `w`. See
+    /// [`SimpleBloomFilter::write_to`].
+    pub fn write_shard<W: Write>(&self, index: usize, w: W) -> Result<(), serialize::Error> {
+        self.shards[index].write_to(w)
+    }
+
+    /// Replaces the shard with index `index` by one read from `r`, as
+    /// written by [`write_shard`](ShardedFilterMap::write_shard). The
+    /// shard is left unchanged if reading fails.
+    pub fn read_shard<Rd: Read>(&mut self, index: usize, r: Rd) -> Result<(), serialize::Error>
+    where
+        V: FromIterator<S>,
+    {
+        self.shards[index] = SimpleBloomFilter::read_from(r)?;
+        Ok(())
+    }
+}
+
+impl<A, F, R> Extend<A> for ShardedFilterMap<F, R>
+where
+    A: Hash,
+    F: BloomFilter,
+    R: BuildHasher,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for key in iter {
+            self.insert(&key);
+        }
+    }
+}
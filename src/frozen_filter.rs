@@ -0,0 +1,121 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{SeededState, SimpleBloomFilter};
+use bitvec::vec::BitVec;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+/// An immutable [`SimpleBloomFilter`] which can be shared cheaply.
+///
+/// The filter is stored behind an [`Arc`], so cloning a
+/// `FrozenBloomFilter` only increments a reference count, and any
+/// number of threads can query their clones without synchronization.
+/// Frozen filters are created with
+/// [`SimpleBloomFilter::freeze`] (or [`From`]), and can be turned
+/// back into mutable filters with
+/// [`into_filter`](FrozenBloomFilter::into_filter).
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+/// use bitvec::prelude::*;
+/// use std::thread;
+///
+/// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+/// let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, _> =
+///     SimpleBloomFilter::with_hashers(hashers, 2000);
+/// filter.extend(0..100);
+///
+/// let frozen = filter.freeze();
+/// let readers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let frozen = frozen.clone();
+///         thread::spawn(move || (0..100).all(|x| frozen.contains(&x)))
+///     })
+///     .collect();
+/// assert!(readers.into_iter().all(|r| r.join().unwrap()));
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct FrozenBloomFilter<B, S = SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    inner: Arc<SimpleBloomFilter<B, S, V>>,
+}
+
+impl<B, S, V> Clone for FrozenBloomFilter<B, S, V>
+where
+    V: AsRef<[S]>,
+{
+    fn clone(&self) -> Self {
+        FrozenBloomFilter {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<B, S, V> FrozenBloomFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Returns the underlying filter, which can be used for any
+    /// queries not provided by `FrozenBloomFilter` itself.
+    pub fn filter(&self) -> &SimpleBloomFilter<B, S, V> {
+        &self.inner
+    }
+
+    /// Returns the underlying filter, cloning it if other clones of
+    /// this `FrozenBloomFilter` still exist.
+    pub fn into_filter(self) -> SimpleBloomFilter<B, S, V>
+    where
+        SimpleBloomFilter<B, S, V>: Clone,
+    {
+        Arc::try_unwrap(self.inner).unwrap_or_else(|inner| (*inner).clone())
+    }
+
+    pub fn hashers(&self) -> &V {
+        self.inner.hashers()
+    }
+
+    pub fn counters(&self) -> &B {
+        self.inner.counters()
+    }
+
+    /// Checks whether the set contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.inner.contains(val)
+    }
+
+    /// Checks whether the set contains each value in `vals`. See
+    /// [`BloomFilter::contains_batch`].
+    pub fn contains_batch<T: Hash>(&self, vals: &[T]) -> BitVec {
+        self.inner.contains_batch(vals)
+    }
+}
+
+impl<B, S, V> From<SimpleBloomFilter<B, S, V>> for FrozenBloomFilter<B, S, V>
+where
+    V: AsRef<[S]>,
+{
+    fn from(filter: SimpleBloomFilter<B, S, V>) -> Self {
+        FrozenBloomFilter {
+            inner: Arc::new(filter),
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Golomb-coded sets, including the compact block filters of
+//! Bitcoin's [BIP-158].
+//!
+//! A [`GcsFilter`] hashes each element uniformly into `0..N * M`,
+//! where `N` is the number of elements and `1 / M` the false positive
+//! rate, and stores the sorted hashes as their differences,
+//! Golomb-Rice coded with parameter `P`. This takes about `P + 2`
+//! bits per element, less than a Bloom filter with the same false
+//! positive rate, but the filter is immutable, and queries decode it
+//! from the start. Elements are hashed with SipHash-2-4 under a
+//! 128-bit key.
+//!
+//! BIP-158's basic filters use `P = 19` and `M = 784931`, keyed by
+//! the first 16 bytes of the block hash, over the output scripts of
+//! a block and the scripts of the outputs it spends.
+//!
+//! # Example
+//! The basic filter of the testnet genesis block, from the BIP-158
+//! test vectors:
+//! ```
+//! use generic_bloom::gcs::GcsFilter;
+//! # fn hex(s: &str) -> Vec<u8> {
+//! #     (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+//! # }
+//!
+//! // Block hashes are usually displayed in reverse byte order.
+//! let mut block_hash: [u8; 32] =
+//!     hex("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943").try_into().unwrap();
+//! block_hash.reverse();
+//! let coinbase_output = hex(
+//!     "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec1\
+//!      12de5c384df7ba0b8d578a4c702b6bf11d5fac",
+//! );
+//!
+//! let filter = GcsFilter::bip158_basic(&block_hash, [&coinbase_output[..]], []);
+//! assert_eq!(filter.to_bytes(), hex("019dfca8"));
+//! assert!(filter.contains(&coinbase_output));
+//!
+//! let received = GcsFilter::bip158_from_bytes(&block_hash, &hex("019dfca8")).unwrap();
+//! assert_eq!(received, filter);
+//! assert!(received.match_any([&b"unrelated script"[..], &coinbase_output]));
+//! ```
+//!
+//! [BIP-158]: https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki
+
+use crate::compat::{read_compact_size, write_compact_size};
+use siphasher::sip::SipHasher24;
+use std::hash::Hasher;
+
+/// The Golomb-Rice parameter of BIP-158 basic filters.
+pub const BIP158_P: u8 = 19;
+
+/// The inverse false positive rate of BIP-158 basic filters.
+pub const BIP158_M: u64 = 784_931;
+
+/// The `OP_RETURN` opcode, which starts unspendable outputs.
+const OP_RETURN: u8 = 0x6a;
+
+/// A Golomb-coded set. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GcsFilter {
+    key: (u64, u64),
+    p: u8,
+    m: u64,
+    n: u64,
+    data: Vec<u8>,
+}
+
+impl GcsFilter {
+    /// Creates a filter of `elements`, hashed with SipHash-2-4 keyed
+    /// by `key`, with Golomb-Rice parameter `p` and false positive
+    /// rate `1 / m`. Repeated elements are only stored once.
+    ///
+    /// # Panics
+    /// Panics if `p` is more than 32, or there are `2^32` or more
+    /// distinct elements.
+    pub fn new<I, E>(key: (u64, u64), p: u8, m: u64, elements: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        assert!(p <= 32, "Golomb-Rice parameter must be at most 32");
+        let mut elements: Vec<E> = elements.into_iter().collect();
+        elements.sort_unstable_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        elements.dedup_by(|a, b| a.as_ref() == b.as_ref());
+        let n = u32::try_from(elements.len()).expect("a GCS filter holds fewer than 2^32 elements") as u64;
+
+        let mut filter = GcsFilter {
+            key,
+            p,
+            m,
+            n,
+            data: Vec::new(),
+        };
+        let mut hashes: Vec<u64> = elements.iter().map(|e| filter.hash_to_range(e.as_ref())).collect();
+        hashes.sort_unstable();
+
+        let mut writer = BitWriter::default();
+        let mut last = 0;
+        for hash in hashes {
+            let delta = hash - last;
+            last = hash;
+            // The quotient in unary, then the remainder in `p` bits.
+            for _ in 0..delta >> p {
+                writer.write_bits(1, 1);
+            }
+            writer.write_bits(0, 1);
+            writer.write_bits(delta, p);
+        }
+        filter.data = writer.bytes;
+        filter
+    }
+
+    /// Reads a filter serialized by [`to_bytes`](GcsFilter::to_bytes)
+    /// with the given parameters. Returns `None` if it is truncated
+    /// or has bytes after its elements.
+    pub fn from_bytes(key: (u64, u64), p: u8, m: u64, bytes: &[u8]) -> Option<Self> {
+        let (n, data) = read_compact_size(bytes)?;
+        if p > 32 || n > u32::MAX as u64 {
+            return None;
+        }
+        let filter = GcsFilter {
+            key,
+            p,
+            m,
+            n,
+            data: data.to_vec(),
+        };
+        let mut reader = BitReader::new(&filter.data);
+        for _ in 0..n {
+            filter.read_delta(&mut reader)?;
+        }
+        // Only padding may follow the last element.
+        (reader.pos.div_ceil(8) == filter.data.len()).then_some(filter)
+    }
+
+    /// Creates a BIP-158 filter of `elements` for the block with hash
+    /// `block_hash`, in the byte order blocks are serialized in.
+    pub fn bip158<I, E>(block_hash: &[u8; 32], elements: I) -> Self
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        GcsFilter::new(bip158_key(block_hash), BIP158_P, BIP158_M, elements)
+    }
+
+    /// Creates the BIP-158 basic filter of the block with hash
+    /// `block_hash`, from the output scripts of its transactions and
+    /// the scripts of the outputs they spend. Empty scripts and
+    /// `OP_RETURN` outputs are left out, as BIP-158 specifies.
+    pub fn bip158_basic<'s, O, S>(block_hash: &[u8; 32], output_scripts: O, spent_scripts: S) -> Self
+    where
+        O: IntoIterator<Item = &'s [u8]>,
+        S: IntoIterator<Item = &'s [u8]>,
+    {
+        let outputs = output_scripts
+            .into_iter()
+            .filter(|script| script.first().is_some_and(|&op| op != OP_RETURN));
+        let spent = spent_scripts.into_iter().filter(|script| !script.is_empty());
+        GcsFilter::bip158(block_hash, outputs.chain(spent))
+    }
+
+    /// Reads a BIP-158 filter for the block with hash `block_hash`,
+    /// as served in a `cfilter` message.
+    pub fn bip158_from_bytes(block_hash: &[u8; 32], bytes: &[u8]) -> Option<Self> {
+        GcsFilter::from_bytes(bip158_key(block_hash), BIP158_P, BIP158_M, bytes)
+    }
+
+    /// Returns the serialized filter: the number of elements, as a
+    /// Bitcoin compact-size integer, then the coded hashes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.data.len() + 9);
+        write_compact_size(&mut bytes, self.n);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn key(&self) -> (u64, u64) {
+        self.key
+    }
+
+    pub fn p(&self) -> u8 {
+        self.p
+    }
+
+    pub fn m(&self) -> u64 {
+        self.m
+    }
+
+    /// Returns the number of distinct elements in the filter.
+    pub fn len(&self) -> u64 {
+        self.n
+    }
+
+    /// Checks whether the filter holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Returns the coded hashes, without the number of elements.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Checks whether the filter contains `element`.
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.match_any([element])
+    }
+
+    /// Checks whether the filter contains any of `elements`, e.g.
+    /// the scripts a wallet watches, decoding the filter only once.
+    pub fn match_any<I, E>(&self, elements: I) -> bool
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        if self.n == 0 {
+            return false;
+        }
+        let mut queries: Vec<u64> = elements
+            .into_iter()
+            .map(|e| self.hash_to_range(e.as_ref()))
+            .collect();
+        queries.sort_unstable();
+
+        let mut reader = BitReader::new(&self.data);
+        let mut queries = queries.into_iter().peekable();
+        let mut value = 0;
+        for _ in 0..self.n {
+            let Some(delta) = self.read_delta(&mut reader) else {
+                return false;
+            };
+            value += delta;
+            while queries.next_if(|&q| q < value).is_some() {}
+            match queries.peek() {
+                None => return false,
+                Some(&q) if q == value => return true,
+                Some(_) => {}
+            }
+        }
+        false
+    }
+
+    /// Returns the hash of `element`, reduced to `0..N * M`.
+    fn hash_to_range(&self, element: &[u8]) -> u64 {
+        let mut hasher = SipHasher24::new_with_keys(self.key.0, self.key.1);
+        hasher.write(element);
+        let range = self.n as u128 * self.m as u128;
+        ((hasher.finish() as u128 * range) >> 64) as u64
+    }
+
+    /// Reads the difference between a coded hash and the previous
+    /// one, or `None` if the data ends first.
+    fn read_delta(&self, reader: &mut BitReader) -> Option<u64> {
+        let mut quotient = 0u64;
+        while reader.read_bits(1)? == 1 {
+            quotient += 1;
+        }
+        let remainder = reader.read_bits(self.p)?;
+        Some(quotient << self.p | remainder)
+    }
+}
+
+/// Returns the SipHash key BIP-158 derives from a block hash.
+fn bip158_key(block_hash: &[u8; 32]) -> (u64, u64) {
+    (
+        u64::from_le_bytes(block_hash[..8].try_into().unwrap()),
+        u64::from_le_bytes(block_hash[8..16].try_into().unwrap()),
+    )
+}
+
+/// Writes bits most significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    // Number of bits written.
+    len: usize,
+}
+
+impl BitWriter {
+    /// Writes the low `n` bits of `value`.
+    fn write_bits(&mut self, value: u64, n: u8) {
+        for i in (0..n).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            let bit = (value >> i & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+}
+
+/// Reads bits most significant first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    // Number of bits read.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader { bytes, pos: 0 }
+    }
+
+    /// Reads `n` bits, or returns `None` if fewer are left.
+    fn read_bits(&mut self, n: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..n {
+            let byte = self.bytes.get(self.pos / 8)?;
+            value = value << 1 | (byte >> (7 - self.pos % 8) & 1) as u64;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+}
@@ -0,0 +1,396 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Batch membership queries evaluated on the GPU, enabled by the
+//! `gpu` feature.
+//!
+//! A [`GpuBloomFilter`] uploads the counters of a filter to the GPU
+//! once, as a bit array, and then answers
+//! [`contains_batch`](GpuBloomFilter::contains_batch) queries with a
+//! compute shader. Keys are still hashed on the CPU, since the GPU
+//! cannot run arbitrary [`Hash`] implementations, but the random
+//! accesses to the bit array, which make large filters
+//! memory-bound on the CPU, are done by the GPU from its own, much
+//! faster, memory. The GPU is reached through [wgpu], so any backend
+//! it supports (Vulkan, Metal, DirectX 12 or OpenGL) can be used.
+//!
+//! # Example
+//! ```no_run
+//! use generic_bloom::{BloomFilter, SimpleBloomFilter};
+//! use generic_bloom::gpu::GpuBloomFilter;
+//! use bitvec::prelude::*;
+//!
+//! let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 7, 1 << 30);
+//! filter.extend(0..1_000_000);
+//!
+//! let gpu = GpuBloomFilter::new(&filter).unwrap();
+//! let candidates: Vec<u64> = (0..100_000_000).collect();
+//! let found = gpu.contains_batch(&candidates).unwrap();
+//! assert!(found[..1_000_000].all());
+//! ```
+//!
+//! [wgpu]: https://wgpu.rs
+
+use crate::hash::hash_indices;
+use crate::traits::set::BloomSet;
+use crate::{BloomFilter, SeededState, SimpleBloomFilter};
+use bitvec::vec::BitVec;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::pin::pin;
+use std::sync::{mpsc, Arc};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = r#"
+struct Params {
+    n_keys: u32,
+    n_hashers: u32,
+}
+
+@group(0) @binding(0) var<storage, read> bits: array<u32>;
+@group(0) @binding(1) var<storage, read> indices: array<u32>;
+@group(0) @binding(2) var<storage, read_write> found: array<atomic<u32>>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let key = id.x;
+    if key >= params.n_keys {
+        return;
+    }
+    let base = key * params.n_hashers;
+    for (var i = 0u; i < params.n_hashers; i++) {
+        let bit = indices[base + i];
+        if (bits[bit / 32u] & (1u << (bit % 32u))) == 0u {
+            return;
+        }
+    }
+    atomicOr(&found[key / 32u], 1u << (key % 32u));
+}
+"#;
+
+/// Number of invocations in each workgroup of the shader.
+const WORKGROUP_SIZE: usize = 256;
+
+/// Maximum number of keys in a batch, so that the counter indices of
+/// a batch take at most this many bytes on the GPU.
+const MAX_BATCH_BYTES: u64 = 64 << 20;
+
+/// Errors which can occur while setting up or querying a
+/// [`GpuBloomFilter`].
+#[derive(Debug)]
+pub enum Error {
+    /// No GPU adapter is available.
+    NoAdapter(wgpu::RequestAdapterError),
+    /// The adapter failed to provide a device.
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The filter has more counters than the GPU can hold in a
+    /// single buffer, or than can be indexed with 32 bits.
+    TooLarge(usize),
+    /// Waiting for the GPU failed.
+    Poll(wgpu::PollError),
+    /// Reading the results back from the GPU failed.
+    Map(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoAdapter(e) => write!(f, "no GPU adapter available: {}", e),
+            Error::RequestDevice(e) => write!(f, "failed to open GPU device: {}", e),
+            Error::TooLarge(size) => write!(f, "filter with {} counters does not fit on the GPU", size),
+            Error::Poll(e) => write!(f, "failed to wait for the GPU: {}", e),
+            Error::Map(e) => write!(f, "failed to read results from the GPU: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NoAdapter(e) => Some(e),
+            Error::RequestDevice(e) => Some(e),
+            Error::Poll(e) => Some(e),
+            Error::Map(e) => Some(e),
+            Error::TooLarge(_) => None,
+        }
+    }
+}
+
+/// A read-only copy of a filter held on the GPU. See the [module
+/// documentation](self).
+///
+/// The copy does not follow later changes to the filter it was
+/// created from.
+#[derive(Debug)]
+pub struct GpuBloomFilter<S = SeededState> {
+    hashers: Box<[S]>,
+    size: usize,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bits: wgpu::Buffer,
+    // Maximum number of keys in a batch.
+    max_batch: usize,
+}
+
+impl<S: BuildHasher + Clone> GpuBloomFilter<S> {
+    /// Uploads `filter` to the default GPU adapter. A counter is set
+    /// on the GPU if it indicates presence in `filter`.
+    pub fn new<B, V>(filter: &SimpleBloomFilter<B, S, V>) -> Result<Self, Error>
+    where
+        B: BloomSet,
+        V: AsRef<[S]>,
+    {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .map_err(Error::NoAdapter)?;
+        let limits = adapter.limits();
+        let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("generic-bloom"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        }))
+        .map_err(Error::RequestDevice)?;
+
+        let set = filter.counters();
+        let size = set.size();
+        let n_words = size.div_ceil(32);
+        let max_binding = limits.max_storage_buffer_binding_size.min(limits.max_buffer_size);
+        if u32::try_from(size - 1).is_err() || n_words as u64 * 4 > max_binding {
+            return Err(Error::TooLarge(size));
+        }
+        let mut words = vec![0u32; n_words];
+        for (i, present) in set.iter().enumerate() {
+            words[i / 32] |= (present as u32) << (i % 32);
+        }
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let bits = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom bits"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom contains"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("bloom contains"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let hashers: Box<[S]> = filter.hashers().as_ref().into();
+        let index_bytes = max_binding.min(MAX_BATCH_BYTES) / (4 * hashers.len() as u64);
+        let max_batch = (index_bytes as usize)
+            .min(limits.max_compute_workgroups_per_dimension as usize * WORKGROUP_SIZE)
+            // Keep batches a whole number of result words long.
+            / 32
+            * 32;
+        if max_batch == 0 {
+            return Err(Error::TooLarge(size));
+        }
+        Ok(GpuBloomFilter {
+            hashers,
+            size,
+            device,
+            queue,
+            pipeline,
+            bits,
+            max_batch,
+        })
+    }
+
+    pub fn hashers(&self) -> &[S] {
+        &self.hashers
+    }
+
+    /// Returns the number of counters.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns information about the GPU adapter in use.
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.device.adapter_info()
+    }
+
+    /// Checks which of `vals` the filter contains, as
+    /// [`BloomFilter::contains_batch`] does. Large batches are split into pieces which fit on the
+    /// GPU; each piece is hashed while the GPU works on the previous
+    /// one.
+    pub fn contains_batch<T: Hash>(&self, vals: &[T]) -> Result<BitVec, Error> {
+        let mut found = BitVec::with_capacity(vals.len());
+        let mut pending = None;
+        for batch in vals.chunks(self.max_batch) {
+            let indices: Vec<u8> = batch
+                .iter()
+                .flat_map(|val| hash_indices(&self.hashers, self.size, val))
+                .flat_map(|i| (i as u32).to_le_bytes())
+                .collect();
+            let next = self.submit(&indices, batch.len());
+            if let Some(prev) = pending.replace(next) {
+                self.read_back(prev, &mut found)?;
+            }
+        }
+        if let Some(last) = pending {
+            self.read_back(last, &mut found)?;
+        }
+        Ok(found)
+    }
+
+    /// Starts evaluating a batch of `n_keys` keys with the given
+    /// counter indices, returning the buffer the results will be
+    /// copied to.
+    fn submit(&self, indices: &[u8], n_keys: usize) -> Batch {
+        let indices = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom indices"),
+            contents: indices,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params: Vec<u8> = [n_keys as u32, self.hashers.len() as u32]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let params = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("bloom params"),
+            contents: &params,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let result_bytes = (n_keys.div_ceil(32) * 4) as u64;
+        let found = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom found"),
+            size: result_bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            // New buffers are zeroed.
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom readback"),
+            size: result_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bloom contains"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.bits.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: found.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(n_keys.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&found, 0, &readback, 0, result_bytes);
+        let (tx, rx) = mpsc::channel();
+        encoder.map_buffer_on_submit(&readback, wgpu::MapMode::Read, .., move |r| {
+            let _ = tx.send(r);
+        });
+        let submission = self.queue.submit(Some(encoder.finish()));
+        Batch {
+            readback,
+            n_keys,
+            submission,
+            mapped: rx,
+        }
+    }
+
+    /// Waits for `batch` to finish and appends its results to
+    /// `found`.
+    fn read_back(&self, batch: Batch, found: &mut BitVec) -> Result<(), Error> {
+        self.device
+            .poll(wgpu::PollType::Wait {
+                submission_index: Some(batch.submission),
+                timeout: None,
+            })
+            .map_err(Error::Poll)?;
+        // The callback has run once the submission is finished.
+        batch
+            .mapped
+            .recv()
+            .expect("map callback runs when the submission finishes")
+            .map_err(Error::Map)?;
+        let words = batch.readback.get_mapped_range(..);
+        found.extend(
+            words
+                .chunks_exact(4)
+                .flat_map(|w| {
+                    let w = u32::from_le_bytes(w.try_into().unwrap());
+                    (0..32).map(move |bit| w & (1 << bit) != 0)
+                })
+                .take(batch.n_keys),
+        );
+        Ok(())
+    }
+}
+
+/// A batch of keys being evaluated on the GPU.
+struct Batch {
+    readback: wgpu::Buffer,
+    n_keys: usize,
+    submission: wgpu::SubmissionIndex,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+/// Runs `future` to completion on the current thread. wgpu's native
+/// backends resolve their futures immediately, so this rarely waits.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! [`BuildHasher`]s with explicit, reproducible seeds.
+
+use siphasher::sip::SipHasher13;
+use std::hash::{BuildHasher, Hash};
+
+/// A [`BuildHasher`] which creates SipHash-1-3 hashers keyed by an
+/// explicit 128-bit seed. Unlike
+/// [`RandomState`](std::collections::hash_map::RandomState), the
+/// seed of a `SeededState` can be stored and restored, so filters
+/// using it hash identically across processes and can be
+/// serialized.
+///
+/// `SeededState` is the default hasher of every filter in this
+/// crate. Filters created with [`Default`] hashers get random seeds,
+/// which can still be read back with
+/// [`seed`](SeededState::seed); to reproduce a filter, create it
+/// from a known seed instead, e.g. with
+/// [`SimpleBloomFilter::with_seed`](crate::SimpleBloomFilter::with_seed).
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+/// use bitvec::prelude::*;
+///
+/// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+/// let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, _> =
+///     SimpleBloomFilter::with_hashers(hashers, 20);
+/// filter.insert(&48);
+/// assert!(filter.contains(&48));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SeededState {
+    k0: u64,
+    k1: u64,
+}
+
+impl SeededState {
+    /// Creates a new `SeededState` with the keys `k0` and `k1`.
+    pub fn new(k0: u64, k1: u64) -> Self {
+        SeededState { k0, k1 }
+    }
+
+    /// Creates a new `SeededState` from a 128-bit seed, whose low
+    /// and high halves are the keys `k0` and `k1`.
+    pub fn from_seed(seed: u128) -> Self {
+        SeededState::new(seed as u64, (seed >> 64) as u64)
+    }
+
+    /// Returns the keys of this `SeededState`.
+    pub fn keys(&self) -> (u64, u64) {
+        (self.k0, self.k1)
+    }
+
+    /// Returns the 128-bit seed of this `SeededState`, from which
+    /// [`from_seed`](SeededState::from_seed) recreates it.
+    pub fn seed(&self) -> u128 {
+        (self.k1 as u128) << 64 | self.k0 as u128
+    }
+
+    /// Returns `n` `SeededState`s whose keys are derived from those
+    /// of `self`, e.g. as the hashers of a filter. The same `self`
+    /// always gives the same states, and different states hash
+    /// independently.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::SeededState;
+    ///
+    /// let a: Vec<SeededState> = SeededState::from_seed(48).derive(3).collect();
+    /// let b: Vec<SeededState> = SeededState::from_seed(48).derive(3).collect();
+    /// assert_eq!(a, b);
+    /// assert!(a[0] != a[1] && a[1] != a[2]);
+    /// ```
+    pub fn derive(&self, n: usize) -> impl Iterator<Item = SeededState> + '_ {
+        (0..n as u64).map(move |i| SeededState::new(self.hash_one((i, 0u8)), self.hash_one((i, 1u8))))
+    }
+}
+
+/// Creates a `SeededState` with random keys. Like
+/// [`RandomState::new`](std::collections::hash_map::RandomState::new),
+/// each call gives different keys.
+impl Default for SeededState {
+    fn default() -> Self {
+        let random = std::collections::hash_map::RandomState::new();
+        SeededState::new(random.hash_one(0u8), random.hash_one(1u8))
+    }
+}
+
+impl BuildHasher for SeededState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+/// Returns the number of bytes of heap memory used by `hashers`,
+/// which is zero if they are stored inline, e.g. in an array.
+pub(crate) fn hashers_heap_size<S, V: AsRef<[S]>>(hashers: &V) -> usize {
+    let start = hashers as *const V as usize;
+    let slice = hashers.as_ref();
+    let ptr = slice.as_ptr() as usize;
+    if (start..start + std::mem::size_of::<V>()).contains(&ptr) {
+        0
+    } else {
+        std::mem::size_of_val(slice)
+    }
+}
+
+/// Value hashed by [`fingerprint`].
+const FINGERPRINT_PROBE: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Summarizes `hashers` by the hash each of them gives a fixed value,
+/// so that hashers which map values differently almost always have
+/// different fingerprints.
+pub(crate) fn fingerprint<S: BuildHasher>(hashers: &[S]) -> u64 {
+    hashers.iter().fold(hashers.len() as u64, |acc, h| {
+        acc.rotate_left(17) ^ h.hash_one(FINGERPRINT_PROBE)
+    })
+}
+
+/// Maps the 64-bit hash `hash` to an index below `size`. The
+/// reduction is done on the whole hash, rather than on a `usize`
+/// truncated from it, so that a value has the same indices on 32-bit
+/// and 64-bit targets.
+pub(crate) fn reduce(hash: u64, size: usize) -> usize {
+    (hash % size as u64) as usize
+}
+
+/// Returns the index of the counter which each of `hashers` maps
+/// `val` to, in a set with `set_size` counters.
+pub(crate) fn hash_indices<'a, S, T>(
+    hashers: &'a [S],
+    set_size: usize,
+    val: &'a T,
+) -> impl Iterator<Item = usize> + 'a
+where
+    S: BuildHasher,
+    T: Hash + ?Sized,
+{
+    hashers
+        .iter()
+        .map(move |b| reduce(b.hash_one(val), set_size))
+}
+
+/// Advances the xorshift generator `state`, returning a random value.
+/// This is for choices which only need to be spread out, such as the
+/// entries cuckoo filters move, not for hashing.
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
@@ -0,0 +1,333 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::fingerprint;
+use crate::traits::filter::*;
+use crate::SeededState;
+use std::hash::{BuildHasher, Hash};
+
+/// A HyperLogLog sketch, which estimates the number of distinct
+/// values inserted into it in a fixed amount of memory.
+///
+/// The sketch has `2^precision` one-byte registers. Each value's hash
+/// picks a register by its top `precision` bits, which records the
+/// longest run of leading zeros seen in the remaining bits. The
+/// estimate has a relative standard error of about
+/// `1.04 / sqrt(2^precision)`, e.g. 0.8% with the 16 KiB of
+/// precision 14, and small counts are estimated by linear counting
+/// of the empty registers. Values are hashed by a [`BuildHasher`],
+/// as with filters, so sketches built with the same hasher can be
+/// [`merge`](HyperLogLog::merge)d.
+///
+/// # Example
+/// ```
+/// use generic_bloom::HyperLogLog;
+///
+/// let mut hll: HyperLogLog = HyperLogLog::with_seed(1, 14);
+/// for i in 0..100_000 {
+///     hll.insert(&(i % 50_000));
+/// }
+/// assert!((hll.estimate() - 50_000.0).abs() < 50_000.0 * 0.03);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog<S = SeededState> {
+    hasher: S,
+    precision: u8,
+    registers: Box<[u8]>,
+}
+
+impl HyperLogLog<SeededState> {
+    /// Creates an empty `HyperLogLog` with `2^precision` registers,
+    /// hashing values with a [`SeededState`] created from `seed`.
+    ///
+    /// # Panics
+    /// Panics if `precision` is not between 4 and 18.
+    pub fn with_seed(seed: u128, precision: u8) -> Self {
+        HyperLogLog::with_hasher(SeededState::from_seed(seed), precision)
+    }
+}
+
+impl<S: BuildHasher> HyperLogLog<S> {
+    /// Creates an empty `HyperLogLog` with `2^precision` registers.
+    /// The [`BuildHasher`] will be initialized by
+    /// [`default`](Default::default).
+    ///
+    /// # Panics
+    /// Panics if `precision` is not between 4 and 18.
+    pub fn new(precision: u8) -> Self
+    where
+        S: Default,
+    {
+        HyperLogLog::with_hasher(S::default(), precision)
+    }
+
+    /// Creates an empty `HyperLogLog` with a specified `BuildHasher`
+    /// and `2^precision` registers.
+    ///
+    /// # Panics
+    /// Panics if `precision` is not between 4 and 18.
+    pub fn with_hasher(hasher: S, precision: u8) -> Self {
+        assert!(
+            (4..=18).contains(&precision),
+            "HyperLogLog precision must be between 4 and 18"
+        );
+        HyperLogLog {
+            hasher,
+            precision,
+            registers: vec![0; 1 << precision].into_boxed_slice(),
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Records `val`.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        let hash = self.hasher.hash_one(val);
+        let register = (hash >> (64 - self.precision)) as usize;
+        // The set bit bounds the rank when the remaining bits are
+        // all zero.
+        let rest = hash << self.precision | 1 << (self.precision - 1);
+        let rank = rest.leading_zeros() as u8 + 1;
+        let r = &mut self.registers[register];
+        *r = (*r).max(rank);
+    }
+
+    /// Estimates the number of distinct values inserted.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Returns the relative standard error of
+    /// [`estimate`](HyperLogLog::estimate).
+    pub fn standard_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    /// Checks whether no values have been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.registers.iter().all(|&r| r == 0)
+    }
+
+    /// Adds the values recorded by `other`, so that the estimate is
+    /// of the distinct values inserted into either. Returns an error,
+    /// leaving `self` unchanged, if the sketches have different
+    /// precisions or hashers.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        self.check_compatible(other)?;
+        for (r, &o) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *r = (*r).max(o);
+        }
+        Ok(())
+    }
+
+    /// Forgets every value.
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+
+    /// Returns the number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        self.registers.len()
+    }
+
+    /// Checks that `self` and `other` have the same number of
+    /// registers and hashers with the same fingerprint.
+    fn check_compatible(&self, other: &Self) -> Result<(), MismatchError> {
+        let (expected, found) = (self.registers.len(), other.registers.len());
+        if expected != found {
+            Err(MismatchError::SizeMismatch { expected, found })
+        } else if fingerprint(std::slice::from_ref(&self.hasher))
+            != fingerprint(std::slice::from_ref(&other.hasher))
+        {
+            Err(MismatchError::HasherMismatch)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<A: Hash, S: BuildHasher> Extend<A> for HyperLogLog<S> {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
+
+/// A Bloom filter with a [`HyperLogLog`] beside it, so each insertion
+/// updates both, and the same structure answers membership queries
+/// and estimates the number of distinct values.
+///
+/// Unlike [`SimpleBloomFilter::estimated_len`](crate::SimpleBloomFilter::estimated_len),
+/// which infers the count from the filter's load and becomes
+/// useless as the filter saturates, the sketch's estimate stays
+/// accurate however many values are inserted. `DistinctCountingFilter`
+/// implements [`BloomFilter`] itself, so it can be used wherever `F`
+/// was.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, DistinctCountingFilter, HyperLogLog, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut seen = DistinctCountingFilter::new(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::with_seed(1, 7, 100_000),
+///     HyperLogLog::with_seed(2, 12),
+/// );
+/// seen.extend((0..20_000).map(|i| i % 5_000));
+///
+/// assert!(seen.contains(&4_999));
+/// assert!((seen.estimated_distinct() - 5_000.0).abs() < 5_000.0 * 0.1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistinctCountingFilter<F, S = SeededState> {
+    filter: F,
+    hll: HyperLogLog<S>,
+}
+
+impl<F, S> DistinctCountingFilter<F, S>
+where
+    F: BloomFilter,
+    S: BuildHasher,
+{
+    /// Combines `filter` and `hll`, which should both be empty.
+    pub fn new(filter: F, hll: HyperLogLog<S>) -> Self {
+        DistinctCountingFilter { filter, hll }
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the sketch counting distinct values.
+    pub fn hll(&self) -> &HyperLogLog<S> {
+        &self.hll
+    }
+
+    /// Estimates the number of distinct values inserted. See
+    /// [`HyperLogLog::estimate`].
+    pub fn estimated_distinct(&self) -> f64 {
+        self.hll.estimate()
+    }
+
+    /// Returns the underlying filter and sketch.
+    pub fn into_inner(self) -> (F, HyperLogLog<S>) {
+        (self.filter, self.hll)
+    }
+}
+
+impl<F, S> BloomFilter for DistinctCountingFilter<F, S>
+where
+    F: BloomFilter,
+    S: BuildHasher,
+{
+    type Set = F::Set;
+    type Hasher = F::Hasher;
+
+    fn counters(&self) -> &F::Set {
+        self.filter.counters()
+    }
+
+    fn hashers(&self) -> &[F::Hasher] {
+        self.filter.hashers()
+    }
+
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        self.filter.insert(val);
+        self.hll.insert(val);
+    }
+
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.filter.contains(val)
+    }
+
+    fn contains_with_confidence<T: Hash + ?Sized>(&self, val: &T) -> (bool, f64) {
+        self.filter.contains_with_confidence(val)
+    }
+
+    fn clear(&mut self) {
+        self.filter.clear();
+        self.hll.clear();
+    }
+
+    fn expected_false_positive_rate(&self) -> f64 {
+        self.filter.expected_false_positive_rate()
+    }
+
+    fn lost_increments(&self) -> usize {
+        self.filter.lost_increments()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.filter.heap_size_bytes() + self.hll.heap_size_bytes()
+    }
+}
+
+/// Merges both the filters and the sketches. If either pair is
+/// incompatible, `self` is left unchanged.
+impl<F, S> MergeableFilter for DistinctCountingFilter<F, S>
+where
+    F: MergeableFilter,
+    S: BuildHasher,
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        self.hll.check_compatible(&other.hll)?;
+        self.filter.merge(&other.filter)?;
+        self.hll.merge(&other.hll)
+    }
+}
+
+impl<A, F, S> Extend<A> for DistinctCountingFilter<F, S>
+where
+    A: Hash,
+    F: BloomFilter,
+    S: BuildHasher,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
@@ -29,20 +29,153 @@
 //! use generic_bloom::{BloomFilter, SimpleBloomFilter};
 //! use bitvec::prelude::*;
 //!
-//! let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 20);
+//! let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20);
 //! filter.insert(&48);
 //! filter.insert(&32);
 //! assert!(filter.contains(&48));
 //! assert!(filter.contains(&32));
-//! // May fail if 39 happens to be a false positive
 //! assert!(!filter.contains(&39));
 //! ```
 mod simple_filter;
-pub use simple_filter::SimpleBloomFilter;
+pub use simple_filter::{InlineBloomFilter, SimpleBloomFilter};
+
+mod buffered_insert;
+pub use buffered_insert::BufferedInserter;
+
+mod frozen_filter;
+pub use frozen_filter::FrozenBloomFilter;
+
+mod cow_set;
+pub use cow_set::CowSet;
+
+mod persistent;
+pub use persistent::{PersistentBloomFilter, PersistentSet};
+
+mod concurrent_filter;
+pub use concurrent_filter::ConcurrentBloomFilter;
+
+mod sharded_filter;
+pub use sharded_filter::ShardedBloomFilter;
+
+mod rcu_filter;
+pub use rcu_filter::{RcuBloomFilter, RcuReader};
+
+mod writer_thread;
+pub use writer_thread::{ReadHandle, WriterThread};
+
+mod filter_map;
+pub use filter_map::ShardedFilterMap;
+
+mod epoch_set;
+pub use epoch_set::EpochSet;
+
+mod atomic_counters;
+pub use atomic_counters::AtomicCounters;
+
+mod block_filter;
+pub use block_filter::{Block, BlockBloomFilter};
+
+mod block_filter_set;
+pub use block_filter_set::{BlockFilterSet, BlockFilterSetBuilder};
+
+mod morton_filter;
+pub use morton_filter::MortonFilter;
+
+mod adaptive_cuckoo;
+pub use adaptive_cuckoo::AdaptiveCuckooFilter;
+
+mod vacuum_filter;
+pub use vacuum_filter::VacuumFilter;
+
+mod arena;
+pub use arena::FilterArena;
+
+mod dedup_window;
+pub use dedup_window::DedupWindow;
+
+mod time_buckets;
+pub use time_buckets::TimeBucketedFilters;
+
+mod rate_limiter;
+pub use rate_limiter::RateLimiter;
+
+mod top_k;
+pub use top_k::TopK;
+
+mod hyperloglog;
+pub use hyperloglog::{DistinctCountingFilter, HyperLogLog};
+
+mod minhash;
+pub use minhash::MinHash;
+
+mod typed_filter;
+pub use typed_filter::TypedBloomFilter;
+
+mod filter_iter;
+pub use filter_iter::{FilterIterExt, FilterNotIn};
+
+mod prefix_filter;
+pub use prefix_filter::{CappedPrefix, FixedPrefix, PrefixBloomFilter, PrefixExtractor};
+
+mod observed_filter;
+pub use observed_filter::{FilterObserver, ObservedBloomFilter};
+
+mod adaptive_filter;
+pub use adaptive_filter::AdaptiveProbeFilter;
+
+mod explain;
+pub use explain::{Explain, Probe};
+
+mod ops;
 
 pub mod traits;
 pub use traits::filter::*;
-pub use traits::set::BloomSet;
+pub use traits::set::{BloomSet, BloomSetRead};
+
+pub mod hash;
+pub use hash::SeededState;
+
+pub mod serialize;
+
+mod view;
+pub use view::BloomFilterView;
+
+pub mod sizing;
+
+pub mod counters;
+
+pub mod verify;
+
+pub mod simulate;
+
+pub mod kmer;
+
+pub mod maintenance;
+
+pub mod compat;
+
+pub mod gcs;
+
+mod durable;
+pub use durable::DurableBloomFilter;
+
+mod segmented;
+pub use segmented::SegmentedBloomFilter;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+#[cfg(feature = "prost")]
+pub mod proto;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+#[cfg(feature = "rayon")]
+mod parallel;
 
 // #[cfg(test)]
 // mod tests {
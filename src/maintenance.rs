@@ -0,0 +1,244 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Periodic maintenance of filters, such as decaying counts or
+//! rotating time windows.
+//!
+//! A [`Schedule`] counts the periods which are due at a given time,
+//! for applications which already have an event loop or tick. A
+//! [`Maintainer`] runs a task on a background thread instead, until
+//! it is stopped or dropped. In both cases, periods which were missed
+//! because the application was busy or suspended are reported
+//! together, so that e.g. counts decay by the right amount.
+//!
+//! # Example
+//! ```
+//! use generic_bloom::maintenance::Maintainer;
+//! use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+//! use std::sync::{Arc, Mutex};
+//! use std::time::Duration;
+//!
+//! let filter: Arc<Mutex<SimpleBloomFilter<Box<[u8]>>>> = Arc::new(Mutex::new(SimpleBloomFilter::new(4, 10_000)));
+//! for _ in 0..100 {
+//!     filter.lock().unwrap().insert(&"hot");
+//! }
+//!
+//! let maintainer = Maintainer::decay(Arc::clone(&filter), Duration::from_millis(1));
+//! std::thread::sleep(Duration::from_millis(50));
+//! maintainer.stop();
+//! assert!(filter.lock().unwrap().find_count(&"hot") < 100);
+//! ```
+
+use crate::{RateLimiter, SimpleBloomFilter};
+use num_traits::{Bounded, One, PrimInt, SaturatingAdd, Zero};
+use std::hash::BuildHasher;
+use std::ops::Shr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Number of halvings after which any counter of up to 64 bits is
+/// zero, so that further ones can be skipped.
+const MAX_HALVINGS: u32 = 64;
+
+/// Types whose counts can be aged by halving them.
+pub trait Decay {
+    /// Halves every count, rounding down.
+    fn decay(&mut self);
+}
+
+impl<T, S, V> Decay for SimpleBloomFilter<Box<[T]>, S, V>
+where
+    T: SaturatingAdd + One + Zero + Ord + Bounded + Clone + Shr<usize, Output = T>,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn decay(&mut self) {
+        SimpleBloomFilter::decay(self)
+    }
+}
+
+impl<T, S> Decay for RateLimiter<T, S>
+where
+    T: PrimInt + SaturatingAdd,
+    S: BuildHasher,
+{
+    fn decay(&mut self) {
+        RateLimiter::decay(self)
+    }
+}
+
+/// A fixed-rate schedule, which reports how many periods have
+/// elapsed each time it is polled.
+///
+/// # Example
+/// ```
+/// use generic_bloom::maintenance::Schedule;
+/// use std::time::{Duration, Instant};
+///
+/// let start = Instant::now();
+/// let mut schedule = Schedule::starting_at(start, Duration::from_secs(1));
+/// assert_eq!(schedule.due(start + Duration::from_millis(500)), 0);
+/// assert_eq!(schedule.due(start + Duration::from_millis(3500)), 3);
+/// assert_eq!(schedule.due(start + Duration::from_millis(3900)), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    period: Duration,
+    next: Instant,
+}
+
+impl Schedule {
+    /// Creates a schedule whose first period ends `period` from now.
+    ///
+    /// # Panics
+    /// Panics if `period` is zero.
+    pub fn new(period: Duration) -> Self {
+        Schedule::starting_at(Instant::now(), period)
+    }
+
+    /// Creates a schedule whose first period starts at `start`.
+    ///
+    /// # Panics
+    /// Panics if `period` is zero.
+    pub fn starting_at(start: Instant, period: Duration) -> Self {
+        assert!(!period.is_zero(), "the period must not be zero");
+        Schedule {
+            period,
+            next: start + period,
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns the time at which the next period ends.
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+
+    /// Returns the number of periods which have ended by `now` since
+    /// the last call, and moves the schedule past them.
+    pub fn due(&mut self, now: Instant) -> u32 {
+        if now < self.next {
+            return 0;
+        }
+        let missed = (now - self.next).as_nanos() / self.period.as_nanos();
+        let due = u32::try_from(missed + 1).unwrap_or(u32::MAX);
+        self.next += self.period * due;
+        due
+    }
+}
+
+/// A background thread running a maintenance task on a
+/// [`Schedule`]. The thread is stopped when the `Maintainer` is
+/// [`stop`](Maintainer::stop)ped or dropped.
+#[derive(Debug)]
+pub struct Maintainer {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Maintainer {
+    /// Spawns a thread calling `task` at the end of every `period`,
+    /// with the number of periods which ended since it was last
+    /// called, which is more than one if the thread fell behind.
+    ///
+    /// # Example
+    /// Rotating a [`DedupWindow`](crate::DedupWindow) in the
+    /// background, so that packets never wait for a sub-filter to be
+    /// cleared:
+    /// ```
+    /// use generic_bloom::DedupWindow;
+    /// use generic_bloom::maintenance::Maintainer;
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let window: Arc<DedupWindow> = Arc::new(DedupWindow::new(Duration::from_secs(1), 4, 1 << 16));
+    /// let rotated = Arc::clone(&window);
+    /// let maintainer = Maintainer::spawn(Duration::from_millis(250), move |_| rotated.rotate());
+    /// assert!(window.insert_if_absent(&48u64));
+    /// # maintainer.stop();
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `period` is zero.
+    pub fn spawn<F>(period: Duration, mut task: F) -> Self
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        let mut schedule = Schedule::new(period);
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            let wait = schedule.next().saturating_duration_since(Instant::now());
+            match stopped.recv_timeout(wait) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let due = schedule.due(Instant::now());
+                    if due > 0 {
+                        task(due);
+                    }
+                }
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+        Maintainer {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Spawns a thread which [`decay`](Decay::decay)s `filter` at the
+    /// end of every `period`, holding the lock only while doing so.
+    ///
+    /// # Panics
+    /// Panics if `period` is zero.
+    pub fn decay<D>(filter: Arc<Mutex<D>>, period: Duration) -> Self
+    where
+        D: Decay + Send + 'static,
+    {
+        Maintainer::spawn(period, move |due| {
+            let mut filter = filter.lock().unwrap_or_else(PoisonError::into_inner);
+            for _ in 0..due.min(MAX_HALVINGS) {
+                filter.decay();
+            }
+        })
+    }
+
+    /// Stops the thread, waiting for a running task to finish.
+    ///
+    /// # Panics
+    /// Panics if the task panicked.
+    pub fn stop(mut self) {
+        if let Err(e) = self.shutdown() {
+            std::panic::resume_unwind(e);
+        }
+    }
+
+    fn shutdown(&mut self) -> thread::Result<()> {
+        drop(self.stop.take());
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Stops the thread, ignoring any panic of the task.
+impl Drop for Maintainer {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
@@ -0,0 +1,159 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::fingerprint;
+use crate::traits::filter::MismatchError;
+use crate::SeededState;
+use std::hash::{BuildHasher, Hash};
+
+/// A MinHash signature of a set, from which the Jaccard similarity of
+/// two sets can be estimated.
+///
+/// The signature keeps, for each of `k` hashers, the smallest hash of
+/// any value inserted. Each hasher acts as a random permutation of
+/// the values, so two sets have the same minimum for a hasher with
+/// probability equal to their Jaccard similarity, and
+/// [`jaccard`](MinHash::jaccard) estimates it with a standard error
+/// of `sqrt(J * (1 - J) / k)`. Unlike
+/// [`SimpleBloomFilter::jaccard_estimate`](crate::SimpleBloomFilter::jaccard_estimate),
+/// whose error comes from estimating the sizes of both sets and their
+/// union, this error shrinks with the similarity, so dissimilar sets
+/// are told apart accurately. Signatures are only comparable if they
+/// were created with the same hashers.
+///
+/// # Example
+/// ```
+/// use generic_bloom::MinHash;
+///
+/// let mut a: MinHash = MinHash::with_seed(1, 256);
+/// let mut b: MinHash = MinHash::with_seed(1, 256);
+/// a.extend(0..10_000);
+/// b.extend(9_500..19_500);
+///
+/// // The true similarity is 500 / 19_500, about 0.026.
+/// assert!((a.jaccard(&b) - 0.026).abs() < 0.03);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHash<S = SeededState> {
+    hashers: Box<[S]>,
+    mins: Box<[u64]>,
+}
+
+impl MinHash<SeededState> {
+    /// Creates an empty signature of `k` hashes, using
+    /// [`SeededState`]s derived from `seed`.
+    pub fn with_seed(seed: u128, k: usize) -> Self {
+        MinHash::with_hashers(SeededState::from_seed(seed).derive(k).collect())
+    }
+}
+
+impl<S: BuildHasher> MinHash<S> {
+    /// Creates an empty signature of `k` hashes. The
+    /// [`BuildHasher`]s will be initialized by
+    /// [`default`](Default::default).
+    pub fn new(k: usize) -> Self
+    where
+        S: Default,
+    {
+        MinHash::with_hashers(std::iter::repeat_with(S::default).take(k).collect())
+    }
+
+    /// Creates an empty signature with one hash for each of
+    /// `hashers`.
+    ///
+    /// # Panics
+    /// Panics if `hashers` is empty.
+    pub fn with_hashers(hashers: Box<[S]>) -> Self {
+        assert!(!hashers.is_empty(), "a MinHash signature needs at least one hasher");
+        let mins = vec![u64::MAX; hashers.len()].into_boxed_slice();
+        MinHash { hashers, mins }
+    }
+
+    pub fn hashers(&self) -> &[S] {
+        &self.hashers
+    }
+
+    /// Returns the smallest hash seen by each hasher.
+    pub fn signature(&self) -> &[u64] {
+        &self.mins
+    }
+
+    /// Adds `val` to the set.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        for (min, hasher) in self.mins.iter_mut().zip(self.hashers.iter()) {
+            *min = (*min).min(hasher.hash_one(val));
+        }
+    }
+
+    /// Estimates the Jaccard similarity of the sets summarized by
+    /// `self` and `other`, the size of their intersection divided by
+    /// that of their union. Two empty sets have a similarity of 1;
+    /// the result is NaN if the signatures have different hashers.
+    pub fn jaccard(&self, other: &Self) -> f64 {
+        if self.check_compatible(other).is_err() {
+            return f64::NAN;
+        }
+        let equal = self.mins.iter().zip(other.mins.iter()).filter(|(a, b)| a == b).count();
+        equal as f64 / self.mins.len() as f64
+    }
+
+    /// Adds the values summarized by `other`, so that `self`
+    /// summarizes the union of the two sets. Returns an error,
+    /// leaving `self` unchanged, if the signatures have different
+    /// hashers.
+    pub fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        self.check_compatible(other)?;
+        for (min, &o) in self.mins.iter_mut().zip(other.mins.iter()) {
+            *min = (*min).min(o);
+        }
+        Ok(())
+    }
+
+    /// Checks whether no values have been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.mins.iter().all(|&m| m == u64::MAX)
+    }
+
+    /// Removes every value.
+    pub fn clear(&mut self) {
+        self.mins.fill(u64::MAX);
+    }
+
+    /// Returns the number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.hashers) + std::mem::size_of_val(&*self.mins)
+    }
+
+    fn check_compatible(&self, other: &Self) -> Result<(), MismatchError> {
+        let (expected, found) = (self.mins.len(), other.mins.len());
+        if expected != found {
+            Err(MismatchError::SizeMismatch { expected, found })
+        } else if fingerprint(&self.hashers) != fingerprint(&other.hashers) {
+            Err(MismatchError::HasherMismatch)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<A: Hash, S: BuildHasher> Extend<A> for MinHash<S> {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::next_random;
+use crate::SeededState;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of fingerprints a block can hold.
+const SLOTS: usize = 46;
+
+/// Number of logical buckets in each block.
+const BUCKETS: usize = 64;
+
+/// Maximum number of fingerprints in a logical bucket.
+const BUCKET_CAPACITY: usize = 3;
+
+/// Number of fingerprints moved while inserting a value before the
+/// filter is considered full.
+const MAX_KICKS: usize = 500;
+
+/// Load factor at which filters are sized to hold the requested
+/// number of values, leaving room for the last insertions to find
+/// space.
+const MAX_LOAD: f64 = 0.95;
+
+const EVEN_BITS: u128 = 0x5555_5555_5555_5555_5555_5555_5555_5555;
+
+/// A cache line of a [`MortonFilter`], holding the fingerprints of
+/// 64 logical buckets.
+///
+/// The fingerprints are stored in bucket order in the fingerprint
+/// storage array `fsa`, and the fullness counter array `fca` holds
+/// the number of fingerprints in each bucket, two bits apiece, so
+/// that buckets take only as much space as they use. The overflow
+/// tracking array `ota` records, per hash of bucket and fingerprint,
+/// whether a value whose primary bucket is in this block was moved
+/// to its alternate bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C, align(64))]
+struct MortonBlock {
+    fsa: [u8; SLOTS],
+    ota: u16,
+    fca: u128,
+}
+
+impl MortonBlock {
+    const EMPTY: MortonBlock = MortonBlock {
+        fsa: [0; SLOTS],
+        ota: 0,
+        fca: 0,
+    };
+
+    fn count(&self, bucket: usize) -> usize {
+        (self.fca >> (2 * bucket)) as usize & 3
+    }
+
+    /// Returns the total of the 2-bit counters in `fca`.
+    fn sum_counts(fca: u128) -> usize {
+        ((fca & EVEN_BITS).count_ones() + 2 * (fca & !EVEN_BITS).count_ones()) as usize
+    }
+
+    /// Returns the number of fingerprints in the block.
+    fn len(&self) -> usize {
+        MortonBlock::sum_counts(self.fca)
+    }
+
+    /// Returns the position in `fsa` of the first fingerprint of
+    /// `bucket`.
+    fn start(&self, bucket: usize) -> usize {
+        MortonBlock::sum_counts(self.fca & ((1 << (2 * bucket)) - 1))
+    }
+
+    fn bucket(&self, bucket: usize) -> &[u8] {
+        let start = self.start(bucket);
+        &self.fsa[start..start + self.count(bucket)]
+    }
+
+    fn has_room(&self, bucket: usize) -> bool {
+        self.count(bucket) < BUCKET_CAPACITY && self.len() < SLOTS
+    }
+
+    /// Adds `fp` to `bucket`, which must have room.
+    fn insert(&mut self, bucket: usize, fp: u8) {
+        let end = self.start(bucket) + self.count(bucket);
+        let len = self.len();
+        self.fsa.copy_within(end..len, end + 1);
+        self.fsa[end] = fp;
+        self.fca += 1 << (2 * bucket);
+    }
+
+    /// Removes the `i`-th fingerprint of `bucket`, returning it.
+    fn remove_at(&mut self, bucket: usize, i: usize) -> u8 {
+        let pos = self.start(bucket) + i;
+        let len = self.len();
+        let fp = self.fsa[pos];
+        self.fsa.copy_within(pos + 1..len, pos);
+        self.fsa[len - 1] = 0;
+        self.fca -= 1 << (2 * bucket);
+        fp
+    }
+
+    /// Removes one copy of `fp` from `bucket`, returning whether
+    /// there was one.
+    fn remove(&mut self, bucket: usize, fp: u8) -> bool {
+        match self.bucket(bucket).iter().position(|&f| f == fp) {
+            Some(i) => {
+                self.remove_at(bucket, i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the bit of `ota` recording that a value with
+    /// fingerprint `fp` was moved out of `bucket`.
+    fn ota_bit(bucket: usize, fp: u8) -> u16 {
+        1 << ((bucket ^ fp as usize) % 16)
+    }
+
+    /// Returns the bucket holding the fingerprint at position `pos`
+    /// in `fsa`.
+    fn bucket_at(&self, pos: usize) -> usize {
+        let mut end = 0;
+        for bucket in 0..BUCKETS {
+            end += self.count(bucket);
+            if pos < end {
+                return bucket;
+            }
+        }
+        unreachable!("position {} is past the last fingerprint", pos)
+    }
+}
+
+/// A Morton filter: a compressed cuckoo filter which supports
+/// deletion and high load factors while usually touching a single
+/// cache line per query.
+///
+/// Each value is stored as an 8-bit fingerprint in one of two logical
+/// buckets, its primary bucket or an alternate one in a different
+/// block, moving other fingerprints between their buckets to make
+/// room as in a cuckoo filter. Unlike a cuckoo filter, whose buckets
+/// have a fixed size, the buckets of a 64-byte block share its 46
+/// fingerprint slots, so space is not wasted on empty slots, and
+/// values are placed in their primary bucket whenever possible. An
+/// overflow bit per block records when a value was moved out of it,
+/// so most negative queries only read the primary block.
+///
+/// Since each block has far more logical bucket space than slots,
+/// insertions rarely fail before the filter holds nearly its
+/// [`capacity`](MortonFilter::capacity). The false positive rate at
+/// full load is about 0.5%. As with any cuckoo filter,
+/// only values which were inserted may be
+/// [`remove`](MortonFilter::remove)d, and a value inserted several
+/// times must be removed as many times. Overflow bits are not
+/// cleared by removals, so a filter which has seen many removals may
+/// read two blocks for more of its queries until it is
+/// [`clear`](MortonFilter::clear)ed.
+///
+/// # Example
+/// ```
+/// use generic_bloom::MortonFilter;
+///
+/// let mut filter: MortonFilter = MortonFilter::with_seed(1, 10_000);
+/// for i in 0..10_000 {
+///     assert!(filter.insert(&i));
+/// }
+/// assert!((0..10_000).all(|i| filter.contains(&i)));
+///
+/// assert!(filter.remove(&48));
+/// assert!(!filter.contains(&48));
+/// assert_eq!(filter.len(), 9_999);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MortonFilter<S = SeededState> {
+    hasher: S,
+    blocks: Box<[MortonBlock]>,
+    len: usize,
+    // A fingerprint which could not be placed when the filter filled
+    // up, and one of its buckets.
+    victim: Option<(usize, u8)>,
+    // State of the generator choosing fingerprints to move.
+    rng: u64,
+}
+
+impl MortonFilter<SeededState> {
+    /// Creates an empty `MortonFilter` with room for at least
+    /// `capacity` values, hashing them with a [`SeededState`] created
+    /// from `seed`.
+    pub fn with_seed(seed: u128, capacity: usize) -> Self {
+        MortonFilter::with_hasher(SeededState::from_seed(seed), capacity)
+    }
+}
+
+impl<S: BuildHasher> MortonFilter<S> {
+    /// Creates an empty `MortonFilter` with room for at least
+    /// `capacity` values. The [`BuildHasher`] will be initialized by
+    /// [`default`](Default::default).
+    pub fn new(capacity: usize) -> Self
+    where
+        S: Default,
+    {
+        MortonFilter::with_hasher(S::default(), capacity)
+    }
+
+    /// Creates an empty `MortonFilter` with a specified `BuildHasher`
+    /// and room for at least `capacity` values. The number of blocks
+    /// is rounded up to a power of two, so the actual
+    /// [`capacity`](MortonFilter::capacity) may be up to twice as
+    /// large.
+    pub fn with_hasher(hasher: S, capacity: usize) -> Self {
+        let n_blocks = (capacity as f64 / (SLOTS as f64 * MAX_LOAD))
+            .ceil()
+            .max(2.0) as usize;
+        MortonFilter {
+            hasher,
+            blocks: vec![MortonBlock::EMPTY; n_blocks.next_power_of_two()].into_boxed_slice(),
+            len: 0,
+            victim: None,
+            rng: 0x853c_49e6_748f_ea9b,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the number of values in the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the filter holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of fingerprint slots.
+    pub fn capacity(&self) -> usize {
+        self.blocks.len() * SLOTS
+    }
+
+    /// Returns the fraction of fingerprint slots in use.
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Inserts `val`, returning `false` if the filter is full, in
+    /// which case `val` was not inserted.
+    ///
+    /// When the filter fills up, the last fingerprint which could
+    /// not be placed is kept aside, so the insertion which fills it
+    /// still succeeds; later insertions fail until a value is
+    /// removed.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+        let (bucket, fp) = self.locate(val);
+        self.place(bucket, fp);
+        self.len += 1;
+        true
+    }
+
+    /// Checks whether the filter contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        let (bucket, fp) = self.locate(val);
+        let alt = self.alt_bucket(bucket, fp);
+        let block = &self.blocks[bucket / BUCKETS];
+        let slot = bucket % BUCKETS;
+        block.bucket(slot).contains(&fp)
+            || (block.ota & MortonBlock::ota_bit(slot, fp) != 0
+                && self.blocks[alt / BUCKETS].bucket(alt % BUCKETS).contains(&fp))
+            || self.victim.is_some_and(|(b, f)| f == fp && (b == bucket || b == alt))
+    }
+
+    /// Removes `val`, which must have been inserted, returning
+    /// whether its fingerprint was found.
+    pub fn remove<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let (bucket, fp) = self.locate(val);
+        let alt = self.alt_bucket(bucket, fp);
+        let slot = bucket % BUCKETS;
+        let block = &mut self.blocks[bucket / BUCKETS];
+        let overflowed = block.ota & MortonBlock::ota_bit(slot, fp) != 0;
+        let removed =
+            block.remove(slot, fp) || (overflowed && self.blocks[alt / BUCKETS].remove(alt % BUCKETS, fp));
+        if removed {
+            self.len -= 1;
+            // Make room for the fingerprint kept aside, if any.
+            if let Some((b, f)) = self.victim.take() {
+                self.place(b, f);
+            }
+            true
+        } else if self.victim.is_some_and(|(b, f)| f == fp && (b == bucket || b == alt)) {
+            self.victim = None;
+            self.len -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes every value.
+    pub fn clear(&mut self) {
+        self.blocks.fill(MortonBlock::EMPTY);
+        self.len = 0;
+        self.victim = None;
+    }
+
+    /// Returns the approximate probability that a value which was
+    /// not inserted is reported as present, given the current load:
+    /// the chance that its fingerprint matches one of those in its
+    /// primary bucket or, if the overflow bit it maps to is set, in
+    /// its alternate bucket.
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        let per_bucket = self.len as f64 / (self.blocks.len() * BUCKETS) as f64;
+        let ota_set = self.blocks.iter().map(|b| b.ota.count_ones()).sum::<u32>() as f64
+            / (self.blocks.len() * 16) as f64;
+        1.0 - (1.0 - 1.0 / 256.0f64).powf(per_bucket * (1.0 + ota_set))
+    }
+
+    /// Returns the number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.blocks)
+    }
+
+    /// Returns the primary bucket of `val` and its fingerprint.
+    fn locate<T: Hash + ?Sized>(&self, val: &T) -> (usize, u8) {
+        let hash = self.hasher.hash_one(val);
+        let bucket = hash as usize & (self.blocks.len() * BUCKETS - 1);
+        (bucket, (hash >> 56) as u8)
+    }
+
+    /// Returns the other bucket a value with fingerprint `fp` in
+    /// `bucket` can be stored in, which is in a different block.
+    fn alt_bucket(&self, bucket: usize, fp: u8) -> usize {
+        let mask = self.blocks.len() - 1;
+        let offset = ((fp as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize & mask;
+        bucket ^ (offset.max(1) * BUCKETS)
+    }
+
+    /// Stores `fp` in `bucket` or its alternate, moving other
+    /// fingerprints as needed. If no room is found, the last
+    /// fingerprint moved is kept aside as the victim.
+    fn place(&mut self, bucket: usize, mut fp: u8) {
+        if self.try_place(bucket, fp) {
+            return;
+        }
+        self.mark_overflow(bucket, fp);
+        let mut bucket = self.alt_bucket(bucket, fp);
+        if self.try_place(bucket, fp) {
+            return;
+        }
+        for _ in 0..MAX_KICKS {
+            let block = &mut self.blocks[bucket / BUCKETS];
+            let slot = bucket % BUCKETS;
+            // Evict from `slot` if it is full, or from any bucket of
+            // the block if the block is.
+            let r = next_random(&mut self.rng);
+            let from = if block.count(slot) == BUCKET_CAPACITY {
+                slot
+            } else {
+                block.bucket_at(r as usize % SLOTS)
+            };
+            let evicted = block.remove_at(from, (r >> 32) as usize % block.count(from));
+            block.insert(slot, fp);
+
+            let from = bucket - slot + from;
+            self.mark_overflow(from, evicted);
+            fp = evicted;
+            bucket = self.alt_bucket(from, fp);
+            if self.try_place(bucket, fp) {
+                return;
+            }
+        }
+        self.victim = Some((bucket, fp));
+    }
+
+    fn try_place(&mut self, bucket: usize, fp: u8) -> bool {
+        let block = &mut self.blocks[bucket / BUCKETS];
+        let slot = bucket % BUCKETS;
+        if block.has_room(slot) {
+            block.insert(slot, fp);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records that a value with fingerprint `fp` was moved out of
+    /// `bucket` to its alternate bucket.
+    fn mark_overflow(&mut self, bucket: usize, fp: u8) {
+        self.blocks[bucket / BUCKETS].ota |= MortonBlock::ota_bit(bucket % BUCKETS, fp);
+    }
+}
@@ -0,0 +1,219 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use std::hash::Hash;
+
+/// Callbacks for the operations of an [`ObservedBloomFilter`], e.g.
+/// for logging, sampling or comparing a filter against an exact set.
+/// Every method does nothing by default.
+///
+/// The methods take `&self`, since queries only borrow the filter
+/// immutably; observers which keep state should use interior
+/// mutability, such as [`Cell`](std::cell::Cell) or atomics.
+pub trait FilterObserver {
+    /// Called when `val` is inserted.
+    fn on_insert<T: Hash + ?Sized>(&self, val: &T) {
+        let _ = val;
+    }
+
+    /// Called when a query finds `val`, which may be a false
+    /// positive.
+    fn on_probable_hit<T: Hash + ?Sized>(&self, val: &T) {
+        let _ = val;
+    }
+
+    /// Called when a query does not find `val`, which is then
+    /// certainly absent.
+    fn on_definite_miss<T: Hash + ?Sized>(&self, val: &T) {
+        let _ = val;
+    }
+
+    /// Called when inserting `val` lost `lost` counter increments
+    /// because the counters were saturated. See
+    /// [`BloomFilter::lost_increments`].
+    fn on_saturation<T: Hash + ?Sized>(&self, val: &T, lost: usize) {
+        let _ = (val, lost);
+    }
+}
+
+/// An observer which ignores every operation.
+impl FilterObserver for () {}
+
+/// A Bloom filter which reports its operations to a
+/// [`FilterObserver`].
+///
+/// `ObservedBloomFilter` wraps any filter `F` and implements
+/// [`BloomFilter`] itself, so it can be used wherever `F` was. The
+/// provided operations, such as
+/// [`insert_batch`](BloomFilter::insert_batch), are made of calls to
+/// [`insert`](BloomFilter::insert) and
+/// [`contains`](BloomFilter::contains), so that the observer sees
+/// every value, but lose the optimizations of `F`.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, FilterObserver, ObservedBloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+/// use std::cell::Cell;
+/// use std::hash::Hash;
+///
+/// #[derive(Default)]
+/// struct HitRate {
+///     hits: Cell<usize>,
+///     misses: Cell<usize>,
+/// }
+///
+/// impl FilterObserver for HitRate {
+///     fn on_probable_hit<T: Hash + ?Sized>(&self, _: &T) {
+///         self.hits.set(self.hits.get() + 1);
+///     }
+///
+///     fn on_definite_miss<T: Hash + ?Sized>(&self, _: &T) {
+///         self.misses.set(self.misses.get() + 1);
+///     }
+/// }
+///
+/// let mut f = ObservedBloomFilter::new(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::new(10, 2000),
+///     HitRate::default(),
+/// );
+/// f.insert(&1);
+/// f.contains(&1);
+/// f.contains(&2);
+/// assert_eq!(f.observer().hits.get() + f.observer().misses.get(), 2);
+/// assert!(f.observer().hits.get() >= 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedBloomFilter<F, O> {
+    filter: F,
+    observer: O,
+}
+
+impl<F, O> ObservedBloomFilter<F, O>
+where
+    F: BloomFilter,
+    O: FilterObserver,
+{
+    /// Wraps `filter`, reporting its operations to `observer`.
+    pub fn new(filter: F, observer: O) -> Self {
+        ObservedBloomFilter { filter, observer }
+    }
+
+    /// Returns the underlying filter. Operations on it are not
+    /// observed.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Returns the underlying filter and the observer.
+    pub fn into_inner(self) -> (F, O) {
+        (self.filter, self.observer)
+    }
+}
+
+impl<F, O> BloomFilter for ObservedBloomFilter<F, O>
+where
+    F: BloomFilter,
+    O: FilterObserver,
+{
+    type Set = F::Set;
+    type Hasher = F::Hasher;
+
+    fn counters(&self) -> &F::Set {
+        self.filter.counters()
+    }
+
+    fn hashers(&self) -> &[F::Hasher] {
+        self.filter.hashers()
+    }
+
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        let lost = self.filter.lost_increments();
+        self.filter.insert(val);
+        self.observer.on_insert(val);
+        let lost = self.filter.lost_increments().saturating_sub(lost);
+        if lost > 0 {
+            self.observer.on_saturation(val, lost);
+        }
+    }
+
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        let found = self.filter.contains(val);
+        if found {
+            self.observer.on_probable_hit(val);
+        } else {
+            self.observer.on_definite_miss(val);
+        }
+        found
+    }
+
+    fn contains_with_confidence<T: Hash + ?Sized>(&self, val: &T) -> (bool, f64) {
+        let (found, confidence) = self.filter.contains_with_confidence(val);
+        if found {
+            self.observer.on_probable_hit(val);
+        } else {
+            self.observer.on_definite_miss(val);
+        }
+        (found, confidence)
+    }
+
+    fn clear(&mut self) {
+        self.filter.clear()
+    }
+
+    fn expected_false_positive_rate(&self) -> f64 {
+        self.filter.expected_false_positive_rate()
+    }
+
+    fn lost_increments(&self) -> usize {
+        self.filter.lost_increments()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.filter.heap_size_bytes()
+    }
+}
+
+/// Merges the underlying filters. The observer of `other` is not
+/// notified.
+impl<F, O> MergeableFilter for ObservedBloomFilter<F, O>
+where
+    F: MergeableFilter,
+    O: FilterObserver,
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        self.filter.merge(&other.filter)
+    }
+}
+
+impl<A, F, O> Extend<A> for ObservedBloomFilter<F, O>
+where
+    A: Hash,
+    F: BloomFilter,
+    O: FilterObserver,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+// Operator implementations for set algebra on filters. As with
+// `union` and `intersect`, both operands must use the same hashers
+// and number of counters.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{BlockBloomFilter, SimpleBloomFilter};
+use std::hash::BuildHasher;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
+
+/// Implements the by-reference and by-value forms of a binary set
+/// operator on `$filter` in terms of the method `$method`.
+macro_rules! impl_set_op {
+    ([$($gen:tt)*] $filter:ty where [$($bounds:tt)*],
+     $op:ident::$op_fn:ident, $assign:ident::$assign_fn:ident, $method:ident) => {
+        impl<$($gen)*> $assign<&$filter> for $filter
+        where
+            $($bounds)*
+        {
+            fn $assign_fn(&mut self, rhs: &$filter) {
+                self.$method(rhs);
+            }
+        }
+
+        impl<$($gen)*> $op<&$filter> for $filter
+        where
+            $($bounds)*
+        {
+            type Output = $filter;
+
+            fn $op_fn(mut self, rhs: &$filter) -> $filter {
+                self.$method(rhs);
+                self
+            }
+        }
+
+        impl<$($gen)*> $op<&$filter> for &$filter
+        where
+            $($bounds)*
+            $filter: Clone,
+        {
+            type Output = $filter;
+
+            fn $op_fn(self, rhs: &$filter) -> $filter {
+                self.clone().$op_fn(rhs)
+            }
+        }
+    };
+}
+
+impl_set_op!(
+    [B, S, V] SimpleBloomFilter<B, S, V> where [B: BinaryBloomSet, S: BuildHasher, V: AsRef<[S]>,],
+    BitOr::bitor, BitOrAssign::bitor_assign, union
+);
+impl_set_op!(
+    [B, S, V] SimpleBloomFilter<B, S, V> where [B: BinaryBloomSet, S: BuildHasher, V: AsRef<[S]>,],
+    BitAnd::bitand, BitAndAssign::bitand_assign, intersect
+);
+impl_set_op!(
+    [B, S, V] SimpleBloomFilter<B, S, V> where [B: BinaryBloomSet, S: BuildHasher, V: AsRef<[S]>,],
+    BitXor::bitxor, BitXorAssign::bitxor_assign, symmetric_difference
+);
+impl_set_op!(
+    [S] BlockBloomFilter<S> where [S: BuildHasher,],
+    BitOr::bitor, BitOrAssign::bitor_assign, union
+);
+impl_set_op!(
+    [S] BlockBloomFilter<S> where [S: BuildHasher,],
+    BitAnd::bitand, BitAndAssign::bitand_assign, intersect
+);
+impl_set_op!(
+    [S] BlockBloomFilter<S> where [S: BuildHasher,],
+    BitXor::bitxor, BitXorAssign::bitxor_assign, symmetric_difference
+);
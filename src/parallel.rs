@@ -0,0 +1,185 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::hash_indices;
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{ConcurrentBloomFilter, FrozenBloomFilter, SimpleBloomFilter};
+use bitvec::vec::BitVec;
+use rayon::prelude::*;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of values hashed in parallel before their indices are
+/// applied to a [`SimpleBloomFilter`], bounding the memory used for
+/// staging.
+const BATCH_SIZE: usize = 1 << 16;
+
+/// Number of words of results computed by each task of
+/// [`par_contains_batch`].
+const WORDS_PER_TASK: usize = 64;
+
+/// Checks whether `filter` contains each value in `vals`, splitting
+/// the values between tasks which each fill a run of words of the
+/// result.
+fn par_contains_batch<F, T>(filter: &F, vals: &[T]) -> BitVec
+where
+    F: BloomFilter + Sync,
+    T: Hash + Sync,
+{
+    let mut found = BitVec::repeat(false, vals.len());
+    found
+        .as_raw_mut_slice()
+        .par_chunks_mut(WORDS_PER_TASK)
+        .zip(vals.par_chunks(WORDS_PER_TASK * usize::BITS as usize))
+        .for_each(|(words, vals)| {
+            words.copy_from_slice(filter.contains_batch(vals).as_raw_slice());
+        });
+    found
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher + Sync,
+    V: AsRef<[S]>,
+{
+    /// Inserts every value in `vals`. The values are hashed in
+    /// parallel in batches, and the resulting indices are applied to
+    /// the set on the calling thread, so this works with any
+    /// [`BloomSet`]. For filters shared between threads, see
+    /// [`ConcurrentBloomFilter::par_insert_all`].
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, _> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 20_000);
+    /// let vals: Vec<u32> = (0..1000).collect();
+    /// filter.par_insert_all(&vals);
+    /// assert!(vals.iter().all(|x| filter.contains(x)));
+    /// ```
+    pub fn par_insert_all<T: Hash + Sync>(&mut self, vals: &[T]) {
+        self.record_inserts(vals.len());
+        let size = self.counters().size();
+        let mut indices = Vec::new();
+        for batch in vals.chunks(BATCH_SIZE) {
+            let hashers = self.hashers().as_ref();
+            indices.clear();
+            indices.par_extend(
+                batch
+                    .par_iter()
+                    .flat_map_iter(|val| hash_indices(hashers, size, val)),
+            );
+            for &i in indices.iter() {
+                self.increment_counter(i);
+            }
+        }
+    }
+
+    /// Checks whether the set contains each value in `vals`, in
+    /// parallel. The result holds one bit per value, in the same
+    /// order as `vals`.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let mut filter: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, _> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 200_000);
+    /// filter.extend((0..10_000).step_by(2));
+    ///
+    /// let vals: Vec<u32> = (0..10_000).collect();
+    /// let found = filter.par_contains_batch(&vals);
+    /// assert_eq!(found.len(), vals.len());
+    /// assert!(found.iter().step_by(2).all(|b| *b));
+    /// ```
+    pub fn par_contains_batch<T: Hash + Sync>(&self, vals: &[T]) -> BitVec
+    where
+        B: Sync,
+        V: Sync,
+    {
+        par_contains_batch(self, vals)
+    }
+}
+
+impl<B, S, V> ConcurrentBloomFilter<B, S, V>
+where
+    B: AtomicBloomSet,
+    S: BuildHasher + Sync,
+    V: AsRef<[S]> + Sync,
+{
+    /// Inserts every value in `vals`, hashing and setting counters
+    /// in parallel.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{ConcurrentBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    /// use std::sync::atomic::AtomicUsize;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let filter: ConcurrentBloomFilter<BitBox<AtomicUsize, Lsb0>, SeededState> =
+    ///     ConcurrentBloomFilter::with_hashers(hashers, 20_000);
+    /// filter.par_insert_all(0..1000);
+    /// assert!((0..1000).all(|x| filter.contains(&x)));
+    /// ```
+    pub fn par_insert_all<I>(&self, vals: I)
+    where
+        I: IntoParallelIterator,
+        I::Item: Hash,
+    {
+        vals.into_par_iter().for_each(|val| self.insert(&val));
+    }
+
+    /// Checks whether the set contains each value in `vals`, in
+    /// parallel. See
+    /// [`SimpleBloomFilter::par_contains_batch`].
+    pub fn par_contains_batch<T: Hash + Sync>(&self, vals: &[T]) -> BitVec {
+        par_contains_batch(self, vals)
+    }
+}
+
+impl<B, S, V> FrozenBloomFilter<B, S, V>
+where
+    B: BloomSet + Sync,
+    S: BuildHasher + Sync,
+    V: AsRef<[S]> + Sync,
+{
+    /// Checks whether the set contains each value in `vals`, in
+    /// parallel. See
+    /// [`SimpleBloomFilter::par_contains_batch`].
+    pub fn par_contains_batch<T: Hash + Sync>(&self, vals: &[T]) -> BitVec {
+        par_contains_batch(self.filter(), vals)
+    }
+}
+
+impl<A, B, S, V> ParallelExtend<A> for ConcurrentBloomFilter<B, S, V>
+where
+    A: Hash + Send,
+    B: AtomicBloomSet,
+    S: BuildHasher + Sync,
+    V: AsRef<[S]> + Sync,
+{
+    fn par_extend<I>(&mut self, par_iter: I)
+    where
+        I: IntoParallelIterator<Item = A>,
+    {
+        self.par_insert_all(par_iter)
+    }
+}
@@ -0,0 +1,260 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{SeededState, SimpleBloomFilter};
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+/// Number of 64-bit words in a leaf of a [`PersistentSet`].
+const LEAF_WORDS: usize = 8;
+
+const LEAF_BITS: usize = LEAF_WORDS * 64;
+
+/// Number of children of each branch of a [`PersistentSet`], as a
+/// power of two.
+const FANOUT_BITS: u32 = 5;
+
+const FANOUT: usize = 1 << FANOUT_BITS;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Branch([Option<Arc<Node>>; FANOUT]),
+    Leaf([u64; LEAF_WORDS]),
+}
+
+impl Node {
+    fn count_ones(&self) -> usize {
+        match self {
+            Node::Branch(children) => children.iter().flatten().map(|c| c.count_ones()).sum(),
+            Node::Leaf(words) => words.iter().map(|w| w.count_ones() as usize).sum(),
+        }
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        let children = match self {
+            Node::Branch(children) => children.iter().flatten().map(|c| c.heap_size_bytes()).sum(),
+            Node::Leaf(_) => 0,
+        };
+        std::mem::size_of::<Node>() + children
+    }
+}
+
+/// A bit set stored as a tree of reference-counted chunks, so that
+/// cloning it takes constant time and a modified clone copies only the
+/// chunks on the paths to the bits it sets.
+///
+/// Each leaf holds 512 bits, and each branch 32 children; chunks
+/// which have never been written are not allocated. Setting a bit in
+/// a set shared with clones copies one leaf and a branch per level of
+/// the tree, i.e. a few hundred bytes, however large the set is. See
+/// [`PersistentBloomFilter`] for a filter built on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersistentSet {
+    size: usize,
+    // Number of levels of branches above the leaves.
+    depth: u32,
+    root: Option<Arc<Node>>,
+}
+
+impl PersistentSet {
+    /// Checks whether `self` and `other` share all of their chunks,
+    /// e.g. because neither was modified since one was cloned from
+    /// the other.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    fn leaf(&self, index: usize) -> Option<&[u64; LEAF_WORDS]> {
+        let leaf = index / LEAF_BITS;
+        let mut node = self.root.as_deref()?;
+        for level in (0..self.depth).rev() {
+            let Node::Branch(children) = node else {
+                unreachable!("leaves are only found at the bottom level")
+            };
+            node = children[(leaf >> (level * FANOUT_BITS)) % FANOUT].as_deref()?;
+        }
+        match node {
+            Node::Leaf(words) => Some(words),
+            Node::Branch(_) => unreachable!("branches are only found above the bottom level"),
+        }
+    }
+
+    /// Returns the leaf holding the counter with index `index`,
+    /// allocating or copying the nodes on the way as needed.
+    fn leaf_mut(&mut self, index: usize) -> &mut [u64; LEAF_WORDS] {
+        let leaf = index / LEAF_BITS;
+        let mut slot = &mut self.root;
+        for level in (0..self.depth).rev() {
+            let node = slot.get_or_insert_with(|| Arc::new(Node::Branch(Default::default())));
+            let Node::Branch(children) = Arc::make_mut(node) else {
+                unreachable!("leaves are only found at the bottom level")
+            };
+            slot = &mut children[(leaf >> (level * FANOUT_BITS)) % FANOUT];
+        }
+        let node = slot.get_or_insert_with(|| Arc::new(Node::Leaf([0; LEAF_WORDS])));
+        match Arc::make_mut(node) {
+            Node::Leaf(words) => words,
+            Node::Branch(_) => unreachable!("branches are only found above the bottom level"),
+        }
+    }
+}
+
+impl BloomSetRead for PersistentSet {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn query(&self, index: usize) -> bool {
+        assert!(index < self.size, "counter {} out of bounds", index);
+        self.leaf(index)
+            .is_some_and(|words| words[index % LEAF_BITS / 64] & (1 << (index % 64)) != 0)
+    }
+
+    fn count_ones(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.count_ones())
+    }
+
+    fn is_clear(&self) -> bool {
+        self.count_ones() == 0
+    }
+
+    /// Returns the size of the allocated chunks, counting chunks
+    /// shared with clones in full.
+    fn heap_size_bytes(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.heap_size_bytes())
+    }
+}
+
+impl BloomSet for PersistentSet {
+    fn new(count: usize) -> Self {
+        let n_leaves = count.div_ceil(LEAF_BITS);
+        let mut depth = 0;
+        while FANOUT.checked_pow(depth).is_some_and(|n| n < n_leaves) {
+            depth += 1;
+        }
+        PersistentSet {
+            size: count,
+            depth,
+            root: None,
+        }
+    }
+
+    fn increment(&mut self, index: usize) {
+        assert!(index < self.size, "counter {} out of bounds", index);
+        self.leaf_mut(index)[index % LEAF_BITS / 64] |= 1 << (index % 64);
+    }
+
+    fn clear(&mut self) {
+        self.root = None;
+    }
+}
+
+/// An immutable Bloom filter whose [`insert`](PersistentBloomFilter::insert)
+/// returns a new filter, leaving the old one unchanged.
+///
+/// The versions share the chunks of their [`PersistentSet`] which
+/// differ in neither, so keeping every version, e.g. to roll back to
+/// an earlier one, costs memory in proportion to the number of
+/// insertions rather than the size of the filter, and cloning a
+/// version takes constant time.
+///
+/// # Example
+/// ```
+/// use generic_bloom::PersistentBloomFilter;
+///
+/// let empty: PersistentBloomFilter = PersistentBloomFilter::with_seed(1, 7, 1 << 24);
+/// let v1 = empty.insert(&"a");
+/// let v2 = v1.insert(&"b");
+///
+/// assert!(!empty.contains(&"a"));
+/// assert!(v1.contains(&"a") && !v1.contains(&"b"));
+/// assert!(v2.contains(&"a") && v2.contains(&"b"));
+/// // Only the chunks holding the bits of "a" and "b" are allocated.
+/// assert!(v2.heap_size_bytes() < 100_000);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersistentBloomFilter<S = SeededState> {
+    filter: SimpleBloomFilter<PersistentSet, S, Arc<[S]>>,
+}
+
+impl PersistentBloomFilter<SeededState> {
+    /// Creates an empty `PersistentBloomFilter` with `n_counters`
+    /// counters and `n_hashers` hashers
+    /// [derived](SeededState::derive) from `seed`.
+    pub fn with_seed(seed: u128, n_hashers: usize, n_counters: usize) -> Self {
+        PersistentBloomFilter {
+            filter: SimpleBloomFilter::with_seed(seed, n_hashers, n_counters),
+        }
+    }
+}
+
+impl<S: BuildHasher + Clone> PersistentBloomFilter<S> {
+    /// Creates an empty `PersistentBloomFilter` with a specified
+    /// number of counters and [`BuildHasher`]s. The `BuildHasher`s
+    /// will be initialized by [`default`](Default::default).
+    pub fn new(n_hashers: usize, n_counters: usize) -> Self
+    where
+        S: Default,
+    {
+        PersistentBloomFilter {
+            filter: SimpleBloomFilter::new(n_hashers, n_counters),
+        }
+    }
+
+    /// Returns the filter as a [`SimpleBloomFilter`], for queries not
+    /// provided by `PersistentBloomFilter` itself.
+    pub fn filter(&self) -> &SimpleBloomFilter<PersistentSet, S, Arc<[S]>> {
+        &self.filter
+    }
+
+    /// Returns a new filter containing `val` and every value in
+    /// `self`.
+    #[must_use]
+    pub fn insert<T: Hash + ?Sized>(&self, val: &T) -> Self {
+        let mut filter = self.filter.clone();
+        filter.insert(val);
+        PersistentBloomFilter { filter }
+    }
+
+    /// Returns a new filter containing `vals` and every value in
+    /// `self`, copying each chunk at most once.
+    #[must_use]
+    pub fn insert_all<T, I>(&self, vals: I) -> Self
+    where
+        T: Hash,
+        I: IntoIterator<Item = T>,
+    {
+        let mut filter = self.filter.clone();
+        filter.extend(vals);
+        PersistentBloomFilter { filter }
+    }
+
+    /// Checks whether the filter contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.filter.contains(val)
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the filter, counting chunks shared with other versions in
+    /// full.
+    pub fn heap_size_bytes(&self) -> usize {
+        self.filter.heap_size_bytes()
+    }
+}
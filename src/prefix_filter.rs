@@ -0,0 +1,189 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use std::hash::Hash;
+
+/// Chooses the prefix under which a key is inserted into a
+/// [`PrefixBloomFilter`].
+///
+/// Any function from a key to a prefix of it, such as a `fn` item
+/// taking and returning byte slices, is an extractor.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{PrefixBloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// /// The table name, up to and including the first `/`.
+/// fn table(key: &[u8]) -> Option<&[u8]> {
+///     let end = key.iter().position(|&b| b == b'/')?;
+///     Some(&key[..=end])
+/// }
+///
+/// let mut filter = PrefixBloomFilter::new(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::with_seed(1, 7, 2000),
+///     table,
+/// );
+/// filter.insert("orders/1234");
+/// assert!(filter.may_contain_prefix("orders/"));
+/// assert!(!filter.may_contain_prefix("users/"));
+/// ```
+pub trait PrefixExtractor {
+    /// Returns the prefix of `key`, or `None` if `key` has none and
+    /// should only be inserted whole.
+    fn prefix<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]>;
+}
+
+impl<F> PrefixExtractor for F
+where
+    F: Fn(&[u8]) -> Option<&[u8]>,
+{
+    fn prefix<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]> {
+        self(key)
+    }
+}
+
+/// An extractor taking the first `n` bytes of each key. Shorter keys
+/// have no prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedPrefix(pub usize);
+
+impl PrefixExtractor for FixedPrefix {
+    fn prefix<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]> {
+        key.get(..self.0)
+    }
+}
+
+/// An extractor taking up to the first `n` bytes of each key, so
+/// shorter keys are their own prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CappedPrefix(pub usize);
+
+impl PrefixExtractor for CappedPrefix {
+    fn prefix<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]> {
+        Some(&key[..self.0.min(key.len())])
+    }
+}
+
+/// The values stored in the underlying filter, which keep a whole key
+/// from being mistaken for an equal prefix.
+#[derive(Hash)]
+enum Entry<'a> {
+    Key(&'a [u8]),
+    Prefix(&'a [u8]),
+}
+
+/// A Bloom filter which answers queries for whole keys and for
+/// prefixes of keys, as needed to skip range scans over a sorted run
+/// which holds no key with a given prefix.
+///
+/// `PrefixBloomFilter` wraps any filter `F`, inserting each key both
+/// whole and under the prefix its [`PrefixExtractor`] chooses, so
+/// `F` should be sized for up to twice as many values as keys. Whole
+/// keys and prefixes are hashed differently, so a key is never found
+/// by a prefix query for the same bytes, or the reverse, except as a
+/// false positive.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{FixedPrefix, PrefixBloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut filter = PrefixBloomFilter::new(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::with_seed(1, 7, 2000),
+///     FixedPrefix(5),
+/// );
+/// filter.extend(["user:alice", "user:bob", "log"]);
+///
+/// assert!(filter.contains("user:bob"));
+/// assert!(filter.may_contain_prefix("user:"));
+/// assert!(!filter.may_contain_prefix("item:"));
+/// // Keys shorter than the prefix are only inserted whole.
+/// assert!(filter.contains("log"));
+/// assert!(!filter.may_contain_prefix("log"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixBloomFilter<F, E> {
+    filter: F,
+    extractor: E,
+}
+
+impl<F, E> PrefixBloomFilter<F, E>
+where
+    F: BloomFilter,
+    E: PrefixExtractor,
+{
+    /// Wraps `filter`, which should be empty, inserting keys under
+    /// the prefixes chosen by `extractor`.
+    pub fn new(filter: F, extractor: E) -> Self {
+        PrefixBloomFilter { filter, extractor }
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    pub fn extractor(&self) -> &E {
+        &self.extractor
+    }
+
+    /// Inserts `key`, and its prefix if it has one.
+    pub fn insert<K: AsRef<[u8]> + ?Sized>(&mut self, key: &K) {
+        let key = key.as_ref();
+        self.filter.insert(&Entry::Key(key));
+        if let Some(prefix) = self.extractor.prefix(key) {
+            self.filter.insert(&Entry::Prefix(prefix));
+        }
+    }
+
+    /// Checks whether the filter contains the whole key `key`.
+    pub fn contains<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> bool {
+        self.filter.contains(&Entry::Key(key.as_ref()))
+    }
+
+    /// Checks whether the filter contains a key with prefix `prefix`,
+    /// which must be a prefix as chosen by the extractor; e.g. with
+    /// [`FixedPrefix`], shorter or longer prefixes are never found.
+    pub fn may_contain_prefix<P: AsRef<[u8]> + ?Sized>(&self, prefix: &P) -> bool {
+        self.filter.contains(&Entry::Prefix(prefix.as_ref()))
+    }
+
+    /// Checks whether the filter is empty.
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+
+    /// Removes every key.
+    pub fn clear(&mut self) {
+        self.filter.clear()
+    }
+}
+
+impl<K, F, E> Extend<K> for PrefixBloomFilter<F, E>
+where
+    K: AsRef<[u8]>,
+    F: BloomFilter,
+    E: PrefixExtractor,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = K>,
+    {
+        for key in iter {
+            self.insert(&key);
+        }
+    }
+}
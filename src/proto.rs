@@ -0,0 +1,148 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Protocol Buffers messages for filters, enabled by the `prost`
+//! feature.
+//!
+//! The schema is in `proto/generic_bloom.proto` in the crate
+//! sources; [`BloomFilter`] is the corresponding prost message, so
+//! that no `protoc` is needed to build this crate. Filters convert to
+//! messages with [`From`] and back with [`TryFrom`].
+//!
+//! # Example
+//! ```
+//! use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+//! use generic_bloom::proto;
+//! use bitvec::prelude::*;
+//! use prost::Message;
+//!
+//! type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>>;
+//!
+//! let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+//! let mut f1: Filter = SimpleBloomFilter::with_hashers(hashers, 20);
+//! f1.insert(&48);
+//!
+//! let bytes = proto::BloomFilter::from(&f1).encode_to_vec();
+//! let f2 = Filter::try_from(proto::BloomFilter::decode(bytes.as_slice()).unwrap()).unwrap();
+//! assert_eq!(f1, f2);
+//! ```
+use crate::serialize::{Error, SerializableHasher, SerializableSet};
+use crate::traits::filter::BloomFilter as _;
+use crate::SimpleBloomFilter;
+
+/// A Bloom filter, as produced by [`SimpleBloomFilter`].
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BloomFilter {
+    /// Kind of the hashers, as given by [`SerializableHasher::KIND`].
+    #[prost(uint32, tag = "1")]
+    pub hasher_kind: u32,
+    /// Seed of each hasher, as written by
+    /// [`SerializableHasher::write_seed`].
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub hasher_seeds: Vec<Vec<u8>>,
+    /// Kind of the set, as given by [`SerializableSet::KIND`].
+    #[prost(uint32, tag = "3")]
+    pub set_kind: u32,
+    /// Number of counters in the set.
+    #[prost(uint64, tag = "4")]
+    pub counter_count: u64,
+    /// Counters, as written by [`SerializableSet::write_counters`].
+    #[prost(bytes = "vec", tag = "5")]
+    pub counters: Vec<u8>,
+}
+
+impl<B, S, V> From<&SimpleBloomFilter<B, S, V>> for BloomFilter
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    fn from(filter: &SimpleBloomFilter<B, S, V>) -> Self {
+        let hasher_seeds = filter
+            .hashers()
+            .as_ref()
+            .iter()
+            .map(|h| {
+                let mut seed = Vec::new();
+                h.write_seed(&mut seed).expect("writing to a Vec cannot fail");
+                seed
+            })
+            .collect();
+        let mut counters = Vec::new();
+        filter
+            .counters()
+            .write_counters(&mut counters)
+            .expect("writing to a Vec cannot fail");
+
+        BloomFilter {
+            hasher_kind: S::KIND.into(),
+            hasher_seeds,
+            set_kind: B::KIND.into(),
+            counter_count: filter.counters().size() as u64,
+            counters,
+        }
+    }
+}
+
+impl<B, S, V> TryFrom<BloomFilter> for SimpleBloomFilter<B, S, V>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]> + FromIterator<S>,
+{
+    type Error = Error;
+
+    fn try_from(msg: BloomFilter) -> Result<Self, Error> {
+        if msg.hasher_kind != u32::from(S::KIND) {
+            return Err(Error::HasherMismatch {
+                expected: S::KIND,
+                found: msg.hasher_kind as u8,
+            });
+        }
+        if msg.set_kind != u32::from(B::KIND) {
+            return Err(Error::SetMismatch {
+                expected: B::KIND,
+                found: msg.set_kind as u8,
+            });
+        }
+        if msg.hasher_seeds.is_empty() {
+            return Err(Error::NoHashers);
+        }
+        let size = match usize::try_from(msg.counter_count) {
+            Ok(size) if size > 0 => size,
+            _ => return Err(Error::BadCounterCount(msg.counter_count)),
+        };
+
+        let hashers = msg
+            .hasher_seeds
+            .iter()
+            .map(|seed| {
+                let mut seed = seed.as_slice();
+                let h = S::read_seed(&mut seed)?;
+                if !seed.is_empty() {
+                    return Err(Error::TrailingBytes);
+                }
+                Ok(h)
+            })
+            .collect::<Result<V, Error>>()?;
+
+        let mut counters = msg.counters.as_slice();
+        let set = B::read_counters(size, &mut counters)?;
+        if !counters.is_empty() {
+            return Err(Error::TrailingBytes);
+        }
+
+        Ok(SimpleBloomFilter::from_parts(hashers, set))
+    }
+}
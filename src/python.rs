@@ -0,0 +1,236 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Python bindings, enabled by the `python` feature.
+//!
+//! The module exposes a `BloomFilter` class backed by a bitmap and a
+//! `CountingBloomFilter` class backed by integer counters, both using
+//! [`SeededState`] hashers so that filters serialized by Rust code
+//! can be loaded and queried from Python and vice versa. Python keys
+//! are hashed the same way as the corresponding Rust types: `str` as
+//! [`str`], `bytes` as `[u8]` and `int` as [`u64`] (negative values
+//! as their two's complement).
+//!
+//! The extension module is built with [maturin](https://www.maturin.rs),
+//! e.g. `maturin build --release`, which enables this feature and
+//! builds the crate as a `cdylib` as configured in `pyproject.toml`.
+
+use crate::serialize;
+use crate::{BloomFilter, BloomFilterDelete, SeededState, SimpleBloomFilter, SpectralBloomFilter};
+use bitvec::{boxed::BitBox, order::Lsb0};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::hash::{Hash, Hasher};
+
+type Hashers = Box<[SeededState]>;
+type BitFilter = SimpleBloomFilter<BitBox<u64, Lsb0>, SeededState, Hashers>;
+type CountingFilter<T> = SimpleBloomFilter<Box<[T]>, SeededState, Hashers>;
+
+/// A key borrowed from a Python `str`, `bytes` or `int`.
+enum Key<'a> {
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    Int(i64),
+    UInt(u64),
+}
+
+impl<'a, 'py> FromPyObject<'a, 'py> for Key<'a> {
+    type Error = PyErr;
+
+    fn extract(ob: Borrowed<'a, 'py, PyAny>) -> PyResult<Self> {
+        if let Ok(s) = <&str>::extract(ob) {
+            Ok(Key::Str(s))
+        } else if let Ok(b) = <&[u8]>::extract(ob) {
+            Ok(Key::Bytes(b))
+        } else if let Ok(i) = i64::extract(ob) {
+            Ok(Key::Int(i))
+        } else if let Ok(u) = u64::extract(ob) {
+            Ok(Key::UInt(u))
+        } else {
+            Err(PyTypeError::new_err("keys must be str, bytes or int"))
+        }
+    }
+}
+
+impl Hash for Key<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Key::Str(s) => s.hash(state),
+            Key::Bytes(b) => b.hash(state),
+            Key::Int(i) => (*i as u64).hash(state),
+            Key::UInt(u) => u.hash(state),
+        }
+    }
+}
+
+fn hashers(n_hashers: usize, n_counters: usize, seed: u64) -> PyResult<Hashers> {
+    if n_hashers == 0 || n_counters == 0 {
+        return Err(PyValueError::new_err(
+            "n_hashers and n_counters must be positive",
+        ));
+    }
+    Ok((0..n_hashers as u64)
+        .map(|i| SeededState::new(seed, i))
+        .collect())
+}
+
+fn value_error(e: serialize::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// A binary Bloom filter.
+#[pyclass(name = "BloomFilter", module = "generic_bloom")]
+struct PyBloomFilter {
+    inner: BitFilter,
+}
+
+#[pymethods]
+impl PyBloomFilter {
+    #[new]
+    #[pyo3(signature = (n_hashers, n_counters, seed = 0))]
+    fn new(n_hashers: usize, n_counters: usize, seed: u64) -> PyResult<Self> {
+        Ok(PyBloomFilter {
+            inner: SimpleBloomFilter::with_hashers(hashers(n_hashers, n_counters, seed)?, n_counters),
+        })
+    }
+
+    fn insert(&mut self, key: Key<'_>) {
+        self.inner.insert(&key);
+    }
+
+    fn contains(&self, key: Key<'_>) -> bool {
+        self.inner.contains(&key)
+    }
+
+    fn __contains__(&self, key: Key<'_>) -> bool {
+        self.inner.contains(&key)
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.to_bytes())
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        Ok(PyBloomFilter {
+            inner: BitFilter::from_bytes(bytes).map_err(value_error)?,
+        })
+    }
+}
+
+enum Counters {
+    U8(CountingFilter<u8>),
+    U16(CountingFilter<u16>),
+    U32(CountingFilter<u32>),
+    U64(CountingFilter<u64>),
+}
+
+macro_rules! with_counters {
+    ($counters:expr, $f:ident => $body:expr) => {
+        match $counters {
+            Counters::U8($f) => $body,
+            Counters::U16($f) => $body,
+            Counters::U32($f) => $body,
+            Counters::U64($f) => $body,
+        }
+    };
+}
+
+/// A counting Bloom filter with 1-, 2-, 4- or 8-byte counters.
+#[pyclass(name = "CountingBloomFilter", module = "generic_bloom")]
+struct PyCountingBloomFilter {
+    inner: Counters,
+}
+
+#[pymethods]
+impl PyCountingBloomFilter {
+    #[new]
+    #[pyo3(signature = (n_hashers, n_counters, seed = 0, width = 4))]
+    fn new(n_hashers: usize, n_counters: usize, seed: u64, width: usize) -> PyResult<Self> {
+        let hashers = hashers(n_hashers, n_counters, seed)?;
+        let inner = match width {
+            1 => Counters::U8(SimpleBloomFilter::with_hashers(hashers, n_counters)),
+            2 => Counters::U16(SimpleBloomFilter::with_hashers(hashers, n_counters)),
+            4 => Counters::U32(SimpleBloomFilter::with_hashers(hashers, n_counters)),
+            8 => Counters::U64(SimpleBloomFilter::with_hashers(hashers, n_counters)),
+            _ => return Err(PyValueError::new_err("width must be 1, 2, 4 or 8")),
+        };
+        Ok(PyCountingBloomFilter { inner })
+    }
+
+    fn insert(&mut self, key: Key<'_>) {
+        with_counters!(&mut self.inner, f => f.insert(&key))
+    }
+
+    fn remove(&mut self, key: Key<'_>) {
+        with_counters!(&mut self.inner, f => f.remove(&key))
+    }
+
+    fn contains(&self, key: Key<'_>) -> bool {
+        with_counters!(&self.inner, f => f.contains(&key))
+    }
+
+    fn __contains__(&self, key: Key<'_>) -> bool {
+        self.contains(key)
+    }
+
+    /// Returns an estimate of the number of times `key` was inserted.
+    fn count(&self, key: Key<'_>) -> u64 {
+        #[allow(clippy::unnecessary_cast)]
+        let count = with_counters!(&self.inner, f => f.find_count(&key) as u64);
+        count
+    }
+
+    fn clear(&mut self) {
+        with_counters!(&mut self.inner, f => f.clear())
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &with_counters!(&self.inner, f => f.to_bytes()))
+    }
+
+    /// Loads a filter serialized with any supported counter width.
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        macro_rules! try_width {
+            ($t:ty, $variant:ident) => {
+                match CountingFilter::<$t>::from_bytes(bytes) {
+                    Err(serialize::Error::SetMismatch { .. }) => {}
+                    r => {
+                        return r
+                            .map(|f| PyCountingBloomFilter { inner: Counters::$variant(f) })
+                            .map_err(value_error)
+                    }
+                }
+            };
+        }
+        try_width!(u8, U8);
+        try_width!(u16, U16);
+        try_width!(u32, U32);
+        try_width!(u64, U64);
+        Err(PyValueError::new_err("not a counting Bloom filter"))
+    }
+}
+
+#[pymodule]
+fn generic_bloom(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyBloomFilter>()?;
+    m.add_class::<PyCountingBloomFilter>()?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::{SeededState, SimpleBloomFilter};
+use num_traits::{PrimInt, SaturatingAdd};
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+/// An approximate per-key rate limiter backed by a counting Bloom
+/// filter, so that its memory does not grow with the number of keys.
+///
+/// Each allowed request increments the counters of its key, with the
+/// Minimal Increase strategy of
+/// [`insert_minimal_increase`](SimpleBloomFilter::insert_minimal_increase),
+/// and a request is denied once its key's count reaches the limit.
+/// Every decay period, the counts are halved by
+/// [`SimpleBloomFilter::decay`], so a key may make `limit` requests
+/// in a burst and then about `limit / 2` per period. Decay is applied
+/// on access, when a request arrives a whole period or more after
+/// the last decay; it can also be driven by a timer with
+/// [`decay`](RateLimiter::decay), after disabling the automatic
+/// decay with [`set_decay_period`](RateLimiter::set_decay_period).
+///
+/// Counts are only ever overestimated, so a key is never allowed more
+/// requests than its limit, but a key sharing counters with busy keys
+/// may be denied early.
+///
+/// # Example
+/// ```
+/// use generic_bloom::RateLimiter;
+/// use std::time::{Duration, Instant};
+///
+/// let mut limiter: RateLimiter = RateLimiter::new(4, 10_000, 10, Duration::from_secs(1));
+/// let start = Instant::now();
+///
+/// let allowed = (0..15).filter(|_| limiter.record_at(&"10.0.0.1", start)).count();
+/// assert_eq!(allowed, 10);
+/// // May fail if "10.0.0.2" shares every counter with "10.0.0.1"
+/// assert!(limiter.record_at(&"10.0.0.2", start));
+///
+/// // A second later, the count of "10.0.0.1" has halved.
+/// let later = start + Duration::from_secs(1);
+/// let allowed = (0..15).filter(|_| limiter.record_at(&"10.0.0.1", later)).count();
+/// assert_eq!(allowed, 5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter<T = u16, S = SeededState> {
+    filter: SimpleBloomFilter<Box<[T]>, S>,
+    limit: T,
+    decay_period: Option<Duration>,
+    last_decay: Instant,
+}
+
+impl<T, S> RateLimiter<T, S>
+where
+    T: PrimInt + SaturatingAdd,
+    S: BuildHasher,
+{
+    /// Creates a `RateLimiter` allowing `limit` requests per key,
+    /// whose counts are halved every `decay_period`, with a specified
+    /// number of counters and [`BuildHasher`]s. The `BuildHasher`s
+    /// will be initialized by [`default`](Default::default).
+    ///
+    /// # Panics
+    /// Panics if `n_hashers` or `n_counters` is zero.
+    pub fn new(n_hashers: usize, n_counters: usize, limit: T, decay_period: Duration) -> Self
+    where
+        S: Default,
+    {
+        RateLimiter::from_filter(SimpleBloomFilter::new(n_hashers, n_counters), limit, decay_period)
+    }
+
+    /// Creates a `RateLimiter` which counts requests in `filter`,
+    /// allowing `limit` requests per key and halving the counts every
+    /// `decay_period`.
+    pub fn from_filter(filter: SimpleBloomFilter<Box<[T]>, S>, limit: T, decay_period: Duration) -> Self {
+        RateLimiter {
+            filter,
+            limit,
+            decay_period: Some(decay_period),
+            last_decay: Instant::now(),
+        }
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &SimpleBloomFilter<Box<[T]>, S> {
+        &self.filter
+    }
+
+    pub fn limit(&self) -> T {
+        self.limit
+    }
+
+    /// Sets how often counts are halved on access, or disables
+    /// automatic decay if `period` is `None`.
+    pub fn set_decay_period(&mut self, period: Option<Duration>) {
+        self.decay_period = period;
+    }
+
+    /// Records a request for `key` now, returning whether it is
+    /// allowed. Denied requests are not counted.
+    pub fn record<K: Hash + ?Sized>(&mut self, key: &K) -> bool {
+        self.record_at(key, Instant::now())
+    }
+
+    /// Like [`record`](RateLimiter::record), but at the time `now`.
+    pub fn record_at<K: Hash + ?Sized>(&mut self, key: &K, now: Instant) -> bool {
+        self.decay_until(now);
+        if self.filter.find_count(key) >= self.limit {
+            return false;
+        }
+        self.filter.insert_minimal_increase(key);
+        true
+    }
+
+    /// Returns an estimate of the decayed number of requests recorded
+    /// for `key`, which is never below the true count.
+    pub fn count<K: Hash + ?Sized>(&self, key: &K) -> T {
+        self.filter.find_count(key)
+    }
+
+    /// Halves every count.
+    pub fn decay(&mut self) {
+        self.filter.decay();
+    }
+
+    /// Applies the decay of every whole period which has passed
+    /// between the last decay and `now`.
+    fn decay_until(&mut self, now: Instant) {
+        let Some(period) = self.decay_period else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(self.last_decay);
+        let periods = elapsed.as_nanos() / period.as_nanos().max(1);
+        if periods == 0 {
+            return;
+        }
+        // Counts reach zero after halving as many times as they have
+        // bits.
+        if periods >= u128::from(T::zero().count_zeros()) {
+            self.filter.clear();
+            self.last_decay = now;
+        } else {
+            for _ in 0..periods {
+                self.filter.decay();
+            }
+            self.last_decay += period * periods as u32;
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A filter for read-mostly workloads, which readers query without
+/// writing to any shared memory.
+///
+/// Insertions go to a private write copy of the filter, under a
+/// lock. Every `publish_every` insertions, or on a call to
+/// [`publish`](RcuBloomFilter::publish), a clone of the write copy
+/// is published as an immutable snapshot, in the manner of
+/// read-copy-update. Each reader, an [`RcuReader`], keeps its own
+/// reference to the latest snapshot, and on each query only loads a
+/// version number which changes when a snapshot is published, so
+/// that concurrent queries never contend for a cache line as they do
+/// when sharing a lock or an atomic set. Readers take the lock
+/// briefly to pick up a new snapshot.
+///
+/// Values are found by readers once a snapshot including them has
+/// been published, so queries may miss the most recent
+/// `publish_every` insertions. Each publication clones the whole
+/// filter, which `publish_every` should amortize.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{RcuBloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+/// use std::thread;
+///
+/// let filter = RcuBloomFilter::new(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::with_seed(1, 7, 100_000),
+///     1000,
+/// );
+/// thread::scope(|s| {
+///     s.spawn(|| (0..5000).for_each(|x| filter.insert(&x)));
+///     s.spawn(|| {
+///         let mut reader = filter.reader();
+///         // Values are found in batches as they are published.
+///         while !reader.contains(&4999) {}
+///         assert!((0..5000).all(|x| reader.contains(&x)));
+///     });
+/// });
+///
+/// filter.insert(&5000);
+/// assert_eq!(filter.pending(), 1);
+/// assert!(!filter.reader().contains(&5000));
+/// filter.publish();
+/// assert!(filter.reader().contains(&5000));
+/// ```
+#[derive(Debug)]
+pub struct RcuBloomFilter<F> {
+    snapshots: Snapshots<F>,
+    writer: Mutex<Writer<F>>,
+    publish_every: usize,
+}
+
+/// The latest published snapshot of a filter, with a version number
+/// which changes whenever a new one is published.
+#[derive(Debug)]
+pub(crate) struct Snapshots<F> {
+    published: Mutex<Arc<F>>,
+    version: AtomicU64,
+}
+
+impl<F> Snapshots<F> {
+    pub(crate) fn new(filter: F) -> Self {
+        Snapshots {
+            published: Mutex::new(Arc::new(filter)),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the latest snapshot and its version.
+    pub(crate) fn latest(&self) -> (Arc<F>, u64) {
+        let published = lock(&self.published);
+        (Arc::clone(&published), self.version.load(Ordering::Acquire))
+    }
+
+    pub(crate) fn publish(&self, filter: F) {
+        let mut published = lock(&self.published);
+        *published = Arc::new(filter);
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Replaces `snapshot`, of version `version`, with the latest
+    /// snapshot if a newer one has been published.
+    pub(crate) fn refresh(&self, snapshot: &mut Arc<F>, version: &mut u64) {
+        if self.version.load(Ordering::Acquire) != *version {
+            (*snapshot, *version) = self.latest();
+        }
+    }
+}
+
+/// The write copy of an [`RcuBloomFilter`].
+#[derive(Debug)]
+struct Writer<F> {
+    filter: F,
+    pending: usize,
+}
+
+impl<F: BloomFilter + Clone> RcuBloomFilter<F> {
+    /// Publishes `filter` and makes a write copy of it, publishing
+    /// again after every `publish_every` insertions.
+    pub fn new(filter: F, publish_every: usize) -> Self {
+        RcuBloomFilter {
+            snapshots: Snapshots::new(filter.clone()),
+            writer: Mutex::new(Writer { filter, pending: 0 }),
+            publish_every,
+        }
+    }
+
+    /// Returns a reader of the latest snapshot.
+    pub fn reader(&self) -> RcuReader<'_, F> {
+        let (snapshot, version) = self.snapshots.latest();
+        RcuReader {
+            filter: self,
+            snapshot,
+            version,
+        }
+    }
+
+    /// Returns the latest snapshot.
+    pub fn snapshot(&self) -> Arc<F> {
+        self.snapshots.latest().0
+    }
+
+    /// Inserts `val` into the write copy, publishing it if
+    /// `publish_every` insertions are now unpublished.
+    pub fn insert<T: Hash + ?Sized>(&self, val: &T) {
+        let mut writer = lock(&self.writer);
+        writer.filter.insert(val);
+        writer.pending += 1;
+        if writer.pending >= self.publish_every {
+            self.publish_locked(&mut writer);
+        }
+    }
+
+    /// Publishes the write copy, so that every value inserted so far
+    /// is found by readers.
+    pub fn publish(&self) {
+        let mut writer = lock(&self.writer);
+        if writer.pending > 0 {
+            self.publish_locked(&mut writer);
+        }
+    }
+
+    /// Returns the number of insertions since the last publication.
+    pub fn pending(&self) -> usize {
+        lock(&self.writer).pending
+    }
+
+    /// Returns the write copy, including unpublished insertions.
+    pub fn into_inner(self) -> F {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .filter
+    }
+
+    fn publish_locked(&self, writer: &mut Writer<F>) {
+        self.snapshots.publish(writer.filter.clone());
+        writer.pending = 0;
+    }
+}
+
+impl<A: Hash, F: BloomFilter + Clone> Extend<A> for RcuBloomFilter<F> {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
+
+/// A reader of an [`RcuBloomFilter`], which queries the latest
+/// snapshot published when the query starts.
+///
+/// Readers are meant to be kept by one thread each, which is why
+/// queries take `&mut self`.
+#[derive(Debug)]
+pub struct RcuReader<'a, F> {
+    filter: &'a RcuBloomFilter<F>,
+    snapshot: Arc<F>,
+    version: u64,
+}
+
+impl<F: BloomFilter + Clone> RcuReader<'_, F> {
+    /// Returns the latest snapshot.
+    pub fn snapshot(&mut self) -> &F {
+        self.filter
+            .snapshots
+            .refresh(&mut self.snapshot, &mut self.version);
+        &self.snapshot
+    }
+
+    /// Checks whether the latest snapshot contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        self.snapshot().contains(val)
+    }
+}
+
+/// Locks `mutex`, ignoring poisoning, since a published snapshot is
+/// never modified and a panicking insertion only affects its own
+/// value in the write copy.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,349 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::durable::sync_dir;
+use crate::serialize::{Error, SerializableHasher, SerializableSet};
+use crate::sizing;
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::SimpleBloomFilter;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const TEMPLATE: &str = "template";
+const SEGMENT_PREFIX: &str = "segment-";
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Maximum number of hashers for which queries compute the indices
+/// of a value once for every segment, instead of once per segment.
+const PROBE_BUF_LEN: usize = 32;
+
+/// A binary filter stored in a directory as a log of sealed segments,
+/// for key sets which keep growing.
+///
+/// Insertions go to an in-memory active segment. Once its expected
+/// false positive rate reaches the seal threshold, it is sealed:
+/// written to its own file, which is never modified afterwards, and
+/// replaced by an empty segment. Queries check the active segment and
+/// every sealed one, so each key is written to disk once when its
+/// segment is sealed, and again only when
+/// [`compact`](SegmentedBloomFilter::compact) merges its segment.
+///
+/// Every segment has the same hashers and number of counters, so
+/// compaction merges segments by [union](BinaryBloomSet::union),
+/// without needing the keys. The merged segment holds the keys of
+/// all of its inputs in the space of one, so compaction only merges
+/// runs of segments whose union stays below a given false positive
+/// rate. Sealing segments at a small fraction of that rate lets many
+/// of them be merged, at the cost of more memory per key until they
+/// are.
+///
+/// Insertions into the active segment are only persisted when it is
+/// sealed, so [`seal`](SegmentedBloomFilter::seal) it before
+/// shutting down. Compaction writes the merged segment before
+/// deleting its inputs, so a crash in between leaves keys in more
+/// than one segment, which is harmless.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SegmentedBloomFilter, SimpleBloomFilter, SeededState};
+/// use bitvec::prelude::*;
+///
+/// let dir = std::env::temp_dir().join("generic-bloom-segmented-example");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let hashers: Box<[SeededState]> = (0..7).map(|i| SeededState::new(0, i)).collect();
+/// let template: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+///     SimpleBloomFilter::with_hashers(hashers, 10_000);
+///
+/// let mut store = SegmentedBloomFilter::create(&dir, template, 1e-4).unwrap();
+/// for key in 0..2000 {
+///     store.insert(&key).unwrap();
+/// }
+/// assert!(store.sealed_segments() > 1);
+///
+/// let merged = store.compact(0.01).unwrap();
+/// assert!(merged > 0);
+/// assert!(store.expected_false_positive_rate() < 0.01);
+/// store.seal().unwrap();
+/// drop(store);
+///
+/// let store: SegmentedBloomFilter<BitBox<usize, Lsb0>> = SegmentedBloomFilter::open(&dir).unwrap();
+/// assert!((0..2000).all(|key| store.contains(&key)));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SegmentedBloomFilter<B, S = crate::SeededState, V = Box<[S]>>
+where
+    V: AsRef<[S]>,
+{
+    dir: PathBuf,
+    active: SimpleBloomFilter<B, S, V>,
+    // Oldest first.
+    sealed: Vec<Segment<B, S, V>>,
+    next_id: u64,
+    seal_fpr: f64,
+}
+
+#[derive(Debug)]
+struct Segment<B, S, V>
+where
+    V: AsRef<[S]>,
+{
+    id: u64,
+    filter: SimpleBloomFilter<B, S, V>,
+}
+
+impl<B, S, V> SegmentedBloomFilter<B, S, V>
+where
+    B: BinaryBloomSet + SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]> + Clone,
+{
+    /// Creates a new segmented filter in `dir`, which is created if
+    /// necessary. Every segment gets the hashers and number of
+    /// counters of `template`, whose contents become those of the
+    /// first active segment, and is sealed once its expected false
+    /// positive rate reaches `seal_fpr`. Any filter previously stored
+    /// in `dir` is replaced.
+    pub fn create<P: AsRef<Path>>(dir: P, template: SimpleBloomFilter<B, S, V>, seal_fpr: f64) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for (_, path) in list_segments(&dir)? {
+            fs::remove_file(path)?;
+        }
+        let empty = template.with_same_hashers(template.counters().size());
+        write_atomic(&dir, TEMPLATE, |w| {
+            w.write_all(&seal_fpr.to_le_bytes())?;
+            empty.write_to(w)
+        })?;
+        Ok(SegmentedBloomFilter {
+            dir,
+            active: template,
+            sealed: Vec::new(),
+            next_id: 0,
+            seal_fpr,
+        })
+    }
+
+    /// Opens the segmented filter stored in `dir`, with an empty
+    /// active segment.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error>
+    where
+        V: FromIterator<S>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        let mut template = BufReader::new(File::open(dir.join(TEMPLATE))?);
+        let mut seal_fpr = [0; 8];
+        template.read_exact(&mut seal_fpr)?;
+        let active: SimpleBloomFilter<B, S, V> = SimpleBloomFilter::read_from(template)?;
+
+        let mut sealed = Vec::new();
+        for (id, path) in list_segments(&dir)? {
+            let filter = SimpleBloomFilter::read_from(BufReader::new(File::open(path)?))?;
+            sealed.push(Segment { id, filter });
+        }
+        Ok(SegmentedBloomFilter {
+            dir,
+            active,
+            next_id: sealed.last().map_or(0, |s| s.id + 1),
+            sealed,
+            seal_fpr: f64::from_le_bytes(seal_fpr),
+        })
+    }
+
+    /// Returns the number of sealed segments.
+    pub fn sealed_segments(&self) -> usize {
+        self.sealed.len()
+    }
+
+    /// Returns the active segment, which has not been persisted yet.
+    pub fn active(&self) -> &SimpleBloomFilter<B, S, V> {
+        &self.active
+    }
+
+    /// Returns the probability that a query for a value which was
+    /// never inserted finds it in any segment, from the expected
+    /// false positive rate of each.
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        1.0 - self.segments().map(|f| 1.0 - f.expected_false_positive_rate()).product::<f64>()
+    }
+
+    /// Returns the number of bytes of heap memory used by every
+    /// segment.
+    pub fn heap_size_bytes(&self) -> usize {
+        self.segments().map(|f| f.heap_size_bytes()).sum::<usize>()
+            + self.sealed.capacity() * std::mem::size_of::<Segment<B, S, V>>()
+    }
+
+    /// Inserts `val` into the active segment, sealing it if it is
+    /// full, i.e. if the false positive rate expected from the number
+    /// of values inserted into it reaches the seal threshold.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) -> Result<(), Error> {
+        self.active.insert(val);
+        // Estimated from the number of insertions, since counting the
+        // set bits on every insertion would be too slow.
+        let fpr = sizing::false_positive_rate(
+            BloomFilter::hashers(&self.active).len(),
+            self.active.counters().size(),
+            self.active.insert_count(),
+        );
+        if fpr >= self.seal_fpr {
+            self.seal()?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether any segment contains `val`. The newest segments
+    /// are checked first.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        let n_hashers = BloomFilter::hashers(&self.active).len();
+        if n_hashers > PROBE_BUF_LEN {
+            return self.segments().any(|f| f.contains(val));
+        }
+        let mut buf = [0; PROBE_BUF_LEN];
+        for (slot, i) in buf.iter_mut().zip(self.active.indices(val)) {
+            *slot = i;
+        }
+        let indices = &buf[..n_hashers];
+        self.segments()
+            .any(|f| query_all(f.counters(), indices.iter().copied()))
+    }
+
+    /// Persists the active segment as a sealed segment and starts a
+    /// new, empty one. Does nothing if the active segment is empty.
+    pub fn seal(&mut self) -> Result<(), Error> {
+        if self.active.is_empty() {
+            return Ok(());
+        }
+        let id = self.next_id;
+        write_segment(&self.dir, id, &self.active)?;
+        self.next_id += 1;
+        let empty = self.active.with_same_hashers(self.active.counters().size());
+        let filter = std::mem::replace(&mut self.active, empty);
+        self.sealed.push(Segment { id, filter });
+        Ok(())
+    }
+
+    /// Merges runs of consecutive sealed segments whose union has an
+    /// expected false positive rate of at most `max_fpr`, returning
+    /// the number of segments removed. Each run is written as a new
+    /// segment before the segments it replaces are deleted.
+    pub fn compact(&mut self, max_fpr: f64) -> Result<usize, Error> {
+        let n_hashers = BloomFilter::hashers(&self.active).len() as i32;
+        let size = self.active.counters().size() as f64;
+        let fpr = |ones: usize| (ones as f64 / size).powi(n_hashers);
+
+        let old = std::mem::take(&mut self.sealed);
+        let mut removed = 0;
+        let mut runs: Vec<(SimpleBloomFilter<B, S, V>, Vec<u64>)> = Vec::new();
+        for segment in old {
+            if let Some((merged, ids)) = runs.last_mut() {
+                if fpr(merged.counters().union_count_ones(segment.filter.counters())) <= max_fpr {
+                    merged.union(&segment.filter);
+                    ids.push(segment.id);
+                    continue;
+                }
+            }
+            runs.push((segment.filter, vec![segment.id]));
+        }
+
+        // On failure, the remaining runs are still kept in memory,
+        // under the id of one of their segments, so that no key is
+        // lost before the error is returned.
+        let mut result = Ok(());
+        for (filter, ids) in runs {
+            let mut id = ids[0];
+            if ids.len() > 1 && result.is_ok() {
+                result = self.replace_segments(&filter, &ids).map(|new_id| {
+                    id = new_id;
+                    removed += ids.len() - 1;
+                });
+            }
+            self.sealed.push(Segment { id, filter });
+        }
+        result?;
+        sync_dir(&self.dir)?;
+        Ok(removed)
+    }
+
+    /// Writes `filter` as a new segment and deletes the segments with
+    /// the given ids, returning the id of the new segment.
+    fn replace_segments(&mut self, filter: &SimpleBloomFilter<B, S, V>, ids: &[u64]) -> Result<u64, Error> {
+        let id = self.next_id;
+        write_segment(&self.dir, id, filter)?;
+        self.next_id += 1;
+        for old in ids {
+            fs::remove_file(self.dir.join(segment_name(*old)))?;
+        }
+        Ok(id)
+    }
+
+    /// Returns every segment, newest first.
+    fn segments(&self) -> impl Iterator<Item = &SimpleBloomFilter<B, S, V>> {
+        std::iter::once(&self.active).chain(self.sealed.iter().rev().map(|s| &s.filter))
+    }
+}
+
+fn segment_name(id: u64) -> String {
+    format!("{}{:016x}", SEGMENT_PREFIX, id)
+}
+
+/// Returns the id and path of every segment in `dir`, in order of
+/// id, removing any left half written by a crash.
+fn list_segments(dir: &Path) -> Result<Vec<(u64, PathBuf)>, Error> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.ends_with(TMP_SUFFIX) {
+            fs::remove_file(&path)?;
+        } else if let Some(id) = name
+            .strip_prefix(SEGMENT_PREFIX)
+            .and_then(|id| u64::from_str_radix(id, 16).ok())
+        {
+            segments.push((id, path));
+        }
+    }
+    segments.sort_unstable_by_key(|&(id, _)| id);
+    Ok(segments)
+}
+
+fn write_segment<B, S, V>(dir: &Path, id: u64, filter: &SimpleBloomFilter<B, S, V>) -> Result<(), Error>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    write_atomic(dir, &segment_name(id), |w| filter.write_to(w))
+}
+
+/// Writes the file `name` in `dir` with `write`, through a temporary
+/// file, so that it is either completely written or absent after a
+/// crash.
+fn write_atomic<F>(dir: &Path, name: &str, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+{
+    let tmp = dir.join(format!("{}{}", name, TMP_SUFFIX));
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    write(&mut w)?;
+    w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, dir.join(name))?;
+    sync_dir(dir)?;
+    Ok(())
+}
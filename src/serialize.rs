@@ -0,0 +1,587 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Binary serialization of Bloom filters.
+//!
+//! A serialized filter consists of a header followed by the hasher
+//! seeds and the counters. All integers are little-endian.
+//!
+//! | Field          | Size                  |
+//! |----------------|-----------------------|
+//! | magic `GBLM`   | 4 bytes               |
+//! | format version | 1 byte                |
+//! | hasher kind    | 1 byte                |
+//! | set kind       | 1 byte                |
+//! | hasher count   | 4 bytes               |
+//! | hasher seeds   | depends on the hasher |
+//! | counter count  | 8 bytes               |
+//! | header CRC-32C | 4 bytes               |
+//! | counter chunks | depends on the set    |
+//!
+//! The header CRC-32C covers all preceding fields, so that corrupted
+//! parameters or hasher seeds are detected rather than silently
+//! producing a filter which gives wrong answers.
+//!
+//! The counters are split into chunks of at most 64 KiB, each
+//! written as a 4-byte length, the data, and the CRC-32C of the
+//! data. A chunk of length zero, without a checksum, ends the
+//! filter. This allows filters to be written and read as streams
+//! without holding a serialized copy in memory, and corruption to be
+//! detected as soon as the affected chunk is read.
+//!
+//! Which hashers and sets can be serialized is determined by the
+//! [`SerializableHasher`] and [`SerializableSet`] traits. A
+//! serialized filter can also be queried without deserializing it,
+//! with a [`BloomFilterView`](crate::BloomFilterView).
+use crate::hash::SeededState;
+use crate::traits::set::BloomSet;
+use bitvec::{boxed::BitBox, order::BitOrder, order::Lsb0, store::BitStore, vec::BitVec, view::BitView};
+use num_traits::{FromBytes, One, SaturatingAdd, ToBytes, Zero};
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+
+const MAGIC: [u8; 4] = *b"GBLM";
+const VERSION: u8 = 4;
+
+/// Size of the buffer used when converting between bits and bytes.
+const BUF_SIZE: usize = 4096;
+
+/// Maximum length of a chunk of counters.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Errors which can occur while deserializing a filter.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying reader or writer failed, or the input ended
+    /// early.
+    Io(io::Error),
+    /// The input does not start with the expected magic bytes.
+    BadMagic,
+    /// The input uses a format version this crate does not support.
+    UnsupportedVersion(u8),
+    /// The input was written with a different kind of hasher.
+    HasherMismatch { expected: u8, found: u8 },
+    /// The input was written with a different kind of set.
+    SetMismatch { expected: u8, found: u8 },
+    /// The input was written with a kind of set this crate does not
+    /// know.
+    UnsupportedSet(u8),
+    /// The input specifies a filter with no hashers.
+    NoHashers,
+    /// The input specifies a filter with no counters, or more
+    /// counters than can be addressed on this platform.
+    BadCounterCount(u64),
+    /// The input continues after the end of the filter.
+    TrailingBytes,
+    /// A chunk of counters is longer than the format allows.
+    BadChunkLength(u32),
+    /// The header or a chunk of counters does not match its
+    /// checksum.
+    ChecksumMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::BadMagic => write!(f, "not a serialized Bloom filter"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            Error::HasherMismatch { expected, found } => write!(
+                f,
+                "expected hasher kind {}, found {}",
+                expected, found
+            ),
+            Error::SetMismatch { expected, found } => {
+                write!(f, "expected set kind {}, found {}", expected, found)
+            }
+            Error::UnsupportedSet(k) => write!(f, "unsupported set kind {}", k),
+            Error::NoHashers => write!(f, "filter has no hashers"),
+            Error::BadCounterCount(n) => write!(f, "invalid counter count {}", n),
+            Error::TrailingBytes => write!(f, "trailing bytes after filter"),
+            Error::BadChunkLength(n) => write!(f, "invalid chunk length {}", n),
+            Error::ChecksumMismatch => write!(f, "checksum mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A trait for [`BuildHasher`]s whose configuration can be
+/// serialized.
+pub trait SerializableHasher: BuildHasher + Sized {
+    /// A tag identifying this kind of hasher in serialized filters.
+    const KIND: u8;
+
+    /// Writes the seed of this hasher to `w`.
+    fn write_seed<W: Write>(&self, w: &mut W) -> io::Result<()>;
+
+    /// Reads a hasher seed written by
+    /// [`write_seed`](SerializableHasher::write_seed).
+    fn read_seed<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+/// A trait for [`BloomSet`]s whose counters can be serialized.
+pub trait SerializableSet: BloomSet + Sized {
+    /// A tag identifying this kind of set in serialized filters.
+    const KIND: u8;
+
+    /// Writes all counters to `w`.
+    fn write_counters<W: Write>(&self, w: &mut W) -> io::Result<()>;
+
+    /// Reads `size` counters written by
+    /// [`write_counters`](SerializableSet::write_counters).
+    fn read_counters<R: Read>(size: usize, r: &mut R) -> io::Result<Self>;
+
+    /// Returns the number of bytes
+    /// [`write_counters`](SerializableSet::write_counters) writes. By
+    /// default, this writes the counters and counts the bytes.
+    fn serialized_len(&self) -> usize {
+        let mut w = CountingWriter(0);
+        self.write_counters(&mut w)
+            .expect("counting bytes cannot fail");
+        w.0
+    }
+}
+
+impl SerializableHasher for SeededState {
+    const KIND: u8 = 1;
+
+    fn write_seed<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (k0, k1) = self.keys();
+        w.write_all(&k0.to_le_bytes())?;
+        w.write_all(&k1.to_le_bytes())
+    }
+
+    fn read_seed<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(SeededState::new(read_u64(r)?, read_u64(r)?))
+    }
+}
+
+/// Bits are packed eight to a byte, least significant bit first,
+/// regardless of `T` and `O`.
+impl<T, O> SerializableSet for BitBox<T, O>
+where
+    T: BitStore,
+    O: BitOrder,
+{
+    const KIND: u8 = 0;
+
+    fn serialized_len(&self) -> usize {
+        self.len().div_ceil(8)
+    }
+
+    fn write_counters<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(BUF_SIZE);
+        for bits in self.chunks(8) {
+            let mut byte = 0u8;
+            for (i, bit) in bits.iter().by_vals().enumerate() {
+                byte |= (bit as u8) << i;
+            }
+            buf.push(byte);
+            if buf.len() == BUF_SIZE {
+                w.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        w.write_all(&buf)
+    }
+
+    fn read_counters<R: Read>(size: usize, r: &mut R) -> io::Result<Self> {
+        let mut bits = BitVec::<T, O>::new();
+        let mut buf = [0u8; BUF_SIZE];
+        let mut remaining = size.div_ceil(8);
+        while remaining > 0 {
+            let n = remaining.min(BUF_SIZE);
+            r.read_exact(&mut buf[..n])?;
+            for byte in &buf[..n] {
+                bits.extend(byte.view_bits::<Lsb0>().iter().by_vals());
+            }
+            remaining -= n;
+        }
+        bits.truncate(size);
+        Ok(bits.into_boxed_bitslice())
+    }
+}
+
+/// Counters are written as fixed-width little-endian integers; the
+/// set kind is the width of `T` in bytes.
+impl<T> SerializableSet for Box<[T]>
+where
+    T: SaturatingAdd + One + Zero + Ord + ToBytes + FromBytes,
+    <T as FromBytes>::Bytes: Sized + Default,
+{
+    const KIND: u8 = std::mem::size_of::<T>() as u8;
+
+    fn serialized_len(&self) -> usize {
+        self.len() * std::mem::size_of::<T>()
+    }
+
+    fn write_counters<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for c in <[T]>::iter(self) {
+            w.write_all(c.to_le_bytes().as_ref())?;
+        }
+        Ok(())
+    }
+
+    fn read_counters<R: Read>(size: usize, r: &mut R) -> io::Result<Self> {
+        let mut counters = Vec::new();
+        for _ in 0..size {
+            let mut bytes = <T as FromBytes>::Bytes::default();
+            r.read_exact(bytes.as_mut())?;
+            counters.push(T::from_le_bytes(&bytes));
+        }
+        Ok(counters.into_boxed_slice())
+    }
+}
+
+/// Computes the CRC-32C of everything written through it.
+struct CrcWriter<W> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(data)?;
+        self.crc = crc32c::crc32c_append(self.crc, &data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Computes the CRC-32C of everything read through it.
+struct CrcReader<R> {
+    inner: R,
+    crc: u32,
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(out)?;
+        self.crc = crc32c::crc32c_append(self.crc, &out[..n]);
+        Ok(n)
+    }
+}
+
+/// Splits everything written to it into checksummed chunks.
+struct ChunkWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    fn new(inner: W) -> Self {
+        ChunkWriter {
+            inner,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.inner.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+            self.inner.write_all(&self.buf)?;
+            self.inner.write_all(&crc32c::crc32c(&self.buf).to_le_bytes())?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes any buffered data followed by the terminating chunk.
+    fn finish(mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.inner.write_all(&0u32.to_le_bytes())
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reads data split into chunks by a [`ChunkWriter`], verifying each
+/// chunk before returning any of its contents.
+struct ChunkReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+    error: Option<Error>,
+}
+
+impl<R: Read> ChunkReader<R> {
+    fn new(inner: R) -> Self {
+        ChunkReader {
+            inner,
+            buf: Vec::new(),
+            pos: 0,
+            done: false,
+            error: None,
+        }
+    }
+
+    fn read_chunk(&mut self) -> Result<(), Error> {
+        let mut len = [0; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len as usize > CHUNK_SIZE {
+            return Err(Error::BadChunkLength(len));
+        }
+
+        self.buf.resize(len as usize, 0);
+        self.pos = 0;
+        if len == 0 {
+            self.done = true;
+            return Ok(());
+        }
+        self.inner.read_exact(&mut self.buf)?;
+
+        let mut crc = [0; 4];
+        self.inner.read_exact(&mut crc)?;
+        if u32::from_le_bytes(crc) != crc32c::crc32c(&self.buf) {
+            return Err(Error::ChecksumMismatch);
+        }
+        Ok(())
+    }
+
+    /// Checks that all chunks were consumed, reading the terminating
+    /// chunk if necessary.
+    fn finish(mut self) -> Result<(), Error> {
+        if self.pos == self.buf.len() && !self.done {
+            self.read_chunk()?;
+        }
+        if self.done {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+
+    /// Converts an error returned by [`read`](Read::read) into the
+    /// [`Error`] which caused it.
+    fn take_error(&mut self, e: io::Error) -> Error {
+        self.error.take().unwrap_or(Error::Io(e))
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() && !self.done {
+            if let Err(e) = self.read_chunk() {
+                let io_error = match &e {
+                    Error::Io(e) => io::Error::new(e.kind(), e.to_string()),
+                    _ => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+                };
+                self.error = Some(e);
+                return Err(io_error);
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..][..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Writes a filter consisting of `hashers` and `set` to `w`.
+pub(crate) fn write_filter<W, S, B>(w: W, hashers: &[S], set: &B) -> Result<(), Error>
+where
+    W: Write,
+    S: SerializableHasher,
+    B: SerializableSet,
+{
+    let mut w = CrcWriter {
+        inner: w,
+        crc: 0,
+    };
+    w.write_all(&MAGIC)?;
+    w.write_all(&[VERSION, S::KIND, B::KIND])?;
+    w.write_all(&(hashers.len() as u32).to_le_bytes())?;
+    for h in hashers {
+        h.write_seed(&mut w)?;
+    }
+    w.write_all(&(set.size() as u64).to_le_bytes())?;
+
+    let CrcWriter { mut inner, crc } = w;
+    inner.write_all(&crc.to_le_bytes())?;
+
+    let mut chunks = ChunkWriter::new(inner);
+    set.write_counters(&mut chunks)?;
+    chunks.finish()?;
+    Ok(())
+}
+
+/// Returns the number of bytes [`write_filter`] writes.
+pub(crate) fn filter_len<S, B>(hashers: &[S], set: &B) -> usize
+where
+    S: SerializableHasher,
+    B: SerializableSet,
+{
+    let mut seeds = CountingWriter(0);
+    for h in hashers {
+        h.write_seed(&mut seeds)
+            .expect("counting bytes cannot fail");
+    }
+    let counters = set.serialized_len();
+    // Magic, version, kinds, hasher count, seeds, counter count and
+    // header checksum, then each chunk with its length and checksum,
+    // and the terminating chunk.
+    MAGIC.len() + 3 + 4 + seeds.0 + 8 + 4 + counters + 8 * counters.div_ceil(CHUNK_SIZE) + 4
+}
+
+/// Returns a hash of the bytes [`write_filter`] writes, computed
+/// with fixed keys so that it is the same in every process.
+pub(crate) fn filter_digest<S, B>(hashers: &[S], set: &B) -> u64
+where
+    S: SerializableHasher,
+    B: SerializableSet,
+{
+    let mut w = HashingWriter(SeededState::new(0, 0).build_hasher());
+    write_filter(&mut w, hashers, set).expect("hashing bytes cannot fail");
+    w.0.finish()
+}
+
+/// Hashes the bytes written to it, discarding them.
+struct HashingWriter<H>(H);
+
+impl<H: Hasher> Write for HashingWriter<H> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Counts the bytes written to it, discarding them.
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0 += data.len();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads a filter written by [`write_filter`] from `r`, returning
+/// its hashers and set.
+pub(crate) fn read_filter<R, S, B, V>(mut r: R) -> Result<(V, B), Error>
+where
+    R: Read,
+    S: SerializableHasher,
+    B: SerializableSet,
+    V: FromIterator<S>,
+{
+    let (set_kind, hashers, size) = read_header(&mut r)?;
+    if set_kind != B::KIND {
+        return Err(Error::SetMismatch {
+            expected: B::KIND,
+            found: set_kind,
+        });
+    }
+
+    let mut chunks = ChunkReader::new(r);
+    let set = B::read_counters(size, &mut chunks).map_err(|e| chunks.take_error(e))?;
+    chunks.finish()?;
+
+    Ok((hashers, set))
+}
+
+/// Reads the header written by [`write_filter`] from `r`, returning
+/// the set kind, the hashers and the number of counters, and leaving
+/// `r` at the first chunk of counters.
+pub(crate) fn read_header<R, S, V>(r: R) -> Result<(u8, V, usize), Error>
+where
+    R: Read,
+    S: SerializableHasher,
+    V: FromIterator<S>,
+{
+    let mut r = CrcReader {
+        inner: r,
+        crc: 0,
+    };
+    let mut header = [0u8; 11];
+    r.read_exact(&mut header)?;
+    if header[..4] != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    if header[4] != VERSION {
+        return Err(Error::UnsupportedVersion(header[4]));
+    }
+    if header[5] != S::KIND {
+        return Err(Error::HasherMismatch {
+            expected: S::KIND,
+            found: header[5],
+        });
+    }
+    let n_hashers = u32::from_le_bytes(header[7..].try_into().unwrap());
+    if n_hashers == 0 {
+        return Err(Error::NoHashers);
+    }
+    let hashers = (0..n_hashers)
+        .map(|_| S::read_seed(&mut r))
+        .collect::<io::Result<V>>()?;
+    let n_counters = read_u64(&mut r)?;
+
+    let CrcReader { mut inner, crc } = r;
+    let mut expected = [0; 4];
+    inner.read_exact(&mut expected)?;
+    if u32::from_le_bytes(expected) != crc {
+        return Err(Error::ChecksumMismatch);
+    }
+
+    match usize::try_from(n_counters) {
+        Ok(size) if size > 0 => Ok((header[6], hashers, size)),
+        _ => Err(Error::BadCounterCount(n_counters)),
+    }
+}
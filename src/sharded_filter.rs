@@ -0,0 +1,194 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use crate::{SeededState, SimpleBloomFilter};
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A Bloom filter split into independently locked shards, so that
+/// insertions from many threads rarely contend for the same lock.
+///
+/// Each value is routed to one shard by the [`BuildHasher`] `R`. All
+/// shards have the same size and share the hashers `V`, so they can
+/// be combined with [`merge_shards`](ShardedBloomFilter::merge_shards)
+/// into a single [`SimpleBloomFilter`] which contains every inserted
+/// value and can be queried without routing.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, ShardedBloomFilter};
+/// use bitvec::prelude::*;
+/// use std::thread;
+///
+/// let filter: ShardedBloomFilter<BitBox<usize, Lsb0>> = ShardedBloomFilter::new(8, 10, 2000);
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         let filter = &filter;
+///         s.spawn(move || {
+///             for x in 0..25 {
+///                 filter.insert(&(t * 25 + x));
+///             }
+///         });
+///     }
+/// });
+/// assert!((0..100).all(|x| filter.contains(&x)));
+///
+/// let merged = filter.merge_shards();
+/// assert!((0..100).all(|x| merged.contains(&x)));
+/// ```
+#[derive(Debug)]
+pub struct ShardedBloomFilter<B, S = SeededState, V = Arc<[S]>, R = SeededState>
+where
+    V: AsRef<[S]>,
+{
+    shards: Box<[Mutex<SimpleBloomFilter<B, S, V>>]>,
+    router: R,
+}
+
+impl<B, S, V, R> ShardedBloomFilter<B, S, V, R>
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]> + Clone,
+    R: BuildHasher,
+{
+    /// Creates a new `ShardedBloomFilter` with `n_shards` shards of
+    /// `n_counters` counters each, sharing `n_hashers` hashers
+    /// initialized by [`default`](Default::default).
+    pub fn new(n_shards: usize, n_hashers: usize, n_counters: usize) -> Self
+    where
+        S: Default,
+        V: FromIterator<S>,
+        R: Default,
+    {
+        ShardedBloomFilter::with_hashers(
+            n_shards,
+            std::iter::repeat_with(|| S::default())
+                .take(n_hashers)
+                .collect(),
+            n_counters,
+        )
+    }
+
+    /// Creates a new `ShardedBloomFilter` with `n_shards` shards of
+    /// `n_counters` counters each, all using `hashers`.
+    pub fn with_hashers(n_shards: usize, hashers: V, n_counters: usize) -> Self
+    where
+        R: Default,
+    {
+        ShardedBloomFilter::with_router(n_shards, hashers, n_counters, R::default())
+    }
+
+    /// Creates a new `ShardedBloomFilter` like
+    /// [`with_hashers`](ShardedBloomFilter::with_hashers), routing
+    /// values to shards using `router`.
+    pub fn with_router(n_shards: usize, hashers: V, n_counters: usize, router: R) -> Self {
+        assert!(n_shards > 0, "a ShardedBloomFilter needs at least one shard");
+        ShardedBloomFilter {
+            shards: (0..n_shards)
+                .map(|_| Mutex::new(SimpleBloomFilter::with_hashers(hashers.clone(), n_counters)))
+                .collect(),
+            router,
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn n_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Locks and returns the shard which `val` is routed to.
+    pub fn shard_for<T: Hash + ?Sized>(&self, val: &T) -> MutexGuard<'_, SimpleBloomFilter<B, S, V>> {
+        let i = crate::hash::reduce(self.router.hash_one(val), self.shards.len());
+        lock(&self.shards[i])
+    }
+
+    /// Inserts `val` into the shard it is routed to.
+    pub fn insert<T: Hash + ?Sized>(&self, val: &T) {
+        self.shard_for(val).insert(val);
+    }
+
+    /// Checks whether the shard `val` is routed to contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        self.shard_for(val).contains(val)
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the filter, counting the hashers once even though every shard
+    /// refers to them.
+    pub fn heap_size_bytes(&self) -> usize {
+        let shards = self.shards.iter().map(|s| lock(s).counters().heap_size_bytes()).sum::<usize>();
+        let hashers = crate::hash::hashers_heap_size(lock(&self.shards[0]).hashers());
+        std::mem::size_of_val(&*self.shards) + shards + hashers
+    }
+
+    /// Clears all shards.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).clear();
+        }
+    }
+
+    /// Returns the shards of the filter.
+    pub fn into_shards(self) -> Vec<SimpleBloomFilter<B, S, V>> {
+        self.shards
+            .into_vec()
+            .into_iter()
+            .map(|s| s.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    /// Combines all shards into a single filter containing every
+    /// value inserted into any shard. Each shard is locked in turn,
+    /// so values inserted concurrently may or may not be included.
+    pub fn merge_shards(&self) -> SimpleBloomFilter<B, S, V>
+    where
+        B: BinaryBloomSet + Clone,
+    {
+        let mut merged = {
+            let first = lock(&self.shards[0]);
+            SimpleBloomFilter::from_parts(first.hashers().clone(), first.counters().clone())
+        };
+        for shard in self.shards[1..].iter() {
+            merged.union(&*lock(shard));
+        }
+        merged
+    }
+}
+
+impl<A, B, S, V, R> Extend<A> for ShardedBloomFilter<B, S, V, R>
+where
+    A: Hash,
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]> + Clone,
+    R: BuildHasher,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = A>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
+
+/// Locks `shard`, ignoring poisoning: a panic during an insertion
+/// can at worst leave that one value partially inserted.
+fn lock<T>(shard: &Mutex<T>) -> MutexGuard<'_, T> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -12,28 +12,105 @@
 // received a copy of the GNU Affero General Public License along with
 // generic-bloom. If not, see <https://www.gnu.org/licenses/>.
 
-use std::collections::hash_map::RandomState;
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::hash::{BuildHasher, Hash};
 use std::iter::{FromIterator, Extend};
+use std::ops::Shr;
+use std::io::{Read, Write};
 use crate::traits::set::*;
 use crate::traits::filter::*;
-use std::rc::Rc;
+use crate::serialize::{self, SerializableHasher, SerializableSet};
+use crate::sizing;
+use crate::{Explain, FrozenBloomFilter, Probe, SeededState};
+use num_traits::{Bounded, One, SaturatingAdd, Zero};
+use bitvec::{boxed::BitBox, order::BitOrder, store::BitStore, vec::BitVec};
+use std::sync::Arc;
 use std::marker::PhantomData;
 
-#[derive(Debug, Clone, PartialEq)]
+/// Number of indices which
+/// [`insert_batch`](BloomFilter::insert_batch),
+/// [`contains_batch`](BloomFilter::contains_batch) and
+/// [`remove_batch`](BloomFilterDelete::remove_batch) compute before
+/// accessing the set.
+const INDEX_BUF_LEN: usize = 256;
+
+#[derive(Debug, Clone)]
 /// A Bloom filter with underlying set `B` and [`BuildHasher`] type
 /// `S`, the `BuildHasher`s being held in a collection of type
 /// `V`. The supported operations are based on the traits implemented
 /// by `B`.
-pub struct SimpleBloomFilter<B, S = RandomState, V = Rc<[S]>>
+///
+/// By default, the hashers are held in an [`Arc`], so that filters
+/// sharing hashers (e.g. those created by
+/// [`with_same_hashers`](SimpleBloomFilter::with_same_hashers)) can
+/// be cloned cheaply and sent between threads.
+pub struct SimpleBloomFilter<B, S = SeededState, V = Arc<[S]>>
 where
     V: AsRef<[S]>,
 {
     hashers: V,
     set: B,
+    inserts: usize,
+    lost_increments: usize,
+    limit: Option<sizing::CapacityLimit>,
     _phantom: PhantomData<S>
 }
 
+/// Filters are equal if they have the same hashers and counters,
+/// regardless of their [`insert_count`](SimpleBloomFilter::insert_count)s,
+/// [`lost_increments`](BloomFilter::lost_increments) and
+/// [`capacity_limit`](SimpleBloomFilter::capacity_limit)s.
+impl<B, S, V> PartialEq for SimpleBloomFilter<B, S, V>
+where
+    B: PartialEq,
+    S: PartialEq,
+    V: AsRef<[S]> + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.hashers == other.hashers && self.set == other.set
+    }
+}
+
+impl<B, V> SimpleBloomFilter<B, SeededState, V>
+where
+    B: BloomSet,
+    V: AsRef<[SeededState]>,
+{
+    /// Creates a new `SimpleBloomFilter` with a specified number of
+    /// counters and hashers [derived](SeededState::derive) from
+    /// `seed`. Filters created with the same arguments hash values
+    /// identically, even in different processes, so they can be
+    /// merged, and tests using them are reproducible.
+    ///
+    /// # Panics
+    /// Panics if `n_hashers` or `n_counters` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(0x5eed, 4, 100);
+    /// let mut f2: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(0x5eed, 4, 100);
+    /// f1.insert(&48);
+    /// f2.insert(&48);
+    /// assert_eq!(f1, f2);
+    /// ```
+    pub fn with_seed(seed: u128, n_hashers: usize, n_counters: usize) -> Self
+    where
+        V: FromIterator<SeededState>,
+    {
+        SimpleBloomFilter::with_hashers(
+            SeededState::from_seed(seed).derive(n_hashers).collect(),
+            n_counters,
+        )
+    }
+}
+
+/// Maximum number of hashers for which
+/// [`insert_if_absent`](BloomFilter::insert_if_absent) keeps the
+/// indices of a value on the stack instead of hashing it twice.
+const PROBE_BUF_LEN: usize = 32;
+
 impl<B, S, V> SimpleBloomFilter<B, S, V>
 where
     B: BloomSet,
@@ -56,17 +133,141 @@ where
         )
     }
 
+    /// Creates a new `SimpleBloomFilter` sized to hold `n_items`
+    /// values with a false positive rate of at most `fpr`, as
+    /// computed by [`sizing`](crate::sizing). The `BuildHasher`s will
+    /// be initialized by [`default`](Default::default).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::for_items(1000, 0.01);
+    /// assert_eq!(f.hashers().len(), 7);
+    /// assert_eq!(f.counters().len(), 9586);
+    /// ```
+    pub fn for_items(n_items: usize, fpr: f64) -> Self
+    where
+        S: Default,
+        V: FromIterator<S>,
+    {
+        let n_counters = sizing::optimal_counters(n_items, fpr);
+        SimpleBloomFilter::new(sizing::optimal_hashers(n_counters, n_items), n_counters)
+    }
+
     /// Creates a new `SimpleBloomFilter` with specified `BuildHasher`s and a
     /// specified number of counters.
+    ///
+    /// # Panics
+    /// Panics if `hashers` is empty or `n_counters` is zero. See
+    /// [`try_with_hashers`](SimpleBloomFilter::try_with_hashers) for
+    /// a fallible version.
     pub fn with_hashers(hashers: V, n_counters: usize) -> Self {
-        debug_assert!(hashers.as_ref().len() > 0);
-        SimpleBloomFilter {
-            hashers: hashers,
+        match SimpleBloomFilter::try_with_hashers(hashers, n_counters) {
+            Ok(filter) => filter,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Creates a new `SimpleBloomFilter` with specified `BuildHasher`s
+    /// and a specified number of counters, or returns an error if
+    /// `hashers` is empty or `n_counters` is zero.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{ConfigError, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    /// use std::collections::hash_map::RandomState;
+    ///
+    /// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>, RandomState, Vec<RandomState>>;
+    ///
+    /// assert!(Filter::try_with_hashers(vec![RandomState::new()], 100).is_ok());
+    /// assert_eq!(Filter::try_with_hashers(vec![], 100).unwrap_err(), ConfigError::NoHashers);
+    /// assert_eq!(
+    ///     Filter::try_with_hashers(vec![RandomState::new()], 0).unwrap_err(),
+    ///     ConfigError::NoCounters
+    /// );
+    /// ```
+    pub fn try_with_hashers(hashers: V, n_counters: usize) -> Result<Self, ConfigError> {
+        ConfigError::check(hashers.as_ref().len(), n_counters)?;
+        Ok(SimpleBloomFilter {
+            hashers,
             set: B::new(n_counters),
+            inserts: 0,
+            lost_increments: 0,
+            limit: None,
             _phantom: PhantomData
+        })
+    }
+
+    /// Creates an empty filter with `n_counters` counters and the
+    /// same hashers as `self`, so that the two can be combined with
+    /// the methods of [`BinaryBloomFilter`]. With the default
+    /// [`Arc`] container, the hashers are shared rather than copied.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BinaryBloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    /// use std::thread;
+    ///
+    /// let mut total: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// let workers: Vec<_> = (0..4)
+    ///     .map(|w| {
+    ///         let mut part = total.with_same_hashers(2000);
+    ///         thread::spawn(move || {
+    ///             part.extend(w * 10..(w + 1) * 10);
+    ///             part
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for worker in workers {
+    ///     total.union(&worker.join().unwrap());
+    /// }
+    /// assert!((0..40).all(|x| total.contains(&x)));
+    /// ```
+    pub fn with_same_hashers(&self, n_counters: usize) -> Self
+    where
+        V: Clone,
+    {
+        SimpleBloomFilter::with_hashers(self.hashers.clone(), n_counters)
+    }
+
+    /// Creates a `SimpleBloomFilter` from hashers and a set, such as
+    /// those returned by [`into_inner`](SimpleBloomFilter::into_inner).
+    /// Since the set does not record how many values were inserted
+    /// into it, the [`insert_count`](SimpleBloomFilter::insert_count)
+    /// starts at the [`estimated_len`](SimpleBloomFilter::estimated_len).
+    ///
+    /// # Panics
+    /// Panics if `hashers` or `set` is empty. See
+    /// [`try_from_parts`](SimpleBloomFilter::try_from_parts) for a
+    /// fallible version.
+    pub fn from_parts(hashers: V, set: B) -> Self {
+        match SimpleBloomFilter::try_from_parts(hashers, set) {
+            Ok(filter) => filter,
+            Err(e) => panic!("{}", e),
         }
     }
 
+    /// Creates a `SimpleBloomFilter` from hashers and a set, as
+    /// [`from_parts`](SimpleBloomFilter::from_parts) does, or returns
+    /// an error if `hashers` or `set` is empty.
+    pub fn try_from_parts(hashers: V, set: B) -> Result<Self, ConfigError> {
+        ConfigError::check(hashers.as_ref().len(), set.size())?;
+        let mut filter = SimpleBloomFilter {
+            hashers,
+            set,
+            inserts: 0,
+            lost_increments: 0,
+            limit: None,
+            _phantom: PhantomData
+        };
+        filter.inserts = filter.estimated_len().round() as usize;
+        Ok(filter)
+    }
+
     /// Returns the hashers and bit set of the filter.
     pub fn into_inner(self) -> (V, B) {
         (self.hashers, self.set)
@@ -76,17 +277,769 @@ where
         &self.hashers
     }
 
-    fn hash_indices<'a, T: Hash>(
+    /// Returns the counters probed when looking up `val`, and whether
+    /// each of them is set. See
+    /// [`explain_counts`](SimpleBloomFilter::explain_counts) for the
+    /// counts of a spectral filter.
+    pub fn explain<T: Hash + ?Sized>(&self, val: &T) -> Explain {
+        Explain {
+            probes: Self::hash_indices(&self.hashers, self.set.size(), val)
+                .map(|index| Probe { index, value: self.set.query(index) })
+                .collect(),
+        }
+    }
+
+    /// Checks whether the set may contain `val`, using only the first
+    /// `probes` hashers, or all of them if there are fewer. This is
+    /// faster than [`contains`](BloomFilter::contains) but has a
+    /// higher false positive rate, given by
+    /// [`approx_false_positive_rate`](SimpleBloomFilter::approx_false_positive_rate),
+    /// so it suits a pre-filter in front of a full check. Like
+    /// `contains`, it never returns `false` for an inserted value.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 7, 9586);
+    /// f.extend(0..1000);
+    /// assert!((0..1000).all(|x| f.contains_approx(&x, 2)));
+    ///
+    /// let hits = (1000..101_000).filter(|x| f.contains_approx(x, 2)).count();
+    /// let expected = f.approx_false_positive_rate(2) * 100_000.0;
+    /// assert!((hits as f64 - expected).abs() < expected * 0.1);
+    /// ```
+    pub fn contains_approx<T: Hash + ?Sized>(&self, val: &T, probes: usize) -> bool {
+        let hashers = self.hashers.as_ref();
+        let hashers = &hashers[..probes.min(hashers.len())];
+        query_all(&self.set, crate::hash::hash_indices(hashers, self.set.size(), val))
+    }
+
+    /// Returns the probability that
+    /// [`contains_approx`](SimpleBloomFilter::contains_approx) with
+    /// `probes` probes reports a value which was never inserted as
+    /// present, computed like
+    /// [`expected_false_positive_rate`](BloomFilter::expected_false_positive_rate).
+    pub fn approx_false_positive_rate(&self, probes: usize) -> f64 {
+        let fill = self.set.count_ones() as f64 / self.set.size() as f64;
+        fill.powi(probes.min(self.hashers.as_ref().len()) as i32)
+    }
+
+    /// Returns the number of values inserted into the filter, minus
+    /// the number removed, since it was created or last cleared. Each
+    /// insertion is counted, even of values the filter already
+    /// contains; set operations such as
+    /// [`union`](BinaryBloomFilter::union) do not change the count.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(7, 2000);
+    /// f.insert_batch(&[1, 2, 3]);
+    /// f.insert(&3);
+    /// assert_eq!(f.insert_count(), 4);
+    /// // May fail if the values share too many counters
+    /// assert_eq!(f.estimated_len().round(), 3.0);
+    ///
+    /// f.clear();
+    /// assert_eq!(f.insert_count(), 0);
+    /// ```
+    pub fn insert_count(&self) -> usize {
+        self.inserts
+    }
+
+    /// Estimates the number of distinct values in the filter from the
+    /// number of counters which indicate presence, as
+    /// [`sizing::estimate_items`] does. Unlike
+    /// [`insert_count`](SimpleBloomFilter::insert_count), this ignores
+    /// repeated insertions and accounts for set operations. Returns
+    /// infinity if the filter is saturated.
+    pub fn estimated_len(&self) -> f64 {
+        sizing::estimate_items(self.hashers.as_ref().len(), self.set.size(), self.set.count_ones())
+    }
+
+    /// Sets the limit enforced by
+    /// [`try_insert`](SimpleBloomFilter::try_insert).
+    pub fn with_capacity_limit(mut self, limit: sizing::CapacityLimit) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns the limit enforced by
+    /// [`try_insert`](SimpleBloomFilter::try_insert), if any.
+    pub fn capacity_limit(&self) -> Option<sizing::CapacityLimit> {
+        self.limit
+    }
+
+    /// Inserts `val` unless doing so would exceed the filter's
+    /// [`capacity_limit`](SimpleBloomFilter::capacity_limit),
+    /// returning whether it was inserted. The limit is checked
+    /// against the expected fill of a filter holding
+    /// [`insert_count`](SimpleBloomFilter::insert_count) values, so
+    /// repeated insertions of the same value count towards it. Once
+    /// this returns `false`, the filter should be replaced, e.g. by
+    /// starting a new one.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{SimpleBloomFilter, sizing::CapacityLimit};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::for_items(100, 0.01)
+    ///     .with_capacity_limit(CapacityLimit::FalsePositiveRate(0.01));
+    /// let inserted = (0..1000).take_while(|x| f.try_insert(x)).count();
+    /// assert!((90..=100).contains(&inserted));
+    /// ```
+    pub fn try_insert<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        if let Some(limit) = self.limit {
+            let n_items = self.inserts.saturating_add(1);
+            if !limit.allows(self.hashers.as_ref().len(), self.set.size(), n_items) {
+                return false;
+            }
+        }
+        self.insert(val);
+        true
+    }
+
+    /// Adds `n` insertions to the
+    /// [`insert_count`](SimpleBloomFilter::insert_count), for callers
+    /// which increment the counters directly.
+    pub(crate) fn record_inserts(&mut self, n: usize) {
+        self.inserts = self.inserts.saturating_add(n);
+    }
+
+    /// Increments the counter with index `i`, recording the increment
+    /// in [`lost_increments`](BloomFilter::lost_increments) if
+    /// the counter is saturated.
+    pub(crate) fn increment_counter(&mut self, i: usize) {
+        Self::increment_in(&mut self.set, &mut self.lost_increments, i);
+    }
+
+    /// Like [`increment_counter`](SimpleBloomFilter::increment_counter),
+    /// for callers which are still borrowing the hashers.
+    fn increment_in(set: &mut B, lost_increments: &mut usize, i: usize) {
+        if !set.try_increment(i) {
+            *lost_increments = lost_increments.saturating_add(1);
+        }
+    }
+
+    /// Checks whether `self` and `other` have the same hashers and
+    /// number of counters, so that they can be combined or compared
+    /// with the methods of [`BinaryBloomFilter`].
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>>;
+    ///
+    /// let hashers = |seed| (0..10).map(|i| SeededState::new(seed, i)).collect();
+    /// let a = Filter::with_hashers(hashers(0), 2000);
+    /// assert!(a.is_compatible(&Filter::with_hashers(hashers(0), 2000)));
+    /// assert!(!a.is_compatible(&Filter::with_hashers(hashers(1), 2000)));
+    /// assert!(!a.is_compatible(&Filter::with_hashers(hashers(0), 1000)));
+    /// ```
+    pub fn is_compatible<V2>(&self, other: &SimpleBloomFilter<B, S, V2>) -> bool
+    where
+        S: PartialEq,
+        V2: AsRef<[S]>,
+    {
+        self.set.size() == other.set.size() && self.hashers.as_ref() == other.hashers.as_ref()
+    }
+
+    /// Returns a binary filter with the same hashers, in which each
+    /// bit is set if the corresponding counter of `self` indicates
+    /// presence. This is useful for shipping a compact, read-only
+    /// copy of a counting filter which was built with deletions.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BloomFilterDelete, BloomSetRead, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut counting: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// counting.extend(0..100);
+    /// counting.remove_batch(&(50..100).collect::<Vec<_>>());
+    ///
+    /// let binary: SimpleBloomFilter<BitBox<usize, Lsb0>> = counting.to_binary();
+    /// assert!((0..50).all(|x| binary.contains(&x)));
+    /// assert_eq!(binary.counters().count_ones(), counting.counters().count_ones());
+    /// ```
+    pub fn to_binary<T, O>(&self) -> SimpleBloomFilter<BitBox<T, O>, S, V>
+    where
+        T: BitStore,
+        O: BitOrder,
+        V: Clone,
+    {
+        let bits: BitVec<T, O> = self.set.iter().collect();
+        SimpleBloomFilter {
+            hashers: self.hashers.clone(),
+            set: bits.into_boxed_bitslice(),
+            inserts: self.inserts,
+            lost_increments: self.lost_increments,
+            limit: self.limit,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Converts the filter into a [`FrozenBloomFilter`], which can no
+    /// longer be modified but can be cloned and shared cheaply.
+    pub fn freeze(self) -> FrozenBloomFilter<B, S, V> {
+        FrozenBloomFilter::from(self)
+    }
+
+    /// Returns the indices of the counters which `val` maps to, one
+    /// for each hasher.
+    pub fn indices<'a, T: Hash + ?Sized>(&'a self, val: &'a T) -> impl Iterator<Item = usize> + 'a {
+        Self::hash_indices(&self.hashers, self.set.size(), val)
+    }
+
+    fn hash_indices<'a, T: Hash + ?Sized>(
         hashers: &'a V,
         set_size: usize,
         val: &'a T,
     ) -> impl Iterator<Item = usize> + 'a
     where S: 'a {
-        hashers.as_ref().iter().map(move |b| {
-            let mut h = b.build_hasher();
-            val.hash(&mut h);
-            h.finish() as usize % set_size
-        })
+        crate::hash::hash_indices(hashers.as_ref(), set_size, val)
+    }
+
+    /// Returns whether the set's membership of any of `vals` is
+    /// `target`, stopping at the first such value. The indices of a
+    /// batch of values are computed and prefetched before any of them
+    /// are queried.
+    fn any_membership_is<I>(&self, vals: I, target: bool) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let n_hashers = self.hashers.as_ref().len();
+        let mut vals = vals.into_iter();
+        if n_hashers > INDEX_BUF_LEN {
+            return vals.any(|val| self.contains(&val) == target);
+        }
+
+        let mut buf = [0; INDEX_BUF_LEN];
+        loop {
+            let mut len = 0;
+            for val in vals.by_ref().take(INDEX_BUF_LEN / n_hashers) {
+                for i in Self::hash_indices(&self.hashers, self.set.size(), &val) {
+                    self.set.prefetch(i);
+                    buf[len] = i;
+                    len += 1;
+                }
+            }
+            if len == 0 {
+                return false;
+            }
+            if buf[..len]
+                .chunks(n_hashers)
+                .any(|indices| indices.iter().all(|&i| self.set.query(i)) == target)
+            {
+                return true;
+            }
+        }
+    }
+
+    /// Writes the indices of every value in `batch` to `buf`, so
+    /// that the hashers are not interleaved with the dependent loads
+    /// and stores of the set. `buf` must have room for the indices
+    /// of all of `batch`.
+    fn hash_batch<'b, T: Hash>(
+        hashers: &V,
+        set_size: usize,
+        batch: &[T],
+        buf: &'b mut [usize; INDEX_BUF_LEN],
+    ) -> &'b [usize] {
+        let indices = batch
+            .iter()
+            .flat_map(|val| Self::hash_indices(hashers, set_size, val));
+        let mut len = 0;
+        for (slot, i) in buf.iter_mut().zip(indices) {
+            *slot = i;
+            len += 1;
+        }
+        &buf[..len]
+    }
+}
+
+/// A [`SimpleBloomFilter`] with `K` hashers stored inline, rather
+/// than in a separate allocation. Since the number of hashers is
+/// known at compile time, the probe loops can be unrolled.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, InlineBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut filter: InlineBloomFilter<BitBox<usize, Lsb0>, 7> = InlineBloomFilter::new_inline(2000);
+/// filter.insert(&48);
+/// assert!(filter.contains(&48));
+/// assert_eq!(filter.hashers().len(), 7);
+/// ```
+pub type InlineBloomFilter<B, const K: usize, S = SeededState> = SimpleBloomFilter<B, S, [S; K]>;
+
+impl<B, S, const K: usize> SimpleBloomFilter<B, S, [S; K]>
+where
+    B: BloomSet,
+    S: BuildHasher,
+{
+    /// Creates a new `SimpleBloomFilter` with `K` inline
+    /// [`BuildHasher`]s, initialized by
+    /// [`default`](Default::default), and a specified number of
+    /// counters.
+    pub fn new_inline(n_counters: usize) -> Self
+    where
+        S: Default,
+    {
+        const { assert!(K > 0, "a filter needs at least one hasher") };
+        SimpleBloomFilter::with_hashers(std::array::from_fn(|_| S::default()), n_counters)
+    }
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: BinaryBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Estimates the Jaccard similarity of the sets of values
+    /// inserted into `self` and `other`, i.e. the size of their
+    /// intersection divided by the size of their union. The sizes are
+    /// estimated from the numbers of counters set in each filter and
+    /// in their union, so the estimate is most accurate for filters
+    /// which are far from full.
+    ///
+    /// **Both filters must have the same hashers.** Two empty filters
+    /// have a similarity of 1; the result is NaN if the filters have
+    /// different numbers of counters or either is completely full.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>>;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..5).map(|i| SeededState::new(0, i)).collect();
+    /// let mut a = Filter::with_hashers(hashers.clone(), 100_000);
+    /// let mut b = Filter::with_hashers(hashers, 100_000);
+    /// a.extend(0..3000);
+    /// b.extend(1000..4000);
+    ///
+    /// // The true similarity is 2000 / 4000
+    /// assert!((a.jaccard_estimate(&b) - 0.5).abs() < 0.05);
+    /// ```
+    pub fn jaccard_estimate<V2: AsRef<[S]>>(&self, other: &SimpleBloomFilter<B, S, V2>) -> f64 {
+        let (m, k) = (self.set.size(), self.hashers.as_ref().len());
+        if m != other.set.size() {
+            return f64::NAN;
+        }
+        let union = sizing::estimate_items(k, m, self.set.union_count_ones(&other.set));
+        if union == 0.0 {
+            return 1.0;
+        }
+        let a = sizing::estimate_items(k, m, self.set.count_ones());
+        let b = sizing::estimate_items(k, m, other.set.count_ones());
+        if !union.is_finite() {
+            return f64::NAN;
+        }
+        ((a + b - union) / union).clamp(0.0, 1.0)
+    }
+
+    /// Inserts all values from each of `filters` into `self`, as
+    /// repeated calls to [`merge`](MergeableFilter::merge) would, but
+    /// traversing the counters of `self` only once; see
+    /// [`BinaryBloomSet::union_all`]. Returns an error, leaving
+    /// `self` unchanged, if any of `filters` is incompatible with
+    /// `self`.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>>;
+    ///
+    /// let parts: Vec<Filter> = (0..8)
+    ///     .map(|p| {
+    ///         let mut f = Filter::with_seed(1, 7, 10_000);
+    ///         f.extend(p * 100..(p + 1) * 100);
+    ///         f
+    ///     })
+    ///     .collect();
+    /// let mut total = Filter::with_seed(1, 7, 10_000);
+    /// total.union_all(&parts).unwrap();
+    /// assert!((0..800).all(|x| total.contains(&x)));
+    ///
+    /// let other = Filter::with_seed(2, 7, 10_000);
+    /// assert!(total.union_all([&other]).is_err());
+    /// ```
+    pub fn union_all<'a, I>(&mut self, filters: I) -> Result<(), MismatchError>
+    where
+        I: IntoIterator<Item = &'a Self>,
+        Self: 'a,
+    {
+        let filters: Vec<&Self> = filters.into_iter().collect();
+        for filter in &filters {
+            MismatchError::check(self, *filter)?;
+        }
+        let sets: Vec<&B> = filters.iter().map(|f| &f.set).collect();
+        self.set.union_all(&sets);
+        self.inserts = filters.iter().map(|f| f.inserts).fold(self.inserts, usize::max);
+        Ok(())
+    }
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: SpectralBloomSetMut,
+    B::Count: Ord + Clone + SaturatingAdd + One,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Inserts `val` using the Minimal Increase strategy: only the
+    /// counters holding the smallest count for `val` are incremented,
+    /// and the others are raised to that new count if they are below
+    /// it. This keeps
+    /// [`find_count`](SpectralBloomFilter::find_count) from
+    /// overestimating as much as with [`insert`](BloomFilter::insert),
+    /// but values inserted this way **must not be removed**, since
+    /// their counters were not all incremented.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u32]>> = SimpleBloomFilter::new(4, 10);
+    /// for x in 0..20 {
+    ///     for _ in 0..x {
+    ///         f.insert_minimal_increase(&x);
+    ///     }
+    /// }
+    /// assert!((0..20).all(|x| f.find_count(&x) >= x));
+    /// ```
+    pub fn insert_minimal_increase<T: Hash + ?Sized>(&mut self, val: &T) {
+        self.record_inserts(1);
+        let old = self.find_count(val);
+        let count = old.saturating_add(&B::Count::one());
+        if count == old {
+            // Every counter is saturated, so none can be raised.
+            self.lost_increments = self.lost_increments.saturating_add(self.hashers.as_ref().len());
+        }
+        for i in Self::hash_indices(&self.hashers, self.set.size(), val) {
+            self.set.increment_to(i, count.clone());
+        }
+    }
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: SpectralBloomSetMut,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Creates a new `SimpleBloomFilter` whose count estimates exceed
+    /// the true counts by at most `epsilon` times the number of
+    /// insertions, except with probability `delta`, as computed by
+    /// [`sizing::count_counters`] and [`sizing::count_hashers`]. The
+    /// `BuildHasher`s will be initialized by
+    /// [`default`](Default::default).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u32]>> = SimpleBloomFilter::for_count_error(0.001, 0.01);
+    /// assert_eq!(f.hashers().len(), 5);
+    /// let bound = f.count_bound();
+    /// assert!(bound.epsilon <= 0.001 && bound.delta <= 0.01);
+    ///
+    /// for x in 0..10_000u32 {
+    ///     f.insert(&(x % 100));
+    /// }
+    /// let error = f.find_count(&7) - 100;
+    /// // Fails with probability at most delta
+    /// assert!(error as f64 <= bound.max_error(f.insert_count()));
+    /// ```
+    pub fn for_count_error(epsilon: f64, delta: f64) -> Self
+    where
+        S: Default,
+        V: FromIterator<S>,
+    {
+        SimpleBloomFilter::new(sizing::count_hashers(delta), sizing::count_counters(epsilon, delta))
+    }
+
+    /// Returns the bound on the overestimate of counts which follows
+    /// from the numbers of hashers and counters. See
+    /// [`sizing::CountBound`].
+    pub fn count_bound(&self) -> sizing::CountBound {
+        sizing::CountBound::new(self.hashers.as_ref().len(), self.set.size())
+    }
+
+    /// Returns the counters probed when looking up `val`, and the
+    /// count of each of them.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert(&48);
+    /// f.insert(&48);
+    /// assert!(f.explain_counts(&48).probes.iter().all(|p| p.value >= 2));
+    /// ```
+    pub fn explain_counts<T: Hash + ?Sized>(&self, val: &T) -> Explain<B::Count> {
+        Explain {
+            probes: Self::hash_indices(&self.hashers, self.set.size(), val)
+                .map(|index| Probe { index, value: self.set.query_count(index) })
+                .collect(),
+        }
+    }
+}
+
+impl<T, S, V> SimpleBloomFilter<Box<[T]>, S, V>
+where
+    T: SaturatingAdd + One + Zero + Ord + Bounded + Clone,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    /// Returns the number of counters which have reached the maximum
+    /// value of `T`, and so no longer count further insertions. A
+    /// growing share of saturated counters means the counts of
+    /// frequent values are being underestimated, and the filter
+    /// should be [`widen`](SimpleBloomFilter::widen)ed.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(3, 100_000);
+    /// for _ in 0..255 {
+    ///     f.insert(&"hot");
+    /// }
+    /// assert_eq!(f.lost_increments(), 0);
+    /// let saturated = f.saturated_counters();
+    /// assert!(saturated > 0 && saturated <= 3);
+    ///
+    /// f.insert(&"hot");
+    /// assert_eq!(f.lost_increments(), 3);
+    /// ```
+    pub fn saturated_counters(&self) -> usize {
+        let max = T::max_value();
+        self.set.iter_counts().filter(|c| *c == max).count()
+    }
+
+    /// Halves every counter, rounding down, so that old insertions
+    /// count for less than recent ones. Calling this periodically
+    /// makes the counts an exponentially decaying average of the
+    /// insertion rate. The [number of
+    /// insertions](SimpleBloomFilter::insert_count) is halved as
+    /// well.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(4, 10_000);
+    /// for _ in 0..9 {
+    ///     f.insert(&"hot");
+    /// }
+    /// f.insert(&"cold");
+    /// f.decay();
+    /// assert!(f.find_count(&"hot") >= 4);
+    /// // May fail if "cold" shares every counter with "hot"
+    /// assert!(!f.contains(&"cold"));
+    /// ```
+    pub fn decay(&mut self)
+    where
+        T: Shr<usize, Output = T>,
+    {
+        for c in self.set.iter_mut() {
+            *c = c.clone() >> 1usize;
+        }
+        self.inserts /= 2;
+    }
+
+    /// Returns a copy of the filter with counters of the wider type
+    /// `U`, keeping every count and the hashers. Counters which were
+    /// already saturated keep their old maximum, which is lower than
+    /// their true count, but can now grow past it.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut narrow: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(3, 1000);
+    /// for _ in 0..300 {
+    ///     narrow.insert(&"hot");
+    /// }
+    /// assert_eq!(narrow.find_count(&"hot"), 255);
+    /// assert!(narrow.saturated_counters() > 0);
+    ///
+    /// let mut wide: SimpleBloomFilter<Box<[u16]>> = narrow.widen();
+    /// wide.insert(&"hot");
+    /// assert_eq!(wide.find_count(&"hot"), 256);
+    /// ```
+    pub fn widen<U>(&self) -> SimpleBloomFilter<Box<[U]>, S, V>
+    where
+        U: From<T>,
+        V: Clone,
+    {
+        SimpleBloomFilter {
+            hashers: self.hashers.clone(),
+            set: self.set.iter_counts().map(U::from).collect(),
+            inserts: self.inserts,
+            lost_increments: self.lost_increments,
+            limit: self.limit,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<B, S, V> SimpleBloomFilter<B, S, V>
+where
+    B: SerializableSet,
+    S: SerializableHasher,
+    V: AsRef<[S]>,
+{
+    /// Writes the filter to `w` in the format described in
+    /// [`serialize`](crate::serialize). The counters are written in
+    /// chunks as they are encoded, so no serialized copy of the
+    /// filter is held in memory.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let mut f1: SimpleBloomFilter<Box<[u16]>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 100_000);
+    /// f1.insert(&48);
+    ///
+    /// let mut file = Vec::new();
+    /// f1.write_to(&mut file).unwrap();
+    ///
+    /// let f2: SimpleBloomFilter<Box<[u16]>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::read_from(file.as_slice()).unwrap();
+    /// assert_eq!(f1, f2);
+    /// ```
+    pub fn write_to<W: Write>(&self, w: W) -> Result<(), serialize::Error> {
+        serialize::write_filter(w, self.hashers.as_ref(), &self.set)
+    }
+
+    /// Reads a filter written by
+    /// [`write_to`](SimpleBloomFilter::write_to) from `r`. Reading
+    /// stops at the end of the filter, so `r` may contain further
+    /// data.
+    pub fn read_from<R: Read>(r: R) -> Result<Self, serialize::Error>
+    where
+        V: FromIterator<S>,
+    {
+        let (hashers, set) = serialize::read_filter(r)?;
+        Ok(SimpleBloomFilter::from_parts(hashers, set))
+    }
+
+    /// Returns the number of bytes
+    /// [`write_to`](SimpleBloomFilter::write_to) writes, without
+    /// serializing the filter (unless the set's
+    /// [`serialized_len`](SerializableSet::serialized_len) does).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{SimpleBloomFilter, SeededState};
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let f: SimpleBloomFilter<Box<[u16]>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 100_000);
+    /// assert_eq!(f.serialized_size_hint(), f.to_bytes().len());
+    /// ```
+    pub fn serialized_size_hint(&self) -> usize {
+        serialize::filter_len(self.hashers.as_ref(), &self.set)
+    }
+
+    /// Returns a hash of the filter's hashers and counters, so that
+    /// replicas can check that they hold the same filter without
+    /// exchanging it. The digest is computed from the serialized
+    /// form with fixed keys, so it is the same across processes and
+    /// platforms, and filters which serialize identically have equal
+    /// digests. It is not a cryptographic hash.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// type Filter = SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>>;
+    ///
+    /// let hashers = || (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let mut a = Filter::with_hashers(hashers(), 2000);
+    /// let mut b = Filter::with_hashers(hashers(), 2000);
+    /// a.insert(&48);
+    /// assert_ne!(a.digest(), b.digest());
+    /// b.insert(&48);
+    /// assert_eq!(a.digest(), b.digest());
+    /// ```
+    pub fn digest(&self) -> u64 {
+        serialize::filter_digest(self.hashers.as_ref(), &self.set)
+    }
+
+    /// Serializes the filter into a byte vector. See
+    /// [`write_to`](SimpleBloomFilter::write_to).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 20);
+    /// f1.insert(&48);
+    ///
+    /// let f2: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::from_bytes(&f1.to_bytes()).unwrap();
+    /// assert_eq!(f1, f2);
+    /// assert!(f2.contains(&48));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Deserializes a filter written by
+    /// [`to_bytes`](SimpleBloomFilter::to_bytes).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{SimpleBloomFilter, SeededState};
+    /// use generic_bloom::serialize::Error;
+    /// use bitvec::prelude::*;
+    ///
+    /// let hashers: Box<[SeededState]> = (0..10).map(|i| SeededState::new(0, i)).collect();
+    /// let f: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 20);
+    ///
+    /// // Flip a bit in the first hasher seed
+    /// let mut bytes = f.to_bytes();
+    /// bytes[11] ^= 1;
+    /// let res = SimpleBloomFilter::<BitBox<usize, Lsb0>, SeededState, Box<[SeededState]>>::from_bytes(&bytes);
+    /// assert!(matches!(res, Err(Error::ChecksumMismatch)));
+    /// ```
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, serialize::Error>
+    where
+        V: FromIterator<S>,
+    {
+        let filter = Self::read_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(serialize::Error::TrailingBytes);
+        }
+        Ok(filter)
     }
 }
 
@@ -100,28 +1053,128 @@ where
     type Hasher = S;
 
     fn counters(&self) -> &B {
-        return &self.set;
+        &self.set
+    }
+
+    fn hashers(&self) -> &[S] {
+        self.hashers.as_ref()
     }
 
-    fn insert<T: Hash>(&mut self, val: &T) {
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T) {
+        self.record_inserts(1);
         for i in Self::hash_indices(&self.hashers, self.set.size(), val) {
-            self.set.increment(i);
+            Self::increment_in(&mut self.set, &mut self.lost_increments, i);
+        }
+    }
+
+    fn insert_batch<T: Hash>(&mut self, vals: &[T]) {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > INDEX_BUF_LEN {
+            for val in vals {
+                self.insert(val);
+            }
+            return;
+        }
+
+        self.record_inserts(vals.len());
+        let mut buf = [0; INDEX_BUF_LEN];
+        for batch in vals.chunks(INDEX_BUF_LEN / n_hashers) {
+            for &i in Self::hash_batch(&self.hashers, self.set.size(), batch, &mut buf) {
+                Self::increment_in(&mut self.set, &mut self.lost_increments, i);
+            }
+        }
+    }
+
+    fn contains_all<I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        !self.any_membership_is(vals, false)
+    }
+
+    fn contains_any<I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        self.any_membership_is(vals, true)
+    }
+
+    fn contains_batch<T: Hash>(&self, vals: &[T]) -> BitVec {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > INDEX_BUF_LEN {
+            return vals.iter().map(|val| self.contains(val)).collect();
         }
+
+        let mut found = BitVec::with_capacity(vals.len());
+        let mut buf = [0; INDEX_BUF_LEN];
+        for batch in vals.chunks(INDEX_BUF_LEN / n_hashers) {
+            let indices = Self::hash_batch(&self.hashers, self.set.size(), batch, &mut buf);
+            for &i in indices {
+                self.set.prefetch(i);
+            }
+            found.extend(
+                indices
+                    .chunks(n_hashers)
+                    .map(|val_indices| val_indices.iter().all(|&i| self.set.query(i))),
+            );
+        }
+        found
     }
 
-    fn contains<T: Hash>(&self, val: &T) -> bool {
+    fn insert_checked<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        self.record_inserts(1);
+        let mut new = false;
         for i in Self::hash_indices(&self.hashers, self.set.size(), val) {
-            if !self.set.query(i) {
+            new |= !self.set.query(i);
+            Self::increment_in(&mut self.set, &mut self.lost_increments, i);
+        }
+        new
+    }
+
+    fn insert_if_absent<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > PROBE_BUF_LEN {
+            if self.contains(val) {
                 return false;
             }
+            self.insert(val);
+            return true;
         }
 
+        let mut buf = [0; PROBE_BUF_LEN];
+        for (slot, i) in buf.iter_mut().zip(Self::hash_indices(&self.hashers, self.set.size(), val)) {
+            *slot = i;
+        }
+        let indices = &buf[..n_hashers];
+        if indices.iter().all(|&i| self.set.query(i)) {
+            return false;
+        }
+        self.record_inserts(1);
+        for &i in indices {
+            self.increment_counter(i);
+        }
         true
     }
 
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        query_all(&self.set, Self::hash_indices(&self.hashers, self.set.size(), val))
+    }
+
+    fn lost_increments(&self) -> usize {
+        self.lost_increments
+    }
+
     fn clear(&mut self) {
+        self.inserts = 0;
+        self.lost_increments = 0;
         self.set.clear()
     }
+
+    fn heap_size_bytes(&self) -> usize {
+        self.set.heap_size_bytes() + crate::hash::hashers_heap_size(&self.hashers)
+    }
 }
 
 impl<B, S, V> BloomFilterDelete for SimpleBloomFilter<B, S, V>
@@ -130,11 +1183,65 @@ where
     S: BuildHasher,
     V: AsRef<[S]>,
 {
-    fn remove<T: Hash>(&mut self, val: &T) {
+    fn remove<T: Hash + ?Sized>(&mut self, val: &T) {
+        self.inserts = self.inserts.saturating_sub(1);
         for i in Self::hash_indices(&self.hashers, self.set.size(), val) {
             self.set.decrement(i);
         }
     }
+
+    fn remove_if_present<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > PROBE_BUF_LEN {
+            if !self.contains(val) {
+                return false;
+            }
+            self.remove(val);
+            return true;
+        }
+
+        let mut buf = [0; PROBE_BUF_LEN];
+        for (slot, i) in buf.iter_mut().zip(Self::hash_indices(&self.hashers, self.set.size(), val)) {
+            *slot = i;
+        }
+        let indices = &buf[..n_hashers];
+        if !indices.iter().all(|&i| self.set.query(i)) {
+            return false;
+        }
+        self.inserts = self.inserts.saturating_sub(1);
+        for &i in indices {
+            // Several hashers may map `val` to the same counter.
+            if self.set.query(i) {
+                self.set.decrement(i);
+            }
+        }
+        true
+    }
+
+    fn remove_batch<T: Hash>(&mut self, vals: &[T]) {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > INDEX_BUF_LEN {
+            for val in vals {
+                self.remove(val);
+            }
+            return;
+        }
+
+        self.inserts = self.inserts.saturating_sub(vals.len());
+        let mut buf = [0; INDEX_BUF_LEN];
+        for batch in vals.chunks(INDEX_BUF_LEN / n_hashers) {
+            for &i in Self::hash_batch(&self.hashers, self.set.size(), batch, &mut buf) {
+                self.set.decrement(i);
+            }
+        }
+    }
+
+    fn subtract<Other>(&mut self, other: &Other)
+    where
+        Other: BloomFilterDelete<Set = Self::Set, Hasher = Self::Hasher>
+    {
+        self.set.subtract(other.counters());
+    }
 }
 
 impl<B, S, V> BinaryBloomFilter for SimpleBloomFilter<B, S, V>
@@ -147,31 +1254,60 @@ where
     where
         Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>
     {
-        self.set.union(&other.counters());
+        self.set.union(other.counters());
     }
 
     fn intersect<Other>(&mut self, other: &Other)
     where
         Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>
     {
-        self.set.intersect(&other.counters());
+        self.set.intersect(other.counters());
+    }
+
+    fn symmetric_difference<Other>(&mut self, other: &Other)
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>
+    {
+        self.set.symmetric_difference(other.counters());
+    }
+
+    fn hasher_fingerprint(&self) -> Option<u64> {
+        Some(crate::hash::fingerprint(self.hashers.as_ref()))
+    }
+}
+
+/// Merges by [`BinaryBloomSet::union`], i.e. OR for bits and the
+/// maximum for counters. The [number of
+/// insertions](SimpleBloomFilter::insert_count) becomes the larger of
+/// the two.
+impl<B, S, V> MergeableFilter for SimpleBloomFilter<B, S, V>
+where
+    B: BinaryBloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+{
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        MismatchError::check(self, other)?;
+        self.set.union(&other.set);
+        self.inserts = self.inserts.max(other.inserts);
+        Ok(())
     }
 }
 
 impl<B, S, V> SpectralBloomFilter for SimpleBloomFilter<B, S, V>
 where
-    B: SpectralBloomSet,
+    B: SpectralBloomSetMut,
     B::Count: Ord,
     S: BuildHasher,
     V: AsRef<[S]>,
 {
-    fn contains_more_than<T: Hash>(
+    fn contains_more_than<T: Hash + ?Sized>(
         &self,
         val: &T,
         count: &<B as SpectralBloomSet>::Count,
     ) -> bool {
         for i in Self::hash_indices(&self.hashers, self.set.size(), val) {
-            if *self.set.query_count(i) <= *count {
+            if self.set.query_count(i) <= *count {
                 return false;
             }
         }
@@ -179,14 +1315,59 @@ where
         true
     }
 
-    fn find_count<T: Hash>(&self, val: &T) -> &<B as SpectralBloomSet>::Count {
+    fn find_count<T: Hash + ?Sized>(&self, val: &T) -> <B as SpectralBloomSet>::Count {
         Self::hash_indices(&self.hashers, self.set.size(), val)
             .map(|i| self.set.query_count(i))
             .min()
-            .unwrap()
+            .expect("filters are constructed with at least one hasher")
+    }
+
+    fn find_counts<T: Hash>(&self, vals: &[T]) -> Vec<B::Count> {
+        let n_hashers = self.hashers.as_ref().len();
+        if n_hashers > INDEX_BUF_LEN {
+            return vals.iter().map(|val| self.find_count(val)).collect();
+        }
+
+        let mut counts = Vec::with_capacity(vals.len());
+        let mut buf = [0; INDEX_BUF_LEN];
+        for batch in vals.chunks(INDEX_BUF_LEN / n_hashers) {
+            let indices = Self::hash_batch(&self.hashers, self.set.size(), batch, &mut buf);
+            for &i in indices {
+                self.set.prefetch(i);
+            }
+            counts.extend(indices.chunks(n_hashers).map(|val_indices| {
+                val_indices
+                    .iter()
+                    .map(|&i| self.set.query_count(i))
+                    .min()
+                    .expect("filters are constructed with at least one hasher")
+            }));
+        }
+        counts
+    }
+
+    fn merge_counts<Other>(&mut self, other: &Other)
+    where
+        Other: SpectralBloomFilter<Set = Self::Set, Hasher = Self::Hasher>
+    {
+        self.set.merge_counts(other.counters());
     }
 }
 
+/// Inserts each value. Since references hash like the values they
+/// refer to, this also accepts iterators over references.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let keys = vec!["a".to_string(), "b".to_string()];
+/// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+/// f.extend(keys.iter());
+/// f.extend(vec!["c".to_string()]);
+/// assert!(f.contains(&"a".to_string()) && f.contains(&"c".to_string()));
+/// ```
 impl<A: Hash, B, S, V> Extend<A> for SimpleBloomFilter<B, S, V>
 where
     B: BloomSet,
@@ -202,3 +1383,34 @@ where
         }
     }
 }
+
+/// Collects values into a filter sized for them with a false positive
+/// rate of [`DEFAULT_FPR`](crate::sizing::DEFAULT_FPR). Since the size
+/// depends on the number of values, they are buffered before being
+/// inserted; use [`for_items`](SimpleBloomFilter::for_items) and
+/// [`extend`](Extend::extend) to avoid this.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let f: SimpleBloomFilter<BitBox<usize, Lsb0>> = (0..100).collect();
+/// assert!((0..100).all(|x| f.contains(&x)));
+/// ```
+impl<A: Hash, B, S, V> FromIterator<A> for SimpleBloomFilter<B, S, V>
+where
+    B: BloomSet,
+    S: BuildHasher + Default,
+    V: AsRef<[S]> + FromIterator<S>,
+{
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = A>
+    {
+        let vals: Vec<A> = iter.into_iter().collect();
+        let mut filter = SimpleBloomFilter::for_items(vals.len(), sizing::DEFAULT_FPR);
+        filter.insert_batch(&vals);
+        filter
+    }
+}
@@ -0,0 +1,306 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Predicts how filters with different parameters would behave under
+//! a workload, without building them.
+//!
+//! [`sweep`] tries every combination of backend, number of counters
+//! and number of hashers which fits in a memory budget, and predicts
+//! the false positive rate, counter overflow and memory accesses per
+//! query of each. The predictions are analytic, so sweeping the
+//! parameters of a filter of hundreds of gigabytes is as fast as for
+//! a small one.
+//!
+//! # Example
+//! ```
+//! use generic_bloom::simulate::{self, Backend, Workload};
+//!
+//! let workload = Workload {
+//!     n_items: 1_000_000,
+//!     positive_fraction: 0.1,
+//!     memory_bytes: 2 << 20,
+//! };
+//! let candidates = simulate::sweep(&workload, &[Backend::Bits, Backend::Blocks]);
+//!
+//! // Candidates are sorted by false positive rate.
+//! let best = &candidates[0];
+//! assert_eq!(best.backend, Backend::Bits);
+//! assert!(best.fpr < 0.01);
+//!
+//! // Blocked filters touch a single cache line per query.
+//! let blocked = candidates.iter().find(|c| c.backend == Backend::Blocks).unwrap();
+//! assert_eq!(blocked.cache_lines_per_query, 1.0);
+//! assert!(blocked.fpr > best.fpr);
+//! ```
+
+use crate::sizing;
+use crate::Block;
+use std::fmt;
+
+/// A kind of set which a simulated filter could use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// One bit per counter, as with a
+    /// [`BitBox`](bitvec::boxed::BitBox).
+    Bits,
+    /// Counters of the given number of bits, as with a `Box<[u8]>`
+    /// for 8.
+    Counters(u32),
+    /// 256-bit blocks setting one bit in each of 8 words, as with a
+    /// [`BlockBloomFilter`](crate::BlockBloomFilter).
+    Blocks,
+}
+
+impl Backend {
+    fn counter_bits(self) -> u32 {
+        match self {
+            Backend::Counters(bits) => {
+                assert!(bits > 0, "counters must have at least one bit");
+                bits
+            }
+            Backend::Bits | Backend::Blocks => 1,
+        }
+    }
+}
+
+/// The expected use of a filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Workload {
+    /// The number of values which will be inserted.
+    pub n_items: usize,
+    /// The fraction of queries for values which were inserted.
+    pub positive_fraction: f64,
+    /// The most memory the counters may use, in bytes.
+    pub memory_bytes: usize,
+}
+
+/// The predicted behavior of a filter with particular parameters
+/// under a [`Workload`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub backend: Backend,
+    pub n_counters: usize,
+    /// The number of hashers, or of bits set per value for
+    /// [`Backend::Blocks`].
+    pub n_hashers: usize,
+    /// The memory used by the counters, in bytes.
+    pub memory_bytes: usize,
+    /// The false positive rate once every value is inserted.
+    pub fpr: f64,
+    /// The expected number of counters which would need to exceed
+    /// their maximum value. This is zero for bits and blocks, which
+    /// do not count.
+    pub expected_saturated: f64,
+    /// The expected number of counters read per query. Queries for
+    /// absent values stop at the first counter which is not set.
+    pub probes_per_query: f64,
+    /// The expected number of distinct cache lines read per query,
+    /// which dominates latency for filters much larger than the
+    /// cache.
+    pub cache_lines_per_query: f64,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}, m = {}, k = {}: {} bytes, fpr {:.3e}, {:.3e} saturated, {:.2} probes, {:.2} cache lines",
+            self.backend,
+            self.n_counters,
+            self.n_hashers,
+            self.memory_bytes,
+            self.fpr,
+            self.expected_saturated,
+            self.probes_per_query,
+            self.cache_lines_per_query,
+        )
+    }
+}
+
+/// Fractions of the memory budget which [`sweep`] tries.
+const BUDGET_FRACTIONS: [usize; 4] = [8, 4, 2, 1];
+/// The largest number of hashers which [`sweep`] tries.
+const MAX_HASHERS: usize = 16;
+/// Bytes in a cache line.
+const CACHE_LINE: usize = 64;
+
+/// Predicts the behavior of every combination of the given backends,
+/// numbers of counters fitting in an eighth, a quarter, half or all
+/// of the memory budget, and 1 to 16 hashers, under `workload`. The
+/// candidates are sorted by false positive rate, lowest first.
+///
+/// # Panics
+/// Panics if any of `backends` is [`Backend::Counters`] with zero
+/// bits.
+pub fn sweep(workload: &Workload, backends: &[Backend]) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for &backend in backends {
+        for fraction in BUDGET_FRACTIONS {
+            let bytes = workload.memory_bytes / fraction;
+            let n_counters = match backend {
+                Backend::Blocks => bytes / (Block::BITS / 8) * Block::BITS,
+                _ => bytes * 8 / backend.counter_bits() as usize,
+            };
+            if n_counters == 0 {
+                continue;
+            }
+            match backend {
+                Backend::Blocks => candidates.push(predict(workload, backend, n_counters, 8)),
+                _ => candidates.extend(
+                    (1..=MAX_HASHERS).map(|k| predict(workload, backend, n_counters, k)),
+                ),
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.fpr.total_cmp(&b.fpr));
+    candidates
+}
+
+/// Predicts the behavior of a single filter under `workload`. For
+/// [`Backend::Blocks`], `n_hashers` is ignored, since each value sets
+/// one bit in each of the 8 words of a block, and fewer counters
+/// than a block are rounded up to one block.
+///
+/// # Panics
+/// Panics if `backend` is [`Backend::Counters`] with zero bits.
+///
+/// # Example
+/// ```
+/// use generic_bloom::simulate::{predict, Backend, Workload};
+///
+/// let workload = Workload { n_items: 1000, positive_fraction: 0.5, memory_bytes: 1 << 20 };
+/// let wide = predict(&workload, Backend::Counters(32), 10_000, 7);
+/// assert_eq!(wide.expected_saturated, 0.0);
+/// let narrow = predict(&workload, Backend::Counters(2), 10_000, 7);
+/// assert!(narrow.expected_saturated > 10.0);
+///
+/// // Fewer counters than a block act as a single block.
+/// assert!(predict(&workload, Backend::Blocks, 100, 8).fpr > 0.99);
+/// ```
+pub fn predict(workload: &Workload, backend: Backend, n_counters: usize, n_hashers: usize) -> Candidate {
+    let n = workload.n_items;
+    let p = workload.positive_fraction;
+    let memory_bytes = (n_counters * backend.counter_bits() as usize).div_ceil(8);
+
+    if backend == Backend::Blocks {
+        let n_blocks = (n_counters / Block::BITS).max(1);
+        let fpr = block_fpr(n as f64 / n_blocks as f64);
+        return Candidate {
+            backend,
+            n_counters,
+            n_hashers: 8,
+            memory_bytes,
+            fpr,
+            expected_saturated: 0.0,
+            probes_per_query: 8.0,
+            cache_lines_per_query: 1.0,
+        };
+    }
+
+    let k = n_hashers as f64;
+    let fill = sizing::fill_ratio(n_hashers, n_counters, n);
+    // A query for an absent value reads counters until one is unset.
+    let negative_probes = if fill < 1.0 {
+        (1.0 - fill.powf(k)) / (1.0 - fill)
+    } else {
+        k
+    };
+    let probes_per_query = p * k + (1.0 - p) * negative_probes;
+    let cache_lines_per_query = if memory_bytes <= CACHE_LINE {
+        1.0
+    } else {
+        probes_per_query
+    };
+
+    let expected_saturated = match backend {
+        Backend::Counters(bits) if bits < 64 => {
+            let max = (1u64 << bits) - 1;
+            n_counters as f64 * poisson_tail(k * n as f64 / n_counters as f64, max + 1)
+        }
+        _ => 0.0,
+    };
+
+    Candidate {
+        backend,
+        n_counters,
+        n_hashers,
+        memory_bytes,
+        fpr: sizing::false_positive_rate(n_hashers, n_counters, n),
+        expected_saturated,
+        probes_per_query,
+        cache_lines_per_query,
+    }
+}
+
+/// Returns the false positive rate of a blocked filter whose blocks
+/// hold `load` values on average. The number of values in a block
+/// is Poisson distributed, and each sets one of the 32 bits in each
+/// word.
+fn block_fpr(load: f64) -> f64 {
+    let mut fpr = 0.0;
+    let mut log_pmf = -load;
+    let mut j = 0.0;
+    // Sum until past the mean and the terms are negligible.
+    loop {
+        let pmf = log_pmf.exp();
+        let word_fill = 1.0 - (31.0f64 / 32.0).powf(j);
+        fpr += pmf * word_fill.powi(8);
+        j += 1.0;
+        if j > load && pmf < 1e-16 {
+            return fpr;
+        }
+        log_pmf += load.ln() - f64::ln(j);
+    }
+}
+
+/// Returns the probability that a Poisson variable with mean `mean`
+/// is at least `min`.
+fn poisson_tail(mean: f64, min: u64) -> f64 {
+    if mean == 0.0 {
+        return 0.0;
+    }
+    let spread = 10.0 * mean.sqrt() + 10.0;
+    if (min as f64) < mean - spread {
+        return 1.0;
+    }
+    // Start from the log of the first term to avoid underflow.
+    let mut log_pmf = -mean + min as f64 * mean.ln() - ln_factorial(min);
+    // Past the mean the terms only shrink, so if the first is too
+    // small to represent, so is the tail.
+    if min as f64 > mean && log_pmf < f64::MIN_POSITIVE.ln() {
+        return 0.0;
+    }
+    let mut tail = 0.0;
+    let mut j = min;
+    loop {
+        let pmf = log_pmf.exp();
+        tail += pmf;
+        if pmf < tail * 1e-15 || (j as f64 > mean && pmf == 0.0) {
+            break;
+        }
+        j += 1;
+        log_pmf += mean.ln() - (j as f64).ln();
+    }
+    tail.min(1.0)
+}
+
+/// Returns `ln(n!)`, exactly for small `n` and by Stirling's series
+/// otherwise.
+fn ln_factorial(n: u64) -> f64 {
+    if n < 256 {
+        return (2..=n).map(|i| (i as f64).ln()).sum();
+    }
+    let n = n as f64;
+    n * n.ln() - n + 0.5 * (std::f64::consts::TAU * n).ln() + 1.0 / (12.0 * n) - 1.0 / (360.0 * n.powi(3))
+}
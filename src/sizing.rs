@@ -0,0 +1,174 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Formulas for choosing the parameters of a Bloom filter.
+//!
+//! For a filter with `m` counters and `k` hashers containing `n`
+//! values, the false positive rate is approximately
+//! `(1 - e^(-kn/m))^k`, and is minimized by `k = (m/n) ln 2`.
+//! Counting filters can instead be sized by how much their count
+//! estimates may be off; see [`CountBound`].
+//!
+//! # Example
+//! ```
+//! use generic_bloom::sizing;
+//!
+//! let n_counters = sizing::optimal_counters(1_000_000, 0.01);
+//! let n_hashers = sizing::optimal_hashers(n_counters, 1_000_000);
+//! assert_eq!(n_hashers, 7);
+//! let fpr = sizing::false_positive_rate(n_hashers, n_counters, 1_000_000);
+//! assert!((fpr - 0.01).abs() < 0.001);
+//! ```
+
+use std::f64::consts::{E, LN_2};
+
+/// False positive rate which filters are sized for when no rate is
+/// specified, e.g. when they are created with
+/// [`FromIterator`].
+pub const DEFAULT_FPR: f64 = 0.01;
+
+/// Returns the number of counters needed to hold `n_items` values
+/// with a false positive rate of at most `fpr`, using the optimal
+/// number of hashers.
+pub fn optimal_counters(n_items: usize, fpr: f64) -> usize {
+    assert!(fpr > 0.0 && fpr < 1.0, "the false positive rate must be between 0 and 1");
+    let m = -(n_items as f64) * fpr.ln() / (LN_2 * LN_2);
+    (m.ceil() as usize).max(1)
+}
+
+/// Returns the number of hashers which minimizes the false positive
+/// rate of a filter with `n_counters` counters holding `n_items`
+/// values.
+pub fn optimal_hashers(n_counters: usize, n_items: usize) -> usize {
+    let k = n_counters as f64 / n_items.max(1) as f64 * LN_2;
+    (k.round() as usize).max(1)
+}
+
+/// Returns the expected false positive rate of a filter with
+/// `n_hashers` hashers and `n_counters` counters holding `n_items`
+/// values.
+pub fn false_positive_rate(n_hashers: usize, n_counters: usize, n_items: usize) -> f64 {
+    fill_ratio(n_hashers, n_counters, n_items).powf(n_hashers as f64)
+}
+
+/// Returns the expected fraction of counters which indicate presence
+/// in a filter with `n_hashers` hashers and `n_counters` counters
+/// holding `n_items` values.
+pub fn fill_ratio(n_hashers: usize, n_counters: usize, n_items: usize) -> f64 {
+    1.0 - (-(n_hashers as f64) * n_items as f64 / n_counters as f64).exp()
+}
+
+/// A probabilistic bound on how much a counting filter overestimates
+/// counts: with probability at least `1 - delta`,
+/// [`find_count`](crate::SpectralBloomFilter::find_count) exceeds
+/// the true count of a value by at most `epsilon` times the total
+/// number of insertions.
+///
+/// Each insertion adds `k` to `m` counters, so the counter for a
+/// value holds on average `k N / m` from other values, and by
+/// Markov's inequality exceeds `εN` with probability at most
+/// `k / (mε)`. The estimate is the smallest of `k` such counters,
+/// so it is that far off with probability at most `(k / (mε))^k`.
+/// This is the bound of a Count-Min sketch with the same number of
+/// counters, and holds as long as no counter saturates.
+///
+/// # Example
+/// ```
+/// use generic_bloom::sizing::{self, CountBound};
+///
+/// let bound = CountBound::new(sizing::count_hashers(0.01), sizing::count_counters(0.001, 0.01));
+/// assert!(bound.epsilon <= 0.001);
+/// assert!(bound.delta <= 0.01);
+/// assert!(bound.max_error(1_000_000) <= 1000.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CountBound {
+    /// The largest overestimate, as a fraction of the number of
+    /// insertions.
+    pub epsilon: f64,
+    /// The probability that an estimate is off by more than
+    /// `epsilon`.
+    pub delta: f64,
+}
+
+impl CountBound {
+    /// Returns the bound of a filter with `n_hashers` hashers and
+    /// `n_counters` counters, taking `epsilon = e k / m`, for which
+    /// `delta = e^(-k)`.
+    pub fn new(n_hashers: usize, n_counters: usize) -> Self {
+        let k = n_hashers as f64;
+        CountBound {
+            epsilon: E * k / n_counters as f64,
+            delta: (-k).exp(),
+        }
+    }
+
+    /// Returns the largest overestimate after `n_inserts`
+    /// insertions, except with probability `delta`.
+    pub fn max_error(&self, n_inserts: usize) -> f64 {
+        self.epsilon * n_inserts as f64
+    }
+}
+
+/// Returns the number of hashers a counting filter needs for its
+/// count estimates to fail the bound of [`count_counters`] with
+/// probability at most `delta`.
+pub fn count_hashers(delta: f64) -> usize {
+    assert!(delta > 0.0 && delta < 1.0, "the failure probability must be between 0 and 1");
+    ((1.0 / delta).ln().ceil() as usize).max(1)
+}
+
+/// Returns the number of counters a counting filter with
+/// [`count_hashers(delta)`](count_hashers) hashers needs so that its
+/// count estimates exceed the true counts by at most `epsilon` times
+/// the number of insertions, except with probability `delta`. See
+/// [`CountBound`].
+pub fn count_counters(epsilon: f64, delta: f64) -> usize {
+    assert!(epsilon > 0.0, "the error bound must be positive");
+    let k = count_hashers(delta) as f64;
+    ((E * k / epsilon).ceil() as usize).max(1)
+}
+
+/// A limit on how full a filter may become, as enforced by
+/// [`SimpleBloomFilter::try_insert`](crate::SimpleBloomFilter::try_insert).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CapacityLimit {
+    /// The expected fraction of counters which indicate presence may
+    /// not exceed this, as given by [`fill_ratio`].
+    FillRatio(f64),
+    /// The expected false positive rate may not exceed this, as given
+    /// by [`false_positive_rate`].
+    FalsePositiveRate(f64),
+}
+
+impl CapacityLimit {
+    /// Checks whether a filter with `n_hashers` hashers and
+    /// `n_counters` counters may hold `n_items` values.
+    pub fn allows(&self, n_hashers: usize, n_counters: usize, n_items: usize) -> bool {
+        match *self {
+            CapacityLimit::FillRatio(max) => fill_ratio(n_hashers, n_counters, n_items) <= max,
+            CapacityLimit::FalsePositiveRate(max) => {
+                false_positive_rate(n_hashers, n_counters, n_items) <= max
+            }
+        }
+    }
+}
+
+/// Estimates the number of distinct values in a filter with
+/// `n_hashers` hashers and `n_counters` counters, of which `n_ones`
+/// indicate presence. Returns infinity if every counter does.
+pub fn estimate_items(n_hashers: usize, n_counters: usize, n_ones: usize) -> f64 {
+    let m = n_counters as f64;
+    -m / n_hashers as f64 * (1.0 - n_ones as f64 / m).ln()
+}
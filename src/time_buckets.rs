@@ -0,0 +1,182 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::ops::{Bound, Range, RangeBounds, RangeInclusive};
+
+/// Filters of the keys seen in each bucket of time, for answering
+/// "was this key seen between these times?".
+///
+/// Timestamps are `u64`s in whatever unit suits, e.g. seconds since
+/// the Unix epoch, and bucket `b` holds the keys inserted at times in
+/// `b * bucket_width..(b + 1) * bucket_width`. Each bucket's filter
+/// is a clone of a template filter, created when the first key is
+/// inserted in it, so buckets without keys take no memory. Unlike a
+/// [`DedupWindow`](crate::DedupWindow), which only knows the current
+/// window, any range of buckets can be queried, and old buckets are
+/// kept until they are [`evict`](TimeBucketedFilters::evict_before)ed.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SimpleBloomFilter, TimeBucketedFilters};
+/// use bitvec::prelude::*;
+///
+/// let template: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 7, 10_000);
+/// // Hourly buckets.
+/// let mut seen = TimeBucketedFilters::new(3600, template);
+/// seen.insert_at(1_000, &"alice");
+/// seen.insert_at(8_000, &"bob");
+///
+/// assert!(seen.contains_in_range(0..3600, &"alice"));
+/// assert!(!seen.contains_in_range(3600.., &"alice"));
+/// assert!(seen.contains_in_range(5_000..=9_000, &"bob"));
+/// assert!(seen.contains_within(9_000, 1_000, &"bob"));
+///
+/// // Forget the first two hours.
+/// assert_eq!(seen.evict_before(7200), 1);
+/// assert!(!seen.contains_in_range(.., &"alice"));
+/// assert!(seen.contains_in_range(.., &"bob"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeBucketedFilters<F> {
+    bucket_width: u64,
+    template: F,
+    buckets: BTreeMap<u64, F>,
+}
+
+impl<F: BloomFilter + Clone> TimeBucketedFilters<F> {
+    /// Creates a `TimeBucketedFilters` with buckets `bucket_width`
+    /// time units wide, whose filters are clones of `template`,
+    /// which should be empty.
+    ///
+    /// # Panics
+    /// Panics if `bucket_width` is zero.
+    pub fn new(bucket_width: u64, template: F) -> Self {
+        assert!(bucket_width > 0, "buckets must be at least one time unit wide");
+        TimeBucketedFilters {
+            bucket_width,
+            template,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn bucket_width(&self) -> u64 {
+        self.bucket_width
+    }
+
+    /// Returns the number of buckets holding keys.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Checks whether no bucket holds keys.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Returns the index of the bucket holding keys inserted at `ts`.
+    pub fn bucket_of(&self, ts: u64) -> u64 {
+        ts / self.bucket_width
+    }
+
+    /// Returns the filter of the bucket holding keys inserted at
+    /// `ts`, if any keys were.
+    pub fn bucket(&self, ts: u64) -> Option<&F> {
+        self.buckets.get(&self.bucket_of(ts))
+    }
+
+    /// Returns the buckets holding keys, in order, with the times
+    /// they cover.
+    pub fn buckets(&self) -> impl Iterator<Item = (Range<u64>, &F)> + '_ {
+        self.buckets.iter().map(|(&b, f)| (self.bucket_range(b), f))
+    }
+
+    /// Inserts `key`, seen at time `ts`.
+    pub fn insert_at<T: Hash + ?Sized>(&mut self, ts: u64, key: &T) {
+        let bucket = self.bucket_of(ts);
+        self.buckets
+            .entry(bucket)
+            .or_insert_with(|| self.template.clone())
+            .insert(key);
+    }
+
+    /// Checks whether `key` was inserted in any bucket overlapping
+    /// the times in `range`. Since whole buckets are checked, keys
+    /// inserted up to a bucket's width outside `range` may also be
+    /// found.
+    pub fn contains_in_range<T, R>(&self, range: R, key: &T) -> bool
+    where
+        T: Hash + ?Sized,
+        R: RangeBounds<u64>,
+    {
+        let Some(buckets) = self.overlapping_buckets(range) else {
+            return false;
+        };
+        self.buckets.range(buckets).any(|(_, f)| f.contains(key))
+    }
+
+    /// Checks whether `key` was seen within `window` time units
+    /// before `now`, inclusive.
+    pub fn contains_within<T: Hash + ?Sized>(&self, now: u64, window: u64, key: &T) -> bool {
+        self.contains_in_range(now.saturating_sub(window)..=now, key)
+    }
+
+    /// Removes the buckets which end at or before `ts`, i.e. hold only
+    /// keys inserted before `ts`, returning how many were removed.
+    /// Keys inserted before `ts` in the bucket containing `ts` are
+    /// kept until that bucket is evicted.
+    pub fn evict_before(&mut self, ts: u64) -> usize {
+        let kept = self.buckets.split_off(&self.bucket_of(ts));
+        let evicted = std::mem::replace(&mut self.buckets, kept);
+        evicted.len()
+    }
+
+    /// Removes every bucket.
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the buckets' filters.
+    pub fn heap_size_bytes(&self) -> usize {
+        self.buckets
+            .values()
+            .map(|f| std::mem::size_of::<(u64, F)>() + f.heap_size_bytes())
+            .sum()
+    }
+
+    /// Returns the times covered by bucket `bucket`.
+    fn bucket_range(&self, bucket: u64) -> Range<u64> {
+        let start = bucket * self.bucket_width;
+        start..start.saturating_add(self.bucket_width)
+    }
+
+    /// Returns the indices of the buckets overlapping `range`, or
+    /// `None` if `range` is empty.
+    fn overlapping_buckets<R: RangeBounds<u64>>(&self, range: R) -> Option<RangeInclusive<u64>> {
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e,
+            Bound::Excluded(&e) => e.checked_sub(1)?,
+            Bound::Unbounded => u64::MAX,
+        };
+        (start <= end).then(|| self.bucket_of(start)..=self.bucket_of(end))
+    }
+}
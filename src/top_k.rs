@@ -0,0 +1,219 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+type Count<F> = <<F as BloomFilter>::Set as SpectralBloomSet>::Count;
+
+/// Tracks the `k` most frequent keys inserted into a
+/// [`SpectralBloomFilter`].
+///
+/// Every key is counted by the filter, but only the current top `k`
+/// are stored, along with their estimated counts, in a min-heap. A
+/// key which is not tracked replaces the least frequent tracked key
+/// once its estimated count exceeds that key's. Since the filter
+/// never underestimates counts, every key which was inserted more
+/// often than the `k`-th most frequent key is tracked, although rare
+/// keys sharing counters with frequent ones may be tracked too.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SimpleBloomFilter, TopK};
+///
+/// let mut top = TopK::new(SimpleBloomFilter::<Box<[u32]>>::new(4, 10_000), 2);
+/// for word in "a b a c a b d a b e".split(' ') {
+///     top.insert(&word);
+/// }
+/// assert_eq!(top.top(), vec![(&"a", 4), (&"b", 3)]);
+/// ```
+pub struct TopK<K, F>
+where
+    F: BloomFilter,
+    F::Set: SpectralBloomSet,
+{
+    filter: F,
+    k: usize,
+    counts: HashMap<K, Count<F>>,
+    // May hold outdated entries for tracked keys, which are skipped
+    // when they reach the top. Each tracked key has exactly one entry
+    // with its current count.
+    heap: BinaryHeap<Reverse<(Count<F>, K)>>,
+}
+
+impl<K, F> TopK<K, F>
+where
+    K: Hash + Eq + Ord + Clone,
+    F: SpectralBloomFilter,
+    F::Set: SpectralBloomSet,
+    Count<F>: Ord + Clone,
+{
+    /// Creates a `TopK` tracking the `k` most frequent keys inserted
+    /// into `filter`. Keys which were inserted into `filter` before
+    /// are only tracked once they are inserted again.
+    ///
+    /// # Panics
+    /// Panics if `k` is zero.
+    pub fn new(filter: F, k: usize) -> Self {
+        assert!(k > 0, "TopK must track at least one key");
+        TopK {
+            filter,
+            k,
+            counts: HashMap::with_capacity(k),
+            heap: BinaryHeap::with_capacity(2 * k),
+        }
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the underlying filter, discarding the tracked keys.
+    pub fn into_filter(self) -> F {
+        self.filter
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Inserts `key` into the filter, and tracks it if it is now
+    /// among the `k` most frequent keys.
+    pub fn insert(&mut self, key: &K) {
+        self.filter.insert(key);
+        let count = self.filter.find_count(key);
+
+        if let Some(tracked) = self.counts.get_mut(key) {
+            // A saturated count does not change, and pushing it again
+            // would leave two current entries for the key.
+            if *tracked != count {
+                *tracked = count.clone();
+                self.push(count, key.clone());
+            }
+            return;
+        }
+
+        if self.counts.len() == self.k {
+            let min = self.pop_outdated().expect("a full TopK tracks at least one key");
+            if count <= min {
+                return;
+            }
+            let Reverse((_, evicted)) = self.heap.pop().expect("pop_outdated leaves the minimum on the heap");
+            self.counts.remove(&evicted);
+        }
+        self.counts.insert(key.clone(), count.clone());
+        self.push(count, key.clone());
+    }
+
+    /// Returns the estimated count of `key`, whether or not it is
+    /// tracked. See [`SpectralBloomFilter::find_count`].
+    pub fn find_count(&self, key: &K) -> Count<F> {
+        self.filter.find_count(key)
+    }
+
+    /// Returns the tracked keys and their estimated counts, most
+    /// frequent first. Keys with equal counts are in ascending order.
+    pub fn top(&self) -> Vec<(&K, Count<F>)> {
+        let mut top: Vec<_> = self.counts.iter().map(|(k, c)| (k, c.clone())).collect();
+        top.sort_by(|(k1, c1), (k2, c2)| c2.cmp(c1).then_with(|| k1.cmp(k2)));
+        top
+    }
+
+    /// Clears the filter and stops tracking every key.
+    pub fn clear(&mut self) {
+        self.filter.clear();
+        self.counts.clear();
+        self.heap.clear();
+    }
+
+    fn push(&mut self, count: Count<F>, key: K) {
+        self.heap.push(Reverse((count, key)));
+        // Outdated entries are only dropped when they reach the top,
+        // so rebuild the heap before they outnumber the current ones.
+        if self.heap.len() > 2 * self.k {
+            self.heap = self
+                .counts
+                .iter()
+                .map(|(k, c)| Reverse((c.clone(), k.clone())))
+                .collect();
+        }
+    }
+
+    /// Drops outdated entries from the top of the heap, and returns
+    /// the smallest tracked count.
+    fn pop_outdated(&mut self) -> Option<Count<F>> {
+        while let Some(Reverse((count, key))) = self.heap.peek() {
+            if self.counts.get(key) == Some(count) {
+                return Some(count.clone());
+            }
+            self.heap.pop();
+        }
+        None
+    }
+}
+
+impl<K, F> Clone for TopK<K, F>
+where
+    K: Clone,
+    F: BloomFilter + Clone,
+    F::Set: SpectralBloomSet,
+    Count<F>: Clone,
+{
+    fn clone(&self) -> Self {
+        TopK {
+            filter: self.filter.clone(),
+            k: self.k,
+            counts: self.counts.clone(),
+            heap: self.heap.clone(),
+        }
+    }
+}
+
+impl<K, F> fmt::Debug for TopK<K, F>
+where
+    K: fmt::Debug,
+    F: BloomFilter + fmt::Debug,
+    F::Set: SpectralBloomSet,
+    Count<F>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TopK")
+            .field("filter", &self.filter)
+            .field("k", &self.k)
+            .field("counts", &self.counts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, F> Extend<K> for TopK<K, F>
+where
+    K: Hash + Eq + Ord + Clone,
+    F: SpectralBloomFilter,
+    F::Set: SpectralBloomSet,
+    Count<F>: Ord + Clone,
+{
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = K>,
+    {
+        for key in iter {
+            self.insert(&key);
+        }
+    }
+}
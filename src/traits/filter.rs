@@ -14,8 +14,10 @@
 
 //! Traits for types which act as Bloom filters.
 
+use std::fmt;
 use std::hash::{Hash, BuildHasher};
 use crate::traits::set::*;
+use bitvec::vec::BitVec;
 
 /// Supertrait for all types which act as Bloom filters.
 pub trait BloomFilter {
@@ -26,14 +28,291 @@ pub trait BloomFilter {
     /// `BloomFilter`.
     fn counters(&self) -> &Self::Set;
 
-    /// Inserts `val` into the set.
-    fn insert<T: Hash>(&mut self, val: &T);
+    /// Gets the [`BuildHasher`]s which this `BloomFilter` uses to map
+    /// values to counters.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SeededState};
+    /// use bitvec::prelude::*;
+    ///
+    /// fn same_hashers<F: BloomFilter<Hasher = SeededState>>(a: &F, b: &F) -> bool {
+    ///     a.hashers() == b.hashers()
+    /// }
+    ///
+    /// let hashers: Vec<SeededState> = (0..10).map(|i| SeededState::new(i, 0)).collect();
+    /// let f1: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Vec<SeededState>> =
+    ///     SimpleBloomFilter::with_hashers(hashers.clone(), 2000);
+    /// let f2: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Vec<SeededState>> =
+    ///     SimpleBloomFilter::with_hashers(hashers, 2000);
+    /// assert!(same_hashers(&f1, &f2));
+    /// ```
+    fn hashers(&self) -> &[Self::Hasher];
+
+    /// Inserts `val` into the set. Unsized values such as `str` and
+    /// `[u8]` can be inserted directly, and hash like the owned types
+    /// which borrow as them (e.g. `String` and `Vec<u8>`).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert("example.com");
+    /// assert!(f.contains(&String::from("example.com")));
+    /// assert!(f.contains("example.com"));
+    /// ```
+    fn insert<T: Hash + ?Sized>(&mut self, val: &T);
+
+    /// Inserts every value in `vals`. Implementations may hash
+    /// several values before updating any counters, which is faster
+    /// than calling [`insert`](BloomFilter::insert) for each value.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// let vals: Vec<u32> = (0..100).collect();
+    /// f.insert_batch(&vals);
+    /// assert!(vals.iter().all(|x| f.contains(x)));
+    /// ```
+    fn insert_batch<T: Hash>(&mut self, vals: &[T]) {
+        for val in vals {
+            self.insert(val);
+        }
+    }
+
+    /// Inserts `val` into the set, returning whether any of its
+    /// counters was previously unset, i.e. whether `val` was
+    /// (probably) new. This can be used to count distinct values
+    /// without a separate query.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// let distinct = [1, 2, 1, 3, 2].iter().filter(|x| f.insert_checked(x)).count();
+    /// // May fail if a value is a false positive
+    /// assert_eq!(distinct, 3);
+    /// ```
+    fn insert_checked<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let new = !self.contains(val);
+        self.insert(val);
+        new
+    }
+
+    /// Inserts `val` unless the set already contains it, returning
+    /// whether it was inserted, i.e. whether it was (probably) new.
+    /// Unlike calling [`contains`](BloomFilter::contains) and then
+    /// [`insert`](BloomFilter::insert), implementations may hash
+    /// `val` only once.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// assert!(f.insert_if_absent(&48));
+    /// assert!(!f.insert_if_absent(&48));
+    /// assert_eq!(f.find_count(&48), 1);
+    /// ```
+    fn insert_if_absent<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        if self.contains(val) {
+            false
+        } else {
+            self.insert(val);
+            true
+        }
+    }
+
+    /// Checks whether no value has been inserted since the set was
+    /// created or cleared, in which case every query would return
+    /// `false`. This scans the counters, stopping at the first
+    /// nonzero one, rather than probing any value.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// assert!(f.is_empty());
+    /// f.insert(&48);
+    /// assert!(!f.is_empty());
+    /// f.clear();
+    /// assert!(f.is_empty());
+    /// ```
+    fn is_empty(&self) -> bool {
+        self.counters().is_clear()
+    }
 
     /// Checks whether the set contains `val`.
-    fn contains<T: Hash>(&self, val: &T) -> bool;
+    fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool;
+
+    /// Checks whether the set contains all of `vals`, stopping at the
+    /// first value it does not contain. Implementations may hash
+    /// several values before probing the set for any of them.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// f.extend(["apple", "banana"]);
+    /// assert!(f.contains_all(["apple", "banana"]));
+    /// // May fail if "cherry" is a false positive
+    /// assert!(!f.contains_all(["apple", "cherry"]));
+    /// ```
+    fn contains_all<I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        vals.into_iter().all(|val| self.contains(&val))
+    }
+
+    /// Checks whether the set contains any of `vals`, stopping at the
+    /// first value it contains. Like
+    /// [`contains_all`](BloomFilter::contains_all), implementations
+    /// may hash several values before probing the set.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert(&"banana");
+    /// assert!(f.contains_any(["apple", "banana"]));
+    /// // May fail if "apple" or "cherry" is a false positive
+    /// assert!(!f.contains_any(["apple", "cherry"]));
+    /// ```
+    fn contains_any<I>(&self, vals: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        vals.into_iter().any(|val| self.contains(&val))
+    }
+
+    /// Checks whether the set contains each value in `vals`,
+    /// returning one bit per value. Like
+    /// [`insert_batch`](BloomFilter::insert_batch), implementations
+    /// may hash several values before probing the set.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert(&1);
+    /// f.insert(&3);
+    ///
+    /// let found = f.contains_batch(&[1, 2, 3]);
+    /// assert!(found[0] && found[2]);
+    /// // May fail if 2 is a false positive
+    /// assert!(!found[1]);
+    /// ```
+    fn contains_batch<T: Hash>(&self, vals: &[T]) -> BitVec {
+        vals.iter().map(|val| self.contains(val)).collect()
+    }
 
     /// Clears all values from the set.
     fn clear(&mut self);
+
+    /// Returns the probability that a value which was never inserted
+    /// is reported as present, given the current counters. By
+    /// default, this is the fraction of counters which indicate
+    /// presence raised to the number of
+    /// [`hashers`](BloomFilter::hashers). See
+    /// [`verify::measure_false_positive_rate`](crate::verify::measure_false_positive_rate)
+    /// to compare it with the observed rate.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(7, 9586);
+    /// assert_eq!(f.expected_false_positive_rate(), 0.0);
+    /// f.insert_batch(&(0..1000).collect::<Vec<_>>());
+    /// assert!((f.expected_false_positive_rate() - 0.01).abs() < 0.005);
+    /// ```
+    fn expected_false_positive_rate(&self) -> f64 {
+        let set = self.counters();
+        let fill = set.count_ones() as f64 / set.size() as f64;
+        fill.powi(self.hashers().len() as i32)
+    }
+
+    /// Checks whether the set contains `val`, and returns the
+    /// probability that the answer is right. A negative answer is
+    /// always right. A positive one is wrong with the probability
+    /// that a value which was never inserted is found, given the
+    /// current fill of the counters and the number of hashers; this
+    /// is not the probability that `val` in particular was inserted,
+    /// which also depends on how often absent values are queried.
+    ///
+    /// By default, the probability is the
+    /// [`expected_false_positive_rate`](BloomFilter::expected_false_positive_rate)
+    /// of the whole filter, which may scan every counter, so callers
+    /// making many queries should compute it once instead.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(7, 10_000);
+    /// f.extend(0..1000);
+    /// let (found, confidence) = f.contains_with_confidence(&48);
+    /// assert!(found);
+    /// assert!(confidence > 0.99 && confidence < 1.0);
+    ///
+    /// f.extend(1000..5000);
+    /// assert!(f.contains_with_confidence(&48).1 < confidence);
+    /// ```
+    fn contains_with_confidence<T: Hash + ?Sized>(&self, val: &T) -> (bool, f64) {
+        if self.contains(val) {
+            (true, 1.0 - self.expected_false_positive_rate())
+        } else {
+            (false, 1.0)
+        }
+    }
+
+    /// Returns the number of counter increments which were lost
+    /// because the counter was already at its maximum value, since
+    /// the filter was created or last cleared. Any lost increment
+    /// means the counts of some values are underestimated; see
+    /// [`SimpleBloomFilter::saturated_counters`](crate::SimpleBloomFilter::saturated_counters).
+    /// Bit sets never lose increments. Returns zero by default, for
+    /// filters which do not keep track.
+    fn lost_increments(&self) -> usize {
+        0
+    }
+
+    /// Returns the approximate number of bytes of heap memory used by
+    /// the filter. By default, this only counts the
+    /// [`counters`](BloomFilter::counters).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let f: SimpleBloomFilter<BitBox<u64, Lsb0>> = SimpleBloomFilter::new(10, 1000);
+    /// // 16 words of counters, plus the hashers.
+    /// assert!(f.heap_size_bytes() >= 16 * 8);
+    /// ```
+    fn heap_size_bytes(&self) -> usize {
+        self.counters().heap_size_bytes()
+    }
 }
 
 /// Trait for types which act as Bloom filters and support deletion.
@@ -63,11 +342,104 @@ where
     /// // Only check if the result is the same as it was
     /// // before, in case it was a false positive.
     /// assert!(f.contains(&30) == contains_30);
-    fn remove<T: Hash>(&mut self, val: &T);
+    fn remove<T: Hash + ?Sized>(&mut self, val: &T);
+
+    /// Removes `val` only if the set contains it, returning whether
+    /// it did. Unlike [`remove`](BloomFilterDelete::remove), this
+    /// never decrements a counter which is already zero, so removing
+    /// a value which was never inserted cannot cause false negatives
+    /// unless the value was a false positive.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BloomFilterDelete, SimpleBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert(&48);
+    /// assert!(f.remove_if_present(&48));
+    /// assert!(!f.remove_if_present(&48));
+    /// ```
+    fn remove_if_present<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        if self.contains(val) {
+            self.remove(val);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes every value in `vals`, like
+    /// [`insert_batch`](BloomFilter::insert_batch) does for
+    /// insertions. **Each value must have been added to the set, as
+    /// for [`remove`](BloomFilterDelete::remove).**
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BloomFilterDelete, SimpleBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// let vals: Vec<u32> = (0..100).collect();
+    /// f.insert_batch(&vals);
+    /// f.remove_batch(&vals[..50]);
+    /// assert!(vals[50..].iter().all(|x| f.contains(x)));
+    /// ```
+    fn remove_batch<T: Hash>(&mut self, vals: &[T]) {
+        for val in vals {
+            self.remove(val);
+        }
+    }
+
+    /// Removes every value inserted into `other` from `self`, e.g. to
+    /// expire a batch of values which was also recorded in its own
+    /// filter. **`other` and `self` must have the same
+    /// [`BuildHasher`]s for this to work, and every value in `other`
+    /// must have been added to `self`, as for
+    /// [`remove`](BloomFilterDelete::remove).**
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BloomFilterDelete, SimpleBloomFilter};
+    ///
+    /// let mut all: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(10, 2000);
+    /// let mut yesterday: SimpleBloomFilter<Box<[u8]>> =
+    ///     SimpleBloomFilter::with_hashers(all.hashers().clone(), 2000);
+    /// all.extend(0..100);
+    /// yesterday.extend(0..50);
+    ///
+    /// all.subtract(&yesterday);
+    /// assert!((50..100).all(|x| all.contains(&x)));
+    /// ```
+    fn subtract<Other>(&mut self, other: &Other)
+    where
+        Other: BloomFilterDelete<Set = Self::Set, Hasher = Self::Hasher>;
 }
 
 /// Trait for types which act as Bloom filters and support set
 /// operations.
+///
+/// The filters provided by this crate also implement the `|`, `&` and
+/// `^` operators (and `|=`, `&=` and `^=`) in terms of
+/// [`union`](BinaryBloomFilter::union),
+/// [`intersect`](BinaryBloomFilter::intersect) and
+/// [`symmetric_difference`](BinaryBloomFilter::symmetric_difference).
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// let mut a: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+/// let mut b: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+///     SimpleBloomFilter::with_hashers(a.hashers().clone(), 2000);
+/// a.insert(&1);
+/// b.insert(&2);
+///
+/// let merged = &a | &b;
+/// assert!(merged.contains(&1) && merged.contains(&2));
+///
+/// a &= &merged;
+/// assert!(a.contains(&1));
+/// ```
 pub trait BinaryBloomFilter: BloomFilter
 where
     Self::Set: BinaryBloomSet,
@@ -83,17 +455,15 @@ where
     /// use generic_bloom::{BloomFilter, BinaryBloomFilter, SimpleBloomFilter};
     /// use bitvec::prelude::*;
     ///
-    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 20);
+    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20);
     /// f1.insert(&48);
     /// f1.insert(&32);
     ///
-    /// let mut f2: SimpleBloomFilter<BitBox<usize, Lsb0>> =
-    ///     SimpleBloomFilter::with_hashers(f1.hashers().clone(), 20);
+    /// let mut f2: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20);
     /// f2.insert(&39);
     ///
     /// assert!(f1.contains(&48));
     /// assert!(f1.contains(&32));
-    /// // May fail if 39 is a false positive
     /// assert!(!f1.contains(&39));
     /// assert!(f2.contains(&39));
     ///
@@ -118,32 +488,298 @@ where
     /// use generic_bloom::{BloomFilter, BinaryBloomFilter, SimpleBloomFilter};
     /// use bitvec::prelude::*;
     ///
-    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 20);
+    /// let mut f1: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20);
     /// f1.insert(&48);
     /// f1.insert(&32);
     ///
-    /// let mut f2: SimpleBloomFilter<BitBox<usize, Lsb0>> =
-    ///     SimpleBloomFilter::with_hashers(f1.hashers().clone(), 20);
+    /// let mut f2: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 10, 20);
     /// f2.insert(&32);
     /// f2.insert(&39);
     ///
     /// assert!(f1.contains(&48));
     /// assert!(f1.contains(&32));
-    /// // May fail if 39 is a false positive
     /// assert!(!f1.contains(&39));
     /// assert!(f2.contains(&39));
     ///
     /// f1.intersect(&f2);
     ///
-    /// // May fail if 48 is a false positive
     /// assert!(!f1.contains(&48));
     /// assert!(f1.contains(&32));
-    /// // May fail if 39 is a false positive
     /// assert!(!f1.contains(&39));
     /// ```
     fn intersect<Other>(&mut self, other: &Other)
     where
         Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>;
+
+    /// Keeps only counters which are set in exactly one of `self` and
+    /// `other`. **`other` and `self` must have the same
+    /// [`BuildHasher`]s for this to work.**
+    ///
+    /// Unlike [`union`](BinaryBloomFilter::union) and
+    /// [`intersect`](BinaryBloomFilter::intersect), the result is
+    /// *not* a filter of the values inserted into exactly one of the
+    /// filters: a value inserted into only one of them can share
+    /// counters with values inserted into both, so queries for it may
+    /// give false negatives. Instead, the result describes which
+    /// counters differ, so that e.g. its
+    /// [`count_ones`](BloomSetRead::count_ones) measures how much a filter
+    /// changed between two snapshots.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BinaryBloomFilter, BloomSetRead, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut before: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// before.extend(0..10);
+    /// let mut after = before.clone();
+    /// after.extend(10..12);
+    ///
+    /// let mut diff = after.clone();
+    /// diff.symmetric_difference(&before);
+    /// assert_eq!(diff.counters().count_ones(), after.counters().count_ones() - before.counters().count_ones());
+    /// ```
+    fn symmetric_difference<Other>(&mut self, other: &Other)
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>;
+
+    /// Checks whether every value in `self` is also in `other`, i.e.
+    /// whether every counter set in `self` is also set in
+    /// `other`. **`other` and `self` must have the same
+    /// [`BuildHasher`]s for the result to be meaningful**; filters
+    /// with different numbers of counters are never subsets of each
+    /// other. See
+    /// [`SimpleBloomFilter::is_compatible`](crate::SimpleBloomFilter::is_compatible)
+    /// for checking hashers which implement [`PartialEq`].
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BinaryBloomFilter, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut shard: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// let mut all: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+    ///     SimpleBloomFilter::with_hashers(shard.hashers().clone(), 2000);
+    /// shard.extend(0..10);
+    /// all.extend(0..100);
+    ///
+    /// assert!(shard.is_subset(&all));
+    /// assert!(all.is_superset(&shard));
+    /// // May fail if all of 10..100 are false positives
+    /// assert!(!all.is_subset(&shard));
+    /// ```
+    fn is_subset<Other>(&self, other: &Other) -> bool
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        self.counters().is_subset(other.counters())
+    }
+
+    /// Checks whether every value in `other` is also in `self`. See
+    /// [`is_subset`](BinaryBloomFilter::is_subset).
+    fn is_superset<Other>(&self, other: &Other) -> bool
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        other.counters().is_subset(self.counters())
+    }
+
+    /// Returns a value summarizing this filter's hashers, if it can
+    /// compute one. Filters whose hashers map values differently
+    /// almost always have different fingerprints, even when the
+    /// hashers do not implement [`PartialEq`]. Returns [`None`] by
+    /// default.
+    fn hasher_fingerprint(&self) -> Option<u64> {
+        None
+    }
+
+    /// Like [`union`](BinaryBloomFilter::union), but first checks
+    /// that the filters have the same number of counters and, if both
+    /// provide a [`hasher_fingerprint`](BinaryBloomFilter::hasher_fingerprint),
+    /// the same hashers. `self` is left unchanged if they do not.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BinaryBloomFilter, MismatchError, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut a: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// let b: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+    ///     SimpleBloomFilter::with_hashers(a.hashers().clone(), 2000);
+    /// assert!(a.try_union(&b).is_ok());
+    ///
+    /// let c: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(10, 2000);
+    /// assert_eq!(a.try_union(&c), Err(MismatchError::HasherMismatch));
+    ///
+    /// let d: SimpleBloomFilter<BitBox<usize, Lsb0>> =
+    ///     SimpleBloomFilter::with_hashers(a.hashers().clone(), 1000);
+    /// assert_eq!(
+    ///     a.try_intersect(&d),
+    ///     Err(MismatchError::SizeMismatch { expected: 2000, found: 1000 })
+    /// );
+    /// ```
+    fn try_union<Other>(&mut self, other: &Other) -> Result<(), MismatchError>
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        check_compatible(self, other)?;
+        self.union(other);
+        Ok(())
+    }
+
+    /// Like [`intersect`](BinaryBloomFilter::intersect), but first
+    /// checks that the filters match, as for
+    /// [`try_union`](BinaryBloomFilter::try_union).
+    fn try_intersect<Other>(&mut self, other: &Other) -> Result<(), MismatchError>
+    where
+        Other: BinaryBloomFilter<Set = Self::Set, Hasher = Self::Hasher>,
+    {
+        check_compatible(self, other)?;
+        self.intersect(other);
+        Ok(())
+    }
+}
+
+/// Trait for filters which can be used as state-based CRDTs
+/// (conflict-free replicated data types).
+///
+/// [`merge`](MergeableFilter::merge) is commutative, associative and
+/// idempotent, so replicas which exchange their states in any order,
+/// any number of times, converge to the same filter: the one they
+/// would have if every value had been inserted into each of them.
+/// Binary filters merge by OR-ing their bits. Counting filters merge
+/// by taking the larger of each pair of counters, so a value inserted
+/// `n` times into one replica and `m` times into another counts
+/// `max(n, m)` times; use
+/// [`SpectralBloomFilter::merge_counts`] instead to add counts from
+/// replicas which saw disjoint parts of a stream, which is not
+/// idempotent.
+///
+/// Replicas must be created with the same hashers and number of
+/// counters, e.g. from a common [`SeededState`](crate::SeededState)
+/// seed, rather than with [`Default`] hashers, whose seeds are
+/// random.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, MergeableFilter, SimpleBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// type Replica = SimpleBloomFilter<BitBox<usize, Lsb0>>;
+/// let new_replica = || -> Replica { SimpleBloomFilter::with_seed(48, 7, 2000) };
+///
+/// let (mut a, mut b) = (new_replica(), new_replica());
+/// a.insert(&"from a");
+/// b.insert(&"from b");
+///
+/// let mut ab = a.clone();
+/// ab.merge(&b).unwrap();
+/// let mut ba = b.clone();
+/// ba.merge(&a).unwrap();
+/// assert_eq!(ab, ba);
+///
+/// // Merging the same state again changes nothing.
+/// ab.merge(&b).unwrap();
+/// assert_eq!(ab, ba);
+/// assert!(ab.contains(&"from a") && ab.contains(&"from b"));
+/// ```
+pub trait MergeableFilter: BloomFilter {
+    /// Merges the state of `other` into `self`, or returns an error,
+    /// leaving `self` unchanged, if the filters have different
+    /// numbers of counters or hashers which can be told apart.
+    fn merge(&mut self, other: &Self) -> Result<(), MismatchError>;
+}
+
+/// The reason two filters could not be combined by
+/// [`BinaryBloomFilter::try_union`],
+/// [`BinaryBloomFilter::try_intersect`] or
+/// [`MergeableFilter::merge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MismatchError {
+    /// The filters have different numbers of counters.
+    SizeMismatch { expected: usize, found: usize },
+    /// The filters have different hashers.
+    HasherMismatch,
+}
+
+impl MismatchError {
+    /// Checks that `a` and `b` have the same number of counters and
+    /// hashers with the same [fingerprint](crate::hash::fingerprint).
+    pub(crate) fn check<F: BloomFilter + ?Sized>(a: &F, b: &F) -> Result<(), MismatchError> {
+        let (expected, found) = (a.counters().size(), b.counters().size());
+        if expected != found {
+            Err(MismatchError::SizeMismatch { expected, found })
+        } else if crate::hash::fingerprint(a.hashers()) != crate::hash::fingerprint(b.hashers()) {
+            Err(MismatchError::HasherMismatch)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for MismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MismatchError::SizeMismatch { expected, found } => {
+                write!(f, "expected {} counters, found {}", expected, found)
+            }
+            MismatchError::HasherMismatch => write!(f, "filters have different hashers"),
+        }
+    }
+}
+
+impl std::error::Error for MismatchError {}
+
+/// The reason a filter could not be created from the given
+/// configuration, e.g. by
+/// [`SimpleBloomFilter::try_with_hashers`](crate::SimpleBloomFilter::try_with_hashers).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// No hashers were given.
+    NoHashers,
+    /// The filter would have no counters.
+    NoCounters,
+}
+
+impl ConfigError {
+    /// Checks that a filter with `n_hashers` hashers and
+    /// `n_counters` counters can be created.
+    pub(crate) fn check(n_hashers: usize, n_counters: usize) -> Result<(), ConfigError> {
+        if n_hashers == 0 {
+            Err(ConfigError::NoHashers)
+        } else if n_counters == 0 {
+            Err(ConfigError::NoCounters)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoHashers => write!(f, "filter has no hashers"),
+            ConfigError::NoCounters => write!(f, "filter has no counters"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn check_compatible<A, B>(a: &A, b: &B) -> Result<(), MismatchError>
+where
+    A: BinaryBloomFilter + ?Sized,
+    B: BinaryBloomFilter<Set = A::Set, Hasher = A::Hasher>,
+    A::Set: BinaryBloomSet,
+{
+    let (expected, found) = (a.counters().size(), b.counters().size());
+    if expected != found {
+        return Err(MismatchError::SizeMismatch { expected, found });
+    }
+    match (a.hasher_fingerprint(), b.hasher_fingerprint()) {
+        (Some(x), Some(y)) if x != y => Err(MismatchError::HasherMismatch),
+        _ => Ok(()),
+    }
 }
 
 /// Trait for types which act as Bloom filters and support
@@ -154,12 +790,55 @@ where
     <<Self as BloomFilter>::Set as SpectralBloomSet>::Count: Ord,
 {
     /// Tests whether the set contains `val` more than `count` times.
-    fn contains_more_than<T: Hash>(
+    fn contains_more_than<T: Hash + ?Sized>(
         &self,
         val: &T,
         count: &<<Self as BloomFilter>::Set as SpectralBloomSet>::Count,
     ) -> bool;
 
     /// Returns an estimate of the number of times the set contains `val`.
-    fn find_count<T: Hash>(&self, val: &T) -> &<<Self as BloomFilter>::Set as SpectralBloomSet>::Count;
+    fn find_count<T: Hash + ?Sized>(&self, val: &T) -> <<Self as BloomFilter>::Set as SpectralBloomSet>::Count;
+
+    /// Estimates the count of each value in `vals`, like
+    /// [`contains_batch`](BloomFilter::contains_batch) does for
+    /// membership. Implementations may hash several values before
+    /// probing any of them, so that the probes' cache misses overlap.
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut f: SimpleBloomFilter<Box<[u32]>> = SimpleBloomFilter::new(10, 2000);
+    /// f.insert_batch(&[1, 2, 2, 3, 3, 3]);
+    /// let counts = f.find_counts(&[1, 2, 3]);
+    /// assert!(counts[0] >= 1 && counts[1] >= 2 && counts[2] >= 3);
+    /// ```
+    fn find_counts<T: Hash>(&self, vals: &[T]) -> Vec<<<Self as BloomFilter>::Set as SpectralBloomSet>::Count> {
+        vals.iter().map(|val| self.find_count(val)).collect()
+    }
+
+    /// Adds the counts of `other` to those of `self`, so that each
+    /// value is counted as many times as it was inserted into either
+    /// filter. This differs from
+    /// [`BinaryBloomFilter::union`],
+    /// which keeps the larger of each pair of counters. **`other` and
+    /// `self` must have the same [`BuildHasher`]s for this to work.**
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, SimpleBloomFilter, SpectralBloomFilter};
+    ///
+    /// let mut total: SimpleBloomFilter<Box<[u32]>> = SimpleBloomFilter::new(10, 2000);
+    /// let mut worker: SimpleBloomFilter<Box<[u32]>> =
+    ///     SimpleBloomFilter::with_hashers(total.hashers().clone(), 2000);
+    /// total.insert(&"a");
+    /// worker.insert(&"a");
+    /// worker.insert(&"a");
+    ///
+    /// total.merge_counts(&worker);
+    /// assert!(total.find_count(&"a") >= 3);
+    /// ```
+    fn merge_counts<Other>(&mut self, other: &Other)
+    where
+        Other: SpectralBloomFilter<Set = Self::Set, Hasher = Self::Hasher>;
 }
@@ -23,7 +23,12 @@
 //! should be implemented using [`BloomSet`](set::BloomSet), while the
 //! Minimal Increase optimization for spectral bloom filters should be
 //! implemented as a [`BloomFilter`](filter::BloomFilter), because it
-//! involves optimizing which counters are incremented.
+//! involves optimizing which counters are incremented. Such
+//! strategies can set counters directly with
+//! [`SpectralBloomSetMut::increment_to`](set::SpectralBloomSetMut::increment_to),
+//! as
+//! [`SimpleBloomFilter::insert_minimal_increase`](crate::SimpleBloomFilter::insert_minimal_increase)
+//! does.
 
 pub mod filter;
 pub mod set;
@@ -19,28 +19,96 @@
 //! [`BloomFilter`](crate::BloomFilter)s. Implementations are provided
 //! for [`BitBox`]es, providing a binary Bloom filter, and for
 //! `Box<[T]>` where `T` is a numeric type, providing a spectral Bloom
-//! filter which supports deletions.
-use bitvec::{boxed::BitBox, order::BitOrder, store::BitStore, vec::BitVec};
-use num_traits::{Bounded, One, SaturatingAdd, Zero};
+//! filter which supports deletions. The counters of a `Box<[T]>`
+//! saturate; [`Counters`](crate::counters::Counters) provides other
+//! overflow and underflow behavior.
+use bitvec::{boxed::BitBox, mem::bits_of, order::BitOrder, slice::BitSlice, store::BitStore, vec::BitVec};
+use num_traits::{Bounded, One, SaturatingAdd, SaturatingSub, Zero};
 use std::ops::SubAssign;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, AtomicUsize};
+
+/// A trait for types whose counters can be queried, such as the
+/// storage of a [`BloomFilter`](crate::BloomFilter). This is
+/// separate from [`BloomSet`] so that read-only storage, e.g. a
+/// memory-mapped file or a borrowed buffer, need not provide
+/// mutation methods.
+pub trait BloomSetRead {
+    /// Returns the number of counters in the storage.
+    fn size(&self) -> usize;
+
+    /// Queries whether a counter indicates presence.
+    fn query(&self, index: usize) -> bool;
+
+    /// Returns the number of counters which indicate presence.
+    fn count_ones(&self) -> usize {
+        (0..self.size()).filter(|&i| self.query(i)).count()
+    }
+
+    /// Checks whether no counter indicates presence, stopping at the
+    /// first one which does.
+    fn is_clear(&self) -> bool {
+        !(0..self.size()).any(|i| self.query(i))
+    }
+
+    /// Returns an iterator over whether each counter indicates
+    /// presence, in index order.
+    ///
+    /// While this trait is in scope, this also takes precedence over
+    /// the slice `iter` methods of [`BitBox`]es and boxed slices when
+    /// called on a reference such as
+    /// [`counters()`](crate::BloomFilter::counters).
+    ///
+    /// # Example
+    /// ```
+    /// use generic_bloom::{BloomFilter, BloomSetRead, SimpleBloomFilter};
+    /// use bitvec::prelude::*;
+    ///
+    /// let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(3, 100);
+    /// f.insert(&"a");
+    /// let set: Vec<usize> = f.counters().iter()
+    ///     .enumerate()
+    ///     .filter_map(|(i, bit)| bit.then_some(i))
+    ///     .collect();
+    /// assert!(!set.is_empty() && set.len() <= 3);
+    /// ```
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.size()).map(move |i| self.query(i))
+    }
+
+    /// Returns the number of bytes of heap memory used by the set. By
+    /// default, this assumes that each counter takes up one bit.
+    fn heap_size_bytes(&self) -> usize {
+        self.size().div_ceil(8)
+    }
+
+    /// Hints that the counter with index `index` will be queried
+    /// soon, so that its cache line can be loaded while other work is
+    /// done. This does nothing by default.
+    fn prefetch(&self, index: usize) {
+        let _ = index;
+    }
+}
 
 /// A trait for types which can serve as the underlying storage for a
 /// [`BloomFilter`](crate::BloomFilter).
-pub trait BloomSet {
+pub trait BloomSet: BloomSetRead {
     /// Creates a new set with `count` counters.
     fn new(count: usize) -> Self;
 
-    /// Returns the number of counters in the storage.
-    fn size(&self) -> usize;
-
     /// Increments the counter with index `index`.
     fn increment(&mut self, index: usize);
 
+    /// Increments the counter with index `index`, returning `false`
+    /// if it was already at its maximum value, so that the increment
+    /// was lost. By default, this always returns `true`, which is
+    /// correct for sets which do not count, such as bit sets.
+    fn try_increment(&mut self, index: usize) -> bool {
+        self.increment(index);
+        true
+    }
+
     /// Clears all counters.
     fn clear(&mut self);
-
-    /// Queries whether a counter indicates presence.
-    fn query(&self, index: usize) -> bool;
 }
 
 /// A trait for types which can serve as the underlying storage for a
@@ -48,16 +116,60 @@ pub trait BloomSet {
 pub trait BloomSetDelete: BloomSet {
     /// Decrements the counter with index `index`.
     fn decrement(&mut self, index: usize);
+
+    /// Decrements the counter with index `index`, returning `false`
+    /// if it could not be decremented, e.g. because it was zero, so
+    /// that the decrement was lost. By default, this always returns
+    /// `true`.
+    fn try_decrement(&mut self, index: usize) -> bool {
+        self.decrement(index);
+        true
+    }
+
+    /// Decrements each counter of `self` by the corresponding counter
+    /// of `other`, as if every value inserted into `other` had been
+    /// removed from `self`.
+    fn subtract(&mut self, other: &Self);
 }
 
-/// A trait for types which can serve as the underlying storage for a
-/// [`BloomFilter`](crate::BloomFilter) and perform threshold-based
-/// lookups.
-pub trait SpectralBloomSet: BloomSet {
+/// A trait for types whose counters hold counts, which can serve as
+/// the underlying storage for a [`BloomFilter`](crate::BloomFilter)
+/// performing threshold-based lookups.
+pub trait SpectralBloomSet: BloomSetRead {
     type Count;
 
-    /// Returns the count at `index`.
-    fn query_count(&self, index: usize) -> &Self::Count;
+    /// Returns the count at `index`. The count is returned by value,
+    /// so that sets which do not store counts as separate values,
+    /// such as packed or memory-mapped counters, can compute it.
+    fn query_count(&self, index: usize) -> Self::Count;
+
+    /// Returns an iterator over the counts, in index order.
+    fn iter_counts(&self) -> impl Iterator<Item = Self::Count> + '_ {
+        (0..self.size()).map(move |i| self.query_count(i))
+    }
+}
+
+/// A trait for [`SpectralBloomSet`]s whose counts can be set
+/// directly.
+pub trait SpectralBloomSetMut: SpectralBloomSet + BloomSet {
+    /// Sets the count at `index` to `count`.
+    fn set_count(&mut self, index: usize, count: Self::Count);
+
+    /// Raises the count at `index` to `count`, leaving it unchanged
+    /// if it is already at least `count`.
+    fn increment_to(&mut self, index: usize, count: Self::Count)
+    where
+        Self::Count: PartialOrd,
+    {
+        if self.query_count(index) < count {
+            self.set_count(index, count);
+        }
+    }
+
+    /// Adds each counter of `other` to the corresponding counter of
+    /// `self`, as if every value inserted into `other` had also been
+    /// inserted into `self`.
+    fn merge_counts(&mut self, other: &Self);
 }
 
 /// A trait for types which can serve as the underlying storage for a
@@ -67,8 +179,107 @@ pub trait BinaryBloomSet: BloomSet {
     /// Inserts all values from `other` into `self`.
     fn union(&mut self, other: &Self);
 
+    /// Inserts all values from each of `others` into `self`. By
+    /// default, this calls [`union`](BinaryBloomSet::union) for each
+    /// of them; implementations may instead combine a block of
+    /// counters from every input before moving on to the next block,
+    /// so that `self` is traversed only once.
+    fn union_all(&mut self, others: &[&Self]) {
+        for other in others {
+            self.union(other);
+        }
+    }
+
     /// Keeps only values in `self` which are also in `other`.
     fn intersect(&mut self, other: &Self);
+
+    /// Keeps only counters which indicate presence in exactly one of
+    /// `self` and `other`. By default, this clears `self` and
+    /// increments each such counter once; implementations may instead
+    /// combine whole words of counters.
+    fn symmetric_difference(&mut self, other: &Self) {
+        let differ: Vec<usize> = (0..self.size().min(other.size()))
+            .filter(|&i| self.query(i) != other.query(i))
+            .collect();
+        self.clear();
+        for i in differ {
+            self.increment(i);
+        }
+    }
+
+    /// Returns the number of counters which indicate presence in
+    /// `self` or `other`, i.e. the
+    /// [`count_ones`](BloomSetRead::count_ones) of their union, without
+    /// computing the union.
+    fn union_count_ones(&self, other: &Self) -> usize {
+        (0..self.size().min(other.size()))
+            .filter(|&i| self.query(i) || other.query(i))
+            .count()
+    }
+
+    /// Checks whether every counter which indicates presence in
+    /// `self` also does so in `other`. Sets of different sizes are
+    /// never subsets of each other.
+    fn is_subset(&self, other: &Self) -> bool {
+        self.size() == other.size() && (0..self.size()).all(|i| !self.query(i) || other.query(i))
+    }
+}
+
+/// A trait for types which can serve as the underlying storage for a
+/// [`ConcurrentBloomFilter`](crate::ConcurrentBloomFilter), allowing
+/// counters to be incremented through shared references.
+pub trait AtomicBloomSet: BloomSet + Sync {
+    /// Increments the counter with index `index` without requiring
+    /// exclusive access to the set.
+    fn increment_shared(&self, index: usize);
+}
+
+/// A trait for [`AtomicBloomSet`]s whose counters can also be
+/// decremented through shared references, allowing a
+/// [`ConcurrentBloomFilter`](crate::ConcurrentBloomFilter) to
+/// perform deletions.
+pub trait AtomicBloomSetDelete: AtomicBloomSet + BloomSetDelete {
+    /// Decrements the counter with index `index` without requiring
+    /// exclusive access to the set.
+    fn decrement_shared(&self, index: usize);
+}
+
+impl<T, O> BloomSetRead for BitBox<T, O>
+where
+    T: BitStore,
+    O: BitOrder,
+{
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn query(&self, index: usize) -> bool {
+        self[index]
+    }
+
+    fn count_ones(&self) -> usize {
+        BitSlice::count_ones(self)
+    }
+
+    fn is_clear(&self) -> bool {
+        self.not_any()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        BitSlice::iter(self).by_vals()
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(self.as_raw_slice())
+    }
+
+    fn prefetch(&self, index: usize) {
+        // Counters are stored in order, whatever the bit order is
+        // within an element.
+        if let Some(elem) = self.as_raw_slice().get(index / bits_of::<T::Mem>()) {
+            prefetch_read(elem);
+        }
+    }
 }
 
 impl<T, O> BloomSet for BitBox<T, O>
@@ -80,10 +291,6 @@ where
         BitVec::repeat(false, count).into_boxed_bitslice()
     }
 
-    fn size(&self) -> usize {
-        self.len()
-    }
-
     fn increment(&mut self, index: usize) {
         self.set(index, true);
     }
@@ -91,10 +298,6 @@ where
     fn clear(&mut self) {
         self.fill(false);
     }
-
-    fn query(&self, index: usize) -> bool {
-        self[index]
-    }
 }
 
 impl<T, O> BinaryBloomSet for BitBox<T, O>
@@ -103,11 +306,162 @@ where
     O: BitOrder,
 {
     fn union(&mut self, other: &Self) {
-        *self |= other;
+        if !zip_words(self, other, |a, b| a | b) {
+            *self |= other;
+        }
+    }
+
+    fn union_all(&mut self, others: &[&Self]) {
+        let aligned = |b: &BitBox<T, O>| b.len() == self.len() && b.as_bitptr().bit().into_inner() == 0;
+        if !aligned(self) || !others.iter().all(|o| aligned(o)) {
+            for other in others {
+                *self |= &***other;
+            }
+            return;
+        }
+        let others: Vec<&[T]> = others.iter().map(|o| o.as_raw_slice()).collect();
+        for (i, block) in self.as_raw_mut_slice().chunks_mut(UNION_BLOCK_LEN).enumerate() {
+            let start = i * UNION_BLOCK_LEN;
+            for other in &others {
+                for (x, y) in block.iter_mut().zip(&other[start..]) {
+                    x.store_value(x.load_value() | y.load_value());
+                }
+            }
+        }
     }
 
     fn intersect(&mut self, other: &Self) {
-        *self &= other;
+        if !zip_words(self, other, |a, b| a & b) {
+            *self &= other;
+        }
+    }
+
+    fn symmetric_difference(&mut self, other: &Self) {
+        if !zip_words(self, other, |a, b| a ^ b) {
+            *self ^= other;
+        }
+    }
+
+    fn union_count_ones(&self, other: &Self) -> usize {
+        let len = self.len().min(other.len());
+        if self.as_bitptr().bit().into_inner() != 0 || other.as_bitptr().bit().into_inner() != 0 {
+            return self[..len].iter().by_vals().zip(other[..len].iter().by_vals()).filter(|&(a, b)| a || b).count();
+        }
+        let full = len / bits_of::<T>();
+        let words = self.as_raw_slice()[..full]
+            .iter()
+            .zip(&other.as_raw_slice()[..full])
+            .map(|(a, b)| BitSlice::<T::Mem, O>::from_element(&(a.load_value() | b.load_value())).count_ones())
+            .sum::<usize>();
+        let tail = full * bits_of::<T>();
+        words
+            + self[tail..len]
+                .iter()
+                .by_vals()
+                .zip(other[tail..len].iter().by_vals())
+                .filter(|&(a, b)| a || b)
+                .count()
+    }
+
+    fn is_subset(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        if self.as_bitptr().bit().into_inner() != 0 || other.as_bitptr().bit().into_inner() != 0 {
+            return BitSlice::iter(self).by_vals().zip(BitSlice::iter(other).by_vals()).all(|(a, b)| !a || b);
+        }
+        // Compare whole elements, then the live bits of the last,
+        // partially used one.
+        let full = self.len() / bits_of::<T>();
+        let words_subset = self.as_raw_slice()[..full]
+            .iter()
+            .zip(&other.as_raw_slice()[..full])
+            .all(|(a, b)| {
+                let b = b.load_value();
+                a.load_value() | b == b
+            });
+        let tail = full * bits_of::<T>();
+        words_subset
+            && self[tail..]
+                .iter()
+                .by_vals()
+                .zip(other[tail..].iter().by_vals())
+                .all(|(a, b)| !a || b)
+    }
+}
+
+/// Number of elements which [`BinaryBloomSet::union_all`] combines
+/// from every input before moving on, small enough that the block of
+/// the output stays in the L1 cache.
+const UNION_BLOCK_LEN: usize = 512;
+
+/// Combines the elements of `a` and `b` with `op`, a whole element at
+/// a time, which is much faster than combining them bit by bit. This
+/// is only possible if both have the same length and start at
+/// the beginning of an element, as every `BitBox` created by
+/// [`BloomSet::new`] does; returns whether it was.
+fn zip_words<T, O>(a: &mut BitBox<T, O>, b: &BitBox<T, O>, op: impl Fn(T::Mem, T::Mem) -> T::Mem) -> bool
+where
+    T: BitStore,
+    O: BitOrder,
+{
+    if a.len() != b.len() || a.as_bitptr().bit().into_inner() != 0 || b.as_bitptr().bit().into_inner() != 0 {
+        return false;
+    }
+    // Any bits past the end of the sets only ever get combined with
+    // each other, and are never observed.
+    for (x, y) in a.as_raw_mut_slice().iter_mut().zip(b.as_raw_slice()) {
+        x.store_value(op(x.load_value(), y.load_value()));
+    }
+    true
+}
+
+macro_rules! impl_atomic_bitbox {
+    ($($atomic:ty),*) => {
+        $(
+            impl<O: BitOrder> AtomicBloomSet for BitBox<$atomic, O> {
+                fn increment_shared(&self, index: usize) {
+                    self.set_aliased(index, true);
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_bitbox!(AtomicU8, AtomicU16, AtomicU32, AtomicU64, AtomicUsize);
+
+impl<T> BloomSetRead for Box<[T]>
+where
+    T: SaturatingAdd + One + Zero + Ord,
+{
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn query(&self, index: usize) -> bool {
+        !self[index].is_zero()
+    }
+
+    fn count_ones(&self) -> usize {
+        <[T]>::iter(self).filter(|c| !c.is_zero()).count()
+    }
+
+    fn is_clear(&self) -> bool {
+        <[T]>::iter(self).all(Zero::is_zero)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        <[T]>::iter(self).map(|c| !c.is_zero())
+    }
+
+    fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&**self)
+    }
+
+    fn prefetch(&self, index: usize) {
+        if let Some(counter) = self.get(index) {
+            prefetch_read(counter);
+        }
     }
 }
 
@@ -122,41 +476,193 @@ where
             .into_boxed_slice()
     }
 
-    fn size(&self) -> usize {
-        self.len()
-    }
-
     fn increment(&mut self, index: usize) {
         self[index] = self[index].saturating_add(&T::one());
     }
 
-    fn clear(&mut self) {
-        self.fill_with(T::zero);
+    fn try_increment(&mut self, index: usize) -> bool {
+        let count = self[index].saturating_add(&T::one());
+        let counted = count != self[index];
+        self[index] = count;
+        counted
     }
 
-    fn query(&self, index: usize) -> bool {
-        self.query_count(index) > &T::zero()
+    fn clear(&mut self) {
+        self.fill_with(T::zero);
     }
 }
 
+/// Saturated counters are never decremented, since their true count
+/// is unknown, and decrementing a zero counter leaves it at zero, so
+/// a saturated counter stays at the maximum forever. This behavior
+/// is deprecated and only kept for existing users: new code should
+/// use [`Counters`](crate::counters::Counters), e.g. with the
+/// [`Spill`](crate::counters::Spill) policy, whose saturated
+/// counters are released again by removals.
+///
+/// # Example
+/// ```
+/// use generic_bloom::traits::set::*;
+///
+/// let mut counters: Box<[u8]> = BloomSet::new(2);
+/// counters.decrement(0);
+/// assert_eq!(counters.query_count(0), 0);
+///
+/// counters.set_count(1, u8::MAX);
+/// counters.decrement(1);
+/// assert_eq!(counters.query_count(1), u8::MAX);
+/// ```
 impl<T> BloomSetDelete for Box<[T]>
 where
-    T: SaturatingAdd + SubAssign + One + Zero + Ord + Bounded,
+    T: SaturatingAdd + SubAssign + One + Zero + Ord + Bounded + Clone,
 {
     fn decrement(&mut self, index: usize) {
-        if self[index] != T::max_value() {
+        self.try_decrement(index);
+    }
+
+    fn try_decrement(&mut self, index: usize) -> bool {
+        let counted = !self[index].is_zero() && self[index] != T::max_value();
+        if counted {
             self[index] -= T::one();
         }
+        counted
+    }
+
+    /// Subtracts element-wise, stopping at zero. Like
+    /// [`decrement`](BloomSetDelete::decrement), this leaves
+    /// saturated counters unchanged, since their true count is
+    /// unknown.
+    fn subtract(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[T]>::iter(other)) {
+            if *a == T::max_value() {
+                continue;
+            }
+            if *b >= *a {
+                *a = T::zero();
+            } else {
+                *a -= b.clone();
+            }
+        }
+    }
+}
+
+/// Unions and intersections of counters take the element-wise
+/// maximum and minimum respectively, like those of multisets, and
+/// symmetric differences take the element-wise absolute difference.
+impl<T> BinaryBloomSet for Box<[T]>
+where
+    T: SaturatingAdd + SaturatingSub + One + Zero + Ord + Clone,
+{
+    fn union(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[T]>::iter(other)) {
+            if *b > *a {
+                *a = b.clone();
+            }
+        }
+    }
+
+    fn union_all(&mut self, others: &[&Self]) {
+        for (i, block) in self.chunks_mut(UNION_BLOCK_LEN).enumerate() {
+            let start = i * UNION_BLOCK_LEN;
+            for other in others {
+                for (a, b) in block.iter_mut().zip(other.get(start..).unwrap_or_default()) {
+                    if *b > *a {
+                        *a = b.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    fn intersect(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[T]>::iter(other)) {
+            if *b < *a {
+                *a = b.clone();
+            }
+        }
+    }
+
+    fn symmetric_difference(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[T]>::iter(other)) {
+            *a = if *b > *a { b.saturating_sub(a) } else { a.saturating_sub(b) };
+        }
+    }
+
+    /// Checks whether each counter of `self` is at most the
+    /// corresponding counter of `other`.
+    fn is_subset(&self, other: &Self) -> bool {
+        self.len() == other.len() && <[T]>::iter(self).zip(<[T]>::iter(other)).all(|(a, b)| a <= b)
     }
 }
 
 impl<T> SpectralBloomSet for Box<[T]>
 where
-    T: SaturatingAdd + One + Zero + Ord,
+    T: SaturatingAdd + One + Zero + Ord + Clone,
 {
     type Count = T;
 
-    fn query_count(&self, index: usize) -> &Self::Count {
-        &self[index]
+    fn query_count(&self, index: usize) -> Self::Count {
+        self[index].clone()
+    }
+
+    fn iter_counts(&self) -> impl Iterator<Item = Self::Count> + '_ {
+        <[T]>::iter(self).cloned()
+    }
+}
+
+impl<T> SpectralBloomSetMut for Box<[T]>
+where
+    T: SaturatingAdd + One + Zero + Ord + Clone,
+{
+    fn set_count(&mut self, index: usize, count: Self::Count) {
+        self[index] = count;
+    }
+
+    /// Adds element-wise, saturating at the maximum count.
+    fn merge_counts(&mut self, other: &Self) {
+        for (a, b) in self.iter_mut().zip(<[T]>::iter(other)) {
+            *a = a.saturating_add(b);
+        }
+    }
+}
+
+/// Number of counters which [`query_all`] prefetches before querying
+/// them.
+const PREFETCH_LEN: usize = 16;
+
+/// Checks whether all of the counters at `indices` indicate presence.
+/// The counters are prefetched in groups before being queried, so
+/// that the cache misses of a large set overlap instead of happening
+/// one after another.
+pub(crate) fn query_all<B: BloomSetRead>(set: &B, mut indices: impl Iterator<Item = usize>) -> bool {
+    let mut buf = [0; PREFETCH_LEN];
+    loop {
+        let mut len = 0;
+        for (slot, i) in buf.iter_mut().zip(&mut indices) {
+            set.prefetch(i);
+            *slot = i;
+            len += 1;
+        }
+        if len == 0 {
+            return true;
+        }
+        if !buf[..len].iter().all(|&i| set.query(i)) {
+            return false;
+        }
+    }
+}
+
+/// Prefetches the cache line containing `p` for reading, on
+/// architectures where this is supported.
+#[inline(always)]
+pub(crate) fn prefetch_read<T>(p: *const T) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        // SAFETY: prefetching never faults, even for invalid
+        // addresses, and SSE is part of the x86_64 baseline.
+        unsafe { _mm_prefetch::<_MM_HINT_T0>(p.cast()) };
     }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = p;
 }
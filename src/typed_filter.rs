@@ -0,0 +1,275 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::traits::filter::*;
+use crate::traits::set::*;
+use bitvec::vec::BitVec;
+use std::fmt;
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// A Bloom filter which only accepts values of type `T`.
+///
+/// The methods of [`BloomFilter`] accept any `T: Hash`, so querying a
+/// filter of user IDs with a string compiles and silently answers
+/// `false` (or worse, a false positive). `TypedBloomFilter` wraps any
+/// filter `F` and fixes the element type, turning such mistakes into
+/// compile errors. Queries which are not provided here can be made
+/// through [`filter`](TypedBloomFilter::filter).
+///
+/// Like the lookups of [`HashMap`](std::collections::HashMap),
+/// lookups accept any type `Q` which `T` [`Borrow`]s as, so a filter
+/// of `String`s can be queried with a `&str`. The contract of
+/// [`Borrow`] requires `T` and `Q` to hash identically, so such
+/// lookups find the values inserted as `T`.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SimpleBloomFilter, TypedBloomFilter};
+/// use bitvec::prelude::*;
+///
+/// #[derive(Hash)]
+/// struct UserId(u64);
+///
+/// let mut users: TypedBloomFilter<UserId, SimpleBloomFilter<BitBox<usize, Lsb0>>> =
+///     TypedBloomFilter::new(SimpleBloomFilter::new(10, 2000));
+/// users.insert(&UserId(48));
+/// assert!(users.contains(&UserId(48)));
+///
+/// // Unsized values can be used directly
+/// let mut domains: TypedBloomFilter<str, SimpleBloomFilter<BitBox<usize, Lsb0>>> =
+///     TypedBloomFilter::new(SimpleBloomFilter::new(10, 2000));
+/// domains.extend(["example.com", "example.org"]);
+/// assert!(domains.contains("example.com"));
+///
+/// let mut names: TypedBloomFilter<String, SimpleBloomFilter<BitBox<usize, Lsb0>>> =
+///     TypedBloomFilter::new(SimpleBloomFilter::new(10, 2000));
+/// names.insert(&"alice".to_string());
+/// assert!(names.contains("alice"));
+/// ```
+///
+/// Values of other types are rejected:
+/// ```compile_fail
+/// # use generic_bloom::{SimpleBloomFilter, TypedBloomFilter};
+/// # use bitvec::prelude::*;
+/// # #[derive(Hash)]
+/// # struct UserId(u64);
+/// let users: TypedBloomFilter<UserId, SimpleBloomFilter<BitBox<usize, Lsb0>>> =
+///     TypedBloomFilter::new(SimpleBloomFilter::new(10, 2000));
+/// users.contains(&"48");
+/// ```
+pub struct TypedBloomFilter<T: ?Sized, F> {
+    filter: F,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T, F> TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: BloomFilter,
+{
+    /// Wraps `filter`, which should only contain values of type `T`.
+    pub fn new(filter: F) -> Self {
+        TypedBloomFilter {
+            filter,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the underlying filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the underlying filter, which no longer restricts the
+    /// type of its values.
+    pub fn into_filter(self) -> F {
+        self.filter
+    }
+
+    pub fn counters(&self) -> &F::Set {
+        self.filter.counters()
+    }
+
+    /// Inserts `val` into the set.
+    pub fn insert(&mut self, val: &T) {
+        self.filter.insert(val)
+    }
+
+    /// Inserts every value in `vals`. See
+    /// [`BloomFilter::insert_batch`].
+    pub fn insert_batch(&mut self, vals: &[T])
+    where
+        T: Sized,
+    {
+        self.filter.insert_batch(vals)
+    }
+
+    /// Inserts `val` unless the set already contains it. See
+    /// [`BloomFilter::insert_if_absent`].
+    pub fn insert_if_absent(&mut self, val: &T) -> bool {
+        self.filter.insert_if_absent(val)
+    }
+
+    /// Checks whether the set contains `val`.
+    pub fn contains<Q>(&self, val: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.filter.contains(val)
+    }
+
+    /// Checks whether the set contains each value in `vals`. See
+    /// [`BloomFilter::contains_batch`].
+    pub fn contains_batch(&self, vals: &[T]) -> BitVec
+    where
+        T: Sized,
+    {
+        self.filter.contains_batch(vals)
+    }
+
+    /// Checks whether the set is empty. See
+    /// [`BloomFilter::is_empty`].
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+
+    /// Clears the set.
+    pub fn clear(&mut self) {
+        self.filter.clear()
+    }
+}
+
+impl<T, F> TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: MergeableFilter,
+{
+    /// Merges the state of `other` into `self`. See
+    /// [`MergeableFilter::merge`].
+    pub fn merge(&mut self, other: &Self) -> Result<(), MismatchError> {
+        self.filter.merge(&other.filter)
+    }
+}
+
+impl<T, F> TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: BloomFilterDelete,
+    F::Set: BloomSetDelete,
+{
+    /// Removes `val` from the set. See [`BloomFilterDelete::remove`].
+    pub fn remove<Q>(&mut self, val: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.filter.remove(val)
+    }
+
+    /// Removes `val` only if the set contains it. See
+    /// [`BloomFilterDelete::remove_if_present`].
+    pub fn remove_if_present<Q>(&mut self, val: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.filter.remove_if_present(val)
+    }
+}
+
+impl<T, F> TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: SpectralBloomFilter,
+    F::Set: SpectralBloomSet,
+    <F::Set as SpectralBloomSet>::Count: Ord,
+{
+    /// Returns an estimate of the number of times the set contains
+    /// `val`.
+    pub fn find_count<Q>(&self, val: &Q) -> <F::Set as SpectralBloomSet>::Count
+    where
+        T: Borrow<Q>,
+        Q: Hash + ?Sized,
+    {
+        self.filter.find_count(val)
+    }
+}
+
+impl<T, F> From<F> for TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: BloomFilter,
+{
+    fn from(filter: F) -> Self {
+        TypedBloomFilter::new(filter)
+    }
+}
+
+impl<T: ?Sized, F: Clone> Clone for TypedBloomFilter<T, F> {
+    fn clone(&self) -> Self {
+        TypedBloomFilter {
+            filter: self.filter.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized, F: fmt::Debug> fmt::Debug for TypedBloomFilter<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedBloomFilter")
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+impl<T: ?Sized, F: PartialEq> PartialEq for TypedBloomFilter<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.filter == other.filter
+    }
+}
+
+impl<T: ?Sized, F: Eq> Eq for TypedBloomFilter<T, F> {}
+
+impl<T, F> Extend<T> for TypedBloomFilter<T, F>
+where
+    T: Hash,
+    F: BloomFilter,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for val in iter {
+            self.insert(&val);
+        }
+    }
+}
+
+impl<'a, T, F> Extend<&'a T> for TypedBloomFilter<T, F>
+where
+    T: Hash + ?Sized,
+    F: BloomFilter,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = &'a T>,
+    {
+        for val in iter {
+            self.insert(val);
+        }
+    }
+}
@@ -0,0 +1,351 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::next_random;
+use crate::SeededState;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of slots in each bucket.
+const BUCKET_SLOTS: usize = 4;
+
+/// Smallest and largest numbers of buckets in a chunk of the table,
+/// within which fingerprints move between their buckets. Smaller
+/// chunks fill unevenly, so the table cannot be loaded as heavily.
+const MIN_CHUNK_BUCKETS: usize = 1024;
+const MAX_CHUNK_BUCKETS: usize = 4096;
+
+/// Number of times a filter can be [`grow`](VacuumFilter::grow)n.
+/// Each doubling uses up a bit of the 16-bit fingerprints.
+const MAX_GROWTH: u32 = 8;
+
+/// Number of fingerprints moved while inserting a value before the
+/// filter is considered full.
+const MAX_KICKS: usize = 500;
+
+/// Load factor at which filters are sized to hold the requested
+/// number of values.
+const MAX_LOAD: f64 = 0.92;
+
+/// A cuckoo filter whose table need not have a power-of-two size, and
+/// which can be grown in place.
+///
+/// As in a cuckoo filter, each value has a 16-bit fingerprint stored
+/// in one of two buckets of four slots, and can be removed. The table
+/// is split into chunks of between 1024 and 4096 buckets, and a
+/// fingerprint's alternate bucket is found by flipping low bits of
+/// its bucket's index, so both buckets are in the same chunk. Since
+/// only the chunk size has to be a power of two, a large table is
+/// sized to within an eighth of the requested capacity, instead of
+/// up to twice it. Fingerprints are split into four classes which
+/// move within ranges of a whole chunk down to an eighth of one: the
+/// small ranges keep moves between nearby buckets, and the large ones
+/// let the table fill evenly. Before evicting a fingerprint to make
+/// room, insertion first looks for one in the two buckets which can
+/// move straight to an empty slot.
+///
+/// Because a fingerprint's chunk depends only on its low bits once
+/// the table has grown, [`grow`](VacuumFilter::grow) doubles the
+/// table by moving each bucket's fingerprints to one of two buckets,
+/// without needing the values they came from. Each doubling halves
+/// the distinct fingerprints a bucket can hold, roughly doubling the
+/// false positive rate, which starts at about 0.01% at full load.
+///
+/// # Example
+/// ```
+/// use generic_bloom::VacuumFilter;
+///
+/// let mut filter: VacuumFilter = VacuumFilter::with_seed(1, 10_000);
+/// for i in 0..10_000 {
+///     assert!(filter.insert(&i));
+/// }
+/// assert!(filter.remove(&48));
+/// assert!(!filter.contains(&48));
+///
+/// // Double the table to make room for more values.
+/// let capacity = filter.capacity();
+/// assert!(filter.grow());
+/// assert_eq!(filter.capacity(), 2 * capacity);
+/// for i in 10_000..20_000 {
+///     assert!(filter.insert(&i));
+/// }
+/// assert!((0..20_000).filter(|&i| i != 48).all(|i| filter.contains(&i)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct VacuumFilter<S = SeededState> {
+    hasher: S,
+    // Slot `i` is in bucket `i / BUCKET_SLOTS`, and is empty if its
+    // fingerprint is zero.
+    slots: Box<[u16]>,
+    chunk_buckets: usize,
+    // Number of chunks before the filter was grown.
+    base_chunks: usize,
+    growth: u32,
+    len: usize,
+    // A fingerprint which could not be placed when the filter filled
+    // up, and one of its buckets.
+    victim: Option<(usize, u16)>,
+    // State of the generator choosing fingerprints to move.
+    rng: u64,
+}
+
+impl VacuumFilter<SeededState> {
+    /// Creates an empty `VacuumFilter` with room for at least
+    /// `capacity` values, hashing them with a [`SeededState`] created
+    /// from `seed`.
+    pub fn with_seed(seed: u128, capacity: usize) -> Self {
+        VacuumFilter::with_hasher(SeededState::from_seed(seed), capacity)
+    }
+}
+
+impl<S: BuildHasher> VacuumFilter<S> {
+    /// Creates an empty `VacuumFilter` with room for at least
+    /// `capacity` values. The [`BuildHasher`] will be initialized by
+    /// [`default`](Default::default).
+    pub fn new(capacity: usize) -> Self
+    where
+        S: Default,
+    {
+        VacuumFilter::with_hasher(S::default(), capacity)
+    }
+
+    /// Creates an empty `VacuumFilter` with a specified
+    /// `BuildHasher` and room for at least `capacity` values. The
+    /// number of buckets is rounded up to a whole number of chunks.
+    pub fn with_hasher(hasher: S, capacity: usize) -> Self {
+        let n_buckets = (capacity as f64 / (BUCKET_SLOTS as f64 * MAX_LOAD))
+            .ceil()
+            .max(2.0) as usize;
+        // Chunks of at most an eighth of the table waste little space
+        // rounding it up. A small table is a single chunk.
+        let min_chunk = n_buckets.next_power_of_two().min(MIN_CHUNK_BUCKETS);
+        let chunk_buckets = n_buckets
+            .div_ceil(16)
+            .next_power_of_two()
+            .clamp(min_chunk, MAX_CHUNK_BUCKETS);
+        let base_chunks = n_buckets.div_ceil(chunk_buckets);
+        VacuumFilter {
+            hasher,
+            slots: vec![0; base_chunks * chunk_buckets * BUCKET_SLOTS].into_boxed_slice(),
+            chunk_buckets,
+            base_chunks,
+            growth: 0,
+            len: 0,
+            victim: None,
+            rng: 0x853c_49e6_748f_ea9b,
+        }
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Returns the number of values in the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Checks whether the filter holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of fingerprint slots.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the fraction of fingerprint slots in use.
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Inserts `val`, returning `false` if the filter is full, in
+    /// which case `val` was not inserted.
+    ///
+    /// When the filter fills up, the last fingerprint which could
+    /// not be placed is kept aside, so the insertion which fills it
+    /// still succeeds; later insertions fail until a value is
+    /// removed or the filter is grown.
+    pub fn insert<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+        let (bucket, fp) = self.locate(val);
+        self.place(bucket, fp);
+        self.len += 1;
+        true
+    }
+
+    /// Checks whether the filter contains `val`.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        let (bucket, fp) = self.locate(val);
+        let alt = self.alt_bucket(bucket, fp);
+        self.bucket(bucket).contains(&fp)
+            || self.bucket(alt).contains(&fp)
+            || self.victim.is_some_and(|(b, f)| f == fp && (b == bucket || b == alt))
+    }
+
+    /// Removes `val`, which must have been inserted, returning
+    /// whether its fingerprint was found.
+    pub fn remove<T: Hash + ?Sized>(&mut self, val: &T) -> bool {
+        let (bucket, fp) = self.locate(val);
+        let alt = self.alt_bucket(bucket, fp);
+        let found = [bucket, alt]
+            .into_iter()
+            .flat_map(|b| b * BUCKET_SLOTS..(b + 1) * BUCKET_SLOTS)
+            .find(|&slot| self.slots[slot] == fp);
+        if let Some(slot) = found {
+            self.slots[slot] = 0;
+            self.len -= 1;
+            // Make room for the fingerprint kept aside, if any.
+            if let Some((b, f)) = self.victim.take() {
+                self.place(b, f);
+            }
+            true
+        } else if self.victim.is_some_and(|(b, f)| f == fp && (b == bucket || b == alt)) {
+            self.victim = None;
+            self.len -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Doubles the number of slots, keeping every value. Returns
+    /// `false`, leaving the filter unchanged, if it has already been
+    /// grown 8 times, since its fingerprints would then be too short
+    /// to be useful.
+    pub fn grow(&mut self) -> bool {
+        if self.growth == MAX_GROWTH {
+            return false;
+        }
+        let n_buckets = self.slots.len() / BUCKET_SLOTS;
+        let mut slots = vec![0; 2 * self.slots.len()].into_boxed_slice();
+        for (bucket, fps) in self.slots.chunks_exact(BUCKET_SLOTS).enumerate() {
+            for &fp in fps.iter().filter(|&&fp| fp != 0) {
+                let to = self.grown_bucket(bucket, fp, n_buckets) * BUCKET_SLOTS;
+                let free = slots[to..to + BUCKET_SLOTS]
+                    .iter_mut()
+                    .find(|s| **s == 0)
+                    .expect("a grown bucket receives fingerprints from only one bucket");
+                *free = fp;
+            }
+        }
+        self.slots = slots;
+        // Retry the fingerprint kept aside, now there is room.
+        if let Some((b, f)) = self.victim.take() {
+            self.place(self.grown_bucket(b, f, n_buckets), f);
+        }
+        self.growth += 1;
+        true
+    }
+
+    /// Removes every value.
+    pub fn clear(&mut self) {
+        self.slots.fill(0);
+        self.len = 0;
+        self.victim = None;
+    }
+
+    /// Returns the approximate probability that a value which was
+    /// not inserted is reported as present, given the current load:
+    /// the chance that its fingerprint matches one of those in its
+    /// two buckets, whose low bits already match once the filter has
+    /// grown.
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        let compared = 2.0 * BUCKET_SLOTS as f64 * self.load_factor();
+        let distinct = (1u32 << (16 - self.growth)) as f64;
+        1.0 - (1.0 - 1.0 / distinct).powf(compared)
+    }
+
+    /// Returns the number of bytes of heap memory used.
+    pub fn heap_size_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.slots)
+    }
+
+    /// Returns the primary bucket of `val` and its nonzero
+    /// fingerprint. The bucket's position in the table the filter was
+    /// created with comes from the low half of the hash, and the
+    /// growth since then picks a copy of that table using the low
+    /// bits of the fingerprint.
+    fn locate<T: Hash + ?Sized>(&self, val: &T) -> (usize, u16) {
+        let hash = self.hasher.hash_one(val);
+        let fp = match (hash >> 32) as u16 {
+            0 => 1,
+            fp => fp,
+        };
+        let base_buckets = self.base_chunks * self.chunk_buckets;
+        let base = ((hash as u32 as u128 * base_buckets as u128) >> 32) as usize;
+        let copy = fp as usize & ((1 << self.growth) - 1);
+        (base + copy * base_buckets, fp)
+    }
+
+    /// Returns the other bucket a fingerprint `fp` in `bucket` can be
+    /// stored in, which is in the same range of buckets as `bucket`.
+    /// The range's size depends on the fingerprint's class.
+    fn alt_bucket(&self, bucket: usize, fp: u16) -> usize {
+        let range = (self.chunk_buckets >> (fp >> 14)).max(2);
+        let mixed = (fp as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        bucket ^ (1 + mixed as usize % (range - 1))
+    }
+
+    /// Returns the bucket which fingerprint `fp` in `bucket` moves to
+    /// when a table of `n_buckets` buckets is doubled.
+    fn grown_bucket(&self, bucket: usize, fp: u16, n_buckets: usize) -> usize {
+        if fp >> self.growth & 1 == 1 {
+            bucket + n_buckets
+        } else {
+            bucket
+        }
+    }
+
+    fn bucket(&self, bucket: usize) -> &[u16] {
+        &self.slots[bucket * BUCKET_SLOTS..(bucket + 1) * BUCKET_SLOTS]
+    }
+
+    fn free_slot(&self, bucket: usize) -> Option<usize> {
+        (bucket * BUCKET_SLOTS..(bucket + 1) * BUCKET_SLOTS).find(|&slot| self.slots[slot] == 0)
+    }
+
+    /// Stores `fp` in `bucket` or its alternate, moving other
+    /// fingerprints as needed. If no room is found, the last
+    /// fingerprint moved is kept aside as the victim.
+    fn place(&mut self, mut bucket: usize, mut fp: u16) {
+        for _ in 0..MAX_KICKS {
+            let alt = self.alt_bucket(bucket, fp);
+            if let Some(slot) = self.free_slot(bucket).or_else(|| self.free_slot(alt)) {
+                self.slots[slot] = fp;
+                return;
+            }
+            // Look for a fingerprint which can move straight to an
+            // empty slot in its other bucket before evicting one.
+            for from in [bucket, alt] {
+                for slot in from * BUCKET_SLOTS..(from + 1) * BUCKET_SLOTS {
+                    let other = self.alt_bucket(from, self.slots[slot]);
+                    if let Some(free) = self.free_slot(other) {
+                        self.slots[free] = self.slots[slot];
+                        self.slots[slot] = fp;
+                        return;
+                    }
+                }
+            }
+            let r = next_random(&mut self.rng);
+            let from = if r & 1 == 0 { bucket } else { alt };
+            let slot = from * BUCKET_SLOTS + (r >> 32) as usize % BUCKET_SLOTS;
+            fp = std::mem::replace(&mut self.slots[slot], fp);
+            bucket = self.alt_bucket(from, fp);
+        }
+        self.victim = Some((bucket, fp));
+    }
+}
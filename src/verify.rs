@@ -0,0 +1,484 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+//! Utilities for checking that a filter behaves as its parameters
+//! predict, e.g. in integration tests.
+//!
+//! [`measure_false_positive_rate`] checks a filter which is already
+//! populated, while [`self_test`] runs a whole experiment against a
+//! [`BloomSet`] backend, which is useful for validating custom
+//! implementations. The p-values these report use normal
+//! approximations, which are accurate when at least a few dozen false
+//! positives are expected. [`hasher_report`] checks the hashers of a
+//! filter on their own, to catch broken or correlated ones.
+//!
+//! # Example
+//! ```
+//! use generic_bloom::{SimpleBloomFilter, verify};
+//! use bitvec::prelude::*;
+//!
+//! let mut f: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::new(7, 9586);
+//! f.extend(0..1000);
+//!
+//! let report = verify::measure_false_positive_rate(&f, 1000..101_000);
+//! assert!(report.is_within_sigmas(5.0), "{}", report);
+//! ```
+
+use crate::hash::{hash_indices, SeededState};
+use crate::traits::filter::BloomFilter;
+use crate::traits::set::{BloomSet, BloomSetRead};
+use crate::SimpleBloomFilter;
+use bitvec::vec::BitVec;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+/// The false positive rate of a filter observed over a number of
+/// queries, as measured by [`measure_false_positive_rate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FprReport {
+    /// The number of values queried.
+    pub queries: usize,
+    /// The number of queried values which the filter reported as
+    /// present.
+    pub false_positives: usize,
+    /// The false positive rate predicted by
+    /// [`BloomFilter::expected_false_positive_rate`].
+    pub expected: f64,
+}
+
+impl FprReport {
+    /// Returns the observed false positive rate, or zero if no values
+    /// were queried.
+    pub fn observed(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.false_positives as f64 / self.queries as f64
+        }
+    }
+
+    /// Returns the standard deviation of the observed rate which the
+    /// expected rate predicts for this number of queries.
+    pub fn std_error(&self) -> f64 {
+        (self.expected * (1.0 - self.expected) / self.queries.max(1) as f64).sqrt()
+    }
+
+    /// Checks whether the observed rate differs from the expected one
+    /// by at most `tolerance`.
+    pub fn is_within(&self, tolerance: f64) -> bool {
+        (self.observed() - self.expected).abs() <= tolerance
+    }
+
+    /// Checks whether the observed rate differs from the expected one
+    /// by at most `sigmas` standard errors. With `sigmas` around 4 or
+    /// 5, a correct filter fails this check too rarely to matter,
+    /// whatever the number of queries.
+    pub fn is_within_sigmas(&self, sigmas: f64) -> bool {
+        self.is_within(sigmas * self.std_error())
+    }
+
+    /// Returns the two-sided p-value of a binomial test of the
+    /// observed number of false positives against the expected rate:
+    /// the probability of a deviation at least this large if the
+    /// filter behaves as expected.
+    pub fn p_value(&self) -> f64 {
+        let n = self.queries as f64;
+        let mean = n * self.expected;
+        let sd = (mean * (1.0 - self.expected)).sqrt();
+        // Continuity correction, since the count is discrete.
+        let deviation = ((self.false_positives as f64 - mean).abs() - 0.5).max(0.0);
+        if sd == 0.0 {
+            return if deviation == 0.0 { 1.0 } else { 0.0 };
+        }
+        erfc(deviation / sd / std::f64::consts::SQRT_2)
+    }
+}
+
+impl fmt::Display for FprReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "observed false positive rate {:.6} ({}/{}), expected {:.6} ± {:.6}",
+            self.observed(),
+            self.false_positives,
+            self.queries,
+            self.expected,
+            self.std_error(),
+        )
+    }
+}
+
+/// Queries `filter` for each of `absent`, which must not have been
+/// inserted into it, and counts how many are reported as present.
+pub fn measure_false_positive_rate<F, I>(filter: &F, absent: I) -> FprReport
+where
+    F: BloomFilter,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let mut queries = 0;
+    let mut false_positives = 0;
+    for val in absent {
+        queries += 1;
+        if filter.contains(&val) {
+            false_positives += 1;
+        }
+    }
+    FprReport {
+        queries,
+        false_positives,
+        expected: filter.expected_false_positive_rate(),
+    }
+}
+
+/// The results of a [`self_test`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfTestReport {
+    /// The number of inserted values which the filter did not
+    /// contain. This is zero for any correct backend.
+    pub false_negatives: usize,
+    /// The false positive rate observed for values which were not
+    /// inserted.
+    pub fpr: FprReport,
+    /// The p-value of a chi-square test of whether the counters
+    /// indicating presence are spread evenly over the set. This is
+    /// conservative, since occupied counters vary less than the
+    /// test assumes.
+    pub uniformity_p_value: f64,
+}
+
+impl SelfTestReport {
+    /// Checks that there were no false negatives and that neither the
+    /// false positive rate nor the spread of counters is significant
+    /// at level `alpha`, e.g. 0.001.
+    pub fn passed(&self, alpha: f64) -> bool {
+        self.false_negatives == 0 && self.fpr.p_value() >= alpha && self.uniformity_p_value >= alpha
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} false negatives; {} (p = {:.4}); uniformity p = {:.4}",
+            self.false_negatives,
+            self.fpr,
+            self.fpr.p_value(),
+            self.uniformity_p_value,
+        )
+    }
+}
+
+/// Number of ranges of counters compared by the uniformity test of
+/// [`self_test`].
+const UNIFORMITY_BUCKETS: usize = 64;
+
+/// Inserts `n_items` values into a [`SimpleBloomFilter`] backed by
+/// `B`, with `n_hashers` hashers and `n_counters` counters, then
+/// checks that they are all present, measures the false positive rate
+/// over `n_queries` other values, and tests whether the counters
+/// indicating presence are spread evenly. The hashers are derived
+/// from `seed`, so each run with the same arguments gives the same
+/// report.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{Block, EpochSet, verify};
+/// use bitvec::prelude::*;
+///
+/// let a = verify::self_test::<BitBox<usize, Lsb0>>(7, 9586, 1000, 100_000, 0);
+/// let b = verify::self_test::<Box<[u8]>>(7, 9586, 1000, 100_000, 0);
+/// let c = verify::self_test::<Box<[Block]>>(7, 9586, 1000, 100_000, 0);
+/// let d = verify::self_test::<EpochSet>(7, 9586, 1000, 100_000, 0);
+/// for report in [a, b, c, d] {
+///     assert!(report.passed(0.001), "{}", report);
+/// }
+/// ```
+pub fn self_test<B: BloomSet>(
+    n_hashers: usize,
+    n_counters: usize,
+    n_items: usize,
+    n_queries: usize,
+    seed: u64,
+) -> SelfTestReport {
+    let hashers: Vec<SeededState> = (0..n_hashers as u64).map(|i| SeededState::new(seed, i)).collect();
+    let mut filter: SimpleBloomFilter<B, SeededState, Vec<SeededState>> =
+        SimpleBloomFilter::with_hashers(hashers, n_counters);
+
+    let n_items = n_items as u64;
+    for val in 0..n_items {
+        filter.insert(&val);
+    }
+    let false_negatives = (0..n_items).filter(|val| !filter.contains(val)).count();
+    let fpr = measure_false_positive_rate(&filter, n_items..n_items + n_queries as u64);
+
+    SelfTestReport {
+        false_negatives,
+        fpr,
+        uniformity_p_value: uniformity_p_value(filter.counters()),
+    }
+}
+
+/// Returns the p-value of a chi-square test of whether the counters
+/// of `set` which indicate presence are spread evenly over it.
+fn uniformity_p_value<B: BloomSetRead>(set: &B) -> f64 {
+    let size = set.size();
+    let buckets = UNIFORMITY_BUCKETS.min(size);
+    let mut ones = vec![0usize; buckets];
+    for (i, present) in set.iter().enumerate() {
+        ones[i * buckets / size] += present as usize;
+    }
+    spread_p_value(size, &ones)
+}
+
+/// Returns the p-value of a chi-square test of whether the counts in
+/// `ones`, the totals of equal ranges of `size` counters, are spread
+/// evenly over the counters.
+fn spread_p_value(size: usize, ones: &[usize]) -> f64 {
+    let buckets = ones.len();
+    // Counter `i` is in range `i * buckets / size`.
+    let lens: Vec<usize> = (0..buckets)
+        .map(|b| ((b + 1) * size).div_ceil(buckets) - (b * size).div_ceil(buckets))
+        .collect();
+
+    let total = ones.iter().sum::<usize>() as f64;
+    if total == 0.0 || buckets < 2 {
+        return 1.0;
+    }
+    let statistic: f64 = lens
+        .iter()
+        .zip(ones)
+        .map(|(&len, &ones)| {
+            let expected = total * len as f64 / size as f64;
+            (ones as f64 - expected).powi(2) / expected
+        })
+        .sum();
+    chi_square_sf(statistic, (buckets - 1) as f64)
+}
+
+/// How one hasher of a filter spreads a sample of values over the
+/// counters, as measured by [`hasher_report`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HasherStats {
+    /// The number of distinct counters the hasher maps the sample to.
+    pub distinct: usize,
+    /// The fraction of all counters the hasher maps the sample to,
+    /// i.e. the fill the hasher alone would give a binary filter.
+    pub density: f64,
+    /// The p-value of a chi-square test of whether the sample is
+    /// spread evenly over the counters.
+    pub uniformity_p_value: f64,
+}
+
+/// Per-hasher diagnostics for the hashers of a filter, as measured by
+/// [`hasher_report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HasherReport {
+    /// The number of values in the sample.
+    pub samples: usize,
+    /// The number of counters of the filter.
+    pub size: usize,
+    /// The statistics of each hasher, in order.
+    pub hashers: Vec<HasherStats>,
+    // `agreements[i * k + j]` is the number of values hashers `i` and
+    // `j` map to the same counter.
+    agreements: Vec<usize>,
+}
+
+impl HasherReport {
+    /// Returns the density each hasher should have if it maps the
+    /// sample, which should consist of distinct values, to
+    /// independent uniformly random counters.
+    pub fn expected_density(&self) -> f64 {
+        1.0 - (1.0 - 1.0 / self.size as f64).powf(self.samples as f64)
+    }
+
+    /// Returns the fraction of the sample which hashers `i` and `j`
+    /// map to the same counter. Independent hashers agree on about
+    /// one value in [`size`](HasherReport::size), and identical ones
+    /// on every value.
+    pub fn agreement(&self, i: usize, j: usize) -> f64 {
+        self.agreements[i * self.hashers.len() + j] as f64 / self.samples.max(1) as f64
+    }
+
+    /// Returns the p-value of a test of whether hashers `i` and `j`
+    /// agree more often than independent hashers would.
+    pub fn agreement_p_value(&self, i: usize, j: usize) -> f64 {
+        let mean = self.samples as f64 / self.size as f64;
+        poisson_sf(self.agreements[i * self.hashers.len() + j], mean)
+    }
+
+    /// Returns the pairs of distinct hashers `(i, j)`, with `i < j`,
+    /// which agree significantly more often than independent hashers
+    /// would at level `alpha`, e.g. 0.001.
+    pub fn correlated_pairs(&self, alpha: f64) -> Vec<(usize, usize)> {
+        let k = self.hashers.len();
+        (0..k)
+            .flat_map(|i| (i + 1..k).map(move |j| (i, j)))
+            .filter(|&(i, j)| self.agreement_p_value(i, j) < alpha)
+            .collect()
+    }
+
+    /// Checks that every hasher spreads the sample evenly and that no
+    /// two hashers are correlated, at level `alpha`, e.g. 0.001.
+    pub fn passed(&self, alpha: f64) -> bool {
+        self.hashers.iter().all(|h| h.uniformity_p_value >= alpha) && self.correlated_pairs(alpha).is_empty()
+    }
+}
+
+impl fmt::Display for HasherReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} samples over {} counters, expected density {:.6}",
+            self.samples,
+            self.size,
+            self.expected_density()
+        )?;
+        for (i, h) in self.hashers.iter().enumerate() {
+            write!(
+                f,
+                "\nhasher {}: density {:.6} ({} counters), uniformity p = {:.4}",
+                i, h.density, h.distinct, h.uniformity_p_value
+            )?;
+        }
+        for (i, j) in self.correlated_pairs(0.001) {
+            write!(f, "\nhashers {} and {} agree on {:.6} of values", i, j, self.agreement(i, j))?;
+        }
+        Ok(())
+    }
+}
+
+/// Hashes each of `sample`, which should consist of distinct values,
+/// e.g. the values inserted into `filter`, with each hasher of
+/// `filter`, and reports how each hasher spreads them over the
+/// counters and how often pairs of hashers agree.
+///
+/// A filter whose hashers all map values the same way, e.g. because
+/// `S::default()` gives identical hashers, behaves like a filter with
+/// a single hasher, with a much higher false positive rate than its
+/// parameters predict, but otherwise works. This report shows such
+/// hashers as correlated pairs, and hashers which ignore part of
+/// their input or map values to few counters as uneven.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SeededState, SimpleBloomFilter, verify};
+/// use bitvec::prelude::*;
+///
+/// let good: SimpleBloomFilter<BitBox<usize, Lsb0>> = SimpleBloomFilter::with_seed(1, 4, 10_000);
+/// let report = verify::hasher_report(&good, 0..10_000);
+/// assert!(report.passed(0.001), "{}", report);
+///
+/// // Four copies of the same hasher.
+/// let copies = vec![SeededState::new(1, 2); 4];
+/// let bad: SimpleBloomFilter<BitBox<usize, Lsb0>, SeededState, Vec<SeededState>> =
+///     SimpleBloomFilter::with_hashers(copies, 10_000);
+/// let report = verify::hasher_report(&bad, 0..10_000);
+/// assert_eq!(report.agreement(0, 3), 1.0);
+/// assert_eq!(report.correlated_pairs(0.001).len(), 6);
+/// ```
+pub fn hasher_report<B, S, V, I>(filter: &SimpleBloomFilter<B, S, V>, sample: I) -> HasherReport
+where
+    B: BloomSet,
+    S: BuildHasher,
+    V: AsRef<[S]>,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    let hashers = filter.hashers().as_ref();
+    let k = hashers.len();
+    let size = filter.counters().size();
+    let buckets = UNIFORMITY_BUCKETS.min(size);
+    let mut hit = vec![BitVec::<usize>::repeat(false, size); k];
+    let mut ones = vec![vec![0usize; buckets]; k];
+    let mut agreements = vec![0usize; k * k];
+    let mut samples = 0;
+    let mut indices = Vec::with_capacity(k);
+    for val in sample {
+        samples += 1;
+        indices.clear();
+        indices.extend(hash_indices(hashers, size, &val));
+        for (i, &a) in indices.iter().enumerate() {
+            hit[i].set(a, true);
+            ones[i][a * buckets / size] += 1;
+            for (j, &b) in indices.iter().enumerate() {
+                agreements[i * k + j] += (a == b) as usize;
+            }
+        }
+    }
+
+    let hashers = hit
+        .iter()
+        .zip(&ones)
+        .map(|(hit, ones)| {
+            let distinct = hit.count_ones();
+            HasherStats {
+                distinct,
+                density: distinct as f64 / size as f64,
+                uniformity_p_value: spread_p_value(size, ones),
+            }
+        })
+        .collect();
+    HasherReport {
+        samples,
+        size,
+        hashers,
+        agreements,
+    }
+}
+
+/// Returns the probability that a Poisson variable with mean `mean`
+/// is at least `x`.
+fn poisson_sf(x: usize, mean: f64) -> f64 {
+    if x == 0 {
+        return 1.0;
+    }
+    if mean > 30.0 {
+        // Normal approximation, with continuity correction.
+        let z = (x as f64 - 0.5 - mean) / mean.sqrt();
+        return 0.5 * erfc(z / std::f64::consts::SQRT_2);
+    }
+    let mut term = (-mean).exp();
+    let mut below = 0.0;
+    for i in 0..x {
+        below += term;
+        term *= mean / (i + 1) as f64;
+        if term == 0.0 {
+            break;
+        }
+    }
+    (1.0 - below).max(0.0)
+}
+
+/// Returns the probability that a chi-square variable with `df`
+/// degrees of freedom exceeds `x`, using the Wilson–Hilferty
+/// approximation.
+fn chi_square_sf(x: f64, df: f64) -> f64 {
+    let v = 2.0 / (9.0 * df);
+    let z = ((x / df).cbrt() - (1.0 - v)) / v.sqrt();
+    0.5 * erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// The complementary error function, accurate to about 1e-7
+/// (Abramowitz and Stegun 7.1.26).
+fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    poly * (-x * x).exp()
+}
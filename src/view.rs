@@ -0,0 +1,156 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::hash::hash_indices;
+use crate::serialize::{self, Error, SerializableHasher, CHUNK_SIZE};
+use crate::SeededState;
+use std::hash::Hash;
+use std::io;
+
+/// A read-only view of a filter in the [serialized
+/// format](crate::serialize), which answers queries directly from the
+/// borrowed bytes.
+///
+/// Creating a view only parses and verifies the header, so a
+/// short-lived process making a few queries, e.g. on a memory-mapped
+/// file, does not pay for reading every counter. The checksums of the
+/// counters are not verified until [`verify`](BloomFilterView::verify)
+/// is called. Views work with filters of any set kind; a counter
+/// indicates presence if it is nonzero.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{BloomFilter, BloomFilterView, SimpleBloomFilter};
+///
+/// let mut f: SimpleBloomFilter<Box<[u8]>> = SimpleBloomFilter::new(7, 10_000);
+/// f.extend(0..1000);
+/// let bytes = f.to_bytes();
+///
+/// let view: BloomFilterView = BloomFilterView::new(&bytes).unwrap();
+/// assert!(view.contains(&48));
+/// assert_eq!(view.contains(&-48), f.contains(&-48));
+/// assert!(view.verify().is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct BloomFilterView<'a, S = SeededState> {
+    hashers: Box<[S]>,
+    size: usize,
+    // Bytes per counter, or zero for bits.
+    counter_len: usize,
+    // The chunks of counters, including their lengths and checksums.
+    chunks: &'a [u8],
+}
+
+impl<'a, S: SerializableHasher> BloomFilterView<'a, S> {
+    /// Parses the header of the filter serialized in `bytes`, which
+    /// must contain nothing else.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let mut rest = bytes;
+        let (set_kind, hashers, size) = serialize::read_header(&mut rest)?;
+        let counter_len = match set_kind {
+            0 | 1 | 2 | 4 | 8 | 16 => set_kind as usize,
+            _ => return Err(Error::UnsupportedSet(set_kind)),
+        };
+        let data_len = if counter_len == 0 {
+            size.div_ceil(8)
+        } else {
+            size.checked_mul(counter_len).ok_or(Error::BadCounterCount(size as u64))?
+        };
+        // Each chunk has a length and a checksum, and an empty chunk
+        // ends the filter.
+        let len = data_len
+            .div_ceil(CHUNK_SIZE)
+            .checked_mul(8)
+            .and_then(|framing| framing.checked_add(4))
+            .and_then(|framing| framing.checked_add(data_len))
+            .ok_or(Error::BadCounterCount(size as u64))?;
+        if rest.len() < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if rest.len() > len {
+            return Err(Error::TrailingBytes);
+        }
+        Ok(BloomFilterView {
+            hashers,
+            size,
+            counter_len,
+            chunks: rest,
+        })
+    }
+
+    pub fn hashers(&self) -> &[S] {
+        &self.hashers
+    }
+
+    /// Returns the number of counters.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Checks whether the counter with index `index` indicates
+    /// presence.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of bounds.
+    pub fn query(&self, index: usize) -> bool {
+        assert!(index < self.size, "counter {} out of bounds", index);
+        if self.counter_len == 0 {
+            self.bytes_at(index / 8)[0] & (1 << (index % 8)) != 0
+        } else {
+            // Chunks hold a whole number of counters, so a counter
+            // never spans two of them.
+            self.bytes_at(index * self.counter_len)[..self.counter_len]
+                .iter()
+                .any(|&b| b != 0)
+        }
+    }
+
+    /// Checks whether the filter contains `val`, as
+    /// [`BloomFilter::contains`](crate::BloomFilter::contains) does
+    /// on the deserialized filter.
+    pub fn contains<T: Hash + ?Sized>(&self, val: &T) -> bool {
+        hash_indices(&self.hashers, self.size, val).all(|i| self.query(i))
+    }
+
+    /// Verifies the checksums of every chunk of counters.
+    pub fn verify(&self) -> Result<(), Error> {
+        let mut rest = self.chunks;
+        loop {
+            if rest.len() < 4 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let (len, tail) = rest.split_at(4);
+            let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+            if len == 0 {
+                return if tail.is_empty() { Ok(()) } else { Err(Error::TrailingBytes) };
+            }
+            if len > tail.len().saturating_sub(4) {
+                return Err(Error::BadChunkLength(len as u32));
+            }
+            let (data, tail) = tail.split_at(len);
+            let (crc, tail) = tail.split_at(4);
+            if u32::from_le_bytes(crc.try_into().unwrap()) != crc32c::crc32c(data) {
+                return Err(Error::ChecksumMismatch);
+            }
+            rest = tail;
+        }
+    }
+
+    /// Returns the serialized bytes starting at byte `offset` of the
+    /// counters.
+    fn bytes_at(&self, offset: usize) -> &'a [u8] {
+        let chunk = offset / CHUNK_SIZE;
+        &self.chunks[chunk * (CHUNK_SIZE + 8) + 4 + offset % CHUNK_SIZE..]
+    }
+}
@@ -0,0 +1,246 @@
+// This file is part of generic-bloom.
+//
+// generic-bloom is free software: you can redistribute it and/or
+// modify it under the terms of the GNU Affero General Public License
+// as published by the Free Software Foundation, either version 3 of
+// the License, or (at your option) any later version.
+//
+// generic-bloom is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.  You should have
+// received a copy of the GNU Affero General Public License along with
+// generic-bloom. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::rcu_filter::Snapshots;
+use crate::traits::filter::*;
+use std::hash::Hash;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SendError, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+enum Message<T> {
+    Insert(T),
+    Publish(mpsc::Sender<()>),
+}
+
+/// A filter owned by a dedicated writer thread, which inserts values
+/// sent to it over a bounded channel and publishes snapshots of the
+/// filter for [`ReadHandle`]s to query.
+///
+/// Since only the writer thread modifies the filter, any
+/// [`BloomFilter`] can be shared this way, without an atomic set.
+/// [`insert`](WriterThread::insert) takes `&self`, so the
+/// `WriterThread` can be shared between producing threads, and
+/// blocks while the channel is full, so producers cannot outrun the
+/// writer.
+///
+/// Like [`RcuBloomFilter`](crate::RcuBloomFilter), the writer
+/// publishes a clone of its filter after every `publish_every`
+/// insertions. When it has caught up with the channel, it also
+/// publishes any pending insertions once `idle_interval` has passed
+/// since the last publication, so that readers of a slow stream of
+/// values are at most about `idle_interval` behind, without a clone
+/// for each value; `Duration::MAX` disables this.
+/// [`flush`](WriterThread::flush) waits until every value sent so
+/// far is visible to readers.
+///
+/// # Example
+/// ```
+/// use generic_bloom::{SimpleBloomFilter, WriterThread};
+/// use bitvec::prelude::*;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let writer = WriterThread::spawn(
+///     SimpleBloomFilter::<BitBox<usize, Lsb0>>::with_seed(1, 7, 100_000),
+///     1024,
+///     1000,
+///     Duration::from_millis(10),
+/// );
+/// let reader = writer.reader();
+///
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         let writer = &writer;
+///         s.spawn(move || {
+///             for x in 0..1000 {
+///                 writer.insert(t * 1000 + x).unwrap();
+///             }
+///         });
+///     }
+/// });
+/// writer.flush();
+///
+/// let mut readers = vec![reader.clone(), reader];
+/// assert!(readers.iter_mut().all(|r| (0..4000).all(|x| r.contains(&x))));
+///
+/// let filter = writer.finish();
+/// assert_eq!(filter.insert_count(), 4000);
+/// ```
+#[derive(Debug)]
+pub struct WriterThread<T, F> {
+    sender: SyncSender<Message<T>>,
+    thread: JoinHandle<F>,
+    snapshots: Arc<Snapshots<F>>,
+}
+
+impl<T, F> WriterThread<T, F>
+where
+    T: Hash + Send + 'static,
+    F: BloomFilter + Clone + Send + Sync + 'static,
+{
+    /// Spawns a writer thread which owns `filter`, with a channel
+    /// holding up to `capacity` values waiting to be inserted.
+    pub fn spawn(filter: F, capacity: usize, publish_every: usize, idle_interval: Duration) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let snapshots = Arc::new(Snapshots::new(filter.clone()));
+        let thread = {
+            let snapshots = Arc::clone(&snapshots);
+            thread::spawn(move || write(filter, receiver, &snapshots, publish_every, idle_interval))
+        };
+        WriterThread {
+            sender,
+            thread,
+            snapshots,
+        }
+    }
+
+    /// Sends `val` to be inserted, blocking while the channel is
+    /// full. Returns `val` in an error if the writer thread has
+    /// panicked.
+    pub fn insert(&self, val: T) -> Result<(), SendError<T>> {
+        self.sender
+            .send(Message::Insert(val))
+            .map_err(|SendError(msg)| match msg {
+                Message::Insert(val) => SendError(val),
+                Message::Publish(_) => unreachable!(),
+            })
+    }
+
+    /// Waits until every value sent so far has been inserted and
+    /// published.
+    ///
+    /// # Panics
+    /// Panics if the writer thread has panicked.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        let published = self.sender.send(Message::Publish(done)).is_ok() && wait.recv().is_ok();
+        assert!(published, "the writer thread panicked");
+    }
+
+    /// Returns a handle for querying the published snapshots.
+    pub fn reader(&self) -> ReadHandle<F> {
+        let (snapshot, version) = self.snapshots.latest();
+        ReadHandle {
+            snapshots: Arc::clone(&self.snapshots),
+            snapshot,
+            version,
+        }
+    }
+
+    /// Stops the writer thread once it has inserted every value sent
+    /// so far, and returns its filter. Readers keep the last
+    /// snapshot.
+    ///
+    /// # Panics
+    /// Panics if the writer thread has panicked.
+    pub fn finish(self) -> F {
+        drop(self.sender);
+        match self.thread.join() {
+            Ok(filter) => filter,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+}
+
+/// The body of the writer thread.
+fn write<T: Hash, F: BloomFilter + Clone>(
+    mut filter: F,
+    receiver: Receiver<Message<T>>,
+    snapshots: &Snapshots<F>,
+    publish_every: usize,
+    idle_interval: Duration,
+) -> F {
+    let mut state = Pending {
+        count: 0,
+        last_publish: Instant::now(),
+    };
+    loop {
+        let msg = match receiver.try_recv() {
+            Ok(msg) => msg,
+            Err(TryRecvError::Empty) if state.count == 0 => match receiver.recv() {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+            Err(TryRecvError::Empty) => {
+                let wait = idle_interval.saturating_sub(state.last_publish.elapsed());
+                match receiver.recv_timeout(wait) {
+                    Ok(msg) => msg,
+                    Err(RecvTimeoutError::Timeout) => {
+                        state.publish(&filter, snapshots);
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        match msg {
+            Message::Insert(val) => {
+                filter.insert(&val);
+                state.count += 1;
+                if state.count >= publish_every {
+                    state.publish(&filter, snapshots);
+                }
+            }
+            Message::Publish(done) => {
+                state.publish(&filter, snapshots);
+                let _ = done.send(());
+            }
+        }
+    }
+    state.publish(&filter, snapshots);
+    filter
+}
+
+/// The insertions of the writer thread since its last publication.
+struct Pending {
+    count: usize,
+    last_publish: Instant,
+}
+
+impl Pending {
+    /// Publishes `filter` if any insertions are pending.
+    fn publish<F: Clone>(&mut self, filter: &F, snapshots: &Snapshots<F>) {
+        if self.count > 0 {
+            snapshots.publish(filter.clone());
+            self.count = 0;
+            self.last_publish = Instant::now();
+        }
+    }
+}
+
+/// A handle for querying the snapshots published by a
+/// [`WriterThread`]. Clones can be sent to other threads, each
+/// querying without contending with the others.
+#[derive(Debug, Clone)]
+pub struct ReadHandle<F> {
+    snapshots: Arc<Snapshots<F>>,
+    snapshot: Arc<F>,
+    version: u64,
+}
+
+impl<F: BloomFilter> ReadHandle<F> {
+    /// Returns the latest snapshot.
+    pub fn snapshot(&mut self) -> &F {
+        self.snapshots.refresh(&mut self.snapshot, &mut self.version);
+        &self.snapshot
+    }
+
+    /// Checks whether the latest snapshot contains `val`.
+    pub fn contains<V: Hash + ?Sized>(&mut self, val: &V) -> bool {
+        self.snapshot().contains(val)
+    }
+}